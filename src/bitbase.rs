@@ -0,0 +1,756 @@
+/*
+ * Copyright (C) 2020-2022 Zixiao Han
+ */
+
+//! Win/draw bitbases for KPK, KRK, KQK and KBNK, generated by retrograde analysis.
+//!
+//! Positions are stored with the stronger side as White, one bit per position set when White wins.
+//! Without pawns the white king is kept in the a1-d1-d4 triangle, with a pawn the pawn is kept
+//! on the a-d files.
+//!
+//! The bitbases take a few seconds to generate, so they are cached on disk: the bytes `FXBB`,
+//! a version byte, then the bits of KQK, KRK, KPK and KBNK as little-endian u64 words.
+
+use crate::{
+    bitmask,
+    def,
+    state::State,
+};
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::convert::TryInto;
+use std::sync::OnceLock;
+
+const BITBASE_MAGIC: [u8; 4] = *b"FXBB";
+const BITBASE_VERSION: u8 = 1;
+
+const TRIANGLE_SQRS: [usize; 10] = [0, 1, 2, 3, 9, 10, 11, 18, 19, 27];
+const PAWN_SQR_COUNT: usize = 24;
+
+const STRONG: usize = 0;
+const WEAK: usize = 1;
+
+const VALUE_UNKNOWN: u8 = 0;
+const VALUE_WIN: u8 = 1;
+const VALUE_INVALID: u8 = 2;
+
+static BITBASES: OnceLock<Bitbases> = OnceLock::new();
+
+/// The result of a position from the side to move's point of view.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BitbaseResult {
+    Win,
+    Draw,
+    Loss,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Material {
+    Kqk,
+    Krk,
+    Kpk,
+    Kbnk,
+}
+
+// in the order of generation, KPK promotes into KQK and KRK
+const MATERIAL_LIST: [Material; 4] = [Material::Kqk, Material::Krk, Material::Kpk, Material::Kbnk];
+
+impl Material {
+    fn get_piece_list(self) -> &'static [u8] {
+        match self {
+            Material::Kqk => &[def::WQ],
+            Material::Krk => &[def::WR],
+            Material::Kpk => &[def::WP],
+            Material::Kbnk => &[def::WB, def::WN],
+        }
+    }
+
+    fn get_size(self) -> usize {
+        match self {
+            Material::Kpk => 2 * PAWN_SQR_COUNT * def::BOARD_SIZE * def::BOARD_SIZE,
+            _ => 2 * TRIANGLE_SQRS.len() * def::BOARD_SIZE.pow(1 + self.get_piece_list().len() as u32),
+        }
+    }
+}
+
+#[inline]
+fn rank_of(index: usize) -> usize {
+    index >> 3
+}
+
+#[inline]
+fn file_of(index: usize) -> usize {
+    index & 7
+}
+
+#[inline]
+fn flip_diagonal(index: usize) -> usize {
+    ((index >> 3) | (index << 3)) & 63
+}
+
+// squares are the white king, the black king, then the white pieces of the material
+fn canonicalize(material: Material, squares: &mut [usize]) {
+    let transform = |squares: &mut [usize], transform_index: fn(usize) -> usize| {
+        for index in squares.iter_mut() {
+            *index = transform_index(*index);
+        }
+    };
+
+    if material == Material::Kpk {
+        if file_of(squares[2]) > 3 {
+            transform(squares, |index| index ^ 7);
+        }
+
+        return
+    }
+
+    if file_of(squares[0]) > 3 {
+        transform(squares, |index| index ^ 7);
+    }
+
+    if rank_of(squares[0]) > 3 {
+        transform(squares, |index| index ^ 56);
+    }
+
+    // with the king on the diagonal, the first piece off the diagonal goes below it
+    let off_diagonal_index = squares.iter().copied().find(|index| rank_of(*index) != file_of(*index)).unwrap_or(0);
+
+    if rank_of(off_diagonal_index) > file_of(off_diagonal_index) {
+        transform(squares, flip_diagonal);
+    }
+}
+
+fn get_index(material: Material, stm: usize, squares: &[usize]) -> usize {
+    let mut canonical_squares = [0; 4];
+    let canonical_squares = &mut canonical_squares[..squares.len()];
+    canonical_squares.copy_from_slice(squares);
+    canonicalize(material, canonical_squares);
+
+    if material == Material::Kpk {
+        let pawn_index = (rank_of(canonical_squares[2]) - 1) * 4 + file_of(canonical_squares[2]);
+        return ((stm * PAWN_SQR_COUNT + pawn_index) * def::BOARD_SIZE + canonical_squares[0]) * def::BOARD_SIZE + canonical_squares[1]
+    }
+
+    let triangle_index = TRIANGLE_SQRS.iter().position(|index| *index == canonical_squares[0]).unwrap();
+
+    canonical_squares[1..].iter().fold(stm * TRIANGLE_SQRS.len() + triangle_index, |idx, index| idx * def::BOARD_SIZE + index)
+}
+
+fn decode_index(material: Material, mut idx: usize) -> (usize, [usize; 4]) {
+    let mut squares = [0; 4];
+    let square_count = 2 + material.get_piece_list().len();
+
+    if material == Material::Kpk {
+        squares[1] = idx % def::BOARD_SIZE;
+        squares[0] = idx / def::BOARD_SIZE % def::BOARD_SIZE;
+        idx /= def::BOARD_SIZE * def::BOARD_SIZE;
+
+        let pawn_index = idx % PAWN_SQR_COUNT;
+        squares[2] = (pawn_index / 4 + 1) * def::DIM_SIZE + pawn_index % 4;
+
+        return (idx / PAWN_SQR_COUNT, squares)
+    }
+
+    for index in squares[1..square_count].iter_mut().rev() {
+        *index = idx % def::BOARD_SIZE;
+        idx /= def::BOARD_SIZE;
+    }
+
+    squares[0] = TRIANGLE_SQRS[idx % TRIANGLE_SQRS.len()];
+
+    (idx / TRIANGLE_SQRS.len(), squares)
+}
+
+fn get_slide_attack_mask(from: usize, occupancy: u64, direction_list: &[(i32, i32)]) -> u64 {
+    let mut attack_mask = 0;
+
+    for (file_step, rank_step) in direction_list {
+        let mut file = file_of(from) as i32 + file_step;
+        let mut rank = rank_of(from) as i32 + rank_step;
+
+        while (0..8).contains(&file) && (0..8).contains(&rank) {
+            let index = (rank * 8 + file) as usize;
+            attack_mask |= 1 << index;
+
+            if occupancy & (1 << index) != 0 {
+                break
+            }
+
+            file += file_step;
+            rank += rank_step;
+        }
+    }
+
+    attack_mask
+}
+
+// the squares a white piece attacks, including the first occupied square of each ray
+fn get_attack_mask(piece: u8, from: usize, occupancy: u64) -> u64 {
+    const DIAGONAL_DIRECTION_LIST: [(i32, i32); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+    const ORTHOGONAL_DIRECTION_LIST: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+
+    let bitmask = bitmask::get_bitmask();
+
+    match piece {
+        def::WK => bitmask.k_attack_masks[from],
+        def::WN => bitmask.n_attack_masks[from],
+        def::WB => get_slide_attack_mask(from, occupancy, &DIAGONAL_DIRECTION_LIST),
+        def::WR => get_slide_attack_mask(from, occupancy, &ORTHOGONAL_DIRECTION_LIST),
+        def::WQ => get_slide_attack_mask(from, occupancy, &DIAGONAL_DIRECTION_LIST) | get_slide_attack_mask(from, occupancy, &ORTHOGONAL_DIRECTION_LIST),
+        _ => {
+            let mut attack_mask = 0;
+
+            if file_of(from) > 0 && from + 7 < def::BOARD_SIZE {
+                attack_mask |= 1 << (from + 7);
+            }
+
+            if file_of(from) < 7 && from + 9 < def::BOARD_SIZE {
+                attack_mask |= 1 << (from + 9);
+            }
+
+            attack_mask
+        },
+    }
+}
+
+/// The bits of one material, with White as the stronger side.
+struct Bitbase {
+    material: Material,
+    bit_list: Vec<u64>,
+}
+
+impl Bitbase {
+    fn is_win(&self, stm: usize, squares: &[usize]) -> bool {
+        let idx = get_index(self.material, stm, squares);
+        self.bit_list[idx >> 6] & (1 << (idx & 63)) != 0
+    }
+}
+
+struct Generator<'a> {
+    material: Material,
+    piece_list: &'static [u8],
+    value_list: Vec<u8>,
+    promo_bitbase_list: &'a [Bitbase],
+}
+
+impl Generator<'_> {
+    fn get_value(&self, stm: usize, squares: &[usize]) -> u8 {
+        self.value_list[get_index(self.material, stm, squares)]
+    }
+
+    fn get_piece(&self, piece_index: usize) -> u8 {
+        match piece_index {
+            0 => def::WK,
+            _ => self.piece_list[piece_index - 2],
+        }
+    }
+
+    fn is_attacked(&self, squares: &[usize], target: usize, occupancy: u64, captured_index: Option<usize>) -> bool {
+        (0..squares.len()).filter(|piece_index| *piece_index != 1 && Some(*piece_index) != captured_index).any(|piece_index| {
+            get_attack_mask(self.get_piece(piece_index), squares[piece_index], occupancy) & (1 << target) != 0
+        })
+    }
+
+    fn is_valid(&self, stm: usize, squares: &[usize]) -> bool {
+        if (1..squares.len()).any(|i| squares[..i].contains(&squares[i])) {
+            return false
+        }
+
+        if bitmask::get_bitmask().k_attack_masks[squares[0]] & (1 << squares[1]) != 0 {
+            return false
+        }
+
+        // Black cannot be in check with White to move
+        stm == WEAK || !self.is_attacked(squares, squares[1], get_occupancy(squares), None)
+    }
+
+    fn is_win(&self, stm: usize, squares: &[usize]) -> bool {
+        let occupancy = get_occupancy(squares);
+        let mut next_squares = [0; 4];
+        let next_squares = &mut next_squares[..squares.len()];
+
+        if stm == STRONG {
+            let bk_guard_mask = bitmask::get_bitmask().k_attack_masks[squares[1]];
+
+            for piece_index in (0..squares.len()).filter(|piece_index| *piece_index != 1) {
+                let piece = self.get_piece(piece_index);
+                let from = squares[piece_index];
+
+                if piece == def::WP {
+                    let mut to_list = vec![from + def::DIM_SIZE];
+
+                    if rank_of(from) == 1 && occupancy & (1 << (from + def::DIM_SIZE)) == 0 {
+                        to_list.push(from + def::DIM_SIZE * 2);
+                    }
+
+                    for to in to_list.into_iter().filter(|to| occupancy & (1 << to) == 0) {
+                        next_squares.copy_from_slice(squares);
+                        next_squares[piece_index] = to;
+
+                        let is_win = if rank_of(to) == 7 {
+                            self.promo_bitbase_list.iter().any(|bitbase| bitbase.is_win(WEAK, next_squares))
+                        } else {
+                            self.get_value(WEAK, next_squares) == VALUE_WIN
+                        };
+
+                        if is_win {
+                            return true
+                        }
+                    }
+
+                    continue
+                }
+
+                let mut to_mask = get_attack_mask(piece, from, occupancy) & !occupancy;
+
+                if piece == def::WK {
+                    to_mask &= !bk_guard_mask;
+                }
+
+                while to_mask != 0 {
+                    let to = to_mask.trailing_zeros() as usize;
+                    to_mask &= to_mask - 1;
+
+                    next_squares.copy_from_slice(squares);
+                    next_squares[piece_index] = to;
+
+                    if self.get_value(WEAK, next_squares) == VALUE_WIN {
+                        return true
+                    }
+                }
+            }
+
+            return false
+        }
+
+        // Black loses when mated, or when every move leads to a win for White
+        let bk_index = squares[1];
+        let is_in_check = self.is_attacked(squares, bk_index, occupancy, None);
+        let mut to_mask = bitmask::get_bitmask().k_attack_masks[bk_index] & !bitmask::get_bitmask().k_attack_masks[squares[0]];
+        let mut has_legal_mov = false;
+
+        while to_mask != 0 {
+            let to = to_mask.trailing_zeros() as usize;
+            to_mask &= to_mask - 1;
+
+            let captured_index = (2..squares.len()).find(|piece_index| squares[*piece_index] == to);
+            let next_occupancy = (occupancy & !(1 << bk_index)) | (1 << to);
+
+            if self.is_attacked(squares, to, next_occupancy, captured_index) {
+                continue
+            }
+
+            has_legal_mov = true;
+
+            // the remaining pieces cannot win
+            if captured_index.is_some() {
+                return false
+            }
+
+            next_squares.copy_from_slice(squares);
+            next_squares[1] = to;
+
+            if self.get_value(STRONG, next_squares) != VALUE_WIN {
+                return false
+            }
+        }
+
+        has_legal_mov || is_in_check
+    }
+
+    // positions that lead to a new win: White to move with a move to a position won with Black to move,
+    // and Black to move with every move now known to lose
+    fn push_prev_wins(&mut self, stm: usize, squares: &[usize], idx_stack: &mut Vec<usize>) {
+        let occupancy = get_occupancy(squares);
+        let mut prev_squares = [0; 4];
+        let prev_squares = &mut prev_squares[..squares.len()];
+
+        if stm == WEAK {
+            for piece_index in (0..squares.len()).filter(|piece_index| *piece_index != 1) {
+                let piece = self.get_piece(piece_index);
+                let to = squares[piece_index];
+
+                let mut from_mask = if piece == def::WP {
+                    let mut from_mask = 0;
+
+                    if rank_of(to) >= 2 && occupancy & (1 << (to - def::DIM_SIZE)) == 0 {
+                        from_mask |= 1 << (to - def::DIM_SIZE);
+
+                        if rank_of(to) == 3 && occupancy & (1 << (to - def::DIM_SIZE * 2)) == 0 {
+                            from_mask |= 1 << (to - def::DIM_SIZE * 2);
+                        }
+                    }
+
+                    from_mask
+                } else {
+                    get_attack_mask(piece, to, occupancy) & !occupancy
+                };
+
+                while from_mask != 0 {
+                    let from = from_mask.trailing_zeros() as usize;
+                    from_mask &= from_mask - 1;
+
+                    prev_squares.copy_from_slice(squares);
+                    prev_squares[piece_index] = from;
+
+                    let prev_idx = get_index(self.material, STRONG, prev_squares);
+
+                    if self.value_list[prev_idx] == VALUE_UNKNOWN && self.is_valid(STRONG, prev_squares) {
+                        self.value_list[prev_idx] = VALUE_WIN;
+                        idx_stack.push(prev_idx);
+                    }
+                }
+            }
+
+            return
+        }
+
+        let mut from_mask = bitmask::get_bitmask().k_attack_masks[squares[1]] & !occupancy & !bitmask::get_bitmask().k_attack_masks[squares[0]];
+
+        while from_mask != 0 {
+            let from = from_mask.trailing_zeros() as usize;
+            from_mask &= from_mask - 1;
+
+            prev_squares.copy_from_slice(squares);
+            prev_squares[1] = from;
+
+            let prev_idx = get_index(self.material, WEAK, prev_squares);
+
+            if self.value_list[prev_idx] == VALUE_UNKNOWN && self.is_win(WEAK, prev_squares) {
+                self.value_list[prev_idx] = VALUE_WIN;
+                idx_stack.push(prev_idx);
+            }
+        }
+    }
+
+    fn generate(mut self) -> Bitbase {
+        let size = self.material.get_size();
+        let square_count = 2 + self.piece_list.len();
+
+        for idx in 0..size {
+            let (stm, squares) = decode_index(self.material, idx);
+            let squares = &squares[..square_count];
+
+            if get_index(self.material, stm, squares) != idx || !self.is_valid(stm, squares) {
+                self.value_list[idx] = VALUE_INVALID;
+            }
+        }
+
+        let mut idx_stack = Vec::new();
+
+        for idx in 0..size {
+            let (stm, squares) = decode_index(self.material, idx);
+
+            if self.value_list[idx] == VALUE_UNKNOWN && self.is_win(stm, &squares[..square_count]) {
+                self.value_list[idx] = VALUE_WIN;
+                idx_stack.push(idx);
+            }
+        }
+
+        while let Some(idx) = idx_stack.pop() {
+            let (stm, squares) = decode_index(self.material, idx);
+            self.push_prev_wins(stm, &squares[..square_count], &mut idx_stack);
+        }
+
+        let mut bit_list = vec![0u64; size.div_ceil(64)];
+
+        for (idx, value) in self.value_list.iter().enumerate() {
+            if *value == VALUE_WIN {
+                bit_list[idx >> 6] |= 1 << (idx & 63);
+            }
+        }
+
+        Bitbase {
+            material: self.material,
+            bit_list,
+        }
+    }
+}
+
+fn get_occupancy(squares: &[usize]) -> u64 {
+    squares.iter().fold(0, |occupancy, index| occupancy | (1 << index))
+}
+
+fn generate_bitbase(material: Material, promo_bitbase_list: &[Bitbase]) -> Bitbase {
+    Generator {
+        material,
+        piece_list: material.get_piece_list(),
+        value_list: vec![VALUE_UNKNOWN; material.get_size()],
+        promo_bitbase_list,
+    }.generate()
+}
+
+/// The bitbases of all materials.
+pub struct Bitbases {
+    bitbase_list: Vec<Bitbase>,
+}
+
+impl Bitbases {
+    /// Generates the bitbases by retrograde analysis.
+    pub fn generate() -> Self {
+        let mut bitbase_list = Vec::new();
+
+        for material in MATERIAL_LIST {
+            let bitbase = generate_bitbase(material, if material == Material::Kpk { &bitbase_list[..2] } else { &[] });
+            bitbase_list.push(bitbase);
+        }
+
+        Bitbases {
+            bitbase_list,
+        }
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Bitbases::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let header_size = BITBASE_MAGIC.len() + 1;
+        let word_count_list: Vec<usize> = MATERIAL_LIST.iter().map(|material| material.get_size().div_ceil(64)).collect();
+
+        if bytes.len() != header_size + word_count_list.iter().sum::<usize>() * 8 || bytes[..4] != BITBASE_MAGIC || bytes[4] != BITBASE_VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a bitbase file"))
+        }
+
+        let mut word_iter = bytes[header_size..].chunks_exact(8).map(|word_bytes| u64::from_le_bytes(word_bytes.try_into().unwrap()));
+
+        Ok(Bitbases {
+            bitbase_list: MATERIAL_LIST.iter().zip(word_count_list).map(|(material, word_count)| Bitbase {
+                material: *material,
+                bit_list: word_iter.by_ref().take(word_count).collect(),
+            }).collect(),
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = BITBASE_MAGIC.to_vec();
+        bytes.push(BITBASE_VERSION);
+
+        for bitbase in &self.bitbase_list {
+            for word in &bitbase.bit_list {
+                bytes.extend_from_slice(&word.to_le_bytes());
+            }
+        }
+
+        bytes
+    }
+
+    /// Returns the result of KPK, KRK, KQK and KBNK positions without castling rights.
+    pub fn probe(&self, state: &State) -> Option<BitbaseResult> {
+        let bitboard = &state.bitboard;
+
+        if (bitboard.w_all | bitboard.b_all).count_ones() > 4 || state.cas_rights != 0 {
+            return None
+        }
+
+        let (strong_player, piece_count_list) = if bitboard.b_all.count_ones() == 1 {
+            (def::PLAYER_W, [state.wp_count, state.wn_count, state.wb_count, state.wr_count, state.wq_count])
+        } else if bitboard.w_all.count_ones() == 1 {
+            (def::PLAYER_B, [state.bp_count, state.bn_count, state.bb_count, state.br_count, state.bq_count])
+        } else {
+            return None
+        };
+
+        let material = match piece_count_list {
+            [1, 0, 0, 0, 0] => Material::Kpk,
+            [0, 1, 1, 0, 0] => Material::Kbnk,
+            [0, 0, 0, 1, 0] => Material::Krk,
+            [0, 0, 0, 0, 1] => Material::Kqk,
+            _ => return None,
+        };
+
+        let bitbase = self.bitbase_list.iter().find(|bitbase| bitbase.material == material)?;
+
+        // the stronger side plays White, with the ranks mirrored for Black
+        let (mut squares, flip_squares) = if strong_player == def::PLAYER_W {
+            ([state.wk_index, state.bk_index, 0, 0], 0)
+        } else {
+            ([state.bk_index, state.wk_index, 0, 0], 56)
+        };
+
+        for (piece_index, piece) in material.get_piece_list().iter().enumerate() {
+            let piece = if strong_player == def::PLAYER_W { *piece } else { *piece ^ def::PLAYER_SWITCH };
+            squares[2 + piece_index] = state.squares.iter().position(|square_piece| *square_piece == piece)?;
+        }
+
+        let squares = &mut squares[..2 + material.get_piece_list().len()];
+
+        for index in squares.iter_mut() {
+            *index ^= flip_squares;
+        }
+
+        let stm = if state.player == strong_player { STRONG } else { WEAK };
+
+        match (bitbase.is_win(stm, squares), stm) {
+            (false, _) => Some(BitbaseResult::Draw),
+            (true, STRONG) => Some(BitbaseResult::Win),
+            (true, _) => Some(BitbaseResult::Loss),
+        }
+    }
+}
+
+/// Sets the bitbases consulted by the evaluation; only the first call has an effect.
+pub fn set_bitbases(bitbases: Bitbases) {
+    BITBASES.set(bitbases).ok();
+}
+
+/// Returns whether the global bitbases have been set.
+pub fn has_bitbases() -> bool {
+    BITBASES.get().is_some()
+}
+
+/// Returns the result of the position if it is in the bitbases and they have been set.
+pub fn probe(state: &State) -> Option<BitbaseResult> {
+    BITBASES.get()?.probe(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        mov_table,
+        util,
+        zob_keys,
+    };
+
+    fn gen_bitbases(material_list: &[Material]) -> Bitbases {
+        let mut bitbase_list = Vec::new();
+
+        for material in material_list {
+            let bitbase = generate_bitbase(*material, if *material == Material::Kpk { &bitbase_list[..2] } else { &[] });
+            bitbase_list.push(bitbase);
+        }
+
+        Bitbases {
+            bitbase_list,
+        }
+    }
+
+    fn probe_fen(bitbases: &Bitbases, fen: &str) -> Option<BitbaseResult> {
+        bitbases.probe(&State::new(fen))
+    }
+
+    #[test]
+    fn test_index() {
+        for material in MATERIAL_LIST {
+            for idx in [0, 1, 777, material.get_size() / 2 + 4321, material.get_size() - 1] {
+                let (stm, squares) = decode_index(material, idx);
+                let squares = &squares[..2 + material.get_piece_list().len()];
+                let canonical_idx = get_index(material, stm, squares);
+                let (canonical_stm, canonical_squares) = decode_index(material, canonical_idx);
+
+                assert_eq!(stm, canonical_stm);
+                assert_eq!(canonical_idx, get_index(material, stm, &canonical_squares[..squares.len()]));
+            }
+        }
+
+        // the mirrored positions share an index
+        let squares = [util::map_sqr_notation_to_index("g7"), util::map_sqr_notation_to_index("b2"), util::map_sqr_notation_to_index("c6")];
+        let mirrored_squares = squares.map(|index| index ^ 63);
+        let flipped_squares = squares.map(flip_diagonal);
+
+        assert_eq!(get_index(Material::Krk, STRONG, &squares), get_index(Material::Krk, STRONG, &mirrored_squares));
+        assert_eq!(get_index(Material::Krk, STRONG, &squares), get_index(Material::Krk, STRONG, &flipped_squares));
+        assert_ne!(get_index(Material::Krk, STRONG, &squares), get_index(Material::Krk, WEAK, &squares));
+    }
+
+    #[test]
+    fn test_kqk_krk_kpk() {
+        zob_keys::init();
+        bitmask::init();
+
+        let bitbases = gen_bitbases(&[Material::Kqk, Material::Krk, Material::Kpk]);
+
+        assert_eq!(Some(BitbaseResult::Win), probe_fen(&bitbases, "8/8/8/4k3/8/8/8/K2Q4 w - - 0 1"));
+        assert_eq!(Some(BitbaseResult::Loss), probe_fen(&bitbases, "8/8/8/4k3/8/8/8/K2Q4 b - - 0 1"));
+        assert_eq!(Some(BitbaseResult::Draw), probe_fen(&bitbases, "k7/2Q5/1K6/8/8/8/8/8 b - - 0 1"));
+        assert_eq!(Some(BitbaseResult::Draw), probe_fen(&bitbases, "8/8/8/3k4/3Q4/8/8/K7 b - - 0 1"));
+        assert_eq!(Some(BitbaseResult::Win), probe_fen(&bitbases, "k2q4/8/8/8/4K3/8/8/8 b - - 0 1"));
+
+        assert_eq!(Some(BitbaseResult::Win), probe_fen(&bitbases, "8/8/8/4k3/8/8/8/R3K3 w - - 0 1"));
+        assert_eq!(Some(BitbaseResult::Draw), probe_fen(&bitbases, "8/8/8/8/8/8/1k6/R1K5 b - - 0 1"));
+        assert_eq!(Some(BitbaseResult::Draw), probe_fen(&bitbases, "7k/6R1/6K1/8/8/8/8/8 b - - 0 1"));
+
+        // the opposition decides
+        assert_eq!(Some(BitbaseResult::Draw), probe_fen(&bitbases, "4k3/8/4P3/4K3/8/8/8/8 w - - 0 1"));
+        assert_eq!(Some(BitbaseResult::Win), probe_fen(&bitbases, "4k3/8/4K3/4P3/8/8/8/8 w - - 0 1"));
+        assert_eq!(Some(BitbaseResult::Loss), probe_fen(&bitbases, "4k3/8/4K3/4P3/8/8/8/8 b - - 0 1"));
+        assert_eq!(Some(BitbaseResult::Draw), probe_fen(&bitbases, "k7/8/8/8/8/8/P7/K7 w - - 0 1"));
+        assert_eq!(Some(BitbaseResult::Win), probe_fen(&bitbases, "8/8/8/8/8/8/1P4k1/K7 w - - 0 1"));
+
+        // the mirrored positions with Black as the stronger side
+        assert_eq!(Some(BitbaseResult::Draw), probe_fen(&bitbases, "8/8/8/8/4k3/4p3/8/4K3 b - - 0 1"));
+        assert_eq!(Some(BitbaseResult::Draw), probe_fen(&bitbases, "8/8/8/8/4k3/4p3/8/4K3 w - - 0 1"));
+        assert_eq!(Some(BitbaseResult::Loss), probe_fen(&bitbases, "8/8/8/8/4p3/4k3/8/4K3 w - - 0 1"));
+
+        assert_eq!(None, probe_fen(&bitbases, "8/8/8/3k4/8/8/8/KQ1Q4 w - - 0 1"));
+        assert_eq!(None, probe_fen(&bitbases, "8/8/8/3k4/8/8/8/KN1N4 w - - 0 1"));
+        assert_eq!(None, probe_fen(&bitbases, "8/8/8/3k4/8/8/8/R3K3 w Q - 0 1"));
+
+        // the results agree with searching one move with the legal moves of the state
+        for fen in [
+            "8/8/3k4/8/2P5/8/8/3K4 w - - 0 1",
+            "8/8/3k4/8/2P5/8/8/3K4 b - - 0 1",
+            "8/5k2/8/5PK1/8/8/8/8 b - - 0 1",
+            "8/8/8/2k5/8/8/1R6/5K2 b - - 0 1",
+            "8/1k6/8/8/8/8/Q7/5K2 w - - 0 1",
+        ] {
+            let mut state = State::new(fen);
+            let result = bitbases.probe(&state).unwrap();
+            let mut next_result_list = Vec::new();
+
+            for mov in mov_table::gen_legal_mov_list(&mut state) {
+                let (from, to, tp, promo) = util::decode_u32_mov(mov);
+                state.do_mov(from, to, tp, promo);
+
+                let next_result = if (state.bitboard.w_all | state.bitboard.b_all).count_ones() == 2 {
+                    BitbaseResult::Draw
+                } else {
+                    bitbases.probe(&state).unwrap_or(BitbaseResult::Draw)
+                };
+
+                next_result_list.push(next_result);
+                state.undo_mov(from, to, tp);
+            }
+
+            let expected_result = if next_result_list.contains(&BitbaseResult::Loss) {
+                BitbaseResult::Win
+            } else if next_result_list.contains(&BitbaseResult::Draw) {
+                BitbaseResult::Draw
+            } else {
+                BitbaseResult::Loss
+            };
+
+            assert_eq!(expected_result, result, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_kbnk_and_cache() {
+        zob_keys::init();
+        bitmask::init();
+
+        let bitbases = Bitbases::generate();
+
+        assert_eq!(Some(BitbaseResult::Win), probe_fen(&bitbases, "7k/8/8/8/8/8/8/KBN5 w - - 0 1"));
+        assert_eq!(Some(BitbaseResult::Loss), probe_fen(&bitbases, "k7/8/1K6/8/8/8/8/1BN5 b - - 0 1"));
+        assert_eq!(Some(BitbaseResult::Draw), probe_fen(&bitbases, "8/8/8/8/8/8/6k1/KB5N b - - 0 1"));
+
+        let cache_path = std::env::temp_dir().join(format!("foxsee_bitbases_{}.bin", std::process::id()));
+        bitbases.save(&cache_path).unwrap();
+
+        let loaded_bitbases = Bitbases::load(&cache_path).unwrap();
+        assert_eq!(bitbases.to_bytes(), loaded_bitbases.to_bytes());
+        assert_eq!(Some(BitbaseResult::Draw), probe_fen(&loaded_bitbases, "4k3/8/4P3/4K3/8/8/8/8 w - - 0 1"));
+
+        fs::remove_file(&cache_path).ok();
+
+        assert!(Bitbases::from_bytes(&bitbases.to_bytes()[1..]).is_err());
+    }
+}
@@ -7,6 +7,8 @@ use crate::{
     util::{self, get_lowest_index, get_highest_index},
 };
 
+use std::sync::OnceLock;
+
 const SLIDE_ATTACK_PERM_COUNT: usize = 256;
 
 pub struct BitMask {
@@ -706,69 +708,25 @@ impl BitMask {
     }
 }
 
-static mut BITMASK: BitMask = BitMask {
-    index_masks: [0; def::BOARD_SIZE],
-    rank_masks: [0; def::BOARD_SIZE],
-    file_masks: [0; def::BOARD_SIZE],
-    diag_up_masks: [0; def::BOARD_SIZE],
-    diag_down_masks: [0; def::BOARD_SIZE],
-
-    wp_forward_masks: [0; def::BOARD_SIZE],
-    bp_forward_masks: [0; def::BOARD_SIZE],
-    wp_behind_masks: [0; def::BOARD_SIZE],
-    bp_behind_masks: [0; def::BOARD_SIZE],
-
-    wp_attack_masks: [0; def::BOARD_SIZE],
-    bp_attack_masks: [0; def::BOARD_SIZE],
-
-    wp_mov_masks: [0; def::BOARD_SIZE],
-    bp_mov_masks: [0; def::BOARD_SIZE],
-
-    wp_init_mov_masks: [0; def::BOARD_SIZE],
-    bp_init_mov_masks: [0; def::BOARD_SIZE],
-
-    wp_front_control_sqr_masks: [0; def::BOARD_SIZE],
-    bp_front_control_sqr_masks: [0; def::BOARD_SIZE],
-    wp_connected_sqr_masks: [0; def::BOARD_SIZE],
-    bp_connected_sqr_masks: [0; def::BOARD_SIZE],
-
-    n_attack_masks: [0; def::BOARD_SIZE],
-    k_attack_masks: [0; def::BOARD_SIZE],
-
-    left_attack_masks: [0; def::BOARD_SIZE],
-    right_attack_masks: [0; def::BOARD_SIZE],
-    up_attack_masks: [0; def::BOARD_SIZE],
-    down_attack_masks: [0; def::BOARD_SIZE],
-
-    up_left_attack_masks: [0; def::BOARD_SIZE],
-    up_right_attack_masks: [0; def::BOARD_SIZE],
-    down_left_attack_masks: [0; def::BOARD_SIZE],
-    down_right_attack_masks: [0; def::BOARD_SIZE],
-
-    horizontal_attack_masks: [[0; SLIDE_ATTACK_PERM_COUNT]; def::BOARD_SIZE],
-    vertical_attack_masks: [[0; SLIDE_ATTACK_PERM_COUNT]; def::BOARD_SIZE],
-    diag_up_attack_masks: [[0; SLIDE_ATTACK_PERM_COUNT]; def::BOARD_SIZE],
-    diag_down_attack_masks: [[0; SLIDE_ATTACK_PERM_COUNT]; def::BOARD_SIZE],
-
-    wk_attack_zone_masks: [0; def::BOARD_SIZE],
-    bk_attack_zone_masks: [0; def::BOARD_SIZE],
-
-    b_cover_masks: [0; def::BOARD_SIZE],
-};
+static BITMASK: OnceLock<BitMask> = OnceLock::new();
 
 pub fn init() {
-    unsafe {
-        BITMASK = BitMask::new();
-    }
+    BITMASK.get_or_init(BitMask::new);
 }
 
 #[inline]
 pub fn get_bitmask() -> &'static BitMask {
-    unsafe {
-        &BITMASK
+    match BITMASK.get() {
+        Some(bitmask) => bitmask,
+        None => init_bitmask(),
     }
 }
 
+#[cold]
+fn init_bitmask() -> &'static BitMask {
+    BITMASK.get_or_init(BitMask::new)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
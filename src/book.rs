@@ -0,0 +1,488 @@
+/*
+ * Copyright (C) 2020-2022 Zixiao Han
+ */
+
+//! Polyglot opening book support.
+
+use crate::{
+    def,
+    mov_table,
+    pgn::{self, PgnGame},
+    polyglot_keys::{self, RANDOM64},
+    prng::XorshiftPrng,
+    state::State,
+    util,
+};
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const ENTRY_SIZE: usize = 16;
+
+pub const DEFAULT_MIN_GAME_COUNT: u32 = 3;
+pub const DEFAULT_MAX_PLY: usize = 40;
+
+/// A single 16-byte record of a Polyglot book.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BookEntry {
+    pub key: u64,
+    pub mov: u16,
+    pub weight: u16,
+    pub learn: u32,
+}
+
+impl BookEntry {
+    fn from_bytes(bytes: &[u8]) -> Self {
+        BookEntry {
+            key: u64::from_be_bytes(bytes[0..8].try_into().unwrap()),
+            mov: u16::from_be_bytes(bytes[8..10].try_into().unwrap()),
+            weight: u16::from_be_bytes(bytes[10..12].try_into().unwrap()),
+            learn: u32::from_be_bytes(bytes[12..16].try_into().unwrap()),
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; ENTRY_SIZE] {
+        let mut bytes = [0; ENTRY_SIZE];
+
+        bytes[0..8].copy_from_slice(&self.key.to_be_bytes());
+        bytes[8..10].copy_from_slice(&self.mov.to_be_bytes());
+        bytes[10..12].copy_from_slice(&self.weight.to_be_bytes());
+        bytes[12..16].copy_from_slice(&self.learn.to_be_bytes());
+
+        bytes
+    }
+}
+
+/// An opening book in the Polyglot `.bin` format.
+pub struct Book {
+    entry_list: Vec<BookEntry>,
+    prng: XorshiftPrng,
+}
+
+impl Book {
+    /// Reads a Polyglot book from disk.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+
+        if bytes.len() % ENTRY_SIZE != 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("book size {} is not a multiple of {}", bytes.len(), ENTRY_SIZE)))
+        }
+
+        Ok(Book::from_entries(bytes.chunks_exact(ENTRY_SIZE).map(BookEntry::from_bytes).collect()))
+    }
+
+    pub fn from_entries(mut entry_list: Vec<BookEntry>) -> Self {
+        entry_list.sort_by_key(|entry| entry.key);
+
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_nanos() as u64).unwrap_or(0);
+
+        Book {
+            entry_list,
+            prng: XorshiftPrng::with_seed(seed),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entry_list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entry_list.is_empty()
+    }
+
+    /// Returns the entries of the given Polyglot key.
+    pub fn get_entries(&self, key: u64) -> &[BookEntry] {
+        let start_index = self.entry_list.partition_point(|entry| entry.key < key);
+        let end_index = self.entry_list.partition_point(|entry| entry.key <= key);
+
+        &self.entry_list[start_index..end_index]
+    }
+
+    /// Returns the legal book moves of the position with their weights.
+    pub fn get_weighted_mov_list(&self, state: &mut State) -> Vec<(u32, u16)> {
+        let entry_list = self.get_entries(get_polyglot_key(state));
+
+        if entry_list.is_empty() {
+            return Vec::new()
+        }
+
+        let legal_mov_list = mov_table::gen_legal_mov_list(state);
+
+        entry_list.iter().filter_map(|entry| {
+            legal_mov_list.iter().find(|mov| encode_polyglot_mov(state, **mov) == entry.mov).map(|mov| (*mov, entry.weight))
+        }).collect()
+    }
+
+    /// Picks a book move at random, in proportion to the entry weights.
+    pub fn get_mov(&mut self, state: &mut State) -> Option<u32> {
+        let weighted_mov_list = self.get_weighted_mov_list(state);
+        let total_weight = weighted_mov_list.iter().map(|(_mov, weight)| *weight as u64).sum::<u64>();
+
+        if total_weight == 0 {
+            return None
+        }
+
+        // take the high bits, the low bits of the generator are poorly distributed for small ranges
+        let mut pick = ((self.prng.gen_rand() as u128 * total_weight as u128) >> 64) as u64;
+
+        for (mov, weight) in weighted_mov_list {
+            if pick < weight as u64 {
+                return Some(mov)
+            }
+
+            pick -= weight as u64;
+        }
+
+        None
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct MovStats {
+    win_count: u32,
+    draw_count: u32,
+    loss_count: u32,
+}
+
+impl MovStats {
+    fn get_game_count(&self) -> u32 {
+        self.win_count + self.draw_count + self.loss_count
+    }
+
+    // a win counts twice as much as a draw, as in Polyglot's own book maker
+    fn get_weight(&self) -> u64 {
+        2 * self.win_count as u64 + self.draw_count as u64
+    }
+}
+
+/// Builds a Polyglot book from the results of played games.
+pub struct BookBuilder {
+    min_game_count: u32,
+    max_ply: usize,
+    min_rating: u32,
+    mov_stats_map: HashMap<(u64, u16), MovStats>,
+    game_count: usize,
+}
+
+impl BookBuilder {
+    /// Moves played in fewer than `min_game_count` games are left out, only the first `max_ply`
+    /// moves of a game are read, and games with a player rated below `min_rating` are skipped.
+    pub fn new(min_game_count: u32, max_ply: usize, min_rating: u32) -> Self {
+        BookBuilder {
+            min_game_count,
+            max_ply,
+            min_rating,
+            mov_stats_map: HashMap::new(),
+            game_count: 0,
+        }
+    }
+
+    /// Returns the number of games added to the book.
+    pub fn get_game_count(&self) -> usize {
+        self.game_count
+    }
+
+    /// Replays the main line of the game and records the result for each move played.
+    /// Returns false if the game is filtered out.
+    pub fn add_game(&mut self, game: &PgnGame) -> bool {
+        let w_score = match game.result.as_str() {
+            "1-0" => 2,
+            "1/2-1/2" => 1,
+            "0-1" => 0,
+            _ => return false,
+        };
+
+        if self.min_rating > 0 {
+            let is_rated = |tag_name| game.get_tag(tag_name).and_then(|rating| rating.parse::<u32>().ok()).is_some_and(|rating| rating >= self.min_rating);
+
+            if !is_rated("WhiteElo") || !is_rated("BlackElo") {
+                return false
+            }
+        }
+
+        let mut state = match State::from_fen(game.get_start_fen()) {
+            Ok(state) => state,
+            Err(_) => return false,
+        };
+
+        for pgn_mov in game.mov_list.iter().take(self.max_ply) {
+            let mov = match pgn::parse_san(&mut state, &pgn_mov.san) {
+                Some(mov) => mov,
+                None => break,
+            };
+
+            let score = if state.player == def::PLAYER_W {
+                w_score
+            } else {
+                2 - w_score
+            };
+
+            let mov_stats = self.mov_stats_map.entry((get_polyglot_key(&state), encode_polyglot_mov(&state, mov))).or_default();
+
+            match score {
+                2 => mov_stats.win_count += 1,
+                1 => mov_stats.draw_count += 1,
+                _ => mov_stats.loss_count += 1,
+            }
+
+            let (from, to, tp, promo) = util::decode_u32_mov(mov);
+            state.do_mov(from, to, tp, promo);
+        }
+
+        self.game_count += 1;
+
+        true
+    }
+
+    /// Returns the book entries sorted by key, and by weight within a key.
+    pub fn build(&self) -> Vec<BookEntry> {
+        let mut weighted_entry_list = self.mov_stats_map.iter()
+            .filter(|(_key_mov, mov_stats)| mov_stats.get_game_count() >= self.min_game_count && mov_stats.get_weight() > 0)
+            .map(|((key, mov), mov_stats)| (*key, *mov, mov_stats.get_weight()))
+            .collect::<Vec<(u64, u16, u64)>>();
+
+        weighted_entry_list.sort_by(|(key_a, mov_a, weight_a), (key_b, mov_b, weight_b)| {
+            key_a.cmp(key_b).then(weight_b.cmp(weight_a)).then(mov_a.cmp(mov_b))
+        });
+
+        let mut entry_list = Vec::with_capacity(weighted_entry_list.len());
+
+        for key_entry_list in weighted_entry_list.chunk_by(|(key_a, _, _), (key_b, _, _)| key_a == key_b) {
+            // only the weights within a position matter, scale them down if they do not fit
+            let max_weight = key_entry_list[0].2.max(u16::MAX as u64);
+
+            for (key, mov, weight) in key_entry_list {
+                entry_list.push(BookEntry {
+                    key: *key,
+                    mov: *mov,
+                    weight: (weight * u16::MAX as u64 / max_weight).max(1) as u16,
+                    learn: 0,
+                });
+            }
+        }
+
+        entry_list
+    }
+
+    /// Writes the book to disk and returns the number of entries written.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> io::Result<usize> {
+        let entry_list = self.build();
+
+        fs::write(path, entry_list.iter().flat_map(BookEntry::to_bytes).collect::<Vec<u8>>())?;
+
+        Ok(entry_list.len())
+    }
+}
+
+/// Returns the Polyglot key of the position.
+pub fn get_polyglot_key(state: &State) -> u64 {
+    let mut key = 0;
+
+    for (index, piece) in state.squares.iter().enumerate() {
+        if *piece != 0 {
+            key ^= RANDOM64[polyglot_keys::PIECE_KEY_OFFSET + 64 * get_piece_kind(*piece) + index];
+        }
+    }
+
+    for cas_index in 0..4 {
+        if state.cas_rights & def::get_cas_right(cas_index) != 0 {
+            key ^= RANDOM64[polyglot_keys::CAS_KEY_OFFSET + cas_index];
+        }
+    }
+
+    // the en passant file only counts when a pawn of the side to move can take
+    if state.enp_square != 0 {
+        let (pawn_index, own_pawn) = if state.player == def::PLAYER_W {
+            (state.enp_square - def::DIM_SIZE, def::WP)
+        } else {
+            (state.enp_square + def::DIM_SIZE, def::BP)
+        };
+
+        let file = state.enp_square % def::DIM_SIZE;
+
+        if (file > 0 && state.squares[pawn_index - 1] == own_pawn) || (file < def::DIM_SIZE - 1 && state.squares[pawn_index + 1] == own_pawn) {
+            key ^= RANDOM64[polyglot_keys::ENP_KEY_OFFSET + file];
+        }
+    }
+
+    if state.player == def::PLAYER_W {
+        key ^= RANDOM64[polyglot_keys::TURN_KEY_OFFSET];
+    }
+
+    key
+}
+
+/// Encodes a move the Polyglot way; castling is written as king takes rook.
+pub fn encode_polyglot_mov(state: &State, mov: u32) -> u16 {
+    let (from, to, tp, promo) = util::decode_u32_mov(mov);
+
+    let to = if tp == def::MOV_CAS {
+        state.cas_rook_squares[def::get_cas_index(to)]
+    } else {
+        to
+    };
+
+    let promo_code = if promo == 0 {
+        0
+    } else if def::is_n(promo) {
+        1
+    } else if def::is_b(promo) {
+        2
+    } else if def::is_r(promo) {
+        3
+    } else {
+        4
+    };
+
+    (to | from << 6 | promo_code << 12) as u16
+}
+
+fn get_piece_kind(piece: u8) -> usize {
+    let kind = if def::is_p(piece) {
+        0
+    } else if def::is_n(piece) {
+        2
+    } else if def::is_b(piece) {
+        4
+    } else if def::is_r(piece) {
+        6
+    } else if def::is_q(piece) {
+        8
+    } else {
+        10
+    };
+
+    if def::on_same_side(def::PLAYER_W, piece) {
+        kind + 1
+    } else {
+        kind
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bitmask,
+        zob_keys,
+    };
+
+    #[test]
+    fn test_polyglot_key() {
+        zob_keys::init();
+        bitmask::init();
+
+        let expected_key_list = [
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 0x463b96181691fc9c),
+            ("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1", 0x823c9b50fd114196),
+            ("rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2", 0x0756b94461c50fb0),
+            ("rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR b KQkq - 0 2", 0x662fafb965db29d4),
+            ("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3", 0x22a48b5a8e47ff78),
+            ("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPPKPPP/RNBQ1BNR b kq - 0 3", 0x652a607ca3f242c1),
+            ("rnbq1bnr/ppp1pkpp/8/3pPp2/8/8/PPPPKPPP/RNBQ1BNR w - - 0 4", 0x00fdd303c946bdd9),
+            ("rnbqkbnr/p1pppppp/8/8/PpP4P/8/1P1PPPP1/RNBQKBNR b KQkq c3 0 3", 0x3c8123ea7b067637),
+            ("rnbqkbnr/p1pppppp/8/8/P6P/R1p5/1P1PPPP1/1NBQKBNR b Kkq - 0 4", 0x5c3f9b829b279560),
+        ];
+
+        for (fen, expected_key) in expected_key_list {
+            assert_eq!(expected_key, get_polyglot_key(&State::new(fen)), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_book_mov() {
+        zob_keys::init();
+        bitmask::init();
+
+        let mut state = State::new("r3k2r/pppq1ppp/2npbn2/2b1p3/2B1P3/2NPBN2/PPPQ1PPP/R3K2R w KQkq - 4 8");
+        let key = get_polyglot_key(&state);
+
+        let e1g1 = util::encode_u32_mov(4, 6, def::MOV_CAS, 0);
+        let e1c1 = util::encode_u32_mov(4, 2, def::MOV_CAS, 0);
+        assert_eq!(0x0107, encode_polyglot_mov(&state, e1g1));
+        assert_eq!(0x0100, encode_polyglot_mov(&state, e1c1));
+
+        let entry_list = [
+            BookEntry { key: key + 1, mov: 0x0100, weight: 1, learn: 0 },
+            BookEntry { key, mov: 0x0107, weight: 3, learn: 0 },
+            BookEntry { key, mov: 0x0100, weight: 0, learn: 0 },
+            BookEntry { key, mov: 0x0fff, weight: 9, learn: 0 },
+        ];
+
+        let path = std::env::temp_dir().join(format!("foxsee_test_book_{}.bin", std::process::id()));
+        fs::write(&path, entry_list.iter().flat_map(BookEntry::to_bytes).collect::<Vec<u8>>()).unwrap();
+        let mut book = Book::load(&path).unwrap();
+        fs::remove_file(&path).ok();
+
+        assert_eq!(4, book.len());
+        assert_eq!(3, book.get_entries(key).len());
+        assert_eq!(vec![(e1g1, 3), (e1c1, 0)], book.get_weighted_mov_list(&mut state));
+
+        for _ in 0..10 {
+            assert_eq!(Some(e1g1), book.get_mov(&mut state));
+        }
+
+        assert_eq!(None, book.get_mov(&mut State::new("8/8/8/8/8/8/8/K6k w - - 0 1")));
+    }
+
+    #[test]
+    fn test_book_builder() {
+        zob_keys::init();
+        bitmask::init();
+
+        let pgn_str = r#"
+[WhiteElo "2500"]
+[BlackElo "2400"]
+1. e4 e5 2. Nf3 1-0
+
+[WhiteElo "2500"]
+[BlackElo "2400"]
+1. e4 c5 1/2-1/2
+
+[WhiteElo "2500"]
+[BlackElo "2500"]
+1. d4 d5 0-1
+
+[WhiteElo "1500"]
+[BlackElo "2400"]
+1. e4 e5 1-0
+
+[WhiteElo "2500"]
+[BlackElo "2400"]
+1. c4 *
+"#;
+
+        let game_list = pgn::parse_pgn(pgn_str);
+        let mut book_builder = BookBuilder::new(1, 2, 2000);
+
+        assert_eq!(vec![true, true, true, false, false], game_list.iter().map(|game| book_builder.add_game(game)).collect::<Vec<bool>>());
+        assert_eq!(3, book_builder.get_game_count());
+
+        let mut state = State::new(crate::uci::FEN_START_POS);
+        let e4 = pgn::parse_san(&mut state, "e4").unwrap();
+        let entry_list = book_builder.build();
+
+        assert_eq!(3, entry_list.len());
+        assert!(entry_list.windows(2).all(|entry_pair| entry_pair[0].key <= entry_pair[1].key));
+        assert!(entry_list.contains(&BookEntry { key: get_polyglot_key(&state), mov: encode_polyglot_mov(&state, e4), weight: 3, learn: 0 }));
+
+        let path = std::env::temp_dir().join(format!("foxsee_test_book_builder_{}.bin", std::process::id()));
+        assert_eq!(3, book_builder.write(&path).unwrap());
+        let book = Book::load(&path).unwrap();
+        fs::remove_file(&path).ok();
+
+        assert_eq!(vec![(e4, 3)], book.get_weighted_mov_list(&mut state));
+
+        let mut book_builder = BookBuilder::new(2, 2, 0);
+        for game in &game_list {
+            book_builder.add_game(game);
+        }
+
+        assert_eq!(4, book_builder.get_game_count());
+        assert_eq!(1, book_builder.build().len());
+    }
+}
@@ -0,0 +1,441 @@
+/*
+ * Copyright (C) 2020-2022 Zixiao Han
+ */
+
+//! Training data from self-play games: quiet positions with the search score and the game result.
+//!
+//! The text format has one position per line, `<fen> | <score> | <result>`, with the score in
+//! centipawns and the result as 1.0, 0.5 or 0.0, both from White's point of view.
+//!
+//! The binary format packs every position into 32 bytes:
+//! - bytes 0-7: occupancy, one bit per square from a1, little-endian
+//! - bytes 8-23: a 4-bit code per occupied square in the same order, low nibble first;
+//!   0 to 5 for pawn, knight, bishop, rook, queen and king, 6 for a rook that can still castle,
+//!   plus 8 for Black
+//! - byte 24: en-passant square (0 for none), plus 128 when Black is to move
+//! - byte 25: half move clock
+//! - bytes 26-27: full move number, little-endian
+//! - bytes 28-29: score, little-endian
+//! - byte 30: result, 2 for a White win, 1 for a draw and 0 for a Black win
+//! - byte 31: unused
+
+use crate::{
+    def,
+    eval,
+    mov_table,
+    prng::XorshiftPrng,
+    search::SearchEngine,
+    selfplay::{self, EngineConfig, GameConfig, GameRecord, GameResult},
+    state::{self, FenError, State},
+    uci,
+    util,
+};
+
+use std::convert::TryInto;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+pub const RECORD_SIZE: usize = 32;
+
+pub const DEFAULT_RANDOM_PLY_COUNT: usize = 8;
+pub const DEFAULT_MAX_OPENING_SCORE: i32 = 1000;
+
+const MAX_PIECE_COUNT: u32 = 32;
+const MAX_OPENING_ATTEMPT_COUNT: usize = 100;
+
+const PIECE_CODE_BLACK: u8 = 0b1000;
+const PIECE_CODE_CAS_ROOK: u8 = 6;
+const PIECE_CODE_LIST: [(u8, u8); 6] = [(def::WP, def::BP), (def::WN, def::BN), (def::WB, def::BB), (def::WR, def::BR), (def::WQ, def::BQ), (def::WK, def::BK)];
+
+const RESULT_BLACK_WIN: u8 = 0;
+const RESULT_DRAW: u8 = 1;
+const RESULT_WHITE_WIN: u8 = 2;
+
+/// A position to train on, with the score and the result from White's point of view.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DataPosition {
+    pub fen: String,
+    pub score: i32,
+    pub result: GameResult,
+}
+
+impl DataPosition {
+    pub fn to_text(&self) -> String {
+        let result_str = match self.result {
+            GameResult::WhiteWin => "1.0",
+            GameResult::Draw => "0.5",
+            GameResult::BlackWin => "0.0",
+        };
+
+        format!("{} | {} | {}", self.fen, self.score, result_str)
+    }
+
+    /// Packs the position into the binary format; it must not have more than 32 pieces.
+    pub fn to_bytes(&self) -> [u8; RECORD_SIZE] {
+        let state = State::new(&self.fen);
+        let fen_segment_list: Vec<&str> = self.fen.split_whitespace().collect();
+
+        let mut bytes = [0; RECORD_SIZE];
+        let mut occupancy = 0u64;
+        let mut piece_count = 0;
+
+        for (index, &piece) in state.squares.iter().enumerate() {
+            if piece == 0 {
+                continue
+            }
+
+            let can_castle = (0..4).any(|cas_index| state.cas_rights & def::get_cas_right(cas_index) != 0 && state.cas_rook_squares[cas_index] == index);
+
+            let mut piece_code = if def::is_r(piece) && can_castle {
+                PIECE_CODE_CAS_ROOK
+            } else {
+                def::get_piece_type_index(piece) as u8
+            };
+
+            if def::on_same_side(def::PLAYER_B, piece) {
+                piece_code |= PIECE_CODE_BLACK;
+            }
+
+            occupancy |= 1 << index;
+            bytes[8 + piece_count / 2] |= piece_code << (4 * (piece_count & 1));
+            piece_count += 1;
+        }
+
+        bytes[0..8].copy_from_slice(&occupancy.to_le_bytes());
+        bytes[24] = state.enp_square as u8;
+
+        if state.player == def::PLAYER_B {
+            bytes[24] |= 0b10000000;
+        }
+
+        bytes[25] = fen_segment_list[4].parse::<u16>().unwrap_or(0).min(u8::MAX as u16) as u8;
+        bytes[26..28].copy_from_slice(&fen_segment_list[5].parse::<u16>().unwrap_or(1).to_le_bytes());
+        bytes[28..30].copy_from_slice(&(self.score.clamp(i16::MIN as i32, i16::MAX as i32) as i16).to_le_bytes());
+        bytes[30] = match self.result {
+            GameResult::WhiteWin => RESULT_WHITE_WIN,
+            GameResult::Draw => RESULT_DRAW,
+            GameResult::BlackWin => RESULT_BLACK_WIN,
+        };
+
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8; RECORD_SIZE]) -> Result<Self, FenError> {
+        let occupancy = u64::from_le_bytes(bytes[0..8].try_into().unwrap());
+
+        if occupancy.count_ones() > MAX_PIECE_COUNT {
+            return Err(FenError::InvalidPiecePlacement(format!("{} pieces", occupancy.count_ones())))
+        }
+
+        let mut squares = [0; def::BOARD_SIZE];
+        let mut cas_rook_index_list = Vec::new();
+
+        for (piece_count, index) in (0..def::BOARD_SIZE).filter(|index| occupancy & (1 << index) != 0).enumerate() {
+            let piece_code = bytes[8 + piece_count / 2] >> (4 * (piece_count & 1)) & 0b1111;
+            let piece_type_code = piece_code & !PIECE_CODE_BLACK;
+
+            let piece_type_index = match piece_type_code {
+                PIECE_CODE_CAS_ROOK => {
+                    cas_rook_index_list.push(index);
+                    def::get_piece_type_index(def::WR)
+                },
+                0..=5 => piece_type_code as usize,
+                _ => return Err(FenError::InvalidPiecePlacement(format!("unknown piece code {}", piece_code))),
+            };
+
+            let (w_piece, b_piece) = PIECE_CODE_LIST[piece_type_index];
+            squares[index] = if piece_code & PIECE_CODE_BLACK == 0 { w_piece } else { b_piece };
+        }
+
+        let mut cas_rights_str: String = cas_rook_index_list.iter().map(|&index| {
+            let file_char = (b'a' + (index % def::DIM_SIZE) as u8) as char;
+
+            if squares[index] == def::WR {
+                file_char.to_ascii_uppercase()
+            } else {
+                file_char
+            }
+        }).collect();
+
+        if cas_rights_str.is_empty() {
+            cas_rights_str.push('-');
+        }
+
+        let enp_square = (bytes[24] & 0b111111) as usize;
+        let enp_sqr_str = if enp_square == 0 {
+            "-".to_owned()
+        } else {
+            util::map_index_to_sqr_notation(enp_square)
+        };
+
+        let player_str = if bytes[24] & 0b10000000 == 0 { "w" } else { "b" };
+        let full_mov_count = u16::from_le_bytes([bytes[26], bytes[27]]);
+
+        let fen = format!("{} {} {} {} {} {}", state::get_fen_sqrs_string(&squares), player_str, cas_rights_str, enp_sqr_str, bytes[25], full_mov_count);
+        let state = State::from_fen(&fen)?;
+
+        let result = match bytes[30] {
+            RESULT_WHITE_WIN => GameResult::WhiteWin,
+            RESULT_BLACK_WIN => GameResult::BlackWin,
+            _ => GameResult::Draw,
+        };
+
+        Ok(DataPosition {
+            fen: state.to_fen(),
+            score: i16::from_le_bytes([bytes[28], bytes[29]]) as i32,
+            result,
+        })
+    }
+}
+
+/// Returns the quiet positions of a game: not in check, with a quiet move played and no mate score.
+pub fn extract_positions(game_record: &GameRecord) -> Vec<DataPosition> {
+    let mut state = State::new(&game_record.start_fen);
+
+    let mut position_list = Vec::new();
+
+    for (&mov, &score) in game_record.mov_list.iter().zip(&game_record.score_list) {
+        let (from, to, tp, promo) = util::decode_u32_mov(mov);
+
+        let is_quiet_mov = tp == def::MOV_CAS || (tp != def::MOV_PROMO && tp != def::MOV_ENP && state.squares[to] == 0);
+        let piece_count = (state.bitboard.w_all | state.bitboard.b_all).count_ones();
+
+        if is_quiet_mov && score.abs() < eval::TERM_VAL && piece_count <= MAX_PIECE_COUNT && !mov_table::is_in_check(&state, state.player) {
+            position_list.push(DataPosition {
+                fen: state.to_fen(),
+                score,
+                result: game_record.result,
+            });
+        }
+
+        state.do_mov(from, to, tp, promo);
+    }
+
+    position_list
+}
+
+/// Plays `random_ply_count` random legal moves from the start position.
+/// Returns `None` if the game ends on the way.
+pub fn gen_random_opening(start_state: &State, random_ply_count: usize, prng: &mut XorshiftPrng) -> Option<State> {
+    let mut state = start_state.clone();
+
+    for _ in 0..random_ply_count {
+        let legal_mov_list = mov_table::gen_legal_mov_list(&mut state);
+
+        if legal_mov_list.is_empty() {
+            return None
+        }
+
+        let (from, to, tp, promo) = util::decode_u32_mov(legal_mov_list[(prng.gen_rand() % legal_mov_list.len() as u64) as usize]);
+        state.do_mov(from, to, tp, promo);
+    }
+
+    if mov_table::gen_legal_mov_list(&mut state).is_empty() {
+        return None
+    }
+
+    Some(State::new(&state.to_fen()))
+}
+
+pub struct DatagenConfig {
+    pub engine_config: EngineConfig,
+    pub opening_fen_list: Vec<String>,
+    pub random_ply_count: usize,
+    pub max_opening_score: i32,
+    pub game_count: usize,
+    pub concurrency: usize,
+    pub game_config: GameConfig,
+    pub seed: u64,
+}
+
+impl DatagenConfig {
+    pub fn new(game_config: GameConfig) -> Self {
+        DatagenConfig {
+            engine_config: EngineConfig::new("datagen"),
+            opening_fen_list: vec![uci::FEN_START_POS.to_owned()],
+            random_ply_count: DEFAULT_RANDOM_PLY_COUNT,
+            max_opening_score: DEFAULT_MAX_OPENING_SCORE,
+            game_count: 1,
+            concurrency: 1,
+            game_config,
+            seed: 0,
+        }
+    }
+}
+
+/// Plays self-play games from random openings on `concurrency` threads.
+/// `on_game_finished` is called on the calling thread with the game index, the game and its quiet positions.
+pub fn run_datagen<F: FnMut(usize, &GameRecord, &[DataPosition])>(datagen_config: &DatagenConfig, mut on_game_finished: F) {
+    let next_game_index = &AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..datagen_config.concurrency.max(1) {
+            let sender = sender.clone();
+
+            scope.spawn(move || {
+                let mut white_engine = datagen_config.engine_config.build_engine();
+                let mut black_engine = datagen_config.engine_config.build_engine();
+
+                loop {
+                    let game_index = next_game_index.fetch_add(1, Ordering::Relaxed);
+
+                    if game_index >= datagen_config.game_count {
+                        break
+                    }
+
+                    white_engine.reset();
+                    black_engine.reset();
+
+                    let mut prng = XorshiftPrng::with_seed(datagen_config.seed.wrapping_add(game_index as u64));
+                    let start_state = gen_balanced_opening(datagen_config, game_index, &mut white_engine, &mut prng);
+
+                    white_engine.reset();
+
+                    let game_record = selfplay::play_game(&start_state, &mut white_engine, &mut black_engine, &datagen_config.game_config);
+                    let position_list = extract_positions(&game_record);
+
+                    if sender.send((game_index, game_record, position_list)).is_err() {
+                        break
+                    }
+                }
+            });
+        }
+
+        drop(sender);
+
+        for (game_index, game_record, position_list) in receiver {
+            on_game_finished(game_index, &game_record, &position_list);
+        }
+    });
+}
+
+fn gen_balanced_opening(datagen_config: &DatagenConfig, game_index: usize, search_engine: &mut SearchEngine, prng: &mut XorshiftPrng) -> State {
+    let opening_fen_list = &datagen_config.opening_fen_list;
+    let start_state = match opening_fen_list.get(game_index % opening_fen_list.len().max(1)) {
+        Some(opening_fen) => State::new(opening_fen),
+        None => State::new(uci::FEN_START_POS),
+    };
+
+    let mut opening_state = start_state.clone();
+
+    for _ in 0..MAX_OPENING_ATTEMPT_COUNT {
+        let random_state = match gen_random_opening(&start_state, datagen_config.random_ply_count, prng) {
+            Some(random_state) => random_state,
+            None => continue,
+        };
+
+        opening_state = random_state;
+
+        let mut check_state = opening_state.clone();
+        selfplay::search_to_limit(search_engine, &mut check_state, datagen_config.game_config.search_limit);
+
+        if search_engine.get_last_score().abs() <= datagen_config.max_opening_score {
+            break
+        }
+    }
+
+    opening_state
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bitmask,
+        selfplay::SearchLimit,
+        tuner,
+        zob_keys,
+    };
+
+    #[test]
+    fn test_position_bytes() {
+        zob_keys::init();
+        bitmask::init();
+
+        for fen in [
+            "r3k2r/pPpp1ppp/8/3Pp3/8/8/PPP2PPP/R3K2R w KQkq e6 0 12",
+            "1r2k1r1/8/8/8/8/8/8/RR2K3 b Qk - 7 40",
+            "8/8/4k3/8/2P5/8/5K2/8 b - c3 0 1",
+        ] {
+            let position = DataPosition {
+                fen: fen.to_owned(),
+                score: -135,
+                result: GameResult::BlackWin,
+            };
+
+            let bytes = position.to_bytes();
+            assert_eq!(position, DataPosition::from_bytes(&bytes).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_extract_positions() {
+        zob_keys::init();
+        bitmask::init();
+
+        let mut state = State::new(uci::FEN_START_POS);
+        let mut mov_list = Vec::new();
+
+        for mov_str in ["e2e4", "d7d5", "e4d5", "d8d5", "b1c3", "d5a5", "f1c4", "a5e5", "g1e2", "g8f6"] {
+            let mov = mov_table::gen_legal_mov_list(&mut state).into_iter().find(|mov| state.format_mov(*mov) == mov_str).unwrap();
+            let (from, to, tp, promo) = util::decode_u32_mov(mov);
+            state.do_mov(from, to, tp, promo);
+            mov_list.push(mov);
+        }
+
+        let game_record = GameRecord {
+            start_fen: uci::FEN_START_POS.to_owned(),
+            mov_list,
+            score_list: vec![30, 40, 50, 45, 60, 55, 20, 25, 10, -eval::MATE_VAL + 1],
+            result: GameResult::BlackWin,
+            termination: "Black mates".to_owned(),
+            adjudicated: false,
+        };
+
+        let position_list = extract_positions(&game_record);
+        let fen_list: Vec<&str> = position_list.iter().map(|position| position.fen.as_str()).collect();
+
+        // captures, the move played in check and the mate score are left out
+        assert_eq!(vec![
+            uci::FEN_START_POS,
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+            "rnb1kbnr/ppp1pppp/8/3q4/8/8/PPPP1PPP/RNBQKBNR w KQkq - 0 3",
+            "rnb1kbnr/ppp1pppp/8/3q4/8/2N5/PPPP1PPP/R1BQKBNR b KQkq - 1 3",
+            "rnb1kbnr/ppp1pppp/8/q7/8/2N5/PPPP1PPP/R1BQKBNR w KQkq - 2 4",
+            "rnb1kbnr/ppp1pppp/8/q7/2B5/2N5/PPPP1PPP/R1BQK1NR b KQkq - 3 4",
+        ], fen_list);
+
+        let text = position_list[2].to_text();
+        assert_eq!("rnb1kbnr/ppp1pppp/8/3q4/8/8/PPPP1PPP/RNBQKBNR w KQkq - 0 3 | 60 | 0.0", text);
+        assert_eq!(Some((position_list[2].fen.clone(), 0.)), tuner::parse_position(&text));
+    }
+
+    #[test]
+    fn test_run_datagen() {
+        zob_keys::init();
+        bitmask::init();
+
+        let mut datagen_config = DatagenConfig::new(GameConfig {
+            search_limit: SearchLimit::Nodes(500),
+            draw_adjudication: None,
+            resign_adjudication: None,
+        });
+
+        datagen_config.game_count = 2;
+        datagen_config.concurrency = 2;
+        datagen_config.engine_config.hash_size = def::MIN_HASH_SIZE_UNIT;
+
+        let mut game_index_list = Vec::new();
+
+        run_datagen(&datagen_config, |game_index, game_record, position_list| {
+            assert!(position_list.len() <= game_record.mov_list.len());
+            assert!(position_list.iter().all(|position| position.result == game_record.result));
+            game_index_list.push(game_index);
+        });
+
+        game_index_list.sort_unstable();
+        assert_eq!(vec![0, 1], game_index_list);
+    }
+}
@@ -12,6 +12,12 @@ pub const MIN_HASH_SIZE_MB: usize = 1;
 pub const MIN_HASH_SIZE_UNIT: usize = 32768;
 pub const MAX_HASH_SIZE_MB: usize = 512;
 
+pub const DEFAULT_MULTI_PV: usize = 1;
+pub const MAX_MULTI_PV: usize = 64;
+
+pub const DEFAULT_THREADS: usize = 1;
+pub const MAX_THREADS: usize = 256;
+
 pub const BOARD_SIZE: usize = 64;
 pub const DIM_SIZE: usize = 8;
 pub const PIECE_CODE_RANGE: usize = 131;
@@ -30,6 +36,10 @@ pub const CAS_SQUARE_WQ: usize = 2;
 pub const CAS_SQUARE_BK: usize = 62;
 pub const CAS_SQUARE_BQ: usize = 58;
 
+pub const CAS_KING_TO_SQUARES: [usize; 4] = [CAS_SQUARE_WK, CAS_SQUARE_WQ, CAS_SQUARE_BK, CAS_SQUARE_BQ];
+pub const CAS_ROOK_TO_SQUARES: [usize; 4] = [CAS_SQUARE_WK - 1, CAS_SQUARE_WQ + 1, CAS_SQUARE_BK - 1, CAS_SQUARE_BQ + 1];
+pub const DEFAULT_CAS_ROOK_SQUARES: [usize; 4] = [7, 0, 63, 56];
+
 pub const PLAYER_W: u8 = 0b10;
 pub const PLAYER_B: u8 = 0b01;
 pub const PLAYER_SWITCH: u8 = 0b11;
@@ -65,6 +75,33 @@ pub const fn on_same_side(player: u8, piece_code: u8) -> bool {
     player & piece_code == player
 }
 
+/// Returns 0 to 5 for pawn, knight, bishop, rook, queen and king of either side.
+#[inline]
+pub const fn get_piece_type_index(piece_code: u8) -> usize {
+    ((piece_code & !PLAYER_SWITCH).trailing_zeros() - 2) as usize
+}
+
+#[inline]
+pub const fn get_cas_right(cas_index: usize) -> u8 {
+    0b1000 >> cas_index
+}
+
+#[inline]
+pub const fn get_cas_index(king_to: usize) -> usize {
+    match king_to {
+        CAS_SQUARE_WK => 0,
+        CAS_SQUARE_WQ => 1,
+        CAS_SQUARE_BK => 2,
+        _ => 3,
+    }
+}
+
+/// Returns the square on the same file with the rank mirrored, e.g. a2 for a7.
+#[inline]
+pub const fn get_mirror_index(index: usize) -> usize {
+    index ^ 0b111000
+}
+
 #[inline]
 pub fn get_passer_rank(player: u8, index: usize) -> usize {
     if player == PLAYER_W {
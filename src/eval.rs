@@ -3,6 +3,7 @@
  */
 
 use crate::{
+    bitbase::{self, BitbaseResult},
     bitmask,
     def,
     mov_table,
@@ -10,11 +11,20 @@ use crate::{
     util,
 };
 
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::iter::Peekable;
+use std::slice;
+use std::vec;
+
 pub const MATE_VAL: i32 = 20000;
 pub const TERM_VAL: i32 = 10000;
 
 pub const EQUAL_EXCHANGE: i32 = -20;
 
+const BITBASE_WIN_VAL: i32 = 2000;
+
 const Q_VAL: i32 = 1400;
 const R_VAL: i32 = 700;
 const B_VAL: i32 = 500;
@@ -96,28 +106,6 @@ const R_MOB_SCORE: [i32; 15] = [-50, -10, 0, 0, 0, 5, 10, 15, 20, 25, 30, 30, 30
 const Q_MOB_SCORE: [i32; 28] = [-30, -20, -10, -5, 0, 5, 10, 15, 20, 25, 30, 35, 40, 45, 50, 50, 50, 50, 50, 50, 50, 50, 50, 50, 50, 50, 50, 50];
 const K_MOB_SCORE: [i32; 9] = [-50, -10, 0, 5, 5, 5, 10, 10, 10];
 
-const SQR_TABLE_BP: [i32; def::BOARD_SIZE] = [
-      0,  0,  0,  0,  0,  0,  0,  0,
-     15, 30, 30, 30, 30, 30, 30, 15,
-     10, 20, 20, 30, 30, 20, 20, 10,
-      0,  0,  0, 20, 20,  0,  0,  0,
-      0,  0,  0, 20, 20,  0,  0,  0,
-     10, 10,  0,  0,  0,  0, 10, 10,
-     10, 10,  5,  0,  0,  5, 10, 10,
-      0,  0,  0,  0,  0,  0,  0,  0,
-];
-
-const SQR_TABLE_BP_ENDGAME: [i32; def::BOARD_SIZE] = [
-      0,  0,  0,  0,  0,  0,  0,  0,
-     20, 30, 30, 30, 30, 30, 30, 20,
-     15, 20, 20, 20, 20, 20, 20, 15,
-     10, 15, 15, 15, 15, 15, 15, 10,
-      5, 10, 10, 10, 10, 10, 10,  5,
-      0,  0,  0,  0,  0,  0,  0,  0,
-      0,  0,  0,  0,  0,  0,  0,  0,
-      0,  0,  0,  0,  0,  0,  0,  0,
-];
-
 const SQR_TABLE_WP: [i32; def::BOARD_SIZE] = [
       0,  0,  0,  0,  0,  0,  0,  0,
      10, 10,  5,  0,  0,  5, 10, 10,
@@ -140,17 +128,6 @@ const SQR_TABLE_WP_ENDGAME: [i32; def::BOARD_SIZE] = [
       0,  0,  0,  0,  0,  0,  0,  0,
 ];
 
-const SQR_TABLE_BN: [i32; def::BOARD_SIZE] = [
-    -60,-30,-20,-20,-20,-20,-30,-60,
-    -30,-30, 20, 10, 10, 20,-30,-30,
-    -20, 10, 15, 20, 20, 15, 10,-20,
-    -20,  5,  0, 20, 20,  0,  5,-20,
-    -20,  0,  0, 20, 20,  0,  0,-20,
-    -20,  5, 10,  0,  0, 10,  5,-20,
-    -30,-30,  0,  0,  0,  0,-30,-30,
-    -60,-30,-20,-20,-20,-20,-30,-60,
-];
-
 const SQR_TABLE_WN: [i32; def::BOARD_SIZE] = [
     -60,-30,-20,-20,-20,-20,-30,-60,
     -30,-30,  0,  0,  0,  0,-30,-30,
@@ -162,17 +139,6 @@ const SQR_TABLE_WN: [i32; def::BOARD_SIZE] = [
     -60,-30,-20,-20,-20,-20,-30,-60,
 ];
 
-const SQR_TABLE_BB: [i32; def::BOARD_SIZE] = [
-    -50,-10,-10,-10,-10,-10,-10,-50,
-    -20,  0, 10,  0,  0, 10,  0,-20,
-    -10,  5,  5, 10, 10,  5,  5,-10,
-    -10,  5,  0, 10, 10,  0,  5,-10,
-    -10,  0,  0, 10, 10,  0,  0,-10,
-    -10,  5,  5,  5,  5,  5,  5,-10,
-    -10, 10,  0,  0,  0,  0, 10,-10,
-    -50,-10,-10,-10,-10,-10,-10,-50,
-];
-
 const SQR_TABLE_WB: [i32; def::BOARD_SIZE] = [
     -50,-10,-10,-10,-10,-10,-10,-50,
     -10, 10,  0,  0,  0,  0, 10,-10,
@@ -184,17 +150,6 @@ const SQR_TABLE_WB: [i32; def::BOARD_SIZE] = [
     -50,-10,-10,-10,-10,-10,-10,-50,
 ];
 
-const SQR_TABLE_BR: [i32; def::BOARD_SIZE] = [
-     10, 10, 20, 20, 20, 20, 10, 10,
-     10, 20, 30, 30, 30, 30, 20, 10,
-     -5, -5, -5, -5, -5, -5, -5, -5,
-     -5, -5, -5, -5, -5, -5, -5, -5,
-     -5, -5, -5, -5, -5, -5, -5, -5,
-     -5, -5, -5, -5, -5, -5, -5, -5,
-    -10, -5, -5, -5, -5, -5, -5,-10,
-     -5,  0,  0,  5,  5,  0,  0, -5,
-];
-
 const SQR_TABLE_WR: [i32; def::BOARD_SIZE] = [
      -5,  0,  0,  5,  5,  0,  0, -5,
     -10, -5, -5, -5, -5, -5, -5,-10,
@@ -206,17 +161,6 @@ const SQR_TABLE_WR: [i32; def::BOARD_SIZE] = [
      10, 10, 20, 20, 20, 20, 10, 10,
 ];
 
-const SQR_TABLE_BQ: [i32; def::BOARD_SIZE] = [
-    -30,-20,-10, -5, -5,-10,-20,-30,
-    -20,-10,  0,  0,  0,  0,-10,-20,
-    -10,  0,  0,  0,  0,  0,  0,-10,
-     -5,  0,  0,  0,  0,  0,  0, -5,
-     -5,  0,  0,  0,  0,  0,  0, -5,
-    -10,  0,  0,  0,  0,  0,  0,-10,
-    -20,-10,  0,  0,  0,  0,-10,-20,
-    -30,-20,-10, -5, -5,-10,-20,-30,
-];
-
 const SQR_TABLE_WQ: [i32; def::BOARD_SIZE] = [
     -30,-20,-10, -5, -5,-10,-20,-30,
     -20,-10,  0,  0,  0,  0,-10,-20,
@@ -228,17 +172,6 @@ const SQR_TABLE_WQ: [i32; def::BOARD_SIZE] = [
     -30,-20,-10, -5, -5,-10,-20,-30,
 ];
 
-const SQR_TABLE_BK: [i32; def::BOARD_SIZE] = [
-    -90,-90,-90,-90,-90,-90,-90,-90,
-    -90,-90,-90,-90,-90,-90,-90,-90,
-    -90,-90,-90,-90,-90,-90,-90,-90,
-    -90,-90,-90,-90,-90,-90,-90,-90,
-    -90,-90,-90,-90,-90,-90,-90,-90,
-    -60,-60,-60,-60,-60,-60,-60,-60,
-     10, 10,-10,-30,-30,-10, 10, 10,
-     10, 20,  0,-20,-20,  0, 20, 10,
-];
-
 const SQR_TABLE_WK: [i32; def::BOARD_SIZE] = [
      10, 20,  0,-20,-20,  0, 20, 10,
      10, 10,-10,-30,-30,-10, 10, 10,
@@ -261,6 +194,370 @@ const SQR_TABLE_K_ENDGAME: [i32; def::BOARD_SIZE] = [
     -50,-40,-30,-30,-30,-30,-40,-50,
 ];
 
+/// The evaluation the search uses. `Nnue` falls back to the classical eval when no network is loaded.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EvalType {
+    Classical,
+    Nnue,
+}
+
+/// The weights of the evaluation. Square tables are from White's point of view
+/// and mirrored for Black, except the shared endgame king table.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EvalParams {
+    pub q_val: i32,
+    pub r_val: i32,
+    pub b_val: i32,
+    pub n_val: i32,
+    pub p_val: i32,
+    pub eg_q_val: i32,
+    pub eg_r_val: i32,
+    pub eg_p_val: i32,
+    pub eg_pawn_essential_val: i32,
+    pub eg_no_piece_bonus: i32,
+    pub eg_different_colored_bishop_val: i32,
+    pub eg_different_colored_bishop_with_rook_val: i32,
+    pub eg_bishop_pair_bonus: i32,
+    pub eg_rn_knight_protected_bonus: i32,
+    pub pass_pawn_val: [i32; def::DIM_SIZE],
+    pub connected_pass_pawn_bonus: [i32; def::DIM_SIZE],
+    pub candidate_pass_pawn_val: [i32; def::DIM_SIZE],
+    pub king_in_passer_path_bonus: i32,
+    pub controlled_pass_pawn_val: i32,
+    pub doubled_pawn_pen: i32,
+    pub isolated_pawn_pen: i32,
+    pub behind_pawn_pen: i32,
+    pub king_exposure_pen: i32,
+    pub k_attack_score: [i32; 200],
+    pub king_lost_cas_rights_pen: i32,
+    pub pin_pen: i32,
+    pub semi_pin_pen: i32,
+    pub rook_open_bonus: i32,
+    pub weak_sqr_pen: i32,
+    pub tempo_val: i32,
+    pub p_mob_score: i32,
+    pub n_mob_score: [i32; 9],
+    pub b_mob_score: [i32; 14],
+    pub r_mob_score: [i32; 15],
+    pub q_mob_score: [i32; 28],
+    pub k_mob_score: [i32; 9],
+    pub sqr_table_wp: [i32; def::BOARD_SIZE],
+    pub sqr_table_wp_endgame: [i32; def::BOARD_SIZE],
+    pub sqr_table_wn: [i32; def::BOARD_SIZE],
+    pub sqr_table_wb: [i32; def::BOARD_SIZE],
+    pub sqr_table_wr: [i32; def::BOARD_SIZE],
+    pub sqr_table_wq: [i32; def::BOARD_SIZE],
+    pub sqr_table_wk: [i32; def::BOARD_SIZE],
+    pub sqr_table_k_endgame: [i32; def::BOARD_SIZE],
+}
+
+/// The hand-set weights the engine plays with by default.
+pub static DEFAULT_EVAL_PARAMS: EvalParams = EvalParams {
+    q_val: Q_VAL,
+    r_val: R_VAL,
+    b_val: B_VAL,
+    n_val: N_VAL,
+    p_val: P_VAL,
+    eg_q_val: EG_Q_VAL,
+    eg_r_val: EG_R_VAL,
+    eg_p_val: EG_P_VAL,
+    eg_pawn_essential_val: EG_PAWN_ESSENTIAL_VAL,
+    eg_no_piece_bonus: EG_NO_PIECE_BONUS,
+    eg_different_colored_bishop_val: EG_DIFFERENT_COLORED_BISHOP_VAL,
+    eg_different_colored_bishop_with_rook_val: EG_DIFFERENT_COLORED_BISHOP_WITH_ROOK_VAL,
+    eg_bishop_pair_bonus: EG_BISHOP_PAIR_BONUS,
+    eg_rn_knight_protected_bonus: EG_RN_KNIGHT_PROTECTED_BONUS,
+    pass_pawn_val: PASS_PAWN_VAL,
+    connected_pass_pawn_bonus: CONNECTED_PASS_PAWN_BONUS,
+    candidate_pass_pawn_val: CANDIDATE_PASS_PAWN_VAL,
+    king_in_passer_path_bonus: KING_IN_PASSER_PATH_BONUS,
+    controlled_pass_pawn_val: CONTROLLED_PASS_PAWN_VAL,
+    doubled_pawn_pen: DOUBLED_PAWN_PEN,
+    isolated_pawn_pen: ISOLATED_PAWN_PEN,
+    behind_pawn_pen: BEHIND_PAWN_PEN,
+    king_exposure_pen: KING_EXPOSURE_PEN,
+    k_attack_score: K_ATTACK_SCORE,
+    king_lost_cas_rights_pen: KING_LOST_CAS_RIGHTS_PEN,
+    pin_pen: PIN_PEN,
+    semi_pin_pen: SEMI_PIN_PEN,
+    rook_open_bonus: ROOK_OPEN_BONUS,
+    weak_sqr_pen: WEAK_SQR_PEN,
+    tempo_val: TEMPO_VAL,
+    p_mob_score: P_MOB_SCORE,
+    n_mob_score: N_MOB_SCORE,
+    b_mob_score: B_MOB_SCORE,
+    r_mob_score: R_MOB_SCORE,
+    q_mob_score: Q_MOB_SCORE,
+    k_mob_score: K_MOB_SCORE,
+    sqr_table_wp: SQR_TABLE_WP,
+    sqr_table_wp_endgame: SQR_TABLE_WP_ENDGAME,
+    sqr_table_wn: SQR_TABLE_WN,
+    sqr_table_wb: SQR_TABLE_WB,
+    sqr_table_wr: SQR_TABLE_WR,
+    sqr_table_wq: SQR_TABLE_WQ,
+    sqr_table_wk: SQR_TABLE_WK,
+    sqr_table_k_endgame: SQR_TABLE_K_ENDGAME,
+};
+
+impl Default for EvalParams {
+    fn default() -> Self {
+        DEFAULT_EVAL_PARAMS.clone()
+    }
+}
+
+impl EvalParams {
+    /// Returns every weight by the name of its constant, scalars as one-element slices.
+    pub fn get_param_list_mut(&mut self) -> Vec<(&'static str, &mut [i32])> {
+        vec![
+            ("Q_VAL", slice::from_mut(&mut self.q_val)),
+            ("R_VAL", slice::from_mut(&mut self.r_val)),
+            ("B_VAL", slice::from_mut(&mut self.b_val)),
+            ("N_VAL", slice::from_mut(&mut self.n_val)),
+            ("P_VAL", slice::from_mut(&mut self.p_val)),
+            ("EG_Q_VAL", slice::from_mut(&mut self.eg_q_val)),
+            ("EG_R_VAL", slice::from_mut(&mut self.eg_r_val)),
+            ("EG_P_VAL", slice::from_mut(&mut self.eg_p_val)),
+            ("EG_PAWN_ESSENTIAL_VAL", slice::from_mut(&mut self.eg_pawn_essential_val)),
+            ("EG_NO_PIECE_BONUS", slice::from_mut(&mut self.eg_no_piece_bonus)),
+            ("EG_DIFFERENT_COLORED_BISHOP_VAL", slice::from_mut(&mut self.eg_different_colored_bishop_val)),
+            ("EG_DIFFERENT_COLORED_BISHOP_WITH_ROOK_VAL", slice::from_mut(&mut self.eg_different_colored_bishop_with_rook_val)),
+            ("EG_BISHOP_PAIR_BONUS", slice::from_mut(&mut self.eg_bishop_pair_bonus)),
+            ("EG_RN_KNIGHT_PROTECTED_BONUS", slice::from_mut(&mut self.eg_rn_knight_protected_bonus)),
+            ("PASS_PAWN_VAL", &mut self.pass_pawn_val),
+            ("CONNECTED_PASS_PAWN_BONUS", &mut self.connected_pass_pawn_bonus),
+            ("CANDIDATE_PASS_PAWN_VAL", &mut self.candidate_pass_pawn_val),
+            ("KING_IN_PASSER_PATH_BONUS", slice::from_mut(&mut self.king_in_passer_path_bonus)),
+            ("CONTROLLED_PASS_PAWN_VAL", slice::from_mut(&mut self.controlled_pass_pawn_val)),
+            ("DOUBLED_PAWN_PEN", slice::from_mut(&mut self.doubled_pawn_pen)),
+            ("ISOLATED_PAWN_PEN", slice::from_mut(&mut self.isolated_pawn_pen)),
+            ("BEHIND_PAWN_PEN", slice::from_mut(&mut self.behind_pawn_pen)),
+            ("KING_EXPOSURE_PEN", slice::from_mut(&mut self.king_exposure_pen)),
+            ("K_ATTACK_SCORE", &mut self.k_attack_score),
+            ("KING_LOST_CAS_RIGHTS_PEN", slice::from_mut(&mut self.king_lost_cas_rights_pen)),
+            ("PIN_PEN", slice::from_mut(&mut self.pin_pen)),
+            ("SEMI_PIN_PEN", slice::from_mut(&mut self.semi_pin_pen)),
+            ("ROOK_OPEN_BONUS", slice::from_mut(&mut self.rook_open_bonus)),
+            ("WEAK_SQR_PEN", slice::from_mut(&mut self.weak_sqr_pen)),
+            ("TEMPO_VAL", slice::from_mut(&mut self.tempo_val)),
+            ("P_MOB_SCORE", slice::from_mut(&mut self.p_mob_score)),
+            ("N_MOB_SCORE", &mut self.n_mob_score),
+            ("B_MOB_SCORE", &mut self.b_mob_score),
+            ("R_MOB_SCORE", &mut self.r_mob_score),
+            ("Q_MOB_SCORE", &mut self.q_mob_score),
+            ("K_MOB_SCORE", &mut self.k_mob_score),
+            ("SQR_TABLE_WP", &mut self.sqr_table_wp),
+            ("SQR_TABLE_WP_ENDGAME", &mut self.sqr_table_wp_endgame),
+            ("SQR_TABLE_WN", &mut self.sqr_table_wn),
+            ("SQR_TABLE_WB", &mut self.sqr_table_wb),
+            ("SQR_TABLE_WR", &mut self.sqr_table_wr),
+            ("SQR_TABLE_WQ", &mut self.sqr_table_wq),
+            ("SQR_TABLE_WK", &mut self.sqr_table_wk),
+            ("SQR_TABLE_K_ENDGAME", &mut self.sqr_table_k_endgame),
+        ]
+    }
+
+    pub fn get_param_list(&self) -> Vec<(&'static str, Vec<i32>)> {
+        let mut eval_params = self.clone();
+        eval_params.get_param_list_mut().into_iter().map(|(name, param_slice)| (name, param_slice.to_vec())).collect()
+    }
+
+    /// Reads weights from either TOML (`NAME = value` lines) or a JSON object (`"NAME": value` pairs).
+    /// Names are those of the constants, case-insensitive; weights left out keep their defaults.
+    pub fn parse(param_str: &str) -> Result<Self, EvalParamsError> {
+        let mut token_iter = tokenize_params(param_str)?.into_iter().peekable();
+        let mut eval_params = EvalParams::default();
+        let mut assigned_name_list: Vec<&'static str> = Vec::new();
+
+        let is_json = token_iter.peek() == Some(&ParamToken::Symbol('{'));
+        if is_json {
+            token_iter.next();
+        }
+
+        loop {
+            let name = match token_iter.next() {
+                Some(ParamToken::Name(name)) => name,
+                Some(ParamToken::Symbol('}')) if is_json => break,
+                None if !is_json => break,
+                None => return Err(EvalParamsError::Syntax("missing closing }".to_string())),
+                Some(token) => return Err(EvalParamsError::Syntax(format!("expected a parameter name but found {}", token))),
+            };
+
+            match token_iter.next() {
+                Some(ParamToken::Symbol('=')) | Some(ParamToken::Symbol(':')) => {},
+                _ => return Err(EvalParamsError::Syntax(format!("expected = or : after {}", name))),
+            }
+
+            let value_list = parse_param_value(&mut token_iter, &name)?;
+
+            if is_json && token_iter.peek() == Some(&ParamToken::Symbol(',')) {
+                token_iter.next();
+            }
+
+            let mut param_list = eval_params.get_param_list_mut();
+            let (param_name, param_slice) = match param_list.iter_mut().find(|(param_name, _)| param_name.eq_ignore_ascii_case(&name)) {
+                Some(param) => param,
+                None => return Err(EvalParamsError::UnknownParam(name)),
+            };
+
+            if assigned_name_list.contains(param_name) {
+                return Err(EvalParamsError::DuplicateParam(param_name.to_string()))
+            }
+
+            if param_slice.len() != value_list.len() {
+                return Err(EvalParamsError::InvalidLength(param_name, param_slice.len(), value_list.len()))
+            }
+
+            param_slice.copy_from_slice(&value_list);
+            assigned_name_list.push(param_name);
+        }
+
+        if let Some(token) = token_iter.next() {
+            return Err(EvalParamsError::Syntax(format!("unexpected {} after closing }}", token)))
+        }
+
+        Ok(eval_params)
+    }
+
+    pub fn load(file_path: &str) -> Result<Self, EvalParamsError> {
+        let param_str = fs::read_to_string(file_path).map_err(|err| EvalParamsError::Io(err.to_string()))?;
+        EvalParams::parse(&param_str)
+    }
+
+    /// Writes every weight as TOML that `parse` reads back, square tables one rank per line.
+    pub fn to_toml(&self) -> String {
+        let mut toml_str = String::new();
+
+        for (name, value_list) in self.get_param_list() {
+            if value_list.len() == 1 {
+                toml_str.push_str(&format!("{} = {}\n", name, value_list[0]));
+                continue
+            }
+
+            let row_size = if value_list.len() == def::BOARD_SIZE { def::DIM_SIZE } else { 20 };
+            toml_str.push_str(&format!("{} = [\n", name));
+            for row in value_list.chunks(row_size) {
+                let row_str_list: Vec<String> = row.iter().map(|value| value.to_string()).collect();
+                toml_str.push_str(&format!("    {},\n", row_str_list.join(", ")));
+            }
+            toml_str.push_str("]\n");
+        }
+
+        toml_str
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum EvalParamsError {
+    Io(String),
+    Syntax(String),
+    UnknownParam(String),
+    DuplicateParam(String),
+    InvalidLength(&'static str, usize, usize),
+}
+
+impl fmt::Display for EvalParamsError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EvalParamsError::Io(reason) => write!(formatter, "unable to read file, {}", reason),
+            EvalParamsError::Syntax(reason) => write!(formatter, "invalid syntax, {}", reason),
+            EvalParamsError::UnknownParam(name) => write!(formatter, "unknown parameter {}", name),
+            EvalParamsError::DuplicateParam(name) => write!(formatter, "parameter {} is set more than once", name),
+            EvalParamsError::InvalidLength(name, expected_len, found_len) => write!(formatter, "expected {} values for {} but found {}", expected_len, name, found_len),
+        }
+    }
+}
+
+impl Error for EvalParamsError {}
+
+#[derive(Debug, PartialEq)]
+enum ParamToken {
+    Name(String),
+    Number(i32),
+    Symbol(char),
+}
+
+impl fmt::Display for ParamToken {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParamToken::Name(name) => write!(formatter, "{}", name),
+            ParamToken::Number(value) => write!(formatter, "{}", value),
+            ParamToken::Symbol(symbol) => write!(formatter, "{}", symbol),
+        }
+    }
+}
+
+fn tokenize_params(param_str: &str) -> Result<Vec<ParamToken>, EvalParamsError> {
+    let mut token_list = Vec::new();
+    let mut char_iter = param_str.chars().peekable();
+
+    while let Some(c) = char_iter.next() {
+        match c {
+            '#' => {
+                while char_iter.next_if(|&next_c| next_c != '\n').is_some() {}
+            },
+            '"' => {
+                let mut name = String::new();
+                loop {
+                    match char_iter.next() {
+                        Some('"') => break,
+                        Some(next_c) => name.push(next_c),
+                        None => return Err(EvalParamsError::Syntax(format!("unterminated string \"{}", name))),
+                    }
+                }
+                token_list.push(ParamToken::Name(name));
+            },
+            '=' | ':' | '[' | ']' | '{' | '}' | ',' => token_list.push(ParamToken::Symbol(c)),
+            '-' | '+' | '0'..='9' => {
+                let mut number_str = c.to_string();
+                while let Some(next_c) = char_iter.next_if(|next_c| next_c.is_ascii_digit()) {
+                    number_str.push(next_c);
+                }
+                match number_str.parse::<i32>() {
+                    Ok(value) => token_list.push(ParamToken::Number(value)),
+                    Err(_) => return Err(EvalParamsError::Syntax(format!("invalid number {}", number_str))),
+                }
+            },
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let mut name = c.to_string();
+                while let Some(next_c) = char_iter.next_if(|next_c| next_c.is_ascii_alphanumeric() || *next_c == '_') {
+                    name.push(next_c);
+                }
+                token_list.push(ParamToken::Name(name));
+            },
+            c if c.is_whitespace() => {},
+            _ => return Err(EvalParamsError::Syntax(format!("unexpected character {}", c))),
+        }
+    }
+
+    Ok(token_list)
+}
+
+fn parse_param_value(token_iter: &mut Peekable<vec::IntoIter<ParamToken>>, name: &str) -> Result<Vec<i32>, EvalParamsError> {
+    match token_iter.next() {
+        Some(ParamToken::Number(value)) => return Ok(vec![value]),
+        Some(ParamToken::Symbol('[')) => {},
+        _ => return Err(EvalParamsError::Syntax(format!("expected a number or a list for {}", name))),
+    }
+
+    let mut value_list = Vec::new();
+
+    loop {
+        match token_iter.next() {
+            Some(ParamToken::Number(value)) => value_list.push(value),
+            Some(ParamToken::Symbol(']')) => break,
+            _ => return Err(EvalParamsError::Syntax(format!("expected a number in the list of {}", name))),
+        }
+
+        match token_iter.next() {
+            Some(ParamToken::Symbol(',')) => {},
+            Some(ParamToken::Symbol(']')) => break,
+            _ => return Err(EvalParamsError::Syntax(format!("expected , or ] in the list of {}", name))),
+        }
+    }
+
+    Ok(value_list)
+}
+
 const W_PAWN_PROMO_RANK: u64 = 0b00000000_11111111_00000000_00000000_00000000_00000000_00000000_00000000;
 const B_PAWN_PROMO_RANK: u64 = 0b00000000_00000000_00000000_00000000_00000000_00000000_11111111_00000000;
 
@@ -368,7 +665,9 @@ pub fn has_promoting_pawn(state: &State, player: u8) -> bool {
     }
 }
 
-pub fn eval_materials(state: &mut State) -> (i32, bool) {
+/// Returns the material score from the side to move's point of view,
+/// and whether the material left is a draw.
+pub fn eval_materials(state: &mut State, eval_params: &EvalParams) -> (i32, bool) {
     let bitboard = state.bitboard;
     let bitmask = bitmask::get_bitmask();
 
@@ -398,17 +697,26 @@ pub fn eval_materials(state: &mut State) -> (i32, bool) {
         }
     }
 
-    let material_score = w_queen_count * Q_VAL
-    + w_rook_count * R_VAL
-    + w_bishop_count * B_VAL
-    + w_knight_count * N_VAL
-    + w_pawn_count * P_VAL
-    - b_queen_count * Q_VAL
-    - b_rook_count * R_VAL
-    - b_bishop_count * B_VAL
-    - b_knight_count * N_VAL
-    - b_pawn_count * P_VAL;
+    let material_score = w_queen_count * eval_params.q_val
+    + w_rook_count * eval_params.r_val
+    + w_bishop_count * eval_params.b_val
+    + w_knight_count * eval_params.n_val
+    + w_pawn_count * eval_params.p_val
+    - b_queen_count * eval_params.q_val
+    - b_rook_count * eval_params.r_val
+    - b_bishop_count * eval_params.b_val
+    - b_knight_count * eval_params.n_val
+    - b_pawn_count * eval_params.p_val;
+
+    match bitbase::probe(state) {
+        Some(BitbaseResult::Draw) => return (0, true),
+        Some(BitbaseResult::Win) => return (material_score.abs() + BITBASE_WIN_VAL, false),
+        Some(BitbaseResult::Loss) => return (-material_score.abs() - BITBASE_WIN_VAL, false),
+        None => {},
+    }
 
+    // a single minor piece against a pawn is not in the bitbases: KNKP and KBKP would need the
+    // KQKN and KQKB tables the pawn promotes into, so this heuristic stays
     if material_score > 0 && (bitboard.w_pawn | bitboard.w_rook | bitboard.w_queen) == 0 && w_knight_count + w_bishop_count == 1 && b_pawn_count == 1 {
         return (0, false)
     }
@@ -419,20 +727,20 @@ pub fn eval_materials(state: &mut State) -> (i32, bool) {
 
     let mut eg_score = 0;
 
-    eg_score += w_queen_count * EG_Q_VAL;
-    eg_score += w_rook_count * EG_R_VAL;
-    eg_score += w_pawn_count * EG_P_VAL;
+    eg_score += w_queen_count * eval_params.eg_q_val;
+    eg_score += w_rook_count * eval_params.eg_r_val;
+    eg_score += w_pawn_count * eval_params.eg_p_val;
 
-    eg_score -= b_queen_count * EG_Q_VAL;
-    eg_score -= b_rook_count * EG_R_VAL;
-    eg_score -= b_pawn_count * EG_P_VAL;
+    eg_score -= b_queen_count * eval_params.eg_q_val;
+    eg_score -= b_rook_count * eval_params.eg_r_val;
+    eg_score -= b_pawn_count * eval_params.eg_p_val;
 
-    if material_score > P_VAL && bitboard.w_pawn == 0 {
-        eg_score -= EG_PAWN_ESSENTIAL_VAL;
+    if material_score > eval_params.p_val && bitboard.w_pawn == 0 {
+        eg_score -= eval_params.eg_pawn_essential_val;
     }
 
-    if material_score < -P_VAL && bitboard.b_pawn == 0 {
-        eg_score += EG_PAWN_ESSENTIAL_VAL;
+    if material_score < -eval_params.p_val && bitboard.b_pawn == 0 {
+        eg_score += eval_params.eg_pawn_essential_val;
     }
 
     let mut is_endgame_with_different_colored_bishop = false;
@@ -461,34 +769,34 @@ pub fn eval_materials(state: &mut State) -> (i32, bool) {
     if is_endgame_with_different_colored_bishop {
         if bitboard.w_rook | bitboard.b_rook == 0 {
             if material_score > 0  {
-                eg_score -= EG_DIFFERENT_COLORED_BISHOP_VAL;
+                eg_score -= eval_params.eg_different_colored_bishop_val;
             } else if material_score < 0 {
-                eg_score += EG_DIFFERENT_COLORED_BISHOP_VAL;
+                eg_score += eval_params.eg_different_colored_bishop_val;
             }
         } else {
             if material_score > 0  {
-                eg_score -= EG_DIFFERENT_COLORED_BISHOP_WITH_ROOK_VAL;
+                eg_score -= eval_params.eg_different_colored_bishop_with_rook_val;
             } else if material_score < 0 {
-                eg_score += EG_DIFFERENT_COLORED_BISHOP_WITH_ROOK_VAL;
+                eg_score += eval_params.eg_different_colored_bishop_with_rook_val;
             }
         }
     } else {
         if w_bishop_count > 1 {
-            eg_score += EG_BISHOP_PAIR_BONUS;
+            eg_score += eval_params.eg_bishop_pair_bonus;
         }
 
         if b_bishop_count > 1 {
-            eg_score -= EG_BISHOP_PAIR_BONUS;
+            eg_score -= eval_params.eg_bishop_pair_bonus;
         }
     }
 
     let phase = get_phase(state);
 
     if phase == 0 {
-        if material_score >= P_VAL {
-            eg_score += EG_NO_PIECE_BONUS;
-        } else if material_score <= -P_VAL {
-            eg_score -= EG_NO_PIECE_BONUS;
+        if material_score >= eval_params.p_val {
+            eg_score += eval_params.eg_no_piece_bonus;
+        } else if material_score <= -eval_params.p_val {
+            eg_score -= eval_params.eg_no_piece_bonus;
         }
     }
 
@@ -508,57 +816,59 @@ pub fn get_phase(state: &mut State) -> i32 {
     + (state.wn_count + state.bn_count) * N_PHASE_WEIGHT
 }
 
-pub fn eval_state(state: &mut State, material_score: i32) -> i32 {
+/// Returns the full static evaluation from the side to move's point of view,
+/// given the material score from `eval_materials`.
+pub fn eval_state(state: &mut State, material_score: i32, eval_params: &EvalParams) -> i32 {
     let score_sign = if state.player == def::PLAYER_W {
         1
     } else {
         -1
     };
 
-    let (w_features_map, b_features_map) = extract_features(state);
+    let (w_features_map, b_features_map) = extract_features(state, eval_params);
 
     let w_king_attack_count = w_features_map.weak_king_attack_count + w_features_map.strong_king_attack_count * STRONG_K_ATTACK_COUNT_MULTIPLIER;
     let b_king_attack_count = b_features_map.weak_king_attack_count + b_features_map.strong_king_attack_count * STRONG_K_ATTACK_COUNT_MULTIPLIER;
 
     let mut midgame_positional_score =
         w_features_map.mg_sqr_point
-        + w_features_map.pin_count * PIN_PEN
-        + w_features_map.semi_pin_count * SEMI_PIN_PEN
-        + w_features_map.rook_open_count * ROOK_OPEN_BONUS
-        + w_features_map.weak_sqr_count * WEAK_SQR_PEN
-        + w_features_map.king_exposure_count * KING_EXPOSURE_PEN
-        + K_ATTACK_SCORE[w_king_attack_count as usize]
+        + w_features_map.pin_count * eval_params.pin_pen
+        + w_features_map.semi_pin_count * eval_params.semi_pin_pen
+        + w_features_map.rook_open_count * eval_params.rook_open_bonus
+        + w_features_map.weak_sqr_count * eval_params.weak_sqr_pen
+        + w_features_map.king_exposure_count * eval_params.king_exposure_pen
+        + eval_params.k_attack_score[w_king_attack_count as usize]
         - b_features_map.mg_sqr_point
-        - b_features_map.pin_count * PIN_PEN
-        - b_features_map.semi_pin_count * SEMI_PIN_PEN
-        - b_features_map.rook_open_count * ROOK_OPEN_BONUS
-        - b_features_map.weak_sqr_count * WEAK_SQR_PEN
-        - b_features_map.king_exposure_count * KING_EXPOSURE_PEN
-        - K_ATTACK_SCORE[b_king_attack_count as usize];
+        - b_features_map.pin_count * eval_params.pin_pen
+        - b_features_map.semi_pin_count * eval_params.semi_pin_pen
+        - b_features_map.rook_open_count * eval_params.rook_open_bonus
+        - b_features_map.weak_sqr_count * eval_params.weak_sqr_pen
+        - b_features_map.king_exposure_count * eval_params.king_exposure_pen
+        - eval_params.k_attack_score[b_king_attack_count as usize];
 
     if state.bitboard.b_queen != 0 {
         if (state.cas_rights | state.cas_history) & 0b1100 == 0 {
-            midgame_positional_score += KING_LOST_CAS_RIGHTS_PEN;
+            midgame_positional_score += eval_params.king_lost_cas_rights_pen;
         }
     }
 
     if state.bitboard.w_queen != 0 {
         if (state.cas_rights | state.cas_history) & 0b0011 == 0 {
-            midgame_positional_score -= KING_LOST_CAS_RIGHTS_PEN;
+            midgame_positional_score -= eval_params.king_lost_cas_rights_pen;
         }
     }
 
     let mut endgame_positional_score =
         w_features_map.eg_sqr_point
         + w_features_map.passed_pawn_point
-        + w_features_map.controlled_passed_pawn_count * CONTROLLED_PASS_PAWN_VAL
+        + w_features_map.controlled_passed_pawn_count * eval_params.controlled_pass_pawn_val
         + w_features_map.eg_mobility
-        + w_features_map.king_in_passer_path_count * KING_IN_PASSER_PATH_BONUS
+        + w_features_map.king_in_passer_path_count * eval_params.king_in_passer_path_bonus
         - b_features_map.eg_sqr_point
         - b_features_map.passed_pawn_point
-        - b_features_map.controlled_passed_pawn_count * CONTROLLED_PASS_PAWN_VAL
+        - b_features_map.controlled_passed_pawn_count * eval_params.controlled_pass_pawn_val
         - b_features_map.eg_mobility
-        - b_features_map.king_in_passer_path_count * KING_IN_PASSER_PATH_BONUS;
+        - b_features_map.king_in_passer_path_count * eval_params.king_in_passer_path_bonus;
 
     let bitboard = state.bitboard;
     let bitmask = bitmask::get_bitmask();
@@ -567,13 +877,13 @@ pub fn eval_state(state: &mut State, material_score: i32) -> i32 {
         if bitboard.w_rook | bitboard.b_knight == 0 {
             if bitboard.b_rook.count_ones() == 1 && bitboard.w_knight.count_ones() == 1 {
                 if bitmask.k_attack_masks[state.wk_index] & bitboard.w_knight != 0 {
-                    endgame_positional_score += EG_RN_KNIGHT_PROTECTED_BONUS;
+                    endgame_positional_score += eval_params.eg_rn_knight_protected_bonus;
                 }
             }
         } else if bitboard.b_rook | bitboard.w_knight == 0 {
             if bitboard.w_rook.count_ones() == 1 && bitboard.b_knight.count_ones() == 1 {
                 if bitmask.k_attack_masks[state.bk_index] & bitboard.b_knight != 0 {
-                    endgame_positional_score -= EG_RN_KNIGHT_PROTECTED_BONUS;
+                    endgame_positional_score -= eval_params.eg_rn_knight_protected_bonus;
                 }
             }
         }
@@ -582,23 +892,23 @@ pub fn eval_state(state: &mut State, material_score: i32) -> i32 {
     let shared_positional_score =
         w_features_map.mobility
         + w_features_map.threat_point / THREAT_DISCOUNT_FACTOR
-        + w_features_map.behind_pawn_count * BEHIND_PAWN_PEN
-        + w_features_map.isolated_pawn_count * ISOLATED_PAWN_PEN
-        + w_features_map.doubled_pawn_count * DOUBLED_PAWN_PEN
+        + w_features_map.behind_pawn_count * eval_params.behind_pawn_pen
+        + w_features_map.isolated_pawn_count * eval_params.isolated_pawn_pen
+        + w_features_map.doubled_pawn_count * eval_params.doubled_pawn_pen
         - b_features_map.mobility
         - b_features_map.threat_point / THREAT_DISCOUNT_FACTOR
-        - b_features_map.behind_pawn_count * BEHIND_PAWN_PEN
-        - b_features_map.isolated_pawn_count * ISOLATED_PAWN_PEN
-        - b_features_map.doubled_pawn_count * DOUBLED_PAWN_PEN;
+        - b_features_map.behind_pawn_count * eval_params.behind_pawn_pen
+        - b_features_map.isolated_pawn_count * eval_params.isolated_pawn_pen
+        - b_features_map.doubled_pawn_count * eval_params.doubled_pawn_pen;
 
     let phase = get_phase(state);
 
     let extra_score = midgame_positional_score * phase / TOTAL_PHASE + endgame_positional_score * (TOTAL_PHASE - phase) / TOTAL_PHASE + shared_positional_score;
 
-    material_score + extra_score * score_sign + TEMPO_VAL
+    material_score + extra_score * score_sign + eval_params.tempo_val
 }
 
-fn extract_features(state: &mut State) -> (FeatureMap, FeatureMap) {
+fn extract_features(state: &mut State, eval_params: &EvalParams) -> (FeatureMap, FeatureMap) {
     let squares = state.squares;
     let bitboard = state.bitboard;
     let bitmask = bitmask::get_bitmask();
@@ -662,7 +972,7 @@ fn extract_features(state: &mut State) -> (FeatureMap, FeatureMap) {
                 }
 
                 if forward_mask & (bitboard.b_pawn | (bitboard.w_pawn & file_mask)) == 0 {
-                    w_feature_map.passed_pawn_point += PASS_PAWN_VAL[rank as usize];
+                    w_feature_map.passed_pawn_point += eval_params.pass_pawn_val[rank as usize];
 
                     if forward_mask & bitmask.k_attack_masks[state.wk_index] != 0 {
                         w_feature_map.king_in_passer_path_count += 1;
@@ -673,7 +983,7 @@ fn extract_features(state: &mut State) -> (FeatureMap, FeatureMap) {
                     }
 
                     if bitmask.wp_connected_sqr_masks[index] & bitboard.w_pawn != 0 {
-                        w_feature_map.passed_pawn_point += CONNECTED_PASS_PAWN_BONUS[rank as usize];
+                        w_feature_map.passed_pawn_point += eval_params.connected_pass_pawn_bonus[rank as usize];
                     }
 
                     if piece_mask == 0 {
@@ -684,10 +994,10 @@ fn extract_features(state: &mut State) -> (FeatureMap, FeatureMap) {
                     }
 
                     if bitmask.index_masks[index+def::DIM_SIZE] & occupy_mask == 0 {
-                        w_feature_map.eg_mobility += P_MOB_SCORE;
+                        w_feature_map.eg_mobility += eval_params.p_mob_score;
                     }
                 } else if forward_mask & (bitboard.w_pawn | bitboard.b_pawn) & file_mask == 0 && (forward_mask & bitboard.b_pawn).count_ones() == 1 && bitmask.wp_connected_sqr_masks[index] & bitboard.w_pawn != 0 {
-                    w_feature_map.passed_pawn_point += CANDIDATE_PASS_PAWN_VAL[rank as usize];
+                    w_feature_map.passed_pawn_point += eval_params.candidate_pass_pawn_val[rank as usize];
                 }
 
                 if (file_mask & bitboard.w_pawn).count_ones() > 1 {
@@ -718,7 +1028,7 @@ fn extract_features(state: &mut State) -> (FeatureMap, FeatureMap) {
                 }
 
                 if forward_mask & (bitboard.w_pawn | (bitboard.b_pawn & file_mask)) == 0 {
-                    b_feature_map.passed_pawn_point += PASS_PAWN_VAL[rank as usize];
+                    b_feature_map.passed_pawn_point += eval_params.pass_pawn_val[rank as usize];
 
                     if forward_mask & bitmask.k_attack_masks[state.bk_index] != 0 {
                         b_feature_map.king_in_passer_path_count += 1;
@@ -729,7 +1039,7 @@ fn extract_features(state: &mut State) -> (FeatureMap, FeatureMap) {
                     }
 
                     if bitmask.bp_connected_sqr_masks[index] & bitboard.b_pawn != 0 {
-                        b_feature_map.passed_pawn_point += CONNECTED_PASS_PAWN_BONUS[rank as usize];
+                        b_feature_map.passed_pawn_point += eval_params.connected_pass_pawn_bonus[rank as usize];
                     }
 
                     if piece_mask == 0 {
@@ -740,10 +1050,10 @@ fn extract_features(state: &mut State) -> (FeatureMap, FeatureMap) {
                     }
 
                     if bitmask.index_masks[index-def::DIM_SIZE] & occupy_mask == 0 {
-                        b_feature_map.eg_mobility += P_MOB_SCORE;
+                        b_feature_map.eg_mobility += eval_params.p_mob_score;
                     }
                 } else if forward_mask & (bitboard.w_pawn | bitboard.b_pawn) & file_mask == 0 && (forward_mask & bitboard.w_pawn).count_ones() == 1 && bitmask.bp_connected_sqr_masks[index] & bitboard.b_pawn != 0 {
-                    b_feature_map.passed_pawn_point += CANDIDATE_PASS_PAWN_VAL[rank as usize];
+                    b_feature_map.passed_pawn_point += eval_params.candidate_pass_pawn_val[rank as usize];
                 }
 
                 if (file_mask & bitboard.b_pawn).count_ones() > 1 {
@@ -856,8 +1166,8 @@ fn extract_features(state: &mut State) -> (FeatureMap, FeatureMap) {
     w_feature_map.weak_sqr_count = (W_CRITICAL_RANK_MASK & !wp_attack_mask).count_ones() as i32;
     b_feature_map.weak_sqr_count = (B_CRITICAL_RANK_MASK & !bp_attack_mask).count_ones() as i32;
 
-    w_feature_map.eg_mobility += K_MOB_SCORE[(wk_ring_mask &!bitboard.w_all & !b_attack_mask).count_ones() as usize];
-    b_feature_map.eg_mobility += K_MOB_SCORE[(bk_ring_mask &!bitboard.b_all & !w_attack_mask).count_ones() as usize];
+    w_feature_map.eg_mobility += eval_params.k_mob_score[(wk_ring_mask &!bitboard.w_all & !b_attack_mask).count_ones() as usize];
+    b_feature_map.eg_mobility += eval_params.k_mob_score[(bk_ring_mask &!bitboard.b_all & !w_attack_mask).count_ones() as usize];
 
     if bitmask.index_masks[state.wk_index] & WK_K_SIDE_MASK != 0 {
         w_feature_map.weak_sqr_count += (W_CRITICAL_RANK_MASK & WK_K_SIDE_MASK & !w_attack_without_king_mask).count_ones() as i32;
@@ -1049,8 +1359,8 @@ fn extract_features(state: &mut State) -> (FeatureMap, FeatureMap) {
 
         match piece {
             def::WP => {
-                w_feature_map.mg_sqr_point += SQR_TABLE_WP[index];
-                w_feature_map.eg_sqr_point += SQR_TABLE_WP_ENDGAME[index];
+                w_feature_map.mg_sqr_point += eval_params.sqr_table_wp[index];
+                w_feature_map.eg_sqr_point += eval_params.sqr_table_wp_endgame[index];
 
                 if index_mask & w_attack_mask == 0 {
                     if index_mask & b_attack_mask != 0 {
@@ -1061,7 +1371,7 @@ fn extract_features(state: &mut State) -> (FeatureMap, FeatureMap) {
                 w_feature_map.weak_king_attack_count += (bk_ring_mask & mov_mask).count_ones() as i32;
             },
             def::WN => {
-                w_feature_map.mg_sqr_point += SQR_TABLE_WN[index];
+                w_feature_map.mg_sqr_point += eval_params.sqr_table_wn[index];
 
                 if index_mask & w_attack_mask == 0 {
                     if index_mask & b_attack_mask != 0 {
@@ -1072,7 +1382,7 @@ fn extract_features(state: &mut State) -> (FeatureMap, FeatureMap) {
                 }
 
                 let mobility_mask = mov_mask & !bp_attack_mask & !bitboard.w_all & !(b_attack_mask & !w_attack_mask);
-                w_feature_map.mobility += N_MOB_SCORE[mobility_mask.count_ones() as usize];
+                w_feature_map.mobility += eval_params.n_mob_score[mobility_mask.count_ones() as usize];
 
                 w_feature_map.weak_king_attack_count += (bk_ring_mask & mov_mask).count_ones() as i32;
                 w_feature_map.weak_king_attack_count += (bitmask.n_attack_masks[state.bk_index] & mov_mask).count_ones() as i32;
@@ -1081,7 +1391,7 @@ fn extract_features(state: &mut State) -> (FeatureMap, FeatureMap) {
                 w_feature_map.weak_king_attack_count += (bitmask.n_attack_masks[state.bk_index] & mov_mask & !(bp_attack_mask | bn_attack_mask | bb_attack_mask)).count_ones() as i32;
             },
             def::WB => {
-                w_feature_map.mg_sqr_point += SQR_TABLE_WB[index];
+                w_feature_map.mg_sqr_point += eval_params.sqr_table_wb[index];
 
                 if index_mask & w_attack_mask == 0 {
                     if index_mask & b_attack_mask != 0 {
@@ -1092,13 +1402,13 @@ fn extract_features(state: &mut State) -> (FeatureMap, FeatureMap) {
                 }
 
                 let mobility_mask = mov_mask & !bp_attack_mask & !bitboard.w_all & !(b_attack_mask & !w_attack_mask);
-                w_feature_map.mobility += B_MOB_SCORE[mobility_mask.count_ones() as usize];
+                w_feature_map.mobility += eval_params.b_mob_score[mobility_mask.count_ones() as usize];
 
                 w_feature_map.weak_king_attack_count += (bk_ring_mask & mov_mask).count_ones() as i32;
                 w_feature_map.weak_king_attack_count += (bk_ring_mask & mov_mask & !(bp_attack_mask | bn_attack_mask | bb_attack_mask)).count_ones() as i32;
             },
             def::WR => {
-                w_feature_map.mg_sqr_point += SQR_TABLE_WR[index];
+                w_feature_map.mg_sqr_point += eval_params.sqr_table_wr[index];
 
                 if index_mask & w_attack_mask == 0 {
                     if index_mask & b_attack_mask != 0 {
@@ -1111,13 +1421,13 @@ fn extract_features(state: &mut State) -> (FeatureMap, FeatureMap) {
                 }
 
                 let mobility_mask = mov_mask & !(bp_attack_mask | bn_attack_mask | bb_attack_mask) & !bitboard.w_all & !(b_attack_mask & !w_attack_mask);
-                w_feature_map.mobility += R_MOB_SCORE[mobility_mask.count_ones() as usize];
+                w_feature_map.mobility += eval_params.r_mob_score[mobility_mask.count_ones() as usize];
 
                 w_feature_map.weak_king_attack_count += (bk_ring_mask & mov_mask).count_ones() as i32;
                 w_feature_map.strong_king_attack_count += (bk_ring_mask & mov_mask & !(bp_attack_mask | bn_attack_mask | bb_attack_mask | br_attack_mask)).count_ones() as i32;
             },
             def::WQ => {
-                w_feature_map.mg_sqr_point += SQR_TABLE_WQ[index];
+                w_feature_map.mg_sqr_point += eval_params.sqr_table_wq[index];
 
                 if index_mask & w_attack_mask == 0 {
                     if index_mask & b_attack_mask != 0 {
@@ -1132,18 +1442,18 @@ fn extract_features(state: &mut State) -> (FeatureMap, FeatureMap) {
                 }
 
                 let mobility_mask = mov_mask & !(bp_attack_mask | bn_attack_mask | bb_attack_mask | br_attack_mask) & !bitboard.w_all & !(b_attack_mask & !w_attack_mask);
-                w_feature_map.mobility += Q_MOB_SCORE[mobility_mask.count_ones() as usize];
+                w_feature_map.mobility += eval_params.q_mob_score[mobility_mask.count_ones() as usize];
 
                 w_feature_map.weak_king_attack_count += (bk_ring_mask & mov_mask).count_ones() as i32;
                 w_feature_map.strong_king_attack_count += (bk_ring_mask & mov_mask & !(bp_attack_mask | bn_attack_mask | bb_attack_mask | br_attack_mask | bq_attack_mask)).count_ones() as i32;
             },
             def::WK => {
-                w_feature_map.mg_sqr_point += SQR_TABLE_WK[index];
-                w_feature_map.eg_sqr_point += SQR_TABLE_K_ENDGAME[index];
+                w_feature_map.mg_sqr_point += eval_params.sqr_table_wk[index];
+                w_feature_map.eg_sqr_point += eval_params.sqr_table_k_endgame[index];
             },
             def::BP => {
-                b_feature_map.mg_sqr_point += SQR_TABLE_BP[index];
-                b_feature_map.eg_sqr_point += SQR_TABLE_BP_ENDGAME[index];
+                b_feature_map.mg_sqr_point += eval_params.sqr_table_wp[def::get_mirror_index(index)];
+                b_feature_map.eg_sqr_point += eval_params.sqr_table_wp_endgame[def::get_mirror_index(index)];
 
                 if index_mask & b_attack_mask == 0 {
                     if index_mask & w_attack_mask != 0 {
@@ -1154,7 +1464,7 @@ fn extract_features(state: &mut State) -> (FeatureMap, FeatureMap) {
                 b_feature_map.weak_king_attack_count += (wk_ring_mask & mov_mask).count_ones() as i32;
             },
             def::BN => {
-                b_feature_map.mg_sqr_point += SQR_TABLE_BN[index];
+                b_feature_map.mg_sqr_point += eval_params.sqr_table_wn[def::get_mirror_index(index)];
 
                 if index_mask & b_attack_mask == 0 {
                     if index_mask & w_attack_mask != 0 {
@@ -1165,7 +1475,7 @@ fn extract_features(state: &mut State) -> (FeatureMap, FeatureMap) {
                 }
 
                 let mobility_mask = mov_mask & !wp_attack_mask & !bitboard.b_all & !(w_attack_mask & !b_attack_mask);
-                b_feature_map.mobility += N_MOB_SCORE[mobility_mask.count_ones() as usize];
+                b_feature_map.mobility += eval_params.n_mob_score[mobility_mask.count_ones() as usize];
 
                 b_feature_map.weak_king_attack_count += (wk_ring_mask & mov_mask).count_ones() as i32;
                 b_feature_map.weak_king_attack_count += (bitmask.n_attack_masks[state.wk_index] & mov_mask).count_ones() as i32;
@@ -1174,7 +1484,7 @@ fn extract_features(state: &mut State) -> (FeatureMap, FeatureMap) {
                 b_feature_map.weak_king_attack_count += (bitmask.n_attack_masks[state.wk_index] & mov_mask & !(wp_attack_mask | wn_attack_mask | wb_attack_mask)).count_ones() as i32;
             },
             def::BB => {
-                b_feature_map.mg_sqr_point += SQR_TABLE_BB[index];
+                b_feature_map.mg_sqr_point += eval_params.sqr_table_wb[def::get_mirror_index(index)];
 
                 if index_mask & b_attack_mask == 0 {
                     if index_mask & w_attack_mask != 0 {
@@ -1185,13 +1495,13 @@ fn extract_features(state: &mut State) -> (FeatureMap, FeatureMap) {
                 }
 
                 let mobility_mask = mov_mask & !wp_attack_mask & !bitboard.b_all & !(w_attack_mask & !b_attack_mask);
-                b_feature_map.mobility += B_MOB_SCORE[mobility_mask.count_ones() as usize];
+                b_feature_map.mobility += eval_params.b_mob_score[mobility_mask.count_ones() as usize];
 
                 b_feature_map.weak_king_attack_count += (wk_ring_mask & mov_mask).count_ones() as i32;
                 b_feature_map.weak_king_attack_count += (wk_ring_mask & mov_mask & !(wp_attack_mask | wn_attack_mask | wb_attack_mask)).count_ones() as i32;
             },
             def::BR => {
-                b_feature_map.mg_sqr_point += SQR_TABLE_BR[index];
+                b_feature_map.mg_sqr_point += eval_params.sqr_table_wr[def::get_mirror_index(index)];
 
                 if index_mask & b_attack_mask == 0 {
                     if index_mask & w_attack_mask != 0 {
@@ -1204,13 +1514,13 @@ fn extract_features(state: &mut State) -> (FeatureMap, FeatureMap) {
                 }
 
                 let mobility_mask = mov_mask & !(wp_attack_mask | wn_attack_mask | wb_attack_mask) & !bitboard.b_all & !(w_attack_mask & !b_attack_mask);
-                b_feature_map.mobility += R_MOB_SCORE[mobility_mask.count_ones() as usize];
+                b_feature_map.mobility += eval_params.r_mob_score[mobility_mask.count_ones() as usize];
 
                 b_feature_map.weak_king_attack_count += (wk_ring_mask & mov_mask).count_ones() as i32;
                 b_feature_map.strong_king_attack_count += (wk_ring_mask & mov_mask & !(wp_attack_mask | wn_attack_mask | wb_attack_mask | wr_attack_mask)).count_ones() as i32;
             },
             def::BQ => {
-                b_feature_map.mg_sqr_point += SQR_TABLE_BQ[index];
+                b_feature_map.mg_sqr_point += eval_params.sqr_table_wq[def::get_mirror_index(index)];
 
                 if index_mask & b_attack_mask == 0 {
                     if index_mask & w_attack_mask != 0 {
@@ -1225,14 +1535,14 @@ fn extract_features(state: &mut State) -> (FeatureMap, FeatureMap) {
                 }
 
                 let mobility_mask = mov_mask & !(wp_attack_mask | wn_attack_mask | wb_attack_mask | wr_attack_mask) & !bitboard.b_all & !(w_attack_mask & !b_attack_mask);
-                b_feature_map.mobility += Q_MOB_SCORE[mobility_mask.count_ones() as usize];
+                b_feature_map.mobility += eval_params.q_mob_score[mobility_mask.count_ones() as usize];
 
                 b_feature_map.weak_king_attack_count += (wk_ring_mask & mov_mask).count_ones() as i32;
                 b_feature_map.strong_king_attack_count += (wk_ring_mask & mov_mask & !(wp_attack_mask | wn_attack_mask | wb_attack_mask | wr_attack_mask | wq_attack_mask)).count_ones() as i32;
             },
             def::BK => {
-                b_feature_map.mg_sqr_point += SQR_TABLE_BK[index];
-                b_feature_map.eg_sqr_point += SQR_TABLE_K_ENDGAME[index];
+                b_feature_map.mg_sqr_point += eval_params.sqr_table_wk[def::get_mirror_index(index)];
+                b_feature_map.eg_sqr_point += eval_params.sqr_table_k_endgame[index];
             },
             _ => {},
         }
@@ -1257,7 +1567,7 @@ mod tests {
         bitmask::init();
 
         let mut state = State::new("rnbqkbnr/ppp1pppp/8/3p4/8/4P3/PPPP1PPP/RNBQKBNR w KQkq - 0 1");
-        let (w_features, b_features) = extract_features(&mut state);
+        let (w_features, b_features) = extract_features(&mut state, &DEFAULT_EVAL_PARAMS);
 
         assert_eq!(0, w_features.weak_sqr_count);
         assert_eq!(0, b_features.weak_sqr_count);
@@ -1269,7 +1579,7 @@ mod tests {
         bitmask::init();
 
         let mut state = State::new("rnbqkbnr/p1p1p1p1/1p3p1p/3p4/8/4P1P1/PPPP1P1P/RNBQKBNR w KQkq - 0 1");
-        let (w_features, b_features) = extract_features(&mut state);
+        let (w_features, b_features) = extract_features(&mut state, &DEFAULT_EVAL_PARAMS);
 
         assert_eq!(2, w_features.weak_sqr_count);
         assert_eq!(4, b_features.weak_sqr_count);
@@ -1280,8 +1590,8 @@ mod tests {
         zob_keys::init();
         bitmask::init();
 
-        let mut state = State::new("rnbq1rk1/ppppppbp/6p1/8/4P3/6P1/PPPP1P1P/RNBQ1RK1 w Qq - 0 1");
-        let (w_features, b_features) = extract_features(&mut state);
+        let mut state = State::new("rnbq1rk1/ppppppbp/6p1/8/4P3/6P1/PPPP1P1P/RNBQ1RK1 w - - 0 1");
+        let (w_features, b_features) = extract_features(&mut state, &DEFAULT_EVAL_PARAMS);
 
         assert_eq!(3, w_features.weak_sqr_count);
         assert_eq!(1, b_features.weak_sqr_count);
@@ -1293,7 +1603,7 @@ mod tests {
         bitmask::init();
 
         let mut state = State::new("r2qk1nr/ppnpbppp/2p5/B3R3/4P1b1/5N2/PPPP1PPP/RN1QKB2 w Qkq - 0 1");
-        let (w_features, b_features) = extract_features(&mut state);
+        let (w_features, b_features) = extract_features(&mut state, &DEFAULT_EVAL_PARAMS);
 
         assert_eq!(0, w_features.pin_count);
         assert_eq!(1, b_features.pin_count);
@@ -1308,7 +1618,7 @@ mod tests {
         bitmask::init();
 
         let mut state = State::new("r2qk1nr/ppnpbppp/2p5/B3R3/4P1b1/8/PPPP1PPP/RN1QKBN1 w Qkq - 0 1");
-        let (w_features, b_features) = extract_features(&mut state);
+        let (w_features, b_features) = extract_features(&mut state, &DEFAULT_EVAL_PARAMS);
 
         assert_eq!(0, w_features.pin_count);
         assert_eq!(1, b_features.pin_count);
@@ -1323,7 +1633,7 @@ mod tests {
         bitmask::init();
 
         let mut state = State::new("r2qkbnr/ppnp1ppp/2p5/B3R3/4P1b1/5N2/PPPP1PPP/RN1QKB2 w Qkq - 0 1");
-        let (w_features, b_features) = extract_features(&mut state);
+        let (w_features, b_features) = extract_features(&mut state, &DEFAULT_EVAL_PARAMS);
 
         assert_eq!(0, w_features.pin_count);
         assert_eq!(0, b_features.pin_count);
@@ -1331,4 +1641,25 @@ mod tests {
         assert_eq!(1, w_features.semi_pin_count);
         assert_eq!(1, b_features.semi_pin_count);
     }
+
+    #[test]
+    fn test_parse_eval_params() {
+        assert_eq!(DEFAULT_EVAL_PARAMS, EvalParams::parse(&DEFAULT_EVAL_PARAMS.to_toml()).unwrap());
+
+        let toml_params = EvalParams::parse("# material\nP_VAL = 120\nn_mob_score = [1, 2, 3, 4, 5, 6, 7, 8, 9,]\n").unwrap();
+        assert_eq!(120, toml_params.p_val);
+        assert_eq!([1, 2, 3, 4, 5, 6, 7, 8, 9], toml_params.n_mob_score);
+        assert_eq!(Q_VAL, toml_params.q_val);
+
+        let json_params = EvalParams::parse("{\"P_VAL\": -5, \"K_MOB_SCORE\": [0, 0, 0, 0, 0, 0, 0, 0, 1]}").unwrap();
+        assert_eq!(-5, json_params.p_val);
+        assert_eq!([0, 0, 0, 0, 0, 0, 0, 0, 1], json_params.k_mob_score);
+
+        assert_eq!(Err(EvalParamsError::UnknownParam("X_VAL".to_string())), EvalParams::parse("X_VAL = 1"));
+        assert_eq!(Err(EvalParamsError::DuplicateParam("P_VAL".to_string())), EvalParams::parse("P_VAL = 1\np_val = 2"));
+        assert_eq!(Err(EvalParamsError::InvalidLength("N_MOB_SCORE", 9, 2)), EvalParams::parse("N_MOB_SCORE = [1, 2]"));
+        assert_eq!(Err(EvalParamsError::InvalidLength("P_VAL", 1, 2)), EvalParams::parse("P_VAL = [1, 2]"));
+        assert!(matches!(EvalParams::parse("{\"P_VAL\": 1"), Err(EvalParamsError::Syntax(_))));
+        assert!(matches!(EvalParams::parse("P_VAL = [1 2]"), Err(EvalParamsError::Syntax(_))));
+    }
 }
@@ -2,31 +2,52 @@
  * Copyright (C) 2020-2021 Zixiao Han
  */
 
-#[derive(Clone, Copy)]
+use std::sync::atomic::{AtomicU64, Ordering};
+
+const SCORE_BIT_MASK: u64 = 0xFFFFFFFF;
+const FLAG_SHIFT: u64 = 32;
+const DEPTH_SHIFT: u64 = 40;
+const AGE_SHIFT: u64 = 48;
+
+// Entries are shared between search threads without locks.
+// The key and safe check are stored xor-ed with the data words,
+// so a torn write from a concurrent store fails the key comparison
+// instead of returning mixed-up data.
 struct TableEntry {
-    key: u64,
-    safe_check: u64,
-    flag: u8,
-    age: u16,
-    depth: u8,
-    score: i32,
-    eval: i32,
-    mov: u32,
+    key: AtomicU64,
+    safe_check: AtomicU64,
+    score_data: AtomicU64,
+    mov_data: AtomicU64,
 }
 
 impl TableEntry {
     fn empty() -> Self {
         TableEntry {
-            key: 0,
-            safe_check: 0,
-            flag: 0,
-            age: 0,
-            depth: 0,
-            score: 0,
-            eval: 0,
-            mov: 0,
+            key: AtomicU64::new(0),
+            safe_check: AtomicU64::new(0),
+            score_data: AtomicU64::new(0),
+            mov_data: AtomicU64::new(0),
         }
     }
+
+    fn store(&self, key: u64, safe_check: u64, score_data: u64, mov_data: u64) {
+        self.key.store(key ^ score_data ^ mov_data, Ordering::Relaxed);
+        self.safe_check.store(safe_check ^ score_data ^ mov_data, Ordering::Relaxed);
+        self.score_data.store(score_data, Ordering::Relaxed);
+        self.mov_data.store(mov_data, Ordering::Relaxed);
+    }
+
+    fn load(&self) -> (u64, u64, u64, u64) {
+        let score_data = self.score_data.load(Ordering::Relaxed);
+        let mov_data = self.mov_data.load(Ordering::Relaxed);
+
+        (
+            self.key.load(Ordering::Relaxed) ^ score_data ^ mov_data,
+            self.safe_check.load(Ordering::Relaxed) ^ score_data ^ mov_data,
+            score_data,
+            mov_data,
+        )
+    }
 }
 
 pub const HASH_TYPE_EXACT: u8 = 1;
@@ -44,61 +65,137 @@ pub struct LookupResult {
 pub struct DepthPreferredHashTable {
     mod_base: u64,
     table: Vec<TableEntry>,
-    utilization_count: u64,
+    utilization_count: AtomicU64,
 }
 
 impl DepthPreferredHashTable {
     pub fn new(size: usize) -> Self {
         DepthPreferredHashTable {
             mod_base: (size - 1) as u64,
-            table: vec![TableEntry::empty(); size],
-            utilization_count: 0,
+            table: (0..size).map(|_| TableEntry::empty()).collect(),
+            utilization_count: AtomicU64::new(0),
         }
     }
 
     pub fn get(&self, key: u64, safe_check: u64) -> Option<LookupResult> {
-        let entry = &self.table[(key & self.mod_base) as usize];
+        let (entry_key, entry_safe_check, score_data, mov_data) = self.table[(key & self.mod_base) as usize].load();
 
-        if entry.key == key && entry.safe_check == safe_check {
+        if entry_key == key && entry_safe_check == safe_check {
             Some(LookupResult {
-                flag: entry.flag,
-                depth: entry.depth,
-                score: entry.score,
-                eval: entry.eval,
-                mov: entry.mov,
+                flag: (mov_data >> FLAG_SHIFT) as u8,
+                depth: (mov_data >> DEPTH_SHIFT) as u8,
+                score: (score_data & SCORE_BIT_MASK) as u32 as i32,
+                eval: (score_data >> 32) as u32 as i32,
+                mov: mov_data as u32,
             })
         } else {
             None
         }
     }
 
-    pub fn set(&mut self, key: u64, safe_check: u64, depth: u8, age: u16, flag: u8, score: i32, eval: i32, mov: u32) {        
-        let entry = &mut self.table[(key & self.mod_base) as usize];
+    pub fn set(&self, key: u64, safe_check: u64, depth: u8, age: u16, flag: u8, score: i32, eval: i32, mov: u32) {
+        let entry = &self.table[(key & self.mod_base) as usize];
+        let (_entry_key, _entry_safe_check, _score_data, entry_mov_data) = entry.load();
+
+        let entry_flag = (entry_mov_data >> FLAG_SHIFT) as u8;
+        let entry_depth = (entry_mov_data >> DEPTH_SHIFT) as u8;
+        let entry_age = (entry_mov_data >> AGE_SHIFT) as u16;
 
-        if entry.flag == 0 {
-            self.utilization_count += 1;
+        if entry_flag == 0 {
+            self.utilization_count.fetch_add(1, Ordering::Relaxed);
         }
 
-        if (depth as u16 + age) >= (entry.depth as u16 + entry.age) {
-            self.table[(key & self.mod_base) as usize] = TableEntry {
-                key,
-                safe_check,
-                flag,
-                age,
-                depth,
-                score,
-                eval,
-                mov,
-            };
+        if (depth as u16 + age) >= (entry_depth as u16 + entry_age) {
+            let score_data = score as u32 as u64 | (eval as u32 as u64) << 32;
+            let mov_data = mov as u64 | (flag as u64) << FLAG_SHIFT | (depth as u64) << DEPTH_SHIFT | (age as u64) << AGE_SHIFT;
+
+            entry.store(key, safe_check, score_data, mov_data);
+        }
+    }
+
+    pub fn remove(&self, key: u64, safe_check: u64) {
+        let entry = &self.table[(key & self.mod_base) as usize];
+        let (entry_key, entry_safe_check, _score_data, entry_mov_data) = entry.load();
+
+        if entry_key == key && entry_safe_check == safe_check {
+            if (entry_mov_data >> FLAG_SHIFT) as u8 != 0 {
+                self.utilization_count.fetch_sub(1, Ordering::Relaxed);
+            }
+
+            entry.store(0, 0, 0, 0);
         }
     }
 
     pub fn get_utilization_permill(&self) -> u64 {
-        self.utilization_count * 1000 / (self.mod_base + 1)
+        self.utilization_count.load(Ordering::Relaxed) * 1000 / (self.mod_base + 1)
+    }
+
+    pub fn clear(&self) {
+        for entry in &self.table {
+            entry.store(0, 0, 0, 0);
+        }
+
+        self.utilization_count.store(0, Ordering::Relaxed);
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_set_get() {
+        let table = DepthPreferredHashTable::new(1024);
+
+        table.set(0b1010_0000_0001, 42, 7, 3, HASH_TYPE_BETA, -19990, -125, 0x01020304);
+
+        let entry = table.get(0b1010_0000_0001, 42).unwrap();
+        assert_eq!(HASH_TYPE_BETA, entry.flag);
+        assert_eq!(7, entry.depth);
+        assert_eq!(-19990, entry.score);
+        assert_eq!(-125, entry.eval);
+        assert_eq!(0x01020304, entry.mov);
+
+        assert!(table.get(0b1010_0000_0001, 43).is_none());
+        assert!(table.get(0b1110_0000_0001, 42).is_none());
+
+        table.set(0b1010_0000_0001, 42, 2, 3, HASH_TYPE_EXACT, 10, 10, 0);
+        assert_eq!(7, table.get(0b1010_0000_0001, 42).unwrap().depth);
 
-    pub fn clear(&mut self) {
-        self.table = vec![TableEntry::empty(); self.mod_base as usize + 1];
-        self.utilization_count = 0;
+        table.remove(0b1010_0000_0001, 42);
+        assert!(table.get(0b1010_0000_0001, 42).is_none());
+        assert_eq!(0, table.get_utilization_permill());
+    }
+
+    #[test]
+    fn test_concurrent_set_get() {
+        let table = Arc::new(DepthPreferredHashTable::new(64));
+
+        let worker_list: Vec<_> = (0..4u64).map(|worker_index| {
+            let table = Arc::clone(&table);
+
+            thread::spawn(move || {
+                for round in 0..100000u64 {
+                    let key = (round % 256 + 1).wrapping_mul(0x9E3779B97F4A7C15);
+                    let safe_check = key.rotate_left(17);
+                    let score = (key >> 48) as i32 + worker_index as i32;
+
+                    table.set(key, safe_check, (round % 20) as u8, 0, HASH_TYPE_EXACT, score, score, key as u32);
+
+                    if let Some(entry) = table.get(key, safe_check) {
+                        assert_eq!(entry.score, entry.eval);
+                        assert_eq!(key as u32, entry.mov);
+                        assert!(entry.score - ((key >> 48) as i32) < 4);
+                    }
+                }
+            })
+        }).collect();
+
+        for worker in worker_list {
+            worker.join().unwrap();
+        }
     }
 }
@@ -0,0 +1,112 @@
+/*
+ * Copyright (C) 2020-2022 Zixiao Han
+ */
+
+//! FoxSEE chess engine as a library.
+//!
+//! The UCI binary is a thin client of this crate; other programs can use the
+//! same building blocks directly:
+//!
+//! ```
+//! use foxsee::{SearchEngine, State, TimeCapacity};
+//!
+//! foxsee::init();
+//!
+//! let mut state = State::new("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3");
+//! assert_eq!(27, foxsee::gen_legal_mov_list(&mut state).len());
+//!
+//! let mut search_engine = SearchEngine::new(foxsee::def::MIN_HASH_SIZE_UNIT);
+//! let time_capacity = TimeCapacity { main_time_millis: 1000 };
+//! let best_mov = search_engine.search(&mut state, time_capacity, 4, u64::MAX);
+//!
+//! println!("{}", foxsee::util::format_mov(best_mov));
+//! ```
+//!
+//! Moves are encoded as `u32` values, see [`util::encode_u32_mov`] and
+//! [`util::decode_u32_mov`].
+
+pub mod bench;
+pub mod bitbase;
+pub mod book;
+pub mod datagen;
+pub mod def;
+pub mod eval;
+pub mod mov_table;
+pub mod nnue;
+pub mod perft;
+pub mod pgn;
+pub mod search;
+pub mod selfplay;
+pub mod state;
+pub mod syzygy;
+pub mod time_control;
+pub mod tuner;
+pub mod uci;
+pub mod util;
+
+mod bitboard;
+mod bitmask;
+mod hashtable;
+mod polyglot_keys;
+mod prng;
+mod simple_rnd;
+mod zob_keys;
+
+pub use search::{SearchEngine, SearchHandle};
+pub use state::{FenError, State};
+pub use time_control::TimeCapacity;
+
+/// Initializes the Zobrist keys and the attack bitmasks.
+/// Must be called before any position is created; calls after the first one do nothing.
+pub fn init() {
+    zob_keys::init();
+    bitmask::init();
+}
+
+/// Returns all legal moves of the side to move.
+pub fn gen_legal_mov_list(state: &mut State) -> Vec<u32> {
+    mov_table::gen_legal_mov_list(state)
+}
+
+/// Returns the static evaluation of the position from the side to move's point of view.
+pub fn eval_state(state: &mut State) -> i32 {
+    let (material_score, _is_draw) = eval::eval_materials(state, &eval::DEFAULT_EVAL_PARAMS);
+    eval::eval_state(state, material_score, &eval::DEFAULT_EVAL_PARAMS)
+}
+
+/// Counts the leaf nodes of the legal move tree to the given depth.
+pub fn perft(state: &mut State, depth: u8) -> usize {
+    mov_table::perft(state, depth)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lib_api() {
+        init();
+
+        let mut state = State::new(uci::FEN_START_POS);
+
+        assert_eq!(20, gen_legal_mov_list(&mut state).len());
+        assert_eq!(8902, perft(&mut state, 3));
+        assert_eq!(eval_state(&mut state), eval_state(&mut state));
+    }
+
+    #[test]
+    fn test_init_repeated() {
+        init();
+
+        let hash_key = State::new(uci::FEN_START_POS).hash_key;
+
+        let init_thread_list: Vec<_> = (0..4).map(|_| std::thread::spawn(|| {
+            init();
+            State::new(uci::FEN_START_POS).hash_key
+        })).collect();
+
+        for init_thread in init_thread_list {
+            assert_eq!(hash_key, init_thread.join().unwrap());
+        }
+    }
+}
@@ -2,28 +2,33 @@
  * Copyright (C) 2020 Zixiao Han
  */
 
-mod bitboard;
-mod bitmask;
-mod def;
-mod eval;
-mod hashtable;
-mod mov_table;
-mod prng;
-mod search;
-mod state;
-mod simple_rnd;
-mod time_control;
-mod uci;
-mod util;
-mod zob_keys;
-
-use prng::XorshiftPrng;
-use state::State;
-use search::SearchEngine;
-use time_control::TimeCapacity;
-use uci::{UciCommand, Rawmov};
+use foxsee::{
+    bench,
+    bitbase::{self, Bitbases},
+    book::{self, Book, BookBuilder},
+    datagen::{self, DatagenConfig},
+    def,
+    eval::EvalParams,
+    nnue::Network,
+    perft::{self, PerftTable},
+    pgn,
+    selfplay::{self, DrawAdjudication, EngineConfig, GameConfig, MatchConfig, ResignAdjudication, SearchLimit, Sprt},
+    syzygy::Tablebase,
+    time_control,
+    tuner::{self, Tuner},
+    uci::{self, UciCommand, Rawmov, TimeInfo},
+    util,
+    SearchEngine,
+    State,
+    TimeCapacity,
+};
 
+use std::env;
+use std::fs;
 use std::io::{self, prelude::*};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
 use std::thread;
 use std::sync::mpsc;
 use std::time;
@@ -31,10 +36,20 @@ use std::u128;
 
 const DEFAULT_MAX_TIME: TimeCapacity = TimeCapacity {
     main_time_millis: u128::MAX,
-    extra_time_millis: 0,
 };
 
 const DEFAULT_MAX_DEPTH: u8 = 128;
+const DEFAULT_MAX_NODES: u64 = u64::MAX;
+
+const DEFAULT_MATCH_GAME_COUNT: usize = 100;
+const DEFAULT_MATCH_NODES: u64 = 10000;
+
+const DEFAULT_DATAGEN_GAME_COUNT: usize = 100;
+const DEFAULT_DATAGEN_NODES: u64 = 5000;
+
+const DEFAULT_TUNE_STEP: i32 = 4;
+const DEFAULT_TUNE_ITERATION_COUNT: usize = 100;
+
 
 fn main() {
     if 1u8 != 0b01 {
@@ -42,108 +57,217 @@ fn main() {
         std::process::exit(0);
     }
 
-    zob_keys::init();
-    bitmask::init();
+    foxsee::init();
+
+    let arg_list = env::args().skip(1).collect::<Vec<String>>();
+
+    if let Some(sub_cmd) = arg_list.first() {
+        let cmd_result = match sub_cmd.as_str() {
+            "book" => run_book_cmd(&arg_list[1..]),
+            "match" => run_match_cmd(&arg_list[1..]),
+            "tune" => run_tune_cmd(&arg_list[1..]),
+            "datagen" => run_datagen_cmd(&arg_list[1..]),
+            "bench" => run_bench_cmd(&arg_list[1..]),
+            "perftsuite" => run_perft_suite_cmd(&arg_list[1..]),
+            "help" => {
+                print_usage();
+                Ok(())
+            },
+            _ => Err(format!("unknown command {}", sub_cmd)),
+        };
+
+        if let Err(error) = cmd_result {
+            println!("{}", error);
+            print_usage();
+            std::process::exit(1);
+        }
+
+        return
+    }
 
     let (sender, receiver) = mpsc::channel();
 
+    let mut search_engine = SearchEngine::new(def::DEFAULT_HASH_SIZE_UNIT);
+    let search_handle = search_engine.get_search_handle();
+
     thread::spawn(move || {
-        let mut search_engine = SearchEngine::new(def::DEFAULT_HASH_SIZE_UNIT);
         let mut state = State::new(uci::FEN_START_POS);
+        let mut chess960 = false;
+        let mut own_book = false;
+        let mut book_file = String::new();
+        let mut bitbase_file = String::new();
+
+        // generating the bitbases takes a few seconds, so it runs alongside the handshake
+        let mut bitbase_generation = Some(thread::spawn(Bitbases::generate));
 
         loop {
             let command: String = receiver.recv().unwrap();
-            let uci_cmd_process_result = uci::process_uci_cmd(command.trim());
+            let mut uci_cmd_process_result = uci::process_uci_cmd(command.trim());
+
+            let uci_cmd_process_result = loop {
+                uci_cmd_process_result = match uci_cmd_process_result {
+                    UciCommand::RestrictSearchMovs(mov_list, search_cmd) => {
+                        let search_mov_list = mov_list.iter().filter_map(|raw_mov| find_legal_mov(&mut state, raw_mov)).collect();
+                        search_engine.set_search_movs(search_mov_list);
+                        *search_cmd
+                    },
+                    UciCommand::Ponder(search_cmd) => {
+                        search_engine.set_ponder();
+                        *search_cmd
+                    },
+                    uci_cmd => break uci_cmd,
+                }
+            };
+
+            // the bitbases are ready before the first search, so that they never appear mid-search
             match uci_cmd_process_result {
+                UciCommand::StartSearchWithTime(_) |
+                UciCommand::StartSearchWithComplextTimeControl(_) |
+                UciCommand::StartSearchToDepth(_) |
+                UciCommand::StartSearchWithLimits(..) |
+                UciCommand::StartSearchMate(_) |
+                UciCommand::StartSearchInfinite |
+                UciCommand::Bench(..) => wait_for_bitbases(&mut bitbase_generation, &bitbase_file),
+                _ => {},
+            }
+
+            match uci_cmd_process_result {
+                UciCommand::IsReady => {
+                    println!("readyok");
+                    io::stdout().flush().ok();
+                },
                 UciCommand::SetHashSize(hash_size) => {
                     search_engine.set_hash_size(hash_size);
                 },
-                UciCommand::Position(fen_str, mov_list) => {
-                    state = State::new(&fen_str);
-    
-                    if mov_list.is_empty() {
-                        continue
+                UciCommand::SetMultiPV(multi_pv) => {
+                    search_engine.set_multi_pv(multi_pv);
+                },
+                UciCommand::SetThreads(thread_count) => {
+                    search_engine.set_threads(thread_count);
+                },
+                UciCommand::SetChess960(is_chess960) => {
+                    chess960 = is_chess960;
+                    state.chess960 = is_chess960;
+                },
+                UciCommand::SetOwnBook(is_own_book) => {
+                    own_book = is_own_book;
+                    search_engine.set_book(load_book(own_book, &book_file));
+                },
+                UciCommand::SetBookFile(file_path) => {
+                    book_file = file_path;
+                    search_engine.set_book(load_book(own_book, &book_file));
+                },
+                UciCommand::SetEvalType(eval_type) => {
+                    search_engine.set_eval_type(eval_type);
+                },
+                UciCommand::SetEvalFile(file_path) => {
+                    load_eval_file(&mut search_engine, &file_path);
+                },
+                UciCommand::SetNetworkFile(file_path) => {
+                    load_network_file(&mut search_engine, &file_path);
+                },
+                UciCommand::SetSyzygyPath(syzygy_path) => {
+                    search_engine.set_tablebase(load_tablebase(&syzygy_path));
+                },
+                UciCommand::SetBitbaseFile(file_path) => {
+                    if bitbase::has_bitbases() {
+                        println!("info string bitbases are already loaded, BitbaseFile applies from the next start");
+                        io::stdout().flush().ok();
+                    } else if let Some(bitbases) = load_bitbases(&file_path) {
+                        println!("info string loaded bitbases from {}", file_path);
+                        io::stdout().flush().ok();
+                        bitbase::set_bitbases(bitbases);
                     }
-    
-                    for Rawmov { from, to, promo, origin_mov_str } in mov_list {
-                        if !promo.is_empty() {
-                            let promo_piece_code = if state.player == def::PLAYER_W {
-                                match promo.as_str() {
-                                    "q" => def::WQ,
-                                    "r" => def::WR,
-                                    "b" => def::WB,
-                                    "n" => def::WN,
-                                    _ => panic!("invalid promo piece"),
-                                }
-                            } else {
-                                match promo.as_str() {
-                                    "q" => def::BQ,
-                                    "r" => def::BR,
-                                    "b" => def::BB,
-                                    "n" => def::BN,
-                                    _ => panic!("invalid promo piece"),
-                                }
-                            };
-    
-                            state.do_mov(from, to, def::MOV_PROMO, promo_piece_code);
-                            continue
-                        }
-    
-                        let moving_piece = state.squares[from];
-                        if def::is_k(moving_piece) && ["e1g1", "e1c1", "e8g8", "e8c8"].contains(&origin_mov_str.as_str()) {
-                            state.do_mov(from, to, def::MOV_CAS, 0);
+
+                    bitbase_file = file_path;
+                },
+                UciCommand::Position(fen_str, mov_list) => {
+                    let mut new_state = match State::from_fen(&fen_str) {
+                        Ok(mut state) => {
+                            state.chess960 = chess960;
+                            state
+                        },
+                        Err(error) => {
+                            println!("info string invalid fen {}: {}", fen_str, error);
+                            io::stdout().flush().ok();
                             continue
-                        }
-    
-                        if def::is_p(moving_piece) {
-                            if to == state.enp_square {
-                                state.do_mov(from, to, def::MOV_ENP, 0);
-                                continue
-                            } else if (from as isize - to as isize).abs() == 16 {
-                                state.do_mov(from, to, def::MOV_CR_ENP, 0);
-                                continue
-                            }
-                        }
-    
-                        state.do_mov(from, to, def::MOV_REG, 0);
+                        },
+                    };
+
+                    if let Err(illegal_mov_str) = do_mov_list(&mut new_state, &mov_list) {
+                        println!("info string illegal move {} in position {}", illegal_mov_str, new_state.to_fen());
+                        io::stdout().flush().ok();
+                        continue
                     }
+
+                    state = new_state;
                 },
                 UciCommand::StartSearchWithTime(time_millis) => {    
-                    let best_mov = search_engine.search(&mut state, time_control::calculate_time_capacity(time_millis, 1, 0), DEFAULT_MAX_DEPTH);
-                    print_best_mov(best_mov);
-                },
-                UciCommand::StartSearchWithComplextTimeControl((w_time_info, b_time_info)) => {
-                    let time_capacity = if state.player == def::PLAYER_W {
-                        time_control::calculate_time_capacity(w_time_info.all_time_millis, w_time_info.moves_to_go, w_time_info.increment_millis)
-                    } else {
-                        time_control::calculate_time_capacity(b_time_info.all_time_millis, b_time_info.moves_to_go, b_time_info.increment_millis)
-                    };
-        
-                    let best_mov = search_engine.search(&mut state, time_capacity, DEFAULT_MAX_DEPTH);
-                    print_best_mov(best_mov);
+                    let best_mov = search_engine.search(&mut state, time_control::calculate_time_capacity(time_millis, 1, 0), DEFAULT_MAX_DEPTH, DEFAULT_MAX_NODES);
+                    print_best_mov(&state, best_mov, search_engine.get_ponder_mov());
+                },
+                UciCommand::StartSearchWithComplextTimeControl(time_control) => {
+                    let time_capacity = get_time_capacity(state.player, time_control);
+                    let best_mov = search_engine.search(&mut state, time_capacity, DEFAULT_MAX_DEPTH, DEFAULT_MAX_NODES);
+                    print_best_mov(&state, best_mov, search_engine.get_ponder_mov());
                 },
                 UciCommand::StartSearchToDepth(depth) => {    
-                    let best_mov = search_engine.search(&mut state, DEFAULT_MAX_TIME, depth);
-                    print_best_mov(best_mov);
+                    let best_mov = search_engine.search(&mut state, DEFAULT_MAX_TIME, depth, DEFAULT_MAX_NODES);
+                    print_best_mov(&state, best_mov, search_engine.get_ponder_mov());
+                },
+                UciCommand::StartSearchWithLimits(max_nodes, max_depth, time_millis, time_control) => {
+                    let move_time_capacity = time_millis.map(|time_millis| time_control::calculate_time_capacity(time_millis, 1, 0));
+                    let clock_time_capacity = time_control.map(|time_control| get_time_capacity(state.player, time_control));
+
+                    // the search stops at whichever time limit runs out first
+                    let time_capacity = match (move_time_capacity, clock_time_capacity) {
+                        (Some(move_time_capacity), Some(clock_time_capacity)) => {
+                            if move_time_capacity.main_time_millis <= clock_time_capacity.main_time_millis {
+                                move_time_capacity
+                            } else {
+                                clock_time_capacity
+                            }
+                        },
+                        (Some(time_capacity), None) | (None, Some(time_capacity)) => time_capacity,
+                        (None, None) => DEFAULT_MAX_TIME,
+                    };
+
+                    let best_mov = search_engine.search(&mut state, time_capacity, max_depth.unwrap_or(DEFAULT_MAX_DEPTH).min(DEFAULT_MAX_DEPTH), max_nodes);
+                    print_best_mov(&state, best_mov, search_engine.get_ponder_mov());
+                },
+                UciCommand::StartSearchMate(mate_mov_count) => {
+                    let best_mov = search_engine.search_mate(&mut state, DEFAULT_MAX_TIME, mate_mov_count, DEFAULT_MAX_NODES);
+                    print_best_mov(&state, best_mov, search_engine.get_ponder_mov());
                 },
                 UciCommand::StartSearchInfinite => {    
-                    let best_mov = search_engine.search(&mut state, DEFAULT_MAX_TIME, DEFAULT_MAX_DEPTH);
-                    print_best_mov(best_mov);
+                    let best_mov = search_engine.search(&mut state, DEFAULT_MAX_TIME, DEFAULT_MAX_DEPTH, DEFAULT_MAX_NODES);
+                    print_best_mov(&state, best_mov, search_engine.get_ponder_mov());
+                },
+                UciCommand::Bench(depth, hash_size) => {
+                    run_bench(depth, hash_size);
                 },
-                UciCommand::Perft(depth) => {    
-                    let start_time = time::Instant::now();
-                    let perft_val = search_engine.perft(&mut state, depth);
-    
-                    println!("depth {} perft {} time {} milliseconds", depth, perft_val, start_time.elapsed().as_millis());
+                UciCommand::Perft(depth, divide, hash, thread_count) => {
+                    run_perft(&mut state, depth, divide, hash, thread_count);
+                },
+                UciCommand::PerftSuite(file_path, max_depth) => {
+                    if let Err(error) = run_perft_suite(&file_path, max_depth) {
+                        println!("info string {}", error);
+                        io::stdout().flush().ok();
+                    }
                 },
                 UciCommand::PrintDebugInfo => {
                     println!("{}", &state);
+                    println!("Fen: {}", state.to_fen());
+                    println!("Key: {:016X}", state.get_zob_key());
+                    io::stdout().flush().ok();
                 },
                 UciCommand::Reset => {
                     search_engine.reset();
                 },
                 UciCommand::IgnoredOption => {},
                 UciCommand::Noop => {},
+                UciCommand::RestrictSearchMovs(_, _) => {},
+                UciCommand::Ponder(_) => {},
             }
         }
     });
@@ -157,21 +281,661 @@ fn main() {
 
         match input.trim() {
             "stop" => {
-                unsafe {
-                    search::ABORT_SEARCH = true;
-                }
+                search_handle.stop();
+            },
+            "ponderhit" => {
+                search_handle.ponder_hit();
             },
             "quit" => {
                 std::process::exit(0);
             },
-            _ => {
+            cmd => {
+                if cmd.starts_with("go") {
+                    search_handle.reset();
+                    search_handle.clear_ponder_hit();
+                }
+
                 sender.send(input).unwrap();
             }
         }
     }
 }
 
-fn print_best_mov(best_mov: u32) {
-    println!("bestmove {}", util::format_mov(best_mov));
+fn print_usage() {
+    println!("usage: foxsee                     start the UCI engine");
+    println!("       foxsee book <games.pgn> <book.bin> [--min-games N] [--max-ply N] [--min-rating N]");
+    println!("                                  build a Polyglot book from a PGN collection");
+    println!("       foxsee match [--engine1 name=X,hash=MB,threads=N,eval=<file>,network=<file>] [--engine2 ...] [--openings <file.epd>]");
+    println!("                    [--games N] [--concurrency N] [--nodes N | --depth N | --movetime MS]");
+    println!("                    [--draw-adj movenumber,movecount,score] [--resign-adj movecount,score]");
+    println!("                    [--sprt elo0,elo1[,alpha,beta]] [--pgn <file.pgn>]");
+    println!("                                  play two engine configurations against each other");
+    println!("       foxsee tune <positions.epd> [--threads N] [--limit N] [--params NAME,...] [--step N]");
+    println!("                   [--iterations N] [--eval <file>] [--output <file>]");
+    println!("                                  tune the evaluation weights on positions with game results");
+    println!("       foxsee datagen <output> [--format text|binary] [--games N] [--concurrency N]");
+    println!("                      [--nodes N | --depth N | --movetime MS] [--random-plies N] [--max-opening-score CP]");
+    println!("                      [--openings <file.epd>] [--engine hash=MB,threads=N,eval=<file>,network=<file>] [--seed N]");
+    println!("                      [--draw-adj movenumber,movecount,score] [--resign-adj movecount,score]");
+    println!("                                  write quiet positions from self-play games for training");
+    println!("       foxsee bench [depth] [hash]  search the built-in positions to a fixed depth and print the node count");
+    println!("       foxsee perftsuite <file.epd> [max depth]");
+    println!("                                  check the perft counts of every position in the suite");
+}
+
+fn run_book_cmd(arg_list: &[String]) -> Result<(), String> {
+    let (pgn_path, book_path) = match arg_list {
+        [pgn_path, book_path, ..] => (pgn_path, book_path),
+        _ => return Err("missing PGN or book file".to_owned()),
+    };
+
+    let mut min_game_count = book::DEFAULT_MIN_GAME_COUNT;
+    let mut max_ply = book::DEFAULT_MAX_PLY;
+    let mut min_rating = 0;
+
+    for option in arg_list[2..].chunks(2) {
+        let value = option.get(1).ok_or(format!("missing value for {}", option[0]))?;
+        let parse_error = |_| format!("invalid value {} for {}", value, option[0]);
+
+        match option[0].as_str() {
+            "--min-games" => min_game_count = value.parse().map_err(parse_error)?,
+            "--max-ply" => max_ply = value.parse().map_err(parse_error)?,
+            "--min-rating" => min_rating = value.parse().map_err(parse_error)?,
+            _ => return Err(format!("unknown option {}", option[0])),
+        }
+    }
+
+    let pgn_bytes = fs::read(pgn_path).map_err(|error| format!("unable to read {}: {}", pgn_path, error))?;
+    let game_list = pgn::parse_pgn(&String::from_utf8_lossy(&pgn_bytes));
+
+    let mut book_builder = BookBuilder::new(min_game_count, max_ply, min_rating);
+
+    for game in &game_list {
+        book_builder.add_game(game);
+    }
+
+    let entry_count = book_builder.write(book_path).map_err(|error| format!("unable to write {}: {}", book_path, error))?;
+
+    println!("read {} games, used {} games, wrote {} entries to {}", game_list.len(), book_builder.get_game_count(), entry_count, book_path);
+
+    Ok(())
+}
+
+fn run_match_cmd(arg_list: &[String]) -> Result<(), String> {
+    let mut match_config = MatchConfig {
+        engine_config_list: [EngineConfig::new("engine1"), EngineConfig::new("engine2")],
+        opening_fen_list: vec![uci::FEN_START_POS.to_owned()],
+        game_count: DEFAULT_MATCH_GAME_COUNT,
+        concurrency: 1,
+        game_config: GameConfig {
+            search_limit: SearchLimit::Nodes(DEFAULT_MATCH_NODES),
+            draw_adjudication: None,
+            resign_adjudication: None,
+        },
+        sprt: None,
+    };
+
+    let mut pgn_path = None;
+
+    for option in arg_list.chunks(2) {
+        let value = option.get(1).ok_or(format!("missing value for {}", option[0]))?;
+        let parse_error = |_| format!("invalid value {} for {}", value, option[0]);
+
+        match option[0].as_str() {
+            "--engine1" => parse_engine_config(&mut match_config.engine_config_list[0], value)?,
+            "--engine2" => parse_engine_config(&mut match_config.engine_config_list[1], value)?,
+            "--openings" => match_config.opening_fen_list = selfplay::read_openings(value).map_err(|error| format!("unable to read {}: {}", value, error))?,
+            "--games" => match_config.game_count = value.parse().map_err(parse_error)?,
+            "--concurrency" => match_config.concurrency = value.parse().map_err(parse_error)?,
+            "--sprt" => {
+                let value_list = parse_value_list::<f64>(option, 2, 4)?;
+                let mut sprt = Sprt::new(value_list[0], value_list[1]);
+
+                if let [_elo0, _elo1, alpha, beta] = value_list[..] {
+                    sprt.alpha = alpha;
+                    sprt.beta = beta;
+                }
+
+                match_config.sprt = Some(sprt);
+            },
+            "--pgn" => pgn_path = Some(value.clone()),
+            _ if parse_game_option(&mut match_config.game_config, option)? => {},
+            _ => return Err(format!("unknown option {}", option[0])),
+        }
+    }
+
+    if match_config.opening_fen_list.is_empty() {
+        return Err("no opening positions found".to_owned())
+    }
+
+    let mut pgn_file = match &pgn_path {
+        Some(pgn_path) => Some(fs::File::create(pgn_path).map_err(|error| format!("unable to create {}: {}", pgn_path, error))?),
+        None => None,
+    };
+
+    let [first_name, second_name] = [&match_config.engine_config_list[0].name, &match_config.engine_config_list[1].name];
+
+    bitbase::set_bitbases(Bitbases::generate());
+
+    let match_stats = selfplay::run_match(&match_config, |game_index, game_record, match_stats| {
+        let (white_name, black_name) = if game_index & 1 == 0 {
+            (first_name, second_name)
+        } else {
+            (second_name, first_name)
+        };
+
+        println!("game {} ({} vs {}): {} {{{}}}", game_index + 1, white_name, black_name, game_record.result.as_pgn_str(), game_record.termination);
+        println!("score of {} vs {}: {} - {} - {} [{:.3}] {}", first_name, second_name, match_stats.win_count, match_stats.loss_count, match_stats.draw_count, match_stats.get_score(), match_stats.get_game_count());
+        println!("elo difference: {:.1} +/- {:.1}", match_stats.get_elo(), match_stats.get_elo_error());
+
+        if let Some(sprt) = &match_config.sprt {
+            let (lower_bound, upper_bound) = sprt.get_llr_bounds();
+            println!("sprt [{:.1}, {:.1}]: llr {:.2} ({:.2}, {:.2})", sprt.elo0, sprt.elo1, match_stats.get_llr(sprt), lower_bound, upper_bound);
+        }
+
+        if let Some(pgn_file) = pgn_file.as_mut() {
+            let pgn_str = game_record.to_pgn_game(white_name, black_name, game_index + 1).to_pgn();
+
+            if let Err(error) = writeln!(pgn_file, "{}", pgn_str) {
+                println!("unable to write game {}: {}", game_index + 1, error);
+            }
+        }
+
+        io::stdout().flush().ok();
+    });
+
+    if let Some(sprt) = &match_config.sprt {
+        let llr = match_stats.get_llr(sprt);
+        let (lower_bound, upper_bound) = sprt.get_llr_bounds();
+
+        if llr >= upper_bound {
+            println!("sprt: H1 accepted");
+        } else if llr <= lower_bound {
+            println!("sprt: H0 accepted");
+        } else {
+            println!("sprt: no decision");
+        }
+    }
+
+    Ok(())
+}
+
+fn run_tune_cmd(arg_list: &[String]) -> Result<(), String> {
+    let position_path = arg_list.first().ok_or("missing position file")?;
+
+    let mut thread_count = 1;
+    let mut max_position_count = usize::MAX;
+    let mut param_name_list = Vec::new();
+    let mut initial_step = DEFAULT_TUNE_STEP;
+    let mut max_iteration_count = DEFAULT_TUNE_ITERATION_COUNT;
+    let mut eval_file = None;
+    let mut output_path = None;
+
+    for option in arg_list[1..].chunks(2) {
+        let value = option.get(1).ok_or(format!("missing value for {}", option[0]))?;
+        let parse_error = |_| format!("invalid value {} for {}", value, option[0]);
+
+        match option[0].as_str() {
+            "--threads" => thread_count = value.parse().map_err(parse_error)?,
+            "--limit" => max_position_count = value.parse().map_err(parse_error)?,
+            "--params" => param_name_list = value.split(',').map(|param_name| param_name.trim().to_owned()).collect(),
+            "--step" => initial_step = value.parse().map_err(parse_error)?,
+            "--iterations" => max_iteration_count = value.parse().map_err(parse_error)?,
+            "--eval" => eval_file = Some(value.clone()),
+            "--output" => output_path = Some(value.clone()),
+            _ => return Err(format!("unknown option {}", option[0])),
+        }
+    }
+
+    let mut eval_params = match eval_file {
+        Some(eval_file) => EvalParams::load(&eval_file).map_err(|error| format!("unable to load eval file {}: {}", eval_file, error))?,
+        None => EvalParams::default(),
+    };
+    let known_param_name_list = eval_params.get_param_list().into_iter().map(|(name, _param_list)| name).collect::<Vec<&str>>();
+
+    if let Some(unknown_param_name) = param_name_list.iter().find(|param_name| !known_param_name_list.contains(&param_name.as_str())) {
+        return Err(format!("unknown parameter {}", unknown_param_name))
+    }
+
+    let (position_list, skipped_count) = tuner::read_positions(position_path, max_position_count).map_err(|error| format!("unable to read {}: {}", position_path, error))?;
+
+    if position_list.is_empty() {
+        return Err(format!("no positions with results found in {}", position_path))
+    }
+
+    println!("loaded {} positions, skipped {} lines", position_list.len(), skipped_count);
+
+    let mut tuner = Tuner::new(position_list, thread_count);
+    let (scaling_factor, initial_error) = tuner.fit_scaling_factor(&eval_params);
+
+    println!("scaling factor {:.4}, error {:.8}", scaling_factor, initial_error);
+    io::stdout().flush().ok();
+
+    let final_error = tuner.tune(&mut eval_params, &param_name_list, initial_step, max_iteration_count, |iteration, error, step| {
+        println!("iteration {}: error {:.8}, step {}", iteration, error, step);
+        io::stdout().flush().ok();
+    });
+
+    println!("error {:.8} -> {:.8}", initial_error, final_error);
+
+    let param_str = match &output_path {
+        Some(output_path) if output_path.ends_with(".toml") => eval_params.to_toml(),
+        _ => tuner::format_eval_params(&eval_params),
+    };
+
+    match output_path {
+        Some(output_path) => {
+            fs::write(&output_path, param_str).map_err(|error| format!("unable to write {}: {}", output_path, error))?;
+            println!("wrote tuned parameters to {}", output_path);
+        },
+        None => print!("{}", param_str),
+    }
+
+    Ok(())
+}
+
+/// Applies the search limit and adjudication options shared by match and datagen.
+/// Returns false if the option is not one of them.
+fn parse_game_option(game_config: &mut GameConfig, option: &[String]) -> Result<bool, String> {
+    let parse_error = |_| format!("invalid value {} for {}", option[1], option[0]);
+
+    match option[0].as_str() {
+        "--nodes" => game_config.search_limit = SearchLimit::Nodes(option[1].parse().map_err(parse_error)?),
+        "--depth" => game_config.search_limit = SearchLimit::Depth(option[1].parse().map_err(parse_error)?),
+        "--movetime" => game_config.search_limit = SearchLimit::MoveTime(option[1].parse().map_err(parse_error)?),
+        "--draw-adj" => {
+            let value_list = parse_value_list::<i32>(option, 3, 3)?;
+
+            game_config.draw_adjudication = Some(DrawAdjudication {
+                min_full_mov_count: value_list[0].max(0) as u16,
+                mov_count: value_list[1].max(0) as usize,
+                score: value_list[2],
+            });
+        },
+        "--resign-adj" => {
+            let value_list = parse_value_list::<i32>(option, 2, 2)?;
+
+            game_config.resign_adjudication = Some(ResignAdjudication {
+                mov_count: value_list[0].max(0) as usize,
+                score: value_list[1],
+            });
+        },
+        _ => return Ok(false),
+    }
+
+    Ok(true)
+}
+
+fn run_datagen_cmd(arg_list: &[String]) -> Result<(), String> {
+    let output_path = arg_list.first().ok_or("missing output file")?;
+
+    let mut datagen_config = DatagenConfig::new(GameConfig {
+        search_limit: SearchLimit::Nodes(DEFAULT_DATAGEN_NODES),
+        draw_adjudication: None,
+        resign_adjudication: None,
+    });
+
+    datagen_config.game_count = DEFAULT_DATAGEN_GAME_COUNT;
+    datagen_config.seed = time::SystemTime::now().duration_since(time::UNIX_EPOCH).map(|duration| duration.as_nanos() as u64).unwrap_or(0);
+
+    let mut is_binary = output_path.ends_with(".bin");
+
+    for option in arg_list[1..].chunks(2) {
+        let value = option.get(1).ok_or(format!("missing value for {}", option[0]))?;
+        let parse_error = |_| format!("invalid value {} for {}", value, option[0]);
+
+        match option[0].as_str() {
+            "--format" => {
+                is_binary = match value.as_str() {
+                    "text" => false,
+                    "binary" => true,
+                    _ => return Err(format!("unknown format {}", value)),
+                };
+            },
+            "--engine" => parse_engine_config(&mut datagen_config.engine_config, value)?,
+            "--openings" => datagen_config.opening_fen_list = selfplay::read_openings(value).map_err(|error| format!("unable to read {}: {}", value, error))?,
+            "--games" => datagen_config.game_count = value.parse().map_err(parse_error)?,
+            "--concurrency" => datagen_config.concurrency = value.parse().map_err(parse_error)?,
+            "--random-plies" => datagen_config.random_ply_count = value.parse().map_err(parse_error)?,
+            "--max-opening-score" => datagen_config.max_opening_score = value.parse().map_err(parse_error)?,
+            "--seed" => datagen_config.seed = value.parse().map_err(parse_error)?,
+            _ if parse_game_option(&mut datagen_config.game_config, option)? => {},
+            _ => return Err(format!("unknown option {}", option[0])),
+        }
+    }
+
+    if datagen_config.opening_fen_list.is_empty() {
+        return Err("no opening positions found".to_owned())
+    }
+
+    let output_file = fs::File::create(output_path).map_err(|error| format!("unable to create {}: {}", output_path, error))?;
+    let mut output_writer = io::BufWriter::new(output_file);
+    let mut total_position_count = 0;
+    let mut write_result = Ok(());
+
+    bitbase::set_bitbases(Bitbases::generate());
+
+    datagen::run_datagen(&datagen_config, |game_index, game_record, position_list| {
+        if write_result.is_err() {
+            return
+        }
+
+        for position in position_list {
+            write_result = if is_binary {
+                output_writer.write_all(&position.to_bytes())
+            } else {
+                writeln!(output_writer, "{}", position.to_text())
+            };
+
+            if write_result.is_err() {
+                return
+            }
+        }
+
+        total_position_count += position_list.len();
+
+        println!("game {}: {} {{{}}}, {} positions, {} in total", game_index + 1, game_record.result.as_pgn_str(), game_record.termination, position_list.len(), total_position_count);
+        io::stdout().flush().ok();
+    });
+
+    write_result.and_then(|_| output_writer.flush()).map_err(|error| format!("unable to write {}: {}", output_path, error))?;
+    println!("wrote {} positions to {}", total_position_count, output_path);
+
+    Ok(())
+}
+
+fn run_bench_cmd(arg_list: &[String]) -> Result<(), String> {
+    let depth = match arg_list.first() {
+        Some(depth) => depth.parse::<u8>().map_err(|_| format!("invalid depth {}", depth))?.max(1),
+        None => bench::DEFAULT_BENCH_DEPTH,
+    };
+
+    let hash_size_mb = match arg_list.get(1) {
+        Some(hash_size_mb) => hash_size_mb.parse::<usize>().map_err(|_| format!("invalid hash size {}", hash_size_mb))?,
+        None => bench::DEFAULT_BENCH_HASH_SIZE_MB,
+    };
+
+    let hash_size = uci::get_hash_size(hash_size_mb).ok_or(format!("hash size {} is not supported", hash_size_mb))?;
+
+    bitbase::set_bitbases(Bitbases::generate());
+    run_bench(depth, hash_size);
+
+    Ok(())
+}
+
+fn run_bench(depth: u8, hash_size: usize) {
+    let bench_result = bench::run_bench(depth, hash_size, |fen_index, fen, node_count| {
+        println!("position {}/{} nodes {} fen {}", fen_index + 1, bench::BENCH_FEN_LIST.len(), node_count, fen);
+    });
+
+    println!("total time {} ms", bench_result.time_millis);
+    println!("nodes searched {}", bench_result.node_count);
+    println!("nps {}", bench_result.get_nps());
+    io::stdout().flush().ok();
+}
+
+fn run_perft_suite_cmd(arg_list: &[String]) -> Result<(), String> {
+    let file_path = arg_list.first().ok_or("missing perft suite file")?;
+
+    let max_depth = match arg_list.get(1) {
+        Some(max_depth) => max_depth.parse::<u8>().map_err(|_| format!("invalid depth {}", max_depth))?,
+        None => u8::MAX,
+    };
+
+    // mismatches are already reported, they are not a usage error
+    if run_perft_suite(file_path, max_depth)? > 0 {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+fn run_perft(state: &mut State, depth: u8, divide: bool, hash: bool, thread_count: usize) {
+    let start_time = time::Instant::now();
+
+    let perft_val = if depth == 0 {
+        1
+    } else {
+        let mov_count_list = perft::perft_divide_parallel(state, depth, thread_count, hash);
+
+        if divide {
+            for line in perft::format_divide(state, &mov_count_list) {
+                println!("{}", line);
+            }
+        }
+
+        mov_count_list.iter().map(|(_, count)| count).sum()
+    };
+
+    println!("depth {} perft {} time {} milliseconds", depth, perft_val, start_time.elapsed().as_millis());
+    io::stdout().flush().ok();
+}
+
+// returns the number of mismatched counts
+fn run_perft_suite(file_path: &str, max_depth: u8) -> Result<usize, String> {
+    let perft_suite = perft::read_perft_suite(file_path).map_err(|error| format!("unable to read {}: {}", file_path, error))?;
+
+    let start_time = time::Instant::now();
+    let mut perft_table = PerftTable::new(perft::DEFAULT_PERFT_TABLE_SIZE);
+    let mut count_check_count = 0;
+    let mut mismatch_count = 0;
+    let mut failed_position_list = Vec::new();
+
+    for (entry_index, perft_suite_entry) in perft_suite.iter().enumerate() {
+        let result_list = perft_suite_entry.check(max_depth, Some(&mut perft_table));
+        let mut is_mismatch = false;
+
+        for (depth, expected_count, count) in result_list.iter() {
+            if count != expected_count {
+                println!("mismatch in position {} at depth {}: expected {} but counted {} for {}", entry_index + 1, depth, expected_count, count, perft_suite_entry.fen);
+                is_mismatch = true;
+                mismatch_count += 1;
+            }
+        }
+
+        if is_mismatch {
+            failed_position_list.push((entry_index + 1).to_string());
+        }
+
+        count_check_count += result_list.len();
+        println!("position {}/{} {}", entry_index + 1, perft_suite.len(), if is_mismatch { "failed" } else { "ok" });
+        io::stdout().flush().ok();
+    }
+
+    println!("checked {} counts of {} positions with {} mismatches in {} milliseconds", count_check_count, perft_suite.len(), mismatch_count, start_time.elapsed().as_millis());
+
+    if !failed_position_list.is_empty() {
+        println!("failed positions: {}", failed_position_list.join(" "));
+    }
+
+    io::stdout().flush().ok();
+
+    Ok(mismatch_count)
+}
+
+fn parse_engine_config(engine_config: &mut EngineConfig, config_str: &str) -> Result<(), String> {
+    for config_pair in config_str.split(',') {
+        let (name, value) = config_pair.split_once('=').ok_or(format!("invalid engine option {}", config_pair))?;
+        let parse_error = |_| format!("invalid value {} for engine option {}", value, name);
+
+        match name {
+            "name" => engine_config.name = value.to_owned(),
+            "hash" => {
+                let hash_size_mb = value.parse::<usize>().map_err(parse_error)?;
+                engine_config.hash_size = uci::get_hash_size(hash_size_mb).ok_or(format!("hash size {} is not supported", hash_size_mb))?;
+            },
+            "threads" => engine_config.thread_count = value.parse::<usize>().map_err(parse_error)?.clamp(1, def::MAX_THREADS),
+            "eval" => engine_config.eval_params = EvalParams::load(value).map_err(|error| format!("unable to load eval file {}: {}", value, error))?,
+            "network" => engine_config.network = Some(Arc::new(Network::load(value).map_err(|error| format!("unable to load network {}: {}", value, error))?)),
+            _ => return Err(format!("unknown engine option {}", name)),
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_value_list<T: FromStr>(option: &[String], min_count: usize, max_count: usize) -> Result<Vec<T>, String> {
+    let value_list = option[1].split(',').map(|value| value.trim().parse::<T>()).collect::<Result<Vec<T>, _>>().map_err(|_| format!("invalid value {} for {}", option[1], option[0]))?;
+
+    if value_list.len() < min_count || value_list.len() > max_count {
+        return Err(format!("invalid value {} for {}", option[1], option[0]))
+    }
+
+    Ok(value_list)
+}
+
+fn do_mov_list(state: &mut State, mov_list: &[Rawmov]) -> Result<(), String> {
+    for raw_mov in mov_list {
+        match find_legal_mov(state, raw_mov) {
+            Some(mov) => {
+                let (from, to, tp, promo) = util::decode_u32_mov(mov);
+                state.do_mov(from, to, tp, promo);
+            },
+            None => return Err(raw_mov.origin_mov_str.clone()),
+        }
+    }
+
+    Ok(())
+}
+
+fn find_legal_mov(state: &mut State, raw_mov: &Rawmov) -> Option<u32> {
+    foxsee::gen_legal_mov_list(state).into_iter().find(|mov| state.format_mov(*mov) == raw_mov.origin_mov_str)
+}
+
+fn load_book(own_book: bool, book_file: &str) -> Option<Book> {
+    if !own_book || book_file.is_empty() || book_file == "<empty>" {
+        return None
+    }
+
+    match Book::load(book_file) {
+        Ok(book) => {
+            println!("info string loaded book {} with {} entries", book_file, book.len());
+            io::stdout().flush().ok();
+            Some(book)
+        },
+        Err(error) => {
+            println!("info string unable to load book {}: {}", book_file, error);
+            io::stdout().flush().ok();
+            None
+        },
+    }
+}
+
+fn load_tablebase(syzygy_path: &str) -> Option<Arc<Tablebase>> {
+    if syzygy_path.is_empty() || syzygy_path == "<empty>" {
+        return None
+    }
+
+    let tablebase = match Tablebase::new(syzygy_path) {
+        Ok(tablebase) => {
+            println!("info string found {} WDL and {} DTZ tablebase files up to {} pieces", tablebase.get_table_count(), tablebase.get_dtz_table_count(), tablebase.get_max_piece_count());
+            Some(Arc::new(tablebase))
+        },
+        Err(error) => {
+            println!("info string unable to read tablebases in {}: {}", syzygy_path, error);
+            None
+        },
+    };
+
+    io::stdout().flush().ok();
+
+    tablebase
+}
+
+fn load_bitbases(bitbase_file: &str) -> Option<Bitbases> {
+    if bitbase_file.is_empty() || bitbase_file == "<empty>" {
+        return None
+    }
+
+    Bitbases::load(Path::new(bitbase_file)).ok()
+}
+
+// takes the bitbases from the background generation unless they were loaded from BitbaseFile,
+// then writes them to BitbaseFile if one is set
+fn wait_for_bitbases(bitbase_generation: &mut Option<thread::JoinHandle<Bitbases>>, bitbase_file: &str) {
+    let bitbase_generation = match bitbase_generation.take() {
+        Some(bitbase_generation) => bitbase_generation,
+        None => return,
+    };
+
+    if bitbase::has_bitbases() {
+        return
+    }
+
+    if !bitbase_generation.is_finished() {
+        println!("info string generating bitbases");
+        io::stdout().flush().ok();
+    }
+
+    let bitbases = match bitbase_generation.join() {
+        Ok(bitbases) => bitbases,
+        Err(_) => return,
+    };
+
+    if !bitbase_file.is_empty() && bitbase_file != "<empty>" {
+        match bitbases.save(Path::new(bitbase_file)) {
+            Ok(_) => println!("info string wrote bitbases to {}", bitbase_file),
+            Err(error) => println!("info string unable to write bitbases to {}: {}", bitbase_file, error),
+        }
+
+        io::stdout().flush().ok();
+    }
+
+    bitbase::set_bitbases(bitbases);
+}
+
+fn load_eval_file(search_engine: &mut SearchEngine, eval_file: &str) {
+    search_engine.set_eval_params(EvalParams::default());
+
+    if eval_file.is_empty() || eval_file == "<empty>" {
+        return
+    }
+
+    match EvalParams::load(eval_file) {
+        Ok(eval_params) => {
+            println!("info string loaded eval file {}", eval_file);
+            search_engine.set_eval_params(eval_params);
+        },
+        Err(error) => println!("info string unable to load eval file {}: {}", eval_file, error),
+    }
+
+    io::stdout().flush().ok();
+}
+
+fn load_network_file(search_engine: &mut SearchEngine, network_file: &str) {
+    search_engine.set_network(None);
+
+    if network_file.is_empty() || network_file == "<empty>" {
+        return
+    }
+
+    match Network::load(network_file) {
+        Ok(network) => {
+            println!("info string loaded network {} with {} hidden neurons", network_file, network.get_hidden_size());
+            search_engine.set_network(Some(Arc::new(network)));
+        },
+        Err(error) => println!("info string unable to load network {}: {}", network_file, error),
+    }
+
+    io::stdout().flush().ok();
+}
+
+fn get_time_capacity(player: u8, (w_time_info, b_time_info): (TimeInfo, TimeInfo)) -> TimeCapacity {
+    let time_info = if player == def::PLAYER_W {
+        w_time_info
+    } else {
+        b_time_info
+    };
+
+    time_control::calculate_time_capacity(time_info.all_time_millis, time_info.moves_to_go, time_info.increment_millis)
+}
+
+fn print_best_mov(state: &State, best_mov: u32, ponder_mov: u32) {
+    if ponder_mov != 0 {
+        println!("bestmove {} ponder {}", state.format_mov(best_mov), state.format_mov(ponder_mov));
+    } else {
+        println!("bestmove {}", state.format_mov(best_mov));
+    }
+
     io::stdout().flush().ok();
 }
@@ -9,24 +9,9 @@ use crate::{
     util::{self, get_lowest_index},
 };
 
-static CAS_WK_OCCUPY_MASK: u64 = 0b00000000_00000000_00000000_00000000_00000000_00000000_00000000_10010000;
-static CAS_WK_R_MASK: u64 = 0b00000000_00000000_00000000_00000000_00000000_00000000_00000000_10000000;
-static CAS_WK_EMPTY_MASK: u64 = 0b00000000_00000000_00000000_00000000_00000000_00000000_00000000_01100000;
-
-static CAS_WQ_OCCUPY_MASK: u64 = 0b00000000_00000000_00000000_00000000_00000000_00000000_00000000_00010001;
-static CAS_WQ_R_MASK: u64 = 0b00000000_00000000_00000000_00000000_00000000_00000000_00000000_00000001;
-static CAS_WQ_EMPTY_MASK: u64 = 0b00000000_00000000_00000000_00000000_00000000_00000000_00000000_00001110;
-
-static CAS_BK_OCCUPY_MASK: u64 = 0b10010000_00000000_00000000_00000000_00000000_00000000_00000000_00000000;
-static CAS_BK_R_MASK: u64 = 0b10000000_00000000_00000000_00000000_00000000_00000000_00000000_00000000;
-static CAS_BK_EMPTY_MASK: u64 = 0b01100000_00000000_00000000_00000000_00000000_00000000_00000000_00000000;
-
-static CAS_BQ_OCCUPY_MASK: u64 = 0b00010001_00000000_00000000_00000000_00000000_00000000_00000000_00000000;
-static CAS_BQ_R_MASK: u64 = 0b00000001_00000000_00000000_00000000_00000000_00000000_00000000_00000000;
-static CAS_BQ_EMPTY_MASK: u64 = 0b00001110_00000000_00000000_00000000_00000000_00000000_00000000_00000000;
-
 static ENP_SQRS_MASK: u64 = 0b00000000_00000000_11111111_00000000_00000000_11111111_00000000_00000000;
 
+/// Generates the pseudo-legal moves of the side to move into a zero-terminated list.
 pub fn gen_reg_mov_list(state: &State, mov_list: &mut [u32; def::MAX_MOV_COUNT]) {
     let player = state.player;
     let bitboard = state.bitboard;
@@ -254,54 +239,45 @@ pub fn gen_reg_mov_list(state: &State, mov_list: &mut [u32; def::MAX_MOV_COUNT])
         }
     }
 
-    let cas_rights = state.cas_rights;
-    let bitboard = state.bitboard;
-    let all_mask = bitboard.w_all | bitboard.b_all;
-
-    if state.player == def::PLAYER_W {
-        if cas_rights & 0b1000 != 0 {
-            if bitboard.w_all & CAS_WK_OCCUPY_MASK == CAS_WK_OCCUPY_MASK
-            && bitboard.w_rook & CAS_WK_R_MASK == CAS_WK_R_MASK
-            && all_mask & CAS_WK_EMPTY_MASK == 0
-            && !is_under_attack(state, def::CAS_SQUARE_WK, def::PLAYER_W)
-            && !is_under_attack(state, def::CAS_SQUARE_WK - 1, def::PLAYER_W)
-            && !is_under_attack(state, def::CAS_SQUARE_WK - 2, def::PLAYER_W) {
-                add_mov(def::CAS_SQUARE_WK - 2, def::CAS_SQUARE_WK, def::MOV_CAS, 0);
-            }
+    if state.cas_rights != 0 {
+        gen_cas_mov_list(state, &mut add_mov);
+    }
+}
+
+// king and rook may start on any back rank square in chess960; all squares between
+// their origins and destinations must be empty, and the king must not pass an attacked square
+fn gen_cas_mov_list<F: FnMut(usize, usize, u8, u8)>(state: &State, add_mov: &mut F) {
+    let (cas_index_list, k_index, rook) = if state.player == def::PLAYER_W {
+        ([0, 1], state.wk_index, def::WR)
+    } else {
+        ([2, 3], state.bk_index, def::BR)
+    };
+
+    for cas_index in cas_index_list {
+        if state.cas_rights & def::get_cas_right(cas_index) == 0 {
+            continue
         }
 
-        if cas_rights & 0b0100 != 0 {
-            if bitboard.w_all & CAS_WQ_OCCUPY_MASK == CAS_WQ_OCCUPY_MASK
-            && bitboard.w_rook & CAS_WQ_R_MASK == CAS_WQ_R_MASK
-            && all_mask & CAS_WQ_EMPTY_MASK == 0
-            && !is_under_attack(state, def::CAS_SQUARE_WQ, def::PLAYER_W)
-            && !is_under_attack(state, def::CAS_SQUARE_WQ + 1, def::PLAYER_W)
-            && !is_under_attack(state, def::CAS_SQUARE_WQ + 2, def::PLAYER_W) {
-                add_mov(def::CAS_SQUARE_WQ + 2, def::CAS_SQUARE_WQ, def::MOV_CAS, 0);
-            }
+        let rook_from = state.cas_rook_squares[cas_index];
+        let k_to = def::CAS_KING_TO_SQUARES[cas_index];
+        let rook_to = def::CAS_ROOK_TO_SQUARES[cas_index];
+
+        if state.squares[rook_from] != rook {
+            continue
         }
-    } else {
-        if cas_rights & 0b0010 != 0 {
-            if bitboard.b_all & CAS_BK_OCCUPY_MASK == CAS_BK_OCCUPY_MASK
-            && bitboard.b_rook & CAS_BK_R_MASK == CAS_BK_R_MASK
-            && all_mask & CAS_BK_EMPTY_MASK == 0
-            && !is_under_attack(state, def::CAS_SQUARE_BK, def::PLAYER_B)
-            && !is_under_attack(state, def::CAS_SQUARE_BK - 1, def::PLAYER_B)
-            && !is_under_attack(state, def::CAS_SQUARE_BK - 2, def::PLAYER_B) {
-                add_mov(def::CAS_SQUARE_BK - 2, def::CAS_SQUARE_BK, def::MOV_CAS, 0);
-            }
+
+        let span_start = k_index.min(k_to).min(rook_from).min(rook_to);
+        let span_end = k_index.max(k_to).max(rook_from).max(rook_to);
+
+        if (span_start..=span_end).any(|index| index != k_index && index != rook_from && state.squares[index] != 0) {
+            continue
         }
 
-        if cas_rights & 0b0001 != 0 {
-            if bitboard.b_all & CAS_BQ_OCCUPY_MASK == CAS_BQ_OCCUPY_MASK
-            && bitboard.b_rook & CAS_BQ_R_MASK == CAS_BQ_R_MASK
-            && all_mask & CAS_BQ_EMPTY_MASK == 0
-            && !is_under_attack(state, def::CAS_SQUARE_BQ, def::PLAYER_B)
-            && !is_under_attack(state, def::CAS_SQUARE_BQ + 1, def::PLAYER_B)
-            && !is_under_attack(state, def::CAS_SQUARE_BQ + 2, def::PLAYER_B) {
-                add_mov(def::CAS_SQUARE_BQ + 2, def::CAS_SQUARE_BQ, def::MOV_CAS, 0);
-            }
+        if (k_index.min(k_to)..=k_index.max(k_to)).any(|index| is_under_attack(state, index, state.player)) {
+            continue
         }
+
+        add_mov(k_index, k_to, def::MOV_CAS, 0);
     }
 }
 
@@ -522,6 +498,68 @@ pub fn gen_capture_and_promo_list(state: &State, cap_list: &mut [u32; def::MAX_C
     }
 }
 
+/// Generates the legal moves of the side to move, filtering out the
+/// pseudo-legal moves of `gen_reg_mov_list` that leave the king in check.
+pub fn gen_legal_mov_list(state: &mut State) -> Vec<u32> {
+    let mut mov_list = [0; def::MAX_MOV_COUNT];
+    gen_reg_mov_list(state, &mut mov_list);
+
+    let player = state.player;
+    let mut legal_mov_list = Vec::new();
+
+    for &mov in mov_list.iter().take_while(|mov| **mov != 0) {
+        let (from, to, tp, promo) = util::decode_u32_mov(mov);
+
+        state.do_mov(from, to, tp, promo);
+
+        if !is_in_check(state, player) {
+            legal_mov_list.push(mov);
+        }
+
+        state.undo_mov(from, to, tp);
+    }
+
+    legal_mov_list
+}
+
+/// Counts the leaf nodes of the legal move tree to the given depth.
+pub fn perft(state: &mut State, depth: u8) -> usize {
+    if is_in_check(state, def::get_opposite_player(state.player)) {
+        return 0
+    }
+
+    if depth == 0 {
+        return 1
+    }
+
+    let mut node_count = 0;
+
+    let mut mov_list = [0; def::MAX_MOV_COUNT];
+
+    gen_reg_mov_list(state, &mut mov_list);
+
+    for mov_index in 0..def::MAX_MOV_COUNT {
+        let mov = mov_list[mov_index];
+
+        if mov == 0 {
+            break
+        }
+
+        let (from, to, tp, promo) = util::decode_u32_mov(mov);
+
+        if tp != def::MOV_CAS && def::is_k(state.squares[to]) {
+            return 0
+        }
+
+        state.do_mov(from, to, tp, promo);
+        node_count += perft(state, depth - 1);
+        state.undo_mov(from, to, tp);
+    }
+
+    node_count
+}
+
+/// Returns true if the king of `player` is attacked.
 pub fn is_in_check(state: &State, player: u8) -> bool {
     let k_index = if player == def::PLAYER_W {
         state.wk_index
@@ -0,0 +1,399 @@
+/*
+ * Copyright (C) 2020-2022 Zixiao Han
+ */
+
+//! NNUE evaluation with a 768 -> N -> 1 network and incrementally updated accumulators.
+//!
+//! A network file starts with the magic `FXNN`, followed by the format version and the
+//! hidden layer size N as little-endian `u32`, then little-endian `i16` values:
+//! the 768 x N feature weights (feature-major), the N feature biases, the 2N output
+//! weights (side to move first) and the output bias.
+//! Features are indexed by `color * 384 + piece_type * 64 + square`, seen from each side,
+//! where color 0 is the side of the perspective and squares are flipped for Black.
+
+use crate::def;
+
+use std::convert::TryInto;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+pub const NETWORK_MAGIC: &[u8; 4] = b"FXNN";
+pub const NETWORK_VERSION: u32 = 1;
+
+pub const INPUT_SIZE: usize = 768;
+pub const MAX_HIDDEN_SIZE: usize = 4096;
+
+const HEADER_SIZE: usize = 12;
+
+const QA: i32 = 255;
+const QB: i32 = 64;
+const EVAL_SCALE: i32 = 400;
+
+const COLOR_FEATURE_SIZE: usize = 384;
+const MIRROR_MASK: usize = 0b111000;
+
+/// The quantized weights of a network.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Network {
+    hidden_size: usize,
+    feature_weights: Vec<i16>,
+    feature_biases: Vec<i16>,
+    output_weights: Vec<i16>,
+    output_bias: i16,
+    use_avx2: bool,
+}
+
+impl Network {
+    /// Reads a network from disk.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Network::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        if bytes.len() < HEADER_SIZE || &bytes[0..4] != NETWORK_MAGIC {
+            return Err(invalid_data("not a network file".to_string()))
+        }
+
+        let version = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+        if version != NETWORK_VERSION {
+            return Err(invalid_data(format!("unsupported network version {}", version)))
+        }
+
+        let hidden_size = u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as usize;
+        if hidden_size == 0 || hidden_size > MAX_HIDDEN_SIZE {
+            return Err(invalid_data(format!("unsupported hidden layer size {}", hidden_size)))
+        }
+
+        let value_count = INPUT_SIZE * hidden_size + hidden_size + 2 * hidden_size + 1;
+        let expected_size = HEADER_SIZE + value_count * 2;
+        if bytes.len() != expected_size {
+            return Err(invalid_data(format!("expected {} bytes for hidden layer size {} but found {}", expected_size, hidden_size, bytes.len())))
+        }
+
+        let mut value_list = bytes[HEADER_SIZE..].chunks_exact(2).map(|value_bytes| i16::from_le_bytes([value_bytes[0], value_bytes[1]]));
+        let mut take_values = |count: usize| value_list.by_ref().take(count).collect::<Vec<i16>>();
+
+        let feature_weights = take_values(INPUT_SIZE * hidden_size);
+        let feature_biases = take_values(hidden_size);
+        let output_weights = take_values(2 * hidden_size);
+        let output_bias = take_values(1)[0];
+
+        Ok(Network::new(hidden_size, feature_weights, feature_biases, output_weights, output_bias))
+    }
+
+    pub fn new(hidden_size: usize, feature_weights: Vec<i16>, feature_biases: Vec<i16>, output_weights: Vec<i16>, output_bias: i16) -> Self {
+        assert_eq!(INPUT_SIZE * hidden_size, feature_weights.len());
+        assert_eq!(hidden_size, feature_biases.len());
+        assert_eq!(2 * hidden_size, output_weights.len());
+
+        Network {
+            hidden_size,
+            feature_weights,
+            feature_biases,
+            output_weights,
+            output_bias,
+            use_avx2: has_avx2(),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + (self.feature_weights.len() + 3 * self.hidden_size + 1) * 2);
+
+        bytes.extend_from_slice(NETWORK_MAGIC);
+        bytes.extend_from_slice(&NETWORK_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(self.hidden_size as u32).to_le_bytes());
+
+        for value in self.feature_weights.iter().chain(&self.feature_biases).chain(&self.output_weights).chain(std::iter::once(&self.output_bias)) {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+
+        bytes
+    }
+
+    pub fn get_hidden_size(&self) -> usize {
+        self.hidden_size
+    }
+
+    fn get_feature_weights(&self, feature_index: usize) -> &[i16] {
+        &self.feature_weights[feature_index * self.hidden_size..(feature_index + 1) * self.hidden_size]
+    }
+
+    fn evaluate(&self, player_acc: &[i16], opponent_acc: &[i16]) -> i32 {
+        let (player_weights, opponent_weights) = self.output_weights.split_at(self.hidden_size);
+        let output = dot_crelu(player_acc, player_weights, self.use_avx2) + dot_crelu(opponent_acc, opponent_weights, self.use_avx2) + self.output_bias as i32;
+
+        output * EVAL_SCALE / (QA * QB)
+    }
+}
+
+/// The hidden layer of every position along the current line, White's half first.
+#[derive(Clone)]
+pub struct Accumulator {
+    network: Arc<Network>,
+    acc_stack: Vec<Vec<i16>>,
+    stack_len: usize,
+}
+
+impl Accumulator {
+    pub fn new(network: Arc<Network>, squares: &[u8; def::BOARD_SIZE]) -> Self {
+        let mut accumulator = Accumulator {
+            acc_stack: vec![vec![0; 2 * network.hidden_size]],
+            stack_len: 1,
+            network,
+        };
+
+        accumulator.refresh(squares);
+
+        accumulator
+    }
+
+    pub fn get_network(&self) -> &Arc<Network> {
+        &self.network
+    }
+
+    /// Recomputes the accumulator from scratch, dropping the saved states.
+    pub fn refresh(&mut self, squares: &[u8; def::BOARD_SIZE]) {
+        self.stack_len = 1;
+
+        let hidden_size = self.network.hidden_size;
+        let acc = &mut self.acc_stack[0];
+        acc[..hidden_size].copy_from_slice(&self.network.feature_biases);
+        acc[hidden_size..].copy_from_slice(&self.network.feature_biases);
+
+        for (index, &piece) in squares.iter().enumerate() {
+            if piece != 0 {
+                update_features(&self.network, acc, piece, index, add_weights);
+            }
+        }
+    }
+
+    /// Saves the current state and applies the difference between two boards.
+    pub fn push_mov(&mut self, prev_squares: &[u8; def::BOARD_SIZE], squares: &[u8; def::BOARD_SIZE]) {
+        if self.stack_len == self.acc_stack.len() {
+            self.acc_stack.push(self.acc_stack[self.stack_len - 1].clone());
+        } else {
+            let (prev_acc_list, acc_list) = self.acc_stack.split_at_mut(self.stack_len);
+            acc_list[0].copy_from_slice(&prev_acc_list[self.stack_len - 1]);
+        }
+
+        let acc = &mut self.acc_stack[self.stack_len];
+        self.stack_len += 1;
+
+        for index in 0..def::BOARD_SIZE {
+            let (prev_piece, piece) = (prev_squares[index], squares[index]);
+
+            if prev_piece == piece {
+                continue
+            }
+
+            if prev_piece != 0 {
+                update_features(&self.network, acc, prev_piece, index, sub_weights);
+            }
+
+            if piece != 0 {
+                update_features(&self.network, acc, piece, index, add_weights);
+            }
+        }
+    }
+
+    /// Restores the state saved by the last `push_mov`; returns false if there is none.
+    pub fn pop_mov(&mut self) -> bool {
+        if self.stack_len == 1 {
+            return false
+        }
+
+        self.stack_len -= 1;
+
+        true
+    }
+
+    /// Returns the score from the point of view of `player`.
+    pub fn evaluate(&self, player: u8) -> i32 {
+        let (w_acc, b_acc) = self.acc_stack[self.stack_len - 1].split_at(self.network.hidden_size);
+
+        if player == def::PLAYER_W {
+            self.network.evaluate(w_acc, b_acc)
+        } else {
+            self.network.evaluate(b_acc, w_acc)
+        }
+    }
+}
+
+fn update_features(network: &Network, acc: &mut [i16], piece: u8, index: usize, update: fn(&mut [i16], &[i16])) {
+    let (w_acc, b_acc) = acc.split_at_mut(network.hidden_size);
+    let (w_feature_index, b_feature_index) = get_feature_indexes(piece, index);
+
+    update(w_acc, network.get_feature_weights(w_feature_index));
+    update(b_acc, network.get_feature_weights(b_feature_index));
+}
+
+#[inline]
+fn get_feature_indexes(piece: u8, index: usize) -> (usize, usize) {
+    let piece_feature_index = def::get_piece_type_index(piece) * def::BOARD_SIZE;
+
+    if def::on_same_side(def::PLAYER_W, piece) {
+        (piece_feature_index + index, COLOR_FEATURE_SIZE + piece_feature_index + (index ^ MIRROR_MASK))
+    } else {
+        (COLOR_FEATURE_SIZE + piece_feature_index + index, piece_feature_index + (index ^ MIRROR_MASK))
+    }
+}
+
+#[inline]
+fn add_weights(acc: &mut [i16], weights: &[i16]) {
+    for (acc_value, weight) in acc.iter_mut().zip(weights) {
+        *acc_value = acc_value.wrapping_add(*weight);
+    }
+}
+
+#[inline]
+fn sub_weights(acc: &mut [i16], weights: &[i16]) {
+    for (acc_value, weight) in acc.iter_mut().zip(weights) {
+        *acc_value = acc_value.wrapping_sub(*weight);
+    }
+}
+
+fn dot_crelu(acc: &[i16], weights: &[i16], use_avx2: bool) -> i32 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if use_avx2 {
+            return unsafe { dot_crelu_avx2(acc, weights) }
+        }
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    let _ = use_avx2;
+
+    dot_crelu_scalar(acc, weights)
+}
+
+fn dot_crelu_scalar(acc: &[i16], weights: &[i16]) -> i32 {
+    acc.iter().zip(weights).map(|(acc_value, weight)| (*acc_value as i32).clamp(0, QA) * *weight as i32).sum()
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn dot_crelu_avx2(acc: &[i16], weights: &[i16]) -> i32 {
+    use std::arch::x86_64::*;
+
+    const LANE_COUNT: usize = 16;
+
+    let chunk_count = acc.len() / LANE_COUNT;
+    let zero = _mm256_setzero_si256();
+    let qa = _mm256_set1_epi16(QA as i16);
+    let mut sum = _mm256_setzero_si256();
+
+    for chunk_index in 0..chunk_count {
+        let acc_values = _mm256_loadu_si256(acc.as_ptr().add(chunk_index * LANE_COUNT) as *const __m256i);
+        let weight_values = _mm256_loadu_si256(weights.as_ptr().add(chunk_index * LANE_COUNT) as *const __m256i);
+        let clamped_values = _mm256_min_epi16(_mm256_max_epi16(acc_values, zero), qa);
+        sum = _mm256_add_epi32(sum, _mm256_madd_epi16(clamped_values, weight_values));
+    }
+
+    let mut lane_sum_list = [0i32; 8];
+    _mm256_storeu_si256(lane_sum_list.as_mut_ptr() as *mut __m256i, sum);
+
+    let tail_index = chunk_count * LANE_COUNT;
+    lane_sum_list.iter().sum::<i32>() + dot_crelu_scalar(&acc[tail_index..], &weights[tail_index..])
+}
+
+fn has_avx2() -> bool {
+    #[cfg(target_arch = "x86_64")]
+    {
+        is_x86_feature_detected!("avx2")
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        false
+    }
+}
+
+fn invalid_data(reason: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bitmask,
+        mov_table,
+        prng::XorshiftPrng,
+        state::State,
+        util,
+        zob_keys,
+    };
+
+    fn gen_test_network(hidden_size: usize) -> Network {
+        let mut prng = XorshiftPrng::with_seed(7);
+        let mut gen_values = |count: usize, range: u64| (0..count).map(|_| (prng.gen_rand() % (2 * range + 1)) as i16 - range as i16).collect::<Vec<i16>>();
+
+        let feature_weights = gen_values(INPUT_SIZE * hidden_size, 40);
+        let feature_biases = gen_values(hidden_size, 80);
+        let output_weights = gen_values(2 * hidden_size, 60);
+
+        Network::new(hidden_size, feature_weights, feature_biases, output_weights, 25)
+    }
+
+    #[test]
+    fn test_network_bytes() {
+        let network = gen_test_network(20);
+        let bytes = network.to_bytes();
+
+        assert_eq!(network, Network::from_bytes(&bytes).unwrap());
+        assert!(Network::from_bytes(&bytes[..bytes.len() - 2]).is_err());
+        assert!(Network::from_bytes(b"FXNB").is_err());
+    }
+
+    #[test]
+    fn test_dot_crelu() {
+        let acc: Vec<i16> = (0..40).map(|value| value * 17 - 300).collect();
+        let weights: Vec<i16> = (0..40).map(|value| 20 - value).collect();
+
+        assert_eq!(dot_crelu_scalar(&acc, &weights), dot_crelu(&acc, &weights, has_avx2()));
+    }
+
+    #[test]
+    fn test_incremental_update() {
+        zob_keys::init();
+        bitmask::init();
+
+        let network = Arc::new(gen_test_network(32));
+        let mut state = State::new("r3k2r/pPpp1ppp/8/3Pp3/8/8/PPP2PPP/R3K2R w KQkq e6 0 1");
+        state.set_network(Some(Arc::clone(&network)));
+
+        let start_w_score = state.nnue.as_ref().unwrap().evaluate(def::PLAYER_W);
+        let mut played_mov_list = Vec::new();
+
+        for mov_str in ["d5e6", "e8g8", "b7a8q", "d7d5", "e1c1"] {
+            let mov = mov_table::gen_legal_mov_list(&mut state).into_iter().find(|mov| state.format_mov(*mov) == mov_str).unwrap();
+            let (from, to, tp, promo) = util::decode_u32_mov(mov);
+            state.do_mov(from, to, tp, promo);
+            played_mov_list.push(mov);
+
+            let full_acc = Accumulator::new(Arc::clone(&network), &state.squares);
+            assert_eq!(full_acc.evaluate(state.player), state.nnue.as_ref().unwrap().evaluate(state.player));
+        }
+
+        for mov in played_mov_list.into_iter().rev() {
+            let (from, to, tp, _promo) = util::decode_u32_mov(mov);
+            state.undo_mov(from, to, tp);
+        }
+
+        assert_eq!(start_w_score, state.nnue.as_ref().unwrap().evaluate(def::PLAYER_W));
+    }
+
+    #[test]
+    fn test_symmetric_eval() {
+        zob_keys::init();
+        bitmask::init();
+
+        let network = Arc::new(gen_test_network(16));
+        let w_acc = Accumulator::new(Arc::clone(&network), &State::new("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3").squares);
+        let b_acc = Accumulator::new(network, &State::new("rnbqkb1r/pppp1ppp/5n2/4p3/4P3/2N5/PPPP1PPP/R1BQKBNR b KQkq - 2 3").squares);
+
+        assert_eq!(w_acc.evaluate(def::PLAYER_W), b_acc.evaluate(def::PLAYER_B));
+    }
+}
@@ -0,0 +1,375 @@
+/*
+ * Copyright (C) 2020-2022 Zixiao Han
+ */
+
+//! Tools for checking the move generator: perft counts per root move, a hash table for
+//! deep counts, and the standard perft suites in EPD format.
+//!
+//! A suite line is a FEN, with or without the move counters, followed by the expected counts
+//! as `;D<depth> <count>` fields, e.g. `8/8/8/8/8/8/8/K6k w - - ;D1 3 ;D2 9`.
+
+use crate::{
+    mov_table,
+    state::State,
+    util,
+};
+
+use std::fs;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+pub const DEFAULT_PERFT_TABLE_SIZE: usize = 1 << 20;
+
+#[derive(Clone, Copy)]
+struct PerftEntry {
+    key: u64,
+    depth: u8,
+    count: usize,
+}
+
+/// A transposition table of perft counts, replacing entries on every store.
+pub struct PerftTable {
+    entry_list: Vec<PerftEntry>,
+    mask: usize,
+}
+
+impl PerftTable {
+    /// Creates a table of `size` entries, which must be a power of two.
+    pub fn new(size: usize) -> Self {
+        PerftTable {
+            entry_list: vec![PerftEntry { key: 0, depth: 0, count: 0 }; size],
+            mask: size - 1,
+        }
+    }
+
+    fn get(&self, key: u64, depth: u8) -> Option<usize> {
+        let entry = &self.entry_list[key as usize & self.mask];
+
+        if entry.key == key && entry.depth == depth {
+            Some(entry.count)
+        } else {
+            None
+        }
+    }
+
+    fn set(&mut self, key: u64, depth: u8, count: usize) {
+        self.entry_list[key as usize & self.mask] = PerftEntry {
+            key,
+            depth,
+            count,
+        };
+    }
+}
+
+/// Counts the leaf nodes of the legal move tree to the given depth, reusing the counts of
+/// transposed positions.
+pub fn perft_hashed(state: &mut State, depth: u8, perft_table: &mut PerftTable) -> usize {
+    if depth == 0 {
+        return 1
+    }
+
+    let key = state.get_zob_key();
+
+    if let Some(count) = perft_table.get(key, depth) {
+        return count
+    }
+
+    let mov_list = mov_table::gen_legal_mov_list(state);
+
+    let count = if depth == 1 {
+        mov_list.len()
+    } else {
+        mov_list.into_iter().map(|mov| {
+            let (from, to, tp, promo) = util::decode_u32_mov(mov);
+
+            state.do_mov(from, to, tp, promo);
+            let count = perft_hashed(state, depth - 1, perft_table);
+            state.undo_mov(from, to, tp);
+
+            count
+        }).sum()
+    };
+
+    perft_table.set(key, depth, count);
+
+    count
+}
+
+/// Counts the leaf nodes of the legal move tree below each legal move, with the hash table if given.
+pub fn perft_divide(state: &mut State, depth: u8, mut perft_table: Option<&mut PerftTable>) -> Vec<(u32, usize)> {
+    if depth == 0 {
+        return Vec::new()
+    }
+
+    mov_table::gen_legal_mov_list(state).into_iter().map(|mov| {
+        let (from, to, tp, promo) = util::decode_u32_mov(mov);
+
+        state.do_mov(from, to, tp, promo);
+
+        let count = match perft_table.as_deref_mut() {
+            Some(perft_table) => perft_hashed(state, depth - 1, perft_table),
+            None => mov_table::perft(state, depth - 1),
+        };
+
+        state.undo_mov(from, to, tp);
+
+        (mov, count)
+    }).collect()
+}
+
+/// Counts the leaf nodes of the legal move tree to the given depth, counting the legal moves
+/// instead of making them at the last ply.
+pub fn perft_bulk(state: &mut State, depth: u8) -> usize {
+    if depth == 0 {
+        return 1
+    }
+
+    let mov_list = mov_table::gen_legal_mov_list(state);
+
+    if depth == 1 {
+        return mov_list.len()
+    }
+
+    mov_list.into_iter().map(|mov| {
+        let (from, to, tp, promo) = util::decode_u32_mov(mov);
+
+        state.do_mov(from, to, tp, promo);
+        let count = perft_bulk(state, depth - 1);
+        state.undo_mov(from, to, tp);
+
+        count
+    }).sum()
+}
+
+/// Counts the leaf nodes below each legal move like `perft_divide`, with the root moves split
+/// across `thread_count` threads working on their own copy of the state.
+/// With `hash`, each thread keeps its own hash table.
+pub fn perft_divide_parallel(state: &mut State, depth: u8, thread_count: usize, hash: bool) -> Vec<(u32, usize)> {
+    if depth == 0 {
+        return Vec::new()
+    }
+
+    let mov_list = mov_table::gen_legal_mov_list(state);
+    let thread_count = thread_count.clamp(1, mov_list.len().max(1));
+    let next_mov_index = AtomicUsize::new(0);
+
+    let mut count_list = vec![0; mov_list.len()];
+
+    thread::scope(|scope| {
+        let handle_list = (0..thread_count).map(|_| {
+            let mut thread_state = state.clone();
+            let mov_list = &mov_list;
+            let next_mov_index = &next_mov_index;
+
+            scope.spawn(move || {
+                let mut perft_table = if hash { Some(PerftTable::new((DEFAULT_PERFT_TABLE_SIZE / thread_count).next_power_of_two())) } else { None };
+                let mut index_count_list = Vec::new();
+
+                loop {
+                    let mov_index = next_mov_index.fetch_add(1, Ordering::Relaxed);
+
+                    if mov_index >= mov_list.len() {
+                        break
+                    }
+
+                    let (from, to, tp, promo) = util::decode_u32_mov(mov_list[mov_index]);
+
+                    thread_state.do_mov(from, to, tp, promo);
+
+                    let count = match perft_table.as_mut() {
+                        Some(perft_table) => perft_hashed(&mut thread_state, depth - 1, perft_table),
+                        None => perft_bulk(&mut thread_state, depth - 1),
+                    };
+
+                    thread_state.undo_mov(from, to, tp);
+
+                    index_count_list.push((mov_index, count));
+                }
+
+                index_count_list
+            })
+        }).collect::<Vec<_>>();
+
+        for handle in handle_list {
+            for (mov_index, count) in handle.join().unwrap() {
+                count_list[mov_index] = count;
+            }
+        }
+    });
+
+    mov_list.into_iter().zip(count_list).collect()
+}
+
+/// A position of a perft suite with its expected counts.
+#[derive(Debug)]
+pub struct PerftSuiteEntry {
+    pub fen: String,
+    pub depth_count_list: Vec<(u8, usize)>,
+}
+
+impl PerftSuiteEntry {
+    /// Counts the position to every expected depth up to `max_depth`,
+    /// returning the depth, expected and actual count of each.
+    pub fn check(&self, max_depth: u8, mut perft_table: Option<&mut PerftTable>) -> Vec<(u8, usize, usize)> {
+        let mut state = State::new(&self.fen);
+
+        self.depth_count_list.iter().filter(|(depth, _)| *depth <= max_depth).map(|(depth, expected_count)| {
+            let count = match perft_table.as_deref_mut() {
+                Some(perft_table) => perft_hashed(&mut state, *depth, perft_table),
+                None => mov_table::perft(&mut state, *depth),
+            };
+
+            (*depth, *expected_count, count)
+        }).collect()
+    }
+}
+
+/// Reads a perft suite in EPD format, skipping empty lines and lines starting with `#`.
+pub fn read_perft_suite(file_path: &str) -> io::Result<Vec<PerftSuiteEntry>> {
+    parse_perft_suite(&fs::read_to_string(file_path)?)
+}
+
+pub fn parse_perft_suite(epd_str: &str) -> io::Result<Vec<PerftSuiteEntry>> {
+    let mut perft_suite = Vec::new();
+
+    for (line_index, line) in epd_str.lines().enumerate() {
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue
+        }
+
+        let invalid_line_error = |reason: String| io::Error::new(io::ErrorKind::InvalidData, format!("invalid perft suite line {}: {}", line_index + 1, reason));
+
+        let mut field_iter = line.split(';');
+        let fen_field_list = field_iter.next().unwrap_or_default().split_whitespace().collect::<Vec<&str>>();
+
+        let fen = if fen_field_list.len() == 4 {
+            format!("{} 0 1", fen_field_list.join(" "))
+        } else {
+            fen_field_list.join(" ")
+        };
+
+        if let Err(error) = State::from_fen(&fen) {
+            return Err(invalid_line_error(error.to_string()))
+        }
+
+        let mut depth_count_list = Vec::new();
+
+        for depth_count_field in field_iter.map(str::trim).filter(|field| !field.is_empty()) {
+            let depth_count = depth_count_field.strip_prefix('D')
+                .and_then(|depth_count| depth_count.split_once(char::is_whitespace))
+                .and_then(|(depth, count)| Some((depth.parse::<u8>().ok()?, count.trim().parse::<usize>().ok()?)));
+
+            match depth_count {
+                Some(depth_count) => depth_count_list.push(depth_count),
+                None => return Err(invalid_line_error(format!("expected D<depth> <count> but found {}", depth_count_field))),
+            }
+        }
+
+        perft_suite.push(PerftSuiteEntry {
+            fen,
+            depth_count_list,
+        });
+    }
+
+    Ok(perft_suite)
+}
+
+/// Formats the counts of each root move in the order of their UCI notation.
+pub fn format_divide(state: &State, mov_count_list: &[(u32, usize)]) -> Vec<String> {
+    let mut line_list = mov_count_list.iter().map(|(mov, count)| format!("{}: {}", state.format_mov(*mov), count)).collect::<Vec<String>>();
+    line_list.sort();
+    line_list
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bitmask,
+        zob_keys,
+    };
+
+    #[test]
+    fn test_perft_hashed() {
+        zob_keys::init();
+        bitmask::init();
+
+        let mut perft_table = PerftTable::new(1 << 16);
+
+        let mut state = State::new("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        assert_eq!(48, perft_hashed(&mut state, 1, &mut perft_table));
+        assert_eq!(2039, perft_hashed(&mut state, 2, &mut perft_table));
+        assert_eq!(97862, perft_hashed(&mut state, 3, &mut perft_table));
+        assert_eq!(4085603, perft_hashed(&mut state, 4, &mut perft_table));
+        assert_eq!("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", state.to_fen());
+
+        let mut state = State::new("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1");
+        assert_eq!(674624, perft_hashed(&mut state, 5, &mut perft_table));
+    }
+
+    #[test]
+    fn test_perft_divide() {
+        zob_keys::init();
+        bitmask::init();
+
+        let mut state = State::new("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        let mov_count_list = perft_divide(&mut state, 3, None);
+
+        assert_eq!(20, mov_count_list.len());
+        assert_eq!(8902, mov_count_list.iter().map(|(_, count)| count).sum::<usize>());
+
+        let line_list = format_divide(&state, &mov_count_list);
+        assert_eq!("a2a3: 380", line_list[0]);
+        assert_eq!("e2e4: 600", line_list[line_list.iter().position(|line| line.starts_with("e2e4")).unwrap()]);
+
+        let mut perft_table = PerftTable::new(1024);
+        assert_eq!(mov_count_list, perft_divide(&mut state, 3, Some(&mut perft_table)));
+        assert!(perft_divide(&mut state, 0, None).is_empty());
+    }
+
+    #[test]
+    fn test_perft_divide_parallel() {
+        zob_keys::init();
+        bitmask::init();
+
+        let mut state = State::new("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        let mov_count_list = perft_divide(&mut state, 3, None);
+
+        assert_eq!(mov_count_list, perft_divide_parallel(&mut state, 3, 4, false));
+        assert_eq!(mov_count_list, perft_divide_parallel(&mut state, 3, 3, true));
+        assert_eq!(mov_count_list, perft_divide_parallel(&mut state, 3, 1, false));
+        assert_eq!(97862, mov_count_list.iter().map(|(_, count)| count).sum::<usize>());
+
+        let mut state = State::new("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9");
+        assert_eq!(326672, perft_divide_parallel(&mut state, 4, 8, false).iter().map(|(_, count)| count).sum::<usize>());
+        assert_eq!(326672, perft_bulk(&mut state, 4));
+
+        assert!(perft_divide_parallel(&mut state, 0, 4, false).is_empty());
+    }
+
+    #[test]
+    fn test_parse_perft_suite() {
+        zob_keys::init();
+        bitmask::init();
+
+        let epd_str = "# comment\n\
+            rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - ;D1 20 ;D2 400 ;D3 8902\n\
+            \n\
+            bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9 ;D1 21 ;D2 528 ;D3 12189\n\
+            8/8/8/8/8/8/8/K6k w - - ;D1 3 ;D2 10\n";
+
+        let perft_suite = parse_perft_suite(epd_str).unwrap();
+        assert_eq!(3, perft_suite.len());
+        assert_eq!("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", perft_suite[0].fen);
+        assert_eq!(vec![(1, 20), (2, 400), (3, 8902)], perft_suite[0].depth_count_list);
+
+        assert_eq!(vec![(1, 21, 21), (2, 528, 528)], perft_suite[1].check(2, None));
+
+        let mut perft_table = PerftTable::new(1024);
+        assert_eq!(vec![(1, 3, 3), (2, 10, 9)], perft_suite[2].check(3, Some(&mut perft_table)));
+
+        assert!(parse_perft_suite("8/8/8/8/8/8/8/K6k w - - ;D1 three").is_err());
+        assert!(parse_perft_suite("8/8/8/8/8/8/8/K6 w - - ;D1 3").is_err());
+    }
+}
@@ -0,0 +1,630 @@
+/*
+ * Copyright (C) 2020-2022 Zixiao Han
+ */
+
+//! Reading and writing games in Portable Game Notation.
+
+use crate::{
+    def,
+    mov_table,
+    state::State,
+    util,
+};
+
+const RESULT_LIST: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+const SUFFIX_ANNOTATION_LIST: [(&str, u8); 6] = [("!", 1), ("?", 2), ("!!", 3), ("??", 4), ("!?", 5), ("?!", 6)];
+
+const MAX_LINE_LEN: usize = 79;
+
+/// A move of a game or variation, with its NAGs, the comment following it and
+/// the variations that may be played instead of it.
+#[derive(Debug, Default, PartialEq)]
+pub struct PgnMov {
+    pub san: String,
+    pub nag_list: Vec<u8>,
+    pub comment: Option<String>,
+    pub variation_list: Vec<Vec<PgnMov>>,
+}
+
+impl PgnMov {
+    pub fn new(san: &str) -> Self {
+        PgnMov {
+            san: san.to_owned(),
+            ..Default::default()
+        }
+    }
+}
+
+/// A game read from PGN: its tag pairs, the comment before the first move, the moves and the result.
+#[derive(Debug, Default, PartialEq)]
+pub struct PgnGame {
+    pub tag_list: Vec<(String, String)>,
+    pub comment: Option<String>,
+    pub mov_list: Vec<PgnMov>,
+    pub result: String,
+}
+
+impl PgnGame {
+    pub fn get_tag(&self, tag_name: &str) -> Option<&str> {
+        self.tag_list.iter().find(|(name, _value)| name == tag_name).map(|(_name, value)| value.as_str())
+    }
+
+    /// Returns the starting FEN of the game, the standard start position unless a FEN tag is given.
+    pub fn get_start_fen(&self) -> &str {
+        self.get_tag("FEN").unwrap_or(crate::uci::FEN_START_POS)
+    }
+
+    /// Writes the game in PGN export format, move text wrapped at 79 characters.
+    pub fn to_pgn(&self) -> String {
+        let mut pgn_str = String::new();
+
+        for (name, value) in &self.tag_list {
+            pgn_str.push_str(&format!("[{} \"{}\"]\n", name, value.replace('\\', "\\\\").replace('"', "\\\"")));
+        }
+
+        if !self.tag_list.is_empty() {
+            pgn_str.push('\n');
+        }
+
+        let mut token_list = Vec::new();
+
+        if let Some(comment) = &self.comment {
+            token_list.push(format!("{{{}}}", comment));
+        }
+
+        write_mov_list(&self.mov_list, get_start_ply(self.get_start_fen()), &mut token_list);
+
+        token_list.push(if RESULT_LIST.contains(&self.result.as_str()) {
+            self.result.clone()
+        } else {
+            "*".to_owned()
+        });
+
+        let mut line_len = 0;
+
+        for token in token_list {
+            if line_len > 0 && line_len + 1 + token.len() > MAX_LINE_LEN {
+                pgn_str.push('\n');
+                line_len = 0;
+            } else if line_len > 0 {
+                pgn_str.push(' ');
+                line_len += 1;
+            }
+
+            pgn_str.push_str(&token);
+            line_len += token.len();
+        }
+
+        pgn_str.push('\n');
+
+        pgn_str
+    }
+}
+
+/// Splits a PGN collection into games.
+/// Comments before the first move of a variation and unterminated variations are dropped.
+pub fn parse_pgn(pgn_str: &str) -> Vec<PgnGame> {
+    let mut game_list = Vec::new();
+    let mut game = PgnGame::default();
+
+    // the variations being read, innermost last
+    let mut variation_stack: Vec<Vec<PgnMov>> = Vec::new();
+
+    let mut char_iter = pgn_str.chars().peekable();
+
+    while let Some(c) = char_iter.next() {
+        match c {
+            '[' => {
+                // a tag after the move text starts the next game when the result was missing
+                if !game.mov_list.is_empty() {
+                    finish_game(&mut game_list, &mut game, &mut variation_stack, "*");
+                }
+
+                if let Some(tag) = parse_tag(&read_until(&mut char_iter, ']')) {
+                    game.tag_list.push(tag);
+                }
+            },
+            '{' => {
+                add_comment(&mut game, &mut variation_stack, &read_until(&mut char_iter, '}'));
+            },
+            ';' => {
+                add_comment(&mut game, &mut variation_stack, &read_until(&mut char_iter, '\n'));
+            },
+            '%' => {
+                read_until(&mut char_iter, '\n');
+            },
+            '(' => {
+                variation_stack.push(Vec::new());
+            },
+            ')' => {
+                if let Some(variation) = variation_stack.pop() {
+                    let current_line = variation_stack.last_mut().unwrap_or(&mut game.mov_list);
+
+                    if let (false, Some(replaced_mov)) = (variation.is_empty(), current_line.last_mut()) {
+                        replaced_mov.variation_list.push(variation);
+                    }
+                }
+            },
+            c if c.is_whitespace() => {},
+            _ => {
+                let mut token = c.to_string();
+
+                while let Some(next_char) = char_iter.peek() {
+                    if next_char.is_whitespace() || "[]{}();".contains(*next_char) {
+                        break
+                    }
+
+                    token.push(*next_char);
+                    char_iter.next();
+                }
+
+                if RESULT_LIST.contains(&token.as_str()) {
+                    if variation_stack.is_empty() {
+                        finish_game(&mut game_list, &mut game, &mut variation_stack, &token);
+                    }
+
+                    continue
+                }
+
+                let current_line = variation_stack.last_mut().unwrap_or(&mut game.mov_list);
+
+                if let Some(nag_str) = token.strip_prefix('$') {
+                    if let (Ok(nag), Some(pgn_mov)) = (nag_str.parse::<u8>(), current_line.last_mut()) {
+                        pgn_mov.nag_list.push(nag);
+                    }
+                } else if let Some(pgn_mov) = get_mov_from_token(&token) {
+                    current_line.push(pgn_mov);
+                }
+            },
+        }
+    }
+
+    if !game.tag_list.is_empty() || !game.mov_list.is_empty() {
+        finish_game(&mut game_list, &mut game, &mut variation_stack, "*");
+    }
+
+    game_list
+}
+
+/// Finds the legal move written in SAN, e.g. `Nbd7`, `exd6`, `e8=Q+` or `O-O`.
+pub fn parse_san(state: &mut State, san: &str) -> Option<u32> {
+    let san = san.trim_end_matches(|c| "+#!?".contains(c));
+    let legal_mov_list = mov_table::gen_legal_mov_list(state);
+
+    if san == "O-O" || san == "0-0" || san == "O-O-O" || san == "0-0-0" {
+        let is_king_side = san.len() == 3;
+
+        return legal_mov_list.into_iter().find(|mov| {
+            let (_from, to, tp, _promo) = util::decode_u32_mov(*mov);
+            tp == def::MOV_CAS && (def::get_cas_index(to) & 1 == 0) == is_king_side
+        })
+    }
+
+    let san_chars = san.chars().filter(|c| *c != 'x' && *c != '-').collect::<Vec<char>>();
+
+    let (san_chars, promo_char) = match san_chars.as_slice() {
+        [rest @ .., '=', promo_char] => (rest, Some(*promo_char)),
+        [rest @ .., rank_char, promo_char] if rank_char.is_ascii_digit() && "NBRQ".contains(*promo_char) => {
+            (&san_chars[..rest.len() + 1], Some(*promo_char))
+        },
+        all_chars => (all_chars, None),
+    };
+
+    let (piece_char, san_chars) = match san_chars {
+        [piece_char, rest @ ..] if "NBRQK".contains(*piece_char) => (Some(*piece_char), rest),
+        rest => (None, rest),
+    };
+
+    if san_chars.len() < 2 {
+        return None
+    }
+
+    let (disambig_chars, to_chars) = san_chars.split_at(san_chars.len() - 2);
+    let to_str = to_chars.iter().collect::<String>();
+
+    if !util::is_sqr_str(&to_str) {
+        return None
+    }
+
+    let to_index = util::map_sqr_notation_to_index(&to_str);
+
+    let mut matched_mov_list = legal_mov_list.into_iter().filter(|mov| {
+        let (from, to, tp, promo) = util::decode_u32_mov(*mov);
+        let moving_piece = state.squares[from];
+
+        if tp == def::MOV_CAS || to != to_index || get_piece_char(moving_piece) != piece_char {
+            return false
+        }
+
+        let promo_matched = match promo_char {
+            Some(promo_char) => promo != 0 && get_piece_char(promo) == Some(promo_char),
+            None => promo == 0,
+        };
+
+        promo_matched && disambig_chars.iter().all(|disambig_char| {
+            match disambig_char {
+                'a'..='h' => from % def::DIM_SIZE == *disambig_char as usize - 'a' as usize,
+                '1'..='8' => from / def::DIM_SIZE == *disambig_char as usize - '1' as usize,
+                _ => false,
+            }
+        })
+    });
+
+    let mov = matched_mov_list.next()?;
+
+    if matched_mov_list.next().is_some() {
+        return None
+    }
+
+    Some(mov)
+}
+
+/// Formats a legal move in SAN, disambiguated and with check or mate marks.
+pub fn format_san(state: &mut State, mov: u32) -> String {
+    let (from, to, tp, promo) = util::decode_u32_mov(mov);
+    let legal_mov_list = mov_table::gen_legal_mov_list(state);
+
+    let mut san = if tp == def::MOV_CAS {
+        if def::get_cas_index(to) & 1 == 0 {
+            "O-O".to_owned()
+        } else {
+            "O-O-O".to_owned()
+        }
+    } else {
+        let moving_piece = state.squares[from];
+        let is_capture = state.squares[to] != 0 || tp == def::MOV_ENP;
+        let from_str = util::map_index_to_sqr_notation(from);
+
+        let mut san = String::new();
+
+        match get_piece_char(moving_piece) {
+            Some(piece_char) => {
+                san.push(piece_char);
+
+                let ambig_from_list = legal_mov_list.iter().filter_map(|legal_mov| {
+                    let (legal_from, legal_to, legal_tp, _promo) = util::decode_u32_mov(*legal_mov);
+
+                    if legal_to == to && legal_from != from && legal_tp != def::MOV_CAS && state.squares[legal_from] == moving_piece {
+                        Some(legal_from)
+                    } else {
+                        None
+                    }
+                }).collect::<Vec<usize>>();
+
+                if !ambig_from_list.is_empty() {
+                    let has_same_file = ambig_from_list.iter().any(|ambig_from| ambig_from % def::DIM_SIZE == from % def::DIM_SIZE);
+                    let has_same_rank = ambig_from_list.iter().any(|ambig_from| ambig_from / def::DIM_SIZE == from / def::DIM_SIZE);
+
+                    if !has_same_file {
+                        san.push_str(&from_str[..1]);
+                    } else if !has_same_rank {
+                        san.push_str(&from_str[1..]);
+                    } else {
+                        san.push_str(&from_str);
+                    }
+                }
+            },
+            None => {
+                if is_capture {
+                    san.push_str(&from_str[..1]);
+                }
+            },
+        }
+
+        if is_capture {
+            san.push('x');
+        }
+
+        san.push_str(&util::map_index_to_sqr_notation(to));
+
+        if promo != 0 {
+            san.push('=');
+            san.push(util::map_piece_code_to_fen_char(promo).to_ascii_uppercase());
+        }
+
+        san
+    };
+
+    state.do_mov(from, to, tp, promo);
+
+    if mov_table::is_in_check(state, state.player) {
+        if mov_table::gen_legal_mov_list(state).is_empty() {
+            san.push('#');
+        } else {
+            san.push('+');
+        }
+    }
+
+    state.undo_mov(from, to, tp);
+
+    san
+}
+
+fn get_piece_char(piece: u8) -> Option<char> {
+    if def::is_p(piece) {
+        None
+    } else {
+        Some(util::map_piece_code_to_fen_char(piece).to_ascii_uppercase())
+    }
+}
+
+fn get_mov_from_token(token: &str) -> Option<PgnMov> {
+    // move numbers may be glued to the move, as in "1.e4" or "12...Nf6"
+    let san = token.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+
+    if san.is_empty() {
+        return None
+    }
+
+    let mov_str = san.trim_end_matches(['!', '?']);
+    let mut pgn_mov = PgnMov::new(mov_str);
+
+    if let Some((_suffix, nag)) = SUFFIX_ANNOTATION_LIST.iter().find(|(suffix, _nag)| *suffix == &san[mov_str.len()..]) {
+        pgn_mov.nag_list.push(*nag);
+    }
+
+    Some(pgn_mov)
+}
+
+fn add_comment(game: &mut PgnGame, variation_stack: &mut [Vec<PgnMov>], comment: &str) {
+    let comment = comment.trim();
+
+    if comment.is_empty() {
+        return
+    }
+
+    let target_comment = match variation_stack.last_mut() {
+        Some(variation) => match variation.last_mut() {
+            Some(pgn_mov) => &mut pgn_mov.comment,
+            None => return,
+        },
+        None => match game.mov_list.last_mut() {
+            Some(pgn_mov) => &mut pgn_mov.comment,
+            None => &mut game.comment,
+        },
+    };
+
+    match target_comment {
+        Some(existing_comment) => {
+            existing_comment.push(' ');
+            existing_comment.push_str(comment);
+        },
+        None => *target_comment = Some(comment.to_owned()),
+    }
+}
+
+fn finish_game(game_list: &mut Vec<PgnGame>, game: &mut PgnGame, variation_stack: &mut Vec<Vec<PgnMov>>, result: &str) {
+    let mut finished_game = std::mem::take(game);
+    variation_stack.clear();
+
+    finished_game.result = match finished_game.get_tag("Result") {
+        Some(tag_result) if result == "*" && RESULT_LIST.contains(&tag_result) => tag_result.to_owned(),
+        _ => result.to_owned(),
+    };
+
+    game_list.push(finished_game);
+}
+
+fn get_start_ply(fen: &str) -> usize {
+    let fen_segment_list = fen.split_whitespace().collect::<Vec<&str>>();
+    let full_mov_count = fen_segment_list.get(5).and_then(|full_mov_str| full_mov_str.parse::<usize>().ok()).unwrap_or(1).max(1);
+
+    (full_mov_count - 1) * 2 + (fen_segment_list.get(1) == Some(&"b")) as usize
+}
+
+fn write_mov_list(mov_list: &[PgnMov], start_ply: usize, token_list: &mut Vec<String>) {
+    let mut needs_mov_number = true;
+
+    for (mov_index, pgn_mov) in mov_list.iter().enumerate() {
+        let ply = start_ply + mov_index;
+
+        // move numbers are kept on the same line as their move
+        if ply & 1 == 0 {
+            token_list.push(format!("{}. {}", ply / 2 + 1, pgn_mov.san));
+        } else if needs_mov_number {
+            token_list.push(format!("{}... {}", ply / 2 + 1, pgn_mov.san));
+        } else {
+            token_list.push(pgn_mov.san.clone());
+        }
+
+        token_list.extend(pgn_mov.nag_list.iter().map(|nag| format!("${}", nag)));
+
+        needs_mov_number = false;
+
+        if let Some(comment) = &pgn_mov.comment {
+            token_list.push(format!("{{{}}}", comment));
+            needs_mov_number = true;
+        }
+
+        for variation in pgn_mov.variation_list.iter().filter(|variation| !variation.is_empty()) {
+            let mut variation_token_list = Vec::new();
+            write_mov_list(variation, ply, &mut variation_token_list);
+
+            variation_token_list[0].insert(0, '(');
+            variation_token_list.last_mut().unwrap().push(')');
+
+            token_list.append(&mut variation_token_list);
+            needs_mov_number = true;
+        }
+    }
+}
+
+fn read_until<I: Iterator<Item = char>>(char_iter: &mut I, end_char: char) -> String {
+    let mut read_str = String::new();
+    let mut escaped = false;
+
+    for c in char_iter {
+        if c == end_char && !escaped {
+            break
+        }
+
+        escaped = c == '\\' && !escaped;
+        read_str.push(c);
+    }
+
+    read_str
+}
+
+fn parse_tag(tag_str: &str) -> Option<(String, String)> {
+    let tag_str = tag_str.trim();
+    let value_start = tag_str.find('"')?;
+    let value_end = tag_str.rfind('"')?;
+
+    if value_end <= value_start {
+        return None
+    }
+
+    let name = tag_str[..value_start].trim().to_owned();
+    let value = tag_str[value_start + 1..value_end].replace("\\\"", "\"").replace("\\\\", "\\");
+
+    Some((name, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bitmask,
+        zob_keys,
+    };
+
+    #[test]
+    fn test_parse_pgn() {
+        let pgn_str = r#"
+[Event "Test \"Open\""]
+[White "Alpha"]
+[Black "Beta"]
+[WhiteElo "2400"]
+[Result "1-0"]
+
+1. e4 {best by test} e5 2. Nf3 (2. f4 exf4 (2... d5) 3. Nf3) 2... Nc6 $1 3.Bb5!? a6
+; a rest of line comment
+4. Ba4 Nf6 1-0
+
+[Event "Second"]
+[Result "*"]
+
+1. d4 d5 *
+"#;
+
+        let game_list = parse_pgn(pgn_str);
+        assert_eq!(2, game_list.len());
+
+        let game = &game_list[0];
+        assert_eq!(Some("Test \"Open\""), game.get_tag("Event"));
+        assert_eq!(Some("2400"), game.get_tag("WhiteElo"));
+        assert_eq!(None, game.get_tag("BlackElo"));
+        assert_eq!(vec!["e4", "e5", "Nf3", "Nc6", "Bb5", "a6", "Ba4", "Nf6"], game.mov_list.iter().map(|pgn_mov| pgn_mov.san.as_str()).collect::<Vec<&str>>());
+        assert_eq!("1-0", game.result);
+
+        assert_eq!(Some("best by test".to_owned()), game.mov_list[0].comment);
+        assert_eq!(vec![1], game.mov_list[3].nag_list);
+        assert_eq!(vec![5], game.mov_list[4].nag_list);
+        assert_eq!(Some("a rest of line comment".to_owned()), game.mov_list[5].comment);
+
+        let variation_list = &game.mov_list[2].variation_list;
+        assert_eq!(1, variation_list.len());
+        assert_eq!(vec!["f4", "exf4", "Nf3"], variation_list[0].iter().map(|pgn_mov| pgn_mov.san.as_str()).collect::<Vec<&str>>());
+        assert_eq!("d5", variation_list[0][1].variation_list[0][0].san);
+
+        assert_eq!(vec![PgnMov::new("d4"), PgnMov::new("d5")], game_list[1].mov_list);
+        assert_eq!("*", game_list[1].result);
+    }
+
+    #[test]
+    fn test_to_pgn() {
+        let pgn_str = r#"[Event "Test \"Open\""]
+[Result "1-0"]
+
+{Opening} 1. e4 {best by test} 1... e5 2. Nf3 (2. f4 exf4 (2... d5) 3. Nf3)
+2... Nc6 $1 3. Bb5 $5 a6 4. Ba4 Nf6 5. O-O Be7 6. Re1 b5 7. Bb3 d6 8. c3 O-O
+9. h3 Nb8 10. d4 Nbd7 1-0
+"#;
+
+        let game_list = parse_pgn(pgn_str);
+        assert_eq!(1, game_list.len());
+        assert_eq!(Some("Opening".to_owned()), game_list[0].comment);
+        assert_eq!(pgn_str, game_list[0].to_pgn());
+
+        let game = PgnGame {
+            tag_list: vec![("FEN".to_owned(), "4k3/8/8/8/8/8/8/4K2R b K - 0 30".to_owned())],
+            mov_list: vec![PgnMov::new("Kd7"), PgnMov::new("O-O")],
+            ..Default::default()
+        };
+
+        assert_eq!("[FEN \"4k3/8/8/8/8/8/8/4K2R b K - 0 30\"]\n\n30... Kd7 31. O-O *\n", game.to_pgn());
+    }
+
+    #[test]
+    fn test_parse_san() {
+        zob_keys::init();
+        bitmask::init();
+
+        let mut state = State::new("r3k2r/1Pn2ppp/8/3pP3/8/5N1N/8/R3K2R w KQkq d6 0 1");
+
+        let san_list = [
+            ("exd6", "e5d6"),
+            ("e6", "e5e6"),
+            ("Nf3g1", "f3g1"),
+            ("Nhg5", "h3g5"),
+            ("bxa8=Q", "b7a8q"),
+            ("b8N+", "b7b8n"),
+            ("O-O", "e1g1"),
+            ("O-O-O", "e1c1"),
+            ("Kd2", "e1d2"),
+            ("Rxa8", "a1a8"),
+        ];
+
+        for (san, expected_mov_str) in san_list {
+            let mov = parse_san(&mut state, san).unwrap_or_else(|| panic!("{} not parsed", san));
+            assert_eq!(expected_mov_str, util::format_mov(mov));
+        }
+
+        assert_eq!(None, parse_san(&mut state, "Ng5"));
+        assert_eq!(None, parse_san(&mut state, "N3g5"));
+        assert_eq!(None, parse_san(&mut state, "b8"));
+        assert_eq!(None, parse_san(&mut state, "Qd4"));
+        assert_eq!(None, parse_san(&mut state, "e4"));
+    }
+
+    #[test]
+    fn test_format_san() {
+        zob_keys::init();
+        bitmask::init();
+
+        let mut state = State::new("r3k2r/1Pn2ppp/8/3pP3/8/5N1N/8/R3K2R w KQkq d6 0 1");
+
+        for mov in mov_table::gen_legal_mov_list(&mut state) {
+            let san = format_san(&mut state, mov);
+            assert_eq!(Some(mov), parse_san(&mut state, &san), "{}", san);
+        }
+
+        let san_list = [
+            ("e5d6", "exd6"),
+            ("h3g5", "Nhg5"),
+            ("f3g5", "Nfg5"),
+            ("b7a8q", "bxa8=Q+"),
+            ("b7b8n", "b8=N"),
+            ("a1a8", "Rxa8+"),
+            ("e1g1", "O-O"),
+            ("e1c1", "O-O-O"),
+        ];
+
+        for (mov_str, expected_san) in san_list {
+            let mov = mov_table::gen_legal_mov_list(&mut state).into_iter().find(|mov| util::format_mov(*mov) == mov_str).unwrap();
+            assert_eq!(expected_san, format_san(&mut state, mov));
+        }
+
+        let mut state = State::new("1k6/8/1K6/8/8/8/8/R6R w - - 0 1");
+        let mov = mov_table::gen_legal_mov_list(&mut state).into_iter().find(|mov| util::format_mov(*mov) == "h1h8").unwrap();
+        assert_eq!("Rh8#", format_san(&mut state, mov));
+
+        let mut state = State::new("k7/8/8/8/8/8/4K3/R6R w - - 0 1");
+        let mov = mov_table::gen_legal_mov_list(&mut state).into_iter().find(|mov| util::format_mov(*mov) == "a1d1").unwrap();
+        assert_eq!("Rad1", format_san(&mut state, mov));
+
+        let mut state = State::new("8/7k/8/8/Q7/8/8/Q3K3 w - - 0 1");
+        let mov = mov_table::gen_legal_mov_list(&mut state).into_iter().find(|mov| util::format_mov(*mov) == "a1a2").unwrap();
+        assert_eq!("Q1a2", format_san(&mut state, mov));
+    }
+}
@@ -5,6 +5,8 @@
 static SEED_C89: u64 = 0b10110110_00101111_10100100_01011000_00001000_01100100_11010111_11111010;
 static SEED_A86: u64 = 0b10111001_11010011_00111100_00010100_00110000_00100110_11001111_10110110;
 
+const SEED_WARMUP_COUNT: usize = 16;
+
 const fn rotate(x: u64, k: usize) -> u64 {
     (x << k) | (x >> (64 - k))
 }
@@ -20,12 +22,25 @@ impl XorshiftPrng {
         }
     }
 
+    pub fn with_seed(seed: u64) -> XorshiftPrng {
+        let mut prng = XorshiftPrng {
+            state: [SEED_C89 ^ seed, SEED_A86],
+        };
+
+        // let the seed spread over the whole state before it is used
+        for _ in 0..SEED_WARMUP_COUNT {
+            prng.gen_rand();
+        }
+
+        prng
+    }
+
     #[allow(arithmetic_overflow)]
     pub fn gen_rand(&mut self) -> u64 {
         let s0 = self.state[0];
         let mut s1 = self.state[1];
 
-        let next_rand = rotate(s0 * 5, 7) * 9;
+        let next_rand = rotate(s0.wrapping_mul(5), 7).wrapping_mul(9);
         s1 ^= s0;
 
         self.state[0] = rotate(s0, 24) ^ s1 ^ (s1 << 16);
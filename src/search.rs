@@ -4,8 +4,9 @@
 
 use crate::{
     bitmask,
+    book::Book,
     def,
-    eval,
+    eval::{self, EvalParams, EvalType},
     hashtable::{
         DepthPreferredHashTable,
         LookupResult,
@@ -14,11 +15,12 @@ use crate::{
         HASH_TYPE_EXACT
     },
     mov_table,
+    nnue::Network,
     state::State,
     simple_rnd::SimpleRnd,
+    syzygy::{Tablebase, Wdl},
     time_control::TimeCapacity,
     util,
-    zob_keys,
 };
 
 const PV_TRACK_LENGTH: usize = 128;
@@ -56,13 +58,64 @@ const SE_MARGIN: i32 = 132;
 
 const WD_SIZE: i32 = 50;
 
+// tablebase wins score below the mate scores
+const TB_WIN_VAL: i32 = eval::TERM_VAL - PV_TRACK_LENGTH as i32;
+
 const TIME_CHECK_INTEVAL: u64 = 1023;
 
-pub static mut ABORT_SEARCH: bool = false;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Cloneable token to stop a running search or signal a ponder hit
+/// from another thread.
+#[derive(Clone)]
+pub struct SearchHandle {
+    stopped: Arc<AtomicBool>,
+    ponder_hit: Arc<AtomicBool>,
+}
+
+impl SearchHandle {
+    pub fn new() -> Self {
+        SearchHandle {
+            stopped: Arc::new(AtomicBool::new(false)),
+            ponder_hit: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed)
+    }
+
+    pub fn ponder_hit(&self) {
+        self.ponder_hit.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_ponder_hit(&self) -> bool {
+        self.ponder_hit.load(Ordering::Relaxed)
+    }
+
+    pub fn clear_ponder_hit(&self) {
+        self.ponder_hit.store(false, Ordering::Relaxed);
+    }
 
-static mut ONLY_LEGAL_MOV: bool = false;
+    /// Clears a stop request. The UCI loop calls this when it hands over `go`, so that a `stop`
+    /// arriving before the search starts is not lost.
+    pub fn reset(&self) {
+        self.stopped.store(false, Ordering::Relaxed);
+    }
+}
 
-use std::time::Instant;
+impl Default for SearchHandle {
+    fn default() -> Self {
+        SearchHandle::new()
+    }
+}
 
 struct OrderedMov {
     mov: u32,
@@ -76,8 +129,18 @@ struct OrderedQMov {
     sort_score: i32,
 }
 
+/// Iterative-deepening alpha-beta search with a shared transposition table.
 pub struct SearchEngine {
-    depth_preferred_hash_table: DepthPreferredHashTable,
+    depth_preferred_hash_table: Arc<DepthPreferredHashTable>,
+    search_handle: SearchHandle,
+    // nodes of the main thread and its helpers, the node limit applies to this total
+    shared_node_count: Arc<AtomicU64>,
+    eval_params: Arc<EvalParams>,
+    eval_type: EvalType,
+    network: Option<Arc<Network>>,
+    tablebase: Option<Arc<Tablebase>>,
+    helper_list: Vec<SearchEngine>,
+    book: Option<Book>,
     primary_killer_table: [(u32, i32, u8); PV_TRACK_LENGTH],
     secondary_killer_table: [(u32, i32, u8); PV_TRACK_LENGTH],
     counter_mov_table: [[[u32; def::BOARD_SIZE]; def::BOARD_SIZE]; 2],
@@ -89,14 +152,41 @@ pub struct SearchEngine {
     root_half_mov_count: u16,
     time_tracker: Instant,
     max_time_millis: u128,
+    max_node_count: u64,
+    mate_search: bool,
+    search_mov_list: Vec<u32>,
+    excluded_root_mov_list: Vec<u32>,
+    root_best_mov: u32,
+    multi_pv: usize,
+    pondering: bool,
+    ponder_time_millis: u128,
+    ponder_mov: u32,
+    only_legal_mov: bool,
+    timed_search: bool,
+    silent: bool,
+    root_score: i32,
     node_count: u64,
+    tb_hit_count: u64,
     seldepth: u8,
 }
 
 impl SearchEngine {
+    /// Creates an engine with a transposition table of `hash_size` entries, which must be a power of two.
     pub fn new(hash_size: usize) -> Self {
+        SearchEngine::with_shared_state(Arc::new(DepthPreferredHashTable::new(hash_size)), SearchHandle::new(), Arc::new(AtomicU64::new(0)))
+    }
+
+    fn with_shared_state(depth_preferred_hash_table: Arc<DepthPreferredHashTable>, search_handle: SearchHandle, shared_node_count: Arc<AtomicU64>) -> Self {
         SearchEngine {
-            depth_preferred_hash_table: DepthPreferredHashTable::new(hash_size),
+            depth_preferred_hash_table,
+            search_handle,
+            shared_node_count,
+            eval_params: Arc::new(EvalParams::default()),
+            eval_type: EvalType::Nnue,
+            network: None,
+            tablebase: None,
+            helper_list: Vec::new(),
+            book: None,
             primary_killer_table: [(0, 0, 0); PV_TRACK_LENGTH],
             secondary_killer_table: [(0, 0, 0); PV_TRACK_LENGTH],
             counter_mov_table: [[[0; def::BOARD_SIZE]; def::BOARD_SIZE]; 2],
@@ -108,59 +198,224 @@ impl SearchEngine {
             root_half_mov_count: 0,
             time_tracker: Instant::now(),
             max_time_millis: 0,
+            max_node_count: 0,
+            mate_search: false,
+            search_mov_list: Vec::new(),
+            excluded_root_mov_list: Vec::new(),
+            root_best_mov: 0,
+            multi_pv: def::DEFAULT_MULTI_PV,
+            pondering: false,
+            ponder_time_millis: 0,
+            ponder_mov: 0,
+            only_legal_mov: false,
+            timed_search: false,
+            silent: false,
+            root_score: 0,
             node_count: 0,
+            tb_hit_count: 0,
             seldepth: 0,
         }
     }
 
+    /// Returns a handle that controls the searches of this engine.
+    pub fn get_search_handle(&self) -> SearchHandle {
+        self.search_handle.clone()
+    }
+
     pub fn reset(&mut self) {
         self.depth_preferred_hash_table.clear();
     }
 
     pub fn set_hash_size(&mut self, hash_size: usize) {
-        self.depth_preferred_hash_table = DepthPreferredHashTable::new(hash_size);
+        self.depth_preferred_hash_table = Arc::new(DepthPreferredHashTable::new(hash_size));
+
+        for helper in &mut self.helper_list {
+            helper.depth_preferred_hash_table = Arc::clone(&self.depth_preferred_hash_table);
+        }
     }
 
-    pub fn perft(&self, state: &mut State, depth: u8) -> usize {
-        if mov_table::is_in_check(state, def::get_opposite_player(state.player)) {
-            return 0
+    /// Sets the total number of search threads, including the calling thread.
+    pub fn set_threads(&mut self, thread_count: usize) {
+        self.helper_list = (1..thread_count.max(1)).map(|_| {
+            let mut helper = SearchEngine::with_shared_state(Arc::clone(&self.depth_preferred_hash_table), self.search_handle.clone(), Arc::clone(&self.shared_node_count));
+            helper.eval_params = Arc::clone(&self.eval_params);
+            helper.tablebase = self.tablebase.clone();
+            helper
+        }).collect();
+    }
+
+    /// Sets the evaluation weights of this engine and its helper threads.
+    pub fn set_eval_params(&mut self, eval_params: EvalParams) {
+        self.eval_params = Arc::new(eval_params);
+
+        for helper in &mut self.helper_list {
+            helper.eval_params = Arc::clone(&self.eval_params);
         }
+    }
 
-        if depth == 0 {
-            return 1
+    pub fn set_eval_type(&mut self, eval_type: EvalType) {
+        self.eval_type = eval_type;
+    }
+
+    /// Sets the network used by `EvalType::Nnue`; without one the classical eval is used.
+    pub fn set_network(&mut self, network: Option<Arc<Network>>) {
+        self.network = network;
+    }
+
+    /// Sets the endgame tablebase of this engine and its helper threads; `None` disables probing.
+    pub fn set_tablebase(&mut self, tablebase: Option<Arc<Tablebase>>) {
+        self.tablebase = tablebase;
+
+        for helper in &mut self.helper_list {
+            helper.tablebase = self.tablebase.clone();
         }
+    }
 
-        let mut node_count = 0;
+    /// Sets the opening book to play from; `None` disables it.
+    pub fn set_book(&mut self, book: Option<Book>) {
+        self.book = book;
+    }
 
-        let mut mov_list = [0; def::MAX_MOV_COUNT];
+    pub fn set_search_movs(&mut self, search_mov_list: Vec<u32>) {
+        self.search_mov_list = search_mov_list;
+    }
 
-        mov_table::gen_reg_mov_list(state, &mut mov_list);
+    pub fn set_multi_pv(&mut self, multi_pv: usize) {
+        self.multi_pv = multi_pv;
+    }
 
-        for mov_index in 0..def::MAX_MOV_COUNT {
-            let mov = mov_list[mov_index];
+    pub fn set_ponder(&mut self) {
+        self.pondering = true;
+    }
 
-            if mov == 0 {
-                break
-            }
+    pub fn get_ponder_mov(&self) -> u32 {
+        self.ponder_mov
+    }
 
-            let (from, to, tp, promo) = util::decode_u32_mov(mov);
+    /// Suppresses the info output, for engines playing games in-process.
+    pub fn set_silent(&mut self, silent: bool) {
+        self.silent = silent;
+    }
 
-            if def::is_k(state.squares[to]) {
-                return 0
-            }
+    /// Returns the number of nodes all threads visited in the last search.
+    pub fn get_node_count(&self) -> u64 {
+        self.shared_node_count.load(Ordering::Relaxed)
+    }
 
-            state.do_mov(from, to, tp, promo);
-            node_count += self.perft(state, depth - 1);
-            state.undo_mov(from, to, tp);
-        }
+    /// Returns the score of the last search from the side to move's point of view.
+    pub fn get_last_score(&self) -> i32 {
+        self.root_score
+    }
+
+    pub fn perft(&self, state: &mut State, depth: u8) -> usize {
+        mov_table::perft(state, depth)
+    }
+
+    /// Searches for a mate in at most `mate_mov_count` moves.
+    pub fn search_mate(&mut self, state: &mut State, time_capacity: TimeCapacity, mate_mov_count: u8, max_nodes: u64) -> u32 {
+        self.depth_preferred_hash_table.clear();
+        self.mate_search = true;
+
+        let max_depth = mate_mov_count.max(1).saturating_mul(2) - 1;
+        let best_mov = self.search(state, time_capacity, max_depth, max_nodes);
 
-        node_count
+        self.mate_search = false;
+
+        best_mov
     }
 
-    pub fn search(&mut self, state: &mut State, time_capacity: TimeCapacity, max_depth: u8) -> u32 {
+    /// Searches the position within the time, depth and node limits and returns the best move.
+    pub fn search(&mut self, state: &mut State, time_capacity: TimeCapacity, max_depth: u8, max_nodes: u64) -> u32 {
+        if let Some(book_mov) = self.get_book_mov(state) {
+            if !self.silent {
+                println!("info string book move {}", state.format_mov(book_mov));
+            }
+
+            self.ponder_mov = 0;
+            self.root_score = 0;
+            self.search_mov_list.clear();
+
+            return book_mov
+        }
+
         self.time_tracker = Instant::now();
         self.max_time_millis = time_capacity.main_time_millis;
+        self.max_node_count = max_nodes;
+        self.timed_search = time_capacity.main_time_millis != u128::MAX;
 
+        if self.pondering {
+            self.ponder_time_millis = self.max_time_millis;
+            self.max_time_millis = u128::MAX;
+        }
+
+        match self.eval_type {
+            EvalType::Nnue => state.set_network(self.network.clone()),
+            EvalType::Classical => state.set_network(None),
+        }
+
+        self.prepare_search(state);
+        self.shared_node_count.store(0, Ordering::Relaxed);
+        self.ponder_mov = 0;
+        self.root_score = 0;
+
+        if !self.mate_search {
+            self.filter_tb_root_movs(state);
+        }
+
+        self.only_legal_mov = false;
+
+        if !self.search_mov_list.is_empty() {
+            self.depth_preferred_hash_table.remove(state.get_zob_key(), state.hash_key);
+        }
+
+        let mut helper_list = std::mem::take(&mut self.helper_list);
+
+        let best_mov = thread::scope(|scope| {
+            for (helper_index, helper) in helper_list.iter_mut().enumerate() {
+                helper.max_time_millis = u128::MAX;
+                helper.max_node_count = self.max_node_count;
+                helper.mate_search = self.mate_search;
+                helper.search_mov_list = self.search_mov_list.clone();
+                helper.prepare_search(state);
+
+                let mut helper_state = state.clone();
+                let start_depth = 1 + helper_index as u8 % 2;
+
+                scope.spawn(move || helper.helper_search(&mut helper_state, start_depth, max_depth));
+            }
+
+            let best_mov = self.main_search(state, max_depth);
+
+            self.search_handle.stop();
+
+            best_mov
+        });
+
+        // the helpers have finished, the next search starts unstopped
+        self.search_handle.reset();
+
+        self.helper_list = helper_list;
+        self.search_mov_list.clear();
+        self.pondering = false;
+
+        best_mov
+    }
+
+    fn get_book_mov(&mut self, state: &mut State) -> Option<u32> {
+        if self.pondering || self.mate_search {
+            return None
+        }
+
+        let book_mov = self.book.as_mut()?.get_mov(state)?;
+
+        if !self.search_mov_list.is_empty() && !self.search_mov_list.contains(&book_mov) {
+            return None
+        }
+
+        Some(book_mov)
+    }
+
+    fn prepare_search(&mut self, state: &State) {
         self.primary_killer_table = [(0, 0, 0); PV_TRACK_LENGTH];
         self.secondary_killer_table = [(0, 0, 0); PV_TRACK_LENGTH];
         self.history_table = [[[0; def::BOARD_SIZE]; def::BOARD_SIZE]; 2];
@@ -169,84 +424,154 @@ impl SearchEngine {
         self.root_full_mov_count = state.full_mov_count;
         self.root_half_mov_count = state.half_mov_count;
 
+        self.null_mov_count = 0;
         self.node_count = 0;
+        self.tb_hit_count = 0;
         self.seldepth = 0;
+        self.root_best_mov = 0;
+        self.excluded_root_mov_list.clear();
+    }
+
+    // helper threads search the same root without reporting,
+    // sharing results with the main thread through the hash table only
+    fn helper_search(&mut self, state: &mut State, start_depth: u8, max_depth: u8) {
+        let in_check = mov_table::is_in_check(state, state.player);
+
+        for depth in start_depth..=max_depth {
+            self.ab_search(state, in_check, false, -eval::MATE_VAL, eval::MATE_VAL, depth, 0);
 
-        unsafe {
-            ABORT_SEARCH = false;
-            ONLY_LEGAL_MOV = false;
+            if self.search_handle.is_stopped() {
+                break
+            }
         }
+    }
 
+    fn main_search(&mut self, state: &mut State, max_depth: u8) -> u32 {
         let in_check = mov_table::is_in_check(state, state.player);
 
-        let mut alpha = -eval::MATE_VAL;
-        let mut beta = eval::MATE_VAL;
+        let multi_pv = self.multi_pv.min(self.gen_root_mov_list(state).len()).max(1);
+        let mut line_score_list = vec![0; multi_pv];
 
         let mut depth = 1;
         let mut best_mov = 0;
+        let mut mate_found = false;
         let mut accumulated_time_taken = 0;
 
-        loop {
-            let score = self.ab_search(state, in_check, false, alpha, beta, depth, 0);
+        'iterative_deepening: loop {
+            let mut line_list = Vec::new();
+
+            self.excluded_root_mov_list.clear();
+
+            for &line_score in &line_score_list {
+                let mut alpha = -eval::MATE_VAL;
+                let mut beta = eval::MATE_VAL;
+
+                if depth > 1 {
+                    alpha = line_score - WD_SIZE;
+                    beta = line_score + WD_SIZE;
+                }
+
+                let score = loop {
+                    let score = self.ab_search(state, in_check, false, alpha, beta, depth, 0);
+
+                    if self.search_handle.is_stopped() {
+                        break 'iterative_deepening;
+                    }
+
+                    if score >= beta {
+                        beta = eval::MATE_VAL;
+                        continue;
+                    }
 
-            unsafe {
-                if ABORT_SEARCH {
+                    if score <= alpha {
+                        alpha = -eval::MATE_VAL;
+                        continue;
+                    }
+
+                    break score;
+                };
+
+                let root_mov = self.root_best_mov;
+
+                if root_mov == 0 {
                     break;
                 }
-            }
 
-            if score >= beta {
-                beta = eval::MATE_VAL;
-                continue;
-            }
+                let mut pv_table = [0; PV_TRACK_LENGTH];
+                pv_table[0] = root_mov;
 
-            if score <= alpha {
-                alpha = -eval::MATE_VAL;
-                continue;
+                let (from, to, tp, promo) = util::decode_u32_mov(root_mov);
+                state.do_mov(from, to, tp, promo);
+                self.retrieve_pv(state, &mut pv_table, 1);
+                state.undo_mov(from, to, tp);
+
+                self.excluded_root_mov_list.push(root_mov);
+
+                line_list.push((score, pv_table));
             }
 
-            let mut pv_table = [0; PV_TRACK_LENGTH];
-            self.retrieve_pv(state, &mut pv_table, 0);
+            if line_list.is_empty() {
+                break
+            }
 
-            let checkmate = score.abs() > eval::TERM_VAL;
+            line_list.sort_by(|(score_a, _), (score_b, _)| score_b.cmp(score_a));
 
             let total_time_taken = self.time_tracker.elapsed().as_millis();
+            let total_node_count = self.get_node_count();
+            let nps = total_node_count as u128 / (total_time_taken / 1000).max(1);
+            let hashfull_permill = self.depth_preferred_hash_table.get_utilization_permill();
+
+            let mut search_finished = false;
 
-            if pv_table[0] != 0 {
-                best_mov = pv_table[0];
+            for (pv_index, (score, pv_table)) in line_list.iter().enumerate() {
+                let score = *score;
 
-                let nps = self.node_count as u128 / (total_time_taken / 1000).max(1);
-                let hashfull_permill = self.depth_preferred_hash_table.get_utilization_permill();
+                line_score_list[pv_index] = score;
+
+                let multi_pv_info = if multi_pv > 1 {
+                    format!(" multipv {}", pv_index + 1)
+                } else {
+                    String::new()
+                };
 
-                if checkmate {
+                if score.abs() > eval::TERM_VAL {
                     let mate_score = if score > 0 {
                         (eval::MATE_VAL - score + 1) / 2
                     } else {
                         (-eval::MATE_VAL - score - 1) / 2
                     };
 
-                    println!("info score mate {} depth {} seldepth {} nodes {} nps {} hashfull {} time {} pv {}",mate_score, depth, self.seldepth, self.node_count, nps, hashfull_permill, total_time_taken, util::format_pv(&pv_table));
-                } else {
-                    println!("info score cp {} depth {} seldepth {} nodes {} nps {} hashfull {} time {} pv {}", score, depth, self.seldepth, self.node_count, nps, hashfull_permill, total_time_taken, util::format_pv(&pv_table));
-                }
+                    if !self.silent {
+                        println!("info{} score mate {} depth {} seldepth {} nodes {} nps {} tbhits {} hashfull {} time {} pv {}", multi_pv_info, mate_score, depth, self.seldepth, total_node_count, nps, self.tb_hit_count, hashfull_permill, total_time_taken, state.format_pv(pv_table));
+                    }
 
-                if checkmate {
-                    break
+                    if pv_index == 0 && (!self.mate_search || mate_score < 0 || mate_score * 2 - 1 <= max_depth as i32) {
+                        mate_found = mate_score > 0;
+                        search_finished = true;
+                    }
+                } else if !self.silent {
+                    println!("info{} score cp {} depth {} seldepth {} nodes {} nps {} tbhits {} hashfull {} time {} pv {}", multi_pv_info, score, depth, self.seldepth, total_node_count, nps, self.tb_hit_count, hashfull_permill, total_time_taken, state.format_pv(pv_table));
                 }
+            }
 
-                if total_time_taken - accumulated_time_taken > self.max_time_millis / 2 {
-                    break
-                }
+            let (score, pv_table) = line_list[0];
+            best_mov = pv_table[0];
+            self.root_score = score;
+            self.ponder_mov = pv_table[1];
 
-                unsafe {
-                    if ONLY_LEGAL_MOV {
-                        break;
-                    }
-                }
+            if search_finished {
+                break
             }
 
-            alpha = score - WD_SIZE;
-            beta = score + WD_SIZE;
+            self.check_ponder_hit();
+
+            if total_time_taken - accumulated_time_taken > self.max_time_millis / 2 {
+                break
+            }
+
+            if self.only_legal_mov {
+                break;
+            }
 
             depth += 1;
             accumulated_time_taken = total_time_taken;
@@ -256,16 +581,39 @@ impl SearchEngine {
             }
         }
 
+        if self.mate_search && !mate_found && !self.silent {
+            println!("info string no mate in {} found", max_depth.div_ceil(2));
+        }
+
+        // the limits stopped the search before it finished the first iteration
+        if best_mov == 0 {
+            self.excluded_root_mov_list.clear();
+            best_mov = self.gen_root_mov_list(state).first().copied().unwrap_or(0);
+            self.ponder_mov = 0;
+        }
+
+        while self.pondering {
+            if self.search_handle.is_stopped() {
+                break;
+            }
+
+            self.check_ponder_hit();
+            thread::sleep(Duration::from_millis(1));
+        }
+
         best_mov
     }
 
     fn ab_search(&mut self, state: &mut State, in_check: bool, on_extend: bool, mut alpha: i32, beta: i32, depth: u8, ply: u8) -> i32 {
+        if self.search_handle.is_stopped() {
+            return alpha;
+        }
+
         self.node_count += 1;
-        if (self.node_count & TIME_CHECK_INTEVAL == 0) && self.time_tracker.elapsed().as_millis() > self.max_time_millis {
-            unsafe {
-                ABORT_SEARCH = true;
-                return alpha;
-            }
+        let total_node_count = self.shared_node_count.fetch_add(1, Ordering::Relaxed) + 1;
+        if total_node_count >= self.max_node_count || ((self.node_count & TIME_CHECK_INTEVAL == 0) && self.is_time_out()) {
+            self.search_handle.stop();
+            return alpha;
         }
 
         if ply > 0 && self.null_mov_count == 0 && state.is_draw(ply) {
@@ -277,7 +625,7 @@ impl SearchEngine {
         if depth == 0 {
             let mut score = self.q_search(state, alpha, beta, ply);
 
-            if !on_pv {
+            if !on_pv || self.mate_search {
                 return score;
             }
 
@@ -310,7 +658,10 @@ impl SearchEngine {
 
         match self.get_hash(state) {
             Some(entry) => {
-                hash_mov = entry.mov;
+                if self.is_search_mov(entry.mov, ply) {
+                    hash_mov = entry.mov;
+                }
+
                 static_eval = entry.eval;
 
                 if !on_pv && entry.depth >= depth {
@@ -340,24 +691,40 @@ impl SearchEngine {
                     }
                 }
 
-                if on_pv && entry.flag == HASH_TYPE_BETA {
+                if on_pv && hash_mov != 0 && entry.flag == HASH_TYPE_BETA {
                     is_singular_mov = true;
                 }
             },
             _ => {
-                let (material_score, is_draw) = eval::eval_materials(state);
+                let (material_score, is_draw) = eval::eval_materials(state, &self.eval_params);
 
                 if is_draw && ply > 0 {
                     return 0;
                 }
 
-                static_eval = eval::eval_state(state, material_score);
+                static_eval = self.eval_state(state, material_score);
             },
         }
 
+        // the tables ignore the fifty-move counter, so only probe right after it is reset
+        if ply > 0 && !self.mate_search && state.half_mov_count == 0 && state.cas_rights == 0 {
+            if let Some(wdl) = self.probe_wdl(state) {
+                let (tb_score, hash_flag) = match wdl {
+                    Wdl::Win => (TB_WIN_VAL - ply as i32, HASH_TYPE_BETA),
+                    Wdl::Loss => (-TB_WIN_VAL + ply as i32, HASH_TYPE_ALPHA),
+                    _ => (0, HASH_TYPE_EXACT),
+                };
+
+                if hash_flag == HASH_TYPE_EXACT || (hash_flag == HASH_TYPE_BETA && tb_score >= beta) || (hash_flag == HASH_TYPE_ALPHA && tb_score <= alpha) {
+                    self.set_hash(state, depth, ply, hash_flag, tb_score, static_eval, 0);
+                    return tb_score;
+                }
+            }
+        }
+
         let mut under_mate_threat = false;
 
-        if !on_pv && !on_extend && !in_check {
+        if !on_pv && !on_extend && !in_check && !self.mate_search {
             if depth <= RAZOR_DEPTH && !eval::has_promoting_pawn(state, state.player) {
                 if static_eval + RAZOR_MARGIN * depth as i32 <= alpha {
                     return self.q_search(state, alpha, beta, ply);
@@ -379,10 +746,8 @@ impl SearchEngine {
 
                 self.null_mov_count -= 1;
 
-                unsafe {
-                    if ABORT_SEARCH {
-                        return alpha;
-                    }
+                if self.search_handle.is_stopped() {
+                    return alpha;
                 }
 
                 if scout_score >= beta && scout_score != 0 && scout_score < eval::TERM_VAL {
@@ -396,17 +761,17 @@ impl SearchEngine {
         if on_pv && hash_mov == 0 && depth >= IID_DEPTH {
             self.ab_search(state, in_check, on_extend, alpha, beta, depth - IID_DEPTH_R, ply);
 
-            unsafe {
-                if ABORT_SEARCH {
-                    return alpha;
-                }
+            if self.search_handle.is_stopped() {
+                return alpha;
             }
 
             match self.get_hash(state) {
                 Some(entry) => {
-                    hash_mov = entry.mov;
+                    if self.is_search_mov(entry.mov, ply) {
+                        hash_mov = entry.mov;
+                    }
 
-                    if entry.flag == HASH_TYPE_BETA {
+                    if hash_mov != 0 && entry.flag == HASH_TYPE_BETA {
                         is_singular_mov = true;
                     }
                 },
@@ -426,7 +791,7 @@ impl SearchEngine {
 
             let (from, to, tp, promo) = util::decode_u32_mov(hash_mov);
 
-            let is_capture = state.squares[to] != 0;
+            let is_capture = is_capture_mov(state, to, tp);
 
             state.do_mov(from, to, tp, promo);
 
@@ -443,10 +808,8 @@ impl SearchEngine {
 
             state.undo_mov(from, to, tp);
 
-            unsafe {
-                if ABORT_SEARCH {
-                    return alpha;
-                }
+            if self.search_handle.is_stopped() {
+                return alpha;
             }
 
             if score >= beta {
@@ -488,7 +851,7 @@ impl SearchEngine {
                 break;
             }
 
-            if mov == hash_mov {
+            if mov == hash_mov || !self.is_search_mov(mov, ply) {
                 continue;
             }
 
@@ -512,7 +875,7 @@ impl SearchEngine {
                     sort_score: 0,
                 };
     
-                if state.squares[to] != 0 || promo != 0 {
+                if is_capture_mov(state, to, tp) || promo != 0 {
                     let see_score = see(state, from, to, tp, promo);
     
                     if gives_check {
@@ -554,11 +917,11 @@ impl SearchEngine {
             }
 
             return -eval::MATE_VAL + ply as i32;
-        } else if legal_mov_count == 1 && ply == 0 {
+        } else if legal_mov_count == 1 && ply == 0 && self.timed_search && !self.mate_search && self.search_mov_list.is_empty() && self.excluded_root_mov_list.is_empty() {
             if hash_mov != 0 {
-                unsafe {
-                    ONLY_LEGAL_MOV = true;
-                }
+                self.only_legal_mov = true;
+
+                self.root_best_mov = hash_mov;
     
                 return alpha;
             }
@@ -568,7 +931,7 @@ impl SearchEngine {
             ordered_mov_b.sort_score.partial_cmp(&ordered_mov_a.sort_score).unwrap()
         });
 
-        if !on_pv && !on_extend && !in_check && !under_mate_threat && depth >= MCP_DEPTH {
+        if !on_pv && !on_extend && !in_check && !under_mate_threat && !self.mate_search && depth >= MCP_DEPTH {
             let mut cut_mov_count = 0;
             let mut cut_count = 0;
 
@@ -588,10 +951,8 @@ impl SearchEngine {
                 let scout_score = -self.ab_search(state, gives_check, false, -beta, -beta+1, depth - MCP_R - 1, ply + 1);
                 state.undo_mov(from, to, tp);
 
-                unsafe {
-                    if ABORT_SEARCH {
-                        return alpha;
-                    }
+                if self.search_handle.is_stopped() {
+                    return alpha;
                 }
 
                 if scout_score >= beta {
@@ -613,9 +974,9 @@ impl SearchEngine {
 
             let (from, to, tp, promo) = util::decode_u32_mov(mov);
 
-            let is_capture = state.squares[to] != 0;
+            let is_capture = is_capture_mov(state, to, tp);
 
-            if mov_count > 1 && !gives_check && !in_check && !under_mate_threat && !is_passer && depth <= FP_DEPTH {
+            if mov_count > 1 && !gives_check && !in_check && !under_mate_threat && !is_passer && !self.mate_search && depth <= FP_DEPTH {
                 if static_eval + eval::val_of(state.squares[to]) + eval::val_of(promo) + FP_MARGIN * depth as i32 <= alpha {
                     continue;
                 }
@@ -631,7 +992,7 @@ impl SearchEngine {
                 extended = true;
             }
 
-            let score = if depth > 2 && mov_count > 1 && !extended {
+            let score = if depth > 2 && mov_count > 1 && !extended && !self.mate_search {
                 let score = -self.ab_search(state, gives_check, extended, -alpha - 1, -alpha, depth - ((mov_count as f64).sqrt() as u8).min(depth-1), ply + 1);
                 if score > alpha {
                     if on_pv && pv_found {
@@ -664,10 +1025,8 @@ impl SearchEngine {
 
             state.undo_mov(from, to, tp);
 
-            unsafe {
-                if ABORT_SEARCH {
-                    return alpha;
-                }
+            if self.search_handle.is_stopped() {
+                return alpha;
             }
 
             if score >= beta {
@@ -678,9 +1037,9 @@ impl SearchEngine {
 
                     if mov_count > 1 {
                         if hash_mov != 0 {
-                            let (from, to, _tp, _promo) = util::decode_u32_mov(hash_mov);
+                            let (from, to, tp, _promo) = util::decode_u32_mov(hash_mov);
 
-                            let is_capture = state.squares[to] != 0;
+                            let is_capture = is_capture_mov(state, to, tp);
 
                             if !is_capture && promo == 0 {
                                 self.update_butterfly_table(state.player, from, to);
@@ -689,9 +1048,9 @@ impl SearchEngine {
                             for index in 0..mov_count-2 {
                                 let prev_mov = &ordered_mov_list[index];
 
-                                let (from, to, _tp, _promo) = util::decode_u32_mov(prev_mov.mov);
+                                let (from, to, tp, _promo) = util::decode_u32_mov(prev_mov.mov);
 
-                                let is_capture = state.squares[to] != 0;
+                                let is_capture = is_capture_mov(state, to, tp);
 
                                 if !is_capture && promo == 0 {
                                     self.update_butterfly_table(state.player, from, to);
@@ -701,9 +1060,9 @@ impl SearchEngine {
                             for index in 0..mov_count-1 {
                                 let prev_mov = &ordered_mov_list[index];
 
-                                let (from, to, _tp, _promo) = util::decode_u32_mov(prev_mov.mov);
+                                let (from, to, tp, _promo) = util::decode_u32_mov(prev_mov.mov);
 
-                                let is_capture = state.squares[to] != 0;
+                                let is_capture = is_capture_mov(state, to, tp);
 
                                 if !is_capture && promo == 0 {
                                     self.update_butterfly_table(state.player, from, to);
@@ -731,7 +1090,7 @@ impl SearchEngine {
 
             let (from, to, tp, promo) = util::decode_u32_mov(hash_mov);
 
-            let is_capture = state.squares[to] != 0;
+            let is_capture = is_capture_mov(state, to, tp);
 
             state.do_mov(from, to, tp, promo);
 
@@ -741,10 +1100,8 @@ impl SearchEngine {
 
             state.undo_mov(from, to, tp);
 
-            unsafe {
-                if ABORT_SEARCH {
-                    return alpha;
-                }
+            if self.search_handle.is_stopped() {
+                return alpha;
             }
 
             if score >= beta {
@@ -775,12 +1132,15 @@ impl SearchEngine {
     }
 
     fn q_search(&mut self, state: &mut State, mut alpha: i32, beta: i32, ply: u8) -> i32 {
+        if self.search_handle.is_stopped() {
+            return alpha;
+        }
+
         self.node_count += 1;
-        if (self.node_count & TIME_CHECK_INTEVAL == 0) && self.time_tracker.elapsed().as_millis() > self.max_time_millis {
-            unsafe {
-                ABORT_SEARCH = true;
-                return alpha;
-            }
+        let total_node_count = self.shared_node_count.fetch_add(1, Ordering::Relaxed) + 1;
+        if total_node_count >= self.max_node_count || ((self.node_count & TIME_CHECK_INTEVAL == 0) && self.is_time_out()) {
+            self.search_handle.stop();
+            return alpha;
         }
 
         if ply > self.seldepth {
@@ -797,9 +1157,9 @@ impl SearchEngine {
             Some(entry) => {
                 static_eval = entry.eval;
 
-                let (_from, to, _tp, promo) = util::decode_u32_mov(entry.mov);
+                let (_from, to, tp, promo) = util::decode_u32_mov(entry.mov);
 
-                if promo != 0 || state.squares[to] != 0 {
+                if promo != 0 || is_capture_mov(state, to, tp) {
                     hash_mov = entry.mov;
                 }
 
@@ -831,13 +1191,13 @@ impl SearchEngine {
                 }
             },
             _ => {
-                let (material_score, is_draw) = eval::eval_materials(state);
+                let (material_score, is_draw) = eval::eval_materials(state, &self.eval_params);
 
                 if is_draw {
                     return 0;
                 }
 
-                static_eval = eval::eval_state(state, material_score);
+                static_eval = self.eval_state(state, material_score);
             },
         }
 
@@ -867,10 +1227,8 @@ impl SearchEngine {
             let score = -self.q_search(state, -beta, -alpha, ply + 1);
             state.undo_mov(from, to, tp);
 
-            unsafe {
-                if ABORT_SEARCH {
-                    return alpha;
-                }
+            if self.search_handle.is_stopped() {
+                return alpha;
             }
 
             if score >= beta {
@@ -952,10 +1310,8 @@ impl SearchEngine {
             let score = -self.q_search(state, -beta, -alpha, ply + 1);
             state.undo_mov(from, to, tp);
 
-            unsafe {
-                if ABORT_SEARCH {
-                    return alpha;
-                }
+            if self.search_handle.is_stopped() {
+                return alpha;
             }
 
             if score >= beta {
@@ -1017,20 +1373,105 @@ impl SearchEngine {
         state.undo_mov(from, to, tp);
     }
 
+    #[inline]
+    fn is_time_out(&mut self) -> bool {
+        self.check_ponder_hit();
+        self.time_tracker.elapsed().as_millis() > self.max_time_millis
+    }
+
+    #[inline]
+    fn check_ponder_hit(&mut self) {
+        if self.pondering && self.search_handle.is_ponder_hit() {
+            self.pondering = false;
+            self.max_time_millis = self.time_tracker.elapsed().as_millis().saturating_add(self.ponder_time_millis);
+        }
+    }
+
+    #[inline]
+    fn is_search_mov(&self, mov: u32, ply: u8) -> bool {
+        if ply > 0 {
+            return true
+        }
+
+        (self.search_mov_list.is_empty() || self.search_mov_list.contains(&mov)) && !self.excluded_root_mov_list.contains(&mov)
+    }
+
+    fn gen_root_mov_list(&self, state: &mut State) -> Vec<u32> {
+        let mut mov_list = [0; def::MAX_MOV_COUNT];
+        mov_table::gen_reg_mov_list(state, &mut mov_list);
+
+        let player = state.player;
+        let mut root_mov_list = Vec::new();
+
+        for &mov in mov_list.iter().take_while(|mov| **mov != 0) {
+            if !self.is_search_mov(mov, 0) {
+                continue
+            }
+
+            let (from, to, tp, promo) = util::decode_u32_mov(mov);
+
+            state.do_mov(from, to, tp, promo);
+
+            if !mov_table::is_in_check(state, player) {
+                root_mov_list.push(mov);
+            }
+
+            state.undo_mov(from, to, tp);
+        }
+
+        root_mov_list
+    }
+
+    fn probe_wdl(&mut self, state: &mut State) -> Option<Wdl> {
+        let tablebase = self.tablebase.as_ref()?;
+        let wdl = tablebase.probe_wdl(state)?;
+
+        self.tb_hit_count += 1;
+
+        Some(wdl)
+    }
+
+    // keeps the root moves with the best tablebase rank, within the search moves
+    fn filter_tb_root_movs(&mut self, state: &mut State) {
+        let ranked_mov_list = match self.tablebase.as_ref().and_then(|tablebase| tablebase.rank_root_movs(state)) {
+            Some(ranked_mov_list) => ranked_mov_list,
+            None => return,
+        };
+
+        let ranked_mov_list: Vec<(u32, i32)> = ranked_mov_list.into_iter().filter(|(mov, _)| self.search_mov_list.is_empty() || self.search_mov_list.contains(mov)).collect();
+        self.tb_hit_count += ranked_mov_list.len() as u64;
+
+        if let Some(best_rank) = ranked_mov_list.iter().map(|(_, rank)| *rank).max() {
+            self.search_mov_list = ranked_mov_list.into_iter().filter(|(_, rank)| *rank == best_rank).map(|(mov, _)| mov).collect();
+        }
+    }
+
+    #[inline]
+    fn eval_state(&self, state: &mut State, material_score: i32) -> i32 {
+        match &state.nnue {
+            Some(nnue) => nnue.evaluate(state.player),
+            None => eval::eval_state(state, material_score, &self.eval_params),
+        }
+    }
+
     #[inline]
     fn get_hash(&self, state: &State) -> Option<LookupResult> {
-        self.depth_preferred_hash_table.get(get_hash_key(state), state.hash_key)
+        self.depth_preferred_hash_table.get(state.get_zob_key(), state.hash_key)
     }
 
     #[inline]
     fn set_hash(&mut self, state: &State, depth: u8, ply: u8, hash_flag: u8, mut score: i32, eval: i32, mov: u32) {
+        if ply == 0 {
+            self.root_best_mov = mov;
+        }
+
         if score > eval::TERM_VAL {
             score += ply as i32;
         } else if score < -eval::TERM_VAL {
             score -= ply as i32;
         }
 
-        self.depth_preferred_hash_table.set(get_hash_key(state), state.hash_key, depth, self.root_full_mov_count, hash_flag, score, eval, mov);
+        self.depth_preferred_hash_table.set(state.get_zob_key(), state.hash_key, depth, self.root_full_mov_count, hash_flag, score, eval, mov);
     }
 
     #[inline]
@@ -1125,17 +1566,10 @@ impl SearchEngine {
     }
 }
 
+// castling in chess960 may move the king onto its own rook
 #[inline]
-fn get_hash_key(state: &State) -> u64 {
-    let mut key = state.hash_key
-    ^ zob_keys::get_enp_sqr_zob_key(state.enp_square as usize)
-    ^ zob_keys::get_cas_rights_zob_key(state.cas_rights);
-
-    if state.player == def::PLAYER_B {
-        key ^= zob_keys::get_b_player_zob_key();
-    }
-
-    key
+fn is_capture_mov(state: &State, to: usize, tp: u8) -> bool {
+    tp != def::MOV_CAS && state.squares[to] != 0
 }
 
 #[inline]
@@ -1267,6 +1701,49 @@ mod tests {
         assert_eq!(15833292, search_engine.perft(&mut state, 5));
     }
 
+    #[test]
+    fn test_perft_chess960_1() {
+        zob_keys::init();
+        bitmask::init();
+
+        let mut state = State::new("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9");
+        let search_engine = SearchEngine::new(131072);
+
+        assert_eq!(21, search_engine.perft(&mut state, 1));
+        assert_eq!(528, search_engine.perft(&mut state, 2));
+        assert_eq!(12189, search_engine.perft(&mut state, 3));
+        assert_eq!(326672, search_engine.perft(&mut state, 4));
+        assert_eq!(8146062, search_engine.perft(&mut state, 5));
+    }
+
+    #[test]
+    fn test_perft_chess960_2() {
+        zob_keys::init();
+        bitmask::init();
+
+        let mut state = State::new("2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9");
+        let search_engine = SearchEngine::new(131072);
+
+        assert_eq!(21, search_engine.perft(&mut state, 1));
+        assert_eq!(807, search_engine.perft(&mut state, 2));
+        assert_eq!(18002, search_engine.perft(&mut state, 3));
+        assert_eq!(667366, search_engine.perft(&mut state, 4));
+    }
+
+    #[test]
+    fn test_perft_chess960_3() {
+        zob_keys::init();
+        bitmask::init();
+
+        let mut state = State::new("b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9");
+        let search_engine = SearchEngine::new(131072);
+
+        assert_eq!(20, search_engine.perft(&mut state, 1));
+        assert_eq!(479, search_engine.perft(&mut state, 2));
+        assert_eq!(10471, search_engine.perft(&mut state, 3));
+        assert_eq!(273318, search_engine.perft(&mut state, 4));
+    }
+
     #[test]
     fn test_perft_6() {
         zob_keys::init();
@@ -1278,6 +1755,306 @@ mod tests {
         assert_eq!(92683, search_engine.perft(&mut state, 6));
     }
 
+    #[test]
+    fn test_search_node_limit() {
+        zob_keys::init();
+        bitmask::init();
+
+        let mut state = State::new("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3");
+
+        let mut search_engine = SearchEngine::new(131072);
+        let best_mov = search_engine.search(&mut state, TimeCapacity { main_time_millis: u128::MAX }, 64, 20000);
+        assert_eq!(20000, search_engine.node_count);
+        assert_ne!(0, best_mov);
+
+        let mut search_engine = SearchEngine::new(131072);
+        assert_eq!(best_mov, search_engine.search(&mut state, TimeCapacity { main_time_millis: u128::MAX }, 64, 20000));
+
+        // stopped before finishing the first iteration
+        for max_nodes in [1, 5] {
+            let mut search_engine = SearchEngine::new(131072);
+            let best_mov = search_engine.search(&mut state, TimeCapacity { main_time_millis: u128::MAX }, 64, max_nodes);
+            assert!(mov_table::gen_legal_mov_list(&mut state).contains(&best_mov));
+        }
+
+        let b1c3 = util::encode_u32_mov(util::map_sqr_notation_to_index("b1"), util::map_sqr_notation_to_index("c3"), def::MOV_REG, 0);
+        let mut search_engine = SearchEngine::new(131072);
+        search_engine.set_search_movs(vec![b1c3]);
+        assert_eq!(b1c3, search_engine.search(&mut state, TimeCapacity { main_time_millis: u128::MAX }, 64, 1));
+    }
+
+    #[test]
+    fn test_search_node_limit_threads() {
+        zob_keys::init();
+        bitmask::init();
+
+        let mut state = State::new("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3");
+
+        let mut search_engine = SearchEngine::new(131072);
+        search_engine.set_threads(2);
+        search_engine.set_silent(true);
+
+        let best_mov = search_engine.search(&mut state, TimeCapacity { main_time_millis: u128::MAX }, 64, 20000);
+        assert!(mov_table::gen_legal_mov_list(&mut state).contains(&best_mov));
+
+        // the limit applies to the nodes of both threads, a thread may pass it by the node it is visiting
+        assert!(search_engine.get_node_count() >= 20000);
+        assert!(search_engine.get_node_count() <= 20002);
+        assert!(search_engine.node_count < 20000);
+    }
+
+    #[test]
+    fn test_search_mate() {
+        zob_keys::init();
+        bitmask::init();
+
+        let mut state = State::new("kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1");
+        let mut search_engine = SearchEngine::new(131072);
+
+        let time_capacity = TimeCapacity {
+            main_time_millis: u128::MAX,
+        };
+
+        let best_mov = search_engine.search_mate(&mut state, time_capacity, 2, u64::MAX);
+
+        let (from, to, _, _) = util::decode_u32_mov(best_mov);
+        assert_eq!(from, util::map_sqr_notation_to_index("a1"));
+        assert_eq!(to, util::map_sqr_notation_to_index("a6"));
+    }
+
+    #[test]
+    fn test_search_movs() {
+        zob_keys::init();
+        bitmask::init();
+
+        let mut state = State::new("kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1");
+        let mut search_engine = SearchEngine::new(131072);
+
+        let time_capacity = TimeCapacity {
+            main_time_millis: u128::MAX,
+        };
+
+        let a1a2 = util::encode_u32_mov(util::map_sqr_notation_to_index("a1"), util::map_sqr_notation_to_index("a2"), def::MOV_REG, 0);
+        let a1a3 = util::encode_u32_mov(util::map_sqr_notation_to_index("a1"), util::map_sqr_notation_to_index("a3"), def::MOV_REG, 0);
+
+        search_engine.search(&mut state, time_capacity, 8, u64::MAX);
+
+        let time_capacity = TimeCapacity {
+            main_time_millis: u128::MAX,
+        };
+
+        search_engine.set_search_movs(vec![a1a2, a1a3]);
+        let best_mov = search_engine.search(&mut state, time_capacity, 4, u64::MAX);
+
+        assert!(best_mov == a1a2 || best_mov == a1a3);
+        assert!(search_engine.search_mov_list.is_empty());
+    }
+
+    #[test]
+    fn test_search_ponder() {
+        zob_keys::init();
+        bitmask::init();
+
+        let mut state = State::new("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3");
+        let mut search_engine = SearchEngine::new(131072);
+
+        let time_capacity = TimeCapacity {
+            main_time_millis: 200,
+        };
+
+        search_engine.get_search_handle().ponder_hit();
+
+        search_engine.set_ponder();
+        let best_mov = search_engine.search(&mut state, time_capacity, 64, u64::MAX);
+
+        assert_ne!(0, best_mov);
+        assert_ne!(0, search_engine.get_ponder_mov());
+        assert!(!search_engine.pondering);
+    }
+
+    #[test]
+    fn test_search_ponder_stop() {
+        zob_keys::init();
+        bitmask::init();
+
+        let mut state = State::new("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3");
+        let mut search_engine = SearchEngine::new(131072);
+        search_engine.set_silent(true);
+
+        let search_handle = search_engine.get_search_handle();
+
+        // go ponder, stop before the search starts
+        search_handle.reset();
+        search_handle.clear_ponder_hit();
+        search_handle.stop();
+
+        search_engine.set_ponder();
+        let best_mov = search_engine.search(&mut state, TimeCapacity { main_time_millis: 200 }, 64, u64::MAX);
+
+        assert!(mov_table::gen_legal_mov_list(&mut state).contains(&best_mov));
+        assert!(!search_engine.pondering);
+
+        // go ponder, stop right after the search starts
+        search_handle.reset();
+        search_handle.clear_ponder_hit();
+
+        let stopper = thread::spawn(move || search_handle.stop());
+
+        search_engine.set_ponder();
+        let best_mov = search_engine.search(&mut state, TimeCapacity { main_time_millis: 200 }, 64, u64::MAX);
+        stopper.join().unwrap();
+
+        assert!(mov_table::gen_legal_mov_list(&mut state).contains(&best_mov));
+        assert!(!search_engine.pondering);
+    }
+
+    #[test]
+    fn test_search_multi_pv() {
+        zob_keys::init();
+        bitmask::init();
+
+        let mut state = State::new("kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1");
+        let mut search_engine = SearchEngine::new(131072);
+
+        let time_capacity = TimeCapacity {
+            main_time_millis: u128::MAX,
+        };
+
+        search_engine.set_multi_pv(3);
+        let best_mov = search_engine.search(&mut state, time_capacity, 6, u64::MAX);
+
+        let (from, to, _, _) = util::decode_u32_mov(best_mov);
+        assert_eq!(from, util::map_sqr_notation_to_index("a1"));
+        assert_eq!(to, util::map_sqr_notation_to_index("a6"));
+
+        let root_mov_list = &search_engine.excluded_root_mov_list;
+        assert_eq!(3, root_mov_list.len());
+        assert!(root_mov_list.contains(&best_mov));
+        assert_ne!(root_mov_list[0], root_mov_list[1]);
+        assert_ne!(root_mov_list[1], root_mov_list[2]);
+        assert_ne!(root_mov_list[0], root_mov_list[2]);
+    }
+
+    #[test]
+    fn test_search_handle_stop() {
+        zob_keys::init();
+        bitmask::init();
+
+        let mut state = State::new("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3");
+        let mut search_engine = SearchEngine::new(131072);
+        let mut other_search_engine = SearchEngine::new(131072);
+
+        let time_capacity = TimeCapacity {
+            main_time_millis: u128::MAX,
+        };
+
+        let search_handle = search_engine.get_search_handle();
+
+        let stopper = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            search_handle.stop();
+        });
+
+        let best_mov = search_engine.search(&mut state, time_capacity, 64, u64::MAX);
+        stopper.join().unwrap();
+
+        assert_ne!(0, best_mov);
+        assert!(!search_engine.get_search_handle().is_stopped());
+        assert!(!other_search_engine.get_search_handle().is_stopped());
+
+        let time_capacity = TimeCapacity {
+            main_time_millis: u128::MAX,
+        };
+
+        let best_mov = other_search_engine.search(&mut state, time_capacity, 3, u64::MAX);
+        assert_ne!(0, best_mov);
+    }
+
+    #[test]
+    fn test_search_single_root_mov() {
+        zob_keys::init();
+        bitmask::init();
+
+        let time_capacity = || TimeCapacity {
+            main_time_millis: u128::MAX,
+        };
+
+        let search_to_depth = |fen: &str, search_mov_str: Option<&str>, depth: u8| {
+            let mut state = State::new(fen);
+            let mut search_engine = SearchEngine::new(131072);
+            search_engine.set_silent(true);
+
+            if let Some(search_mov_str) = search_mov_str {
+                let search_mov = mov_table::gen_legal_mov_list(&mut state).into_iter().find(|mov| util::format_mov(*mov) == search_mov_str).unwrap();
+                search_engine.set_search_movs(vec![search_mov]);
+            }
+
+            let best_mov = search_engine.search(&mut state, time_capacity(), depth, u64::MAX);
+            (util::format_mov(best_mov), search_engine.get_node_count())
+        };
+
+        let (best_mov, depth_2_node_count) = search_to_depth("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", Some("e2e4"), 2);
+        assert_eq!("e2e4", best_mov);
+
+        let (best_mov, depth_3_node_count) = search_to_depth("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", Some("e2e4"), 3);
+        assert_eq!("e2e4", best_mov);
+        assert!(depth_3_node_count > depth_2_node_count);
+
+        let only_legal_mov_fen = "7k/8/8/8/8/8/6q1/K7 w - - 0 1";
+
+        let (best_mov, depth_2_node_count) = search_to_depth(only_legal_mov_fen, None, 2);
+        assert_eq!("a1b1", best_mov);
+
+        let (best_mov, depth_4_node_count) = search_to_depth(only_legal_mov_fen, None, 4);
+        assert_eq!("a1b1", best_mov);
+        assert!(depth_4_node_count > depth_2_node_count);
+    }
+
+    #[test]
+    fn test_search_handle_stop_before_search() {
+        zob_keys::init();
+        bitmask::init();
+
+        let mut state = State::new("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3");
+        let mut search_engine = SearchEngine::new(131072);
+        search_engine.set_silent(true);
+
+        let time_capacity = TimeCapacity {
+            main_time_millis: u128::MAX,
+        };
+
+        search_engine.get_search_handle().stop();
+
+        let best_mov = search_engine.search(&mut state, time_capacity, 128, u64::MAX);
+        assert!(mov_table::gen_legal_mov_list(&mut state).contains(&best_mov));
+        assert!(!search_engine.get_search_handle().is_stopped());
+    }
+
+    #[test]
+    fn test_search_threads() {
+        zob_keys::init();
+        bitmask::init();
+
+        let mut state = State::new("kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1");
+        let mut search_engine = SearchEngine::new(131072);
+
+        let time_capacity = TimeCapacity {
+            main_time_millis: u128::MAX,
+        };
+
+        search_engine.set_threads(4);
+        assert_eq!(3, search_engine.helper_list.len());
+
+        let best_mov = search_engine.search(&mut state, time_capacity, 6, u64::MAX);
+
+        let (from, to, _, _) = util::decode_u32_mov(best_mov);
+        assert_eq!(from, util::map_sqr_notation_to_index("a1"));
+        assert_eq!(to, util::map_sqr_notation_to_index("a6"));
+
+        assert_eq!(3, search_engine.helper_list.len());
+        assert!(search_engine.helper_list.iter().all(|helper| Arc::ptr_eq(&helper.depth_preferred_hash_table, &search_engine.depth_preferred_hash_table)));
+    }
+
     #[test]
     fn test_search_0() {
         zob_keys::init();
@@ -1288,10 +2065,9 @@ mod tests {
 
         let time_capacity = TimeCapacity {
             main_time_millis: 55500,
-            extra_time_millis: 5500,
         };
 
-        let best_mov = search_engine.search(&mut state, time_capacity, 64);
+        let best_mov = search_engine.search(&mut state, time_capacity, 64, u64::MAX);
 
         let (from, to, _, _) = util::decode_u32_mov(best_mov);
         assert_eq!(from, util::map_sqr_notation_to_index("f2"));
@@ -1308,10 +2084,9 @@ mod tests {
 
         let time_capacity = TimeCapacity {
             main_time_millis: 15500,
-            extra_time_millis: 15500,
         };
 
-        let best_mov = search_engine.search(&mut state, time_capacity, 64);
+        let best_mov = search_engine.search(&mut state, time_capacity, 64, u64::MAX);
 
         let (from, to, _, _) = util::decode_u32_mov(best_mov);
         assert_eq!(from, util::map_sqr_notation_to_index("e2"));
@@ -1328,10 +2103,9 @@ mod tests {
 
         let time_capacity = TimeCapacity {
             main_time_millis: 25500,
-            extra_time_millis: 15500,
         };
 
-        let best_mov = search_engine.search(&mut state, time_capacity, 64);
+        let best_mov = search_engine.search(&mut state, time_capacity, 64, u64::MAX);
 
         let (from, to, _, _) = util::decode_u32_mov(best_mov);
         assert_eq!(from, util::map_sqr_notation_to_index("c6"));
@@ -1348,10 +2122,9 @@ mod tests {
 
         let time_capacity = TimeCapacity {
             main_time_millis: 15500,
-            extra_time_millis: 15500,
         };
 
-        let best_mov = search_engine.search(&mut state, time_capacity, 64);
+        let best_mov = search_engine.search(&mut state, time_capacity, 64, u64::MAX);
 
         let (from, to, _, _) = util::decode_u32_mov(best_mov);
         assert_eq!(from, util::map_sqr_notation_to_index("h5"));
@@ -1368,10 +2141,9 @@ mod tests {
 
         let time_capacity = TimeCapacity {
             main_time_millis: 15500,
-            extra_time_millis: 5500,
         };
 
-        let best_mov = search_engine.search(&mut state, time_capacity, 64);
+        let best_mov = search_engine.search(&mut state, time_capacity, 64, u64::MAX);
 
         let (from, to, _, _) = util::decode_u32_mov(best_mov);
         assert_eq!(from, util::map_sqr_notation_to_index("c6"));
@@ -1388,10 +2160,9 @@ mod tests {
 
         let time_capacity = TimeCapacity {
             main_time_millis: 25500,
-            extra_time_millis: 5500,
         };
 
-        let best_mov = search_engine.search(&mut state, time_capacity, 64);
+        let best_mov = search_engine.search(&mut state, time_capacity, 64, u64::MAX);
 
         let (from, to, _, _) = util::decode_u32_mov(best_mov);
         assert_eq!(from, util::map_sqr_notation_to_index("a8"));
@@ -1408,10 +2179,9 @@ mod tests {
 
         let time_capacity = TimeCapacity {
             main_time_millis: 15500,
-            extra_time_millis: 5500,
         };
 
-        let best_mov = search_engine.search(&mut state, time_capacity, 64);
+        let best_mov = search_engine.search(&mut state, time_capacity, 64, u64::MAX);
 
         let (from, to, _, _) = util::decode_u32_mov(best_mov);
         assert_eq!(from, util::map_sqr_notation_to_index("c6"));
@@ -1428,10 +2198,9 @@ mod tests {
 
         let time_capacity = TimeCapacity {
             main_time_millis: 15500,
-            extra_time_millis: 5500,
         };
 
-        let best_mov = search_engine.search(&mut state, time_capacity, 64);
+        let best_mov = search_engine.search(&mut state, time_capacity, 64, u64::MAX);
 
         let (from, to, _, _) = util::decode_u32_mov(best_mov);
         assert_eq!(from, util::map_sqr_notation_to_index("e1"));
@@ -1448,10 +2217,9 @@ mod tests {
 
         let time_capacity = TimeCapacity {
             main_time_millis: 15500,
-            extra_time_millis: 5500,
         };
 
-        let best_mov = search_engine.search(&mut state, time_capacity, 64);
+        let best_mov = search_engine.search(&mut state, time_capacity, 64, u64::MAX);
 
         let (from, to, _, _) = util::decode_u32_mov(best_mov);
         assert_eq!(from, util::map_sqr_notation_to_index("b3"));
@@ -1468,10 +2236,9 @@ mod tests {
 
         let time_capacity = TimeCapacity {
             main_time_millis: 55500,
-            extra_time_millis: 5500,
         };
 
-        let best_mov = search_engine.search(&mut state, time_capacity, 64);
+        let best_mov = search_engine.search(&mut state, time_capacity, 64, u64::MAX);
 
         let (from, to, _, _) = util::decode_u32_mov(best_mov);
         assert_eq!(from, util::map_sqr_notation_to_index("e5"));
@@ -1488,10 +2255,9 @@ mod tests {
 
         let time_capacity = TimeCapacity {
             main_time_millis: 55500,
-            extra_time_millis: 5500,
         };
 
-        let best_mov = search_engine.search(&mut state, time_capacity, 64);
+        let best_mov = search_engine.search(&mut state, time_capacity, 64, u64::MAX);
 
         let (from, to, _, _) = util::decode_u32_mov(best_mov);
         assert_eq!(from, util::map_sqr_notation_to_index("e2"));
@@ -0,0 +1,667 @@
+/*
+ * Copyright (C) 2020-2022 Zixiao Han
+ */
+
+//! Games between engine configurations played in-process, and match statistics.
+
+use crate::{
+    def,
+    eval::EvalParams,
+    mov_table,
+    nnue::Network,
+    pgn::{self, PgnGame, PgnMov},
+    search::SearchEngine,
+    state::State,
+    time_control::TimeCapacity,
+    uci,
+    util,
+};
+
+use std::fs;
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+pub const DEFAULT_HASH_SIZE_MB: usize = 16;
+
+const MAX_DEPTH: u8 = 128;
+const MAX_NON_CAP_HALF_MOV_COUNT: u16 = 100;
+const REP_DRAW_COUNT: usize = 3;
+
+const Z_95: f64 = 1.959964;
+
+/// A search engine configuration taking part in a match.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EngineConfig {
+    pub name: String,
+    pub hash_size: usize,
+    pub thread_count: usize,
+    pub eval_params: EvalParams,
+    pub network: Option<Arc<Network>>,
+}
+
+impl EngineConfig {
+    pub fn new(name: &str) -> Self {
+        EngineConfig {
+            name: name.to_owned(),
+            hash_size: DEFAULT_HASH_SIZE_MB * def::MIN_HASH_SIZE_UNIT,
+            thread_count: 1,
+            eval_params: EvalParams::default(),
+            network: None,
+        }
+    }
+
+    pub fn build_engine(&self) -> SearchEngine {
+        let mut search_engine = SearchEngine::new(self.hash_size);
+        search_engine.set_threads(self.thread_count);
+        search_engine.set_eval_params(self.eval_params.clone());
+        search_engine.set_network(self.network.clone());
+        search_engine.set_silent(true);
+
+        search_engine
+    }
+}
+
+/// The limit of every search in a game.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SearchLimit {
+    Nodes(u64),
+    Depth(u8),
+    MoveTime(u128),
+}
+
+/// Ends a game as a draw once, from move `min_full_mov_count` on, the scores of both
+/// sides have stayed within `score` for `mov_count` moves each.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DrawAdjudication {
+    pub min_full_mov_count: u16,
+    pub mov_count: usize,
+    pub score: i32,
+}
+
+/// Ends a game once both sides have agreed for `mov_count` moves each
+/// that one of them is ahead by at least `score`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ResignAdjudication {
+    pub mov_count: usize,
+    pub score: i32,
+}
+
+/// How the moves of a game are searched and when a game is adjudicated.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GameConfig {
+    pub search_limit: SearchLimit,
+    pub draw_adjudication: Option<DrawAdjudication>,
+    pub resign_adjudication: Option<ResignAdjudication>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GameResult {
+    WhiteWin,
+    BlackWin,
+    Draw,
+}
+
+impl GameResult {
+    pub fn as_pgn_str(&self) -> &'static str {
+        match self {
+            GameResult::WhiteWin => "1-0",
+            GameResult::BlackWin => "0-1",
+            GameResult::Draw => "1/2-1/2",
+        }
+    }
+}
+
+/// A finished game: the moves played from the start position with their
+/// search scores from White's point of view, the result and how it was reached.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameRecord {
+    pub start_fen: String,
+    pub mov_list: Vec<u32>,
+    pub score_list: Vec<i32>,
+    pub result: GameResult,
+    pub termination: String,
+    pub adjudicated: bool,
+}
+
+impl GameRecord {
+    /// Converts the game to PGN, ending with a comment on how the game was decided.
+    pub fn to_pgn_game(&self, white_name: &str, black_name: &str, round: usize) -> PgnGame {
+        let mut tag_list = vec![
+            ("Event".to_owned(), "FoxSEE match".to_owned()),
+            ("Site".to_owned(), "?".to_owned()),
+            ("Date".to_owned(), "????.??.??".to_owned()),
+            ("Round".to_owned(), round.to_string()),
+            ("White".to_owned(), white_name.to_owned()),
+            ("Black".to_owned(), black_name.to_owned()),
+            ("Result".to_owned(), self.result.as_pgn_str().to_owned()),
+        ];
+
+        if self.start_fen != uci::FEN_START_POS {
+            tag_list.push(("SetUp".to_owned(), "1".to_owned()));
+            tag_list.push(("FEN".to_owned(), self.start_fen.clone()));
+        }
+
+        tag_list.push(("PlyCount".to_owned(), self.mov_list.len().to_string()));
+        tag_list.push(("Termination".to_owned(), if self.adjudicated { "adjudication" } else { "normal" }.to_owned()));
+
+        let mut state = State::new(&self.start_fen);
+        let mut mov_list = Vec::new();
+
+        for &mov in &self.mov_list {
+            mov_list.push(PgnMov::new(&pgn::format_san(&mut state, mov)));
+
+            let (from, to, tp, promo) = util::decode_u32_mov(mov);
+            state.do_mov(from, to, tp, promo);
+        }
+
+        let mut pgn_game = PgnGame {
+            tag_list,
+            mov_list,
+            result: self.result.as_pgn_str().to_owned(),
+            ..Default::default()
+        };
+
+        match pgn_game.mov_list.last_mut() {
+            Some(last_mov) => last_mov.comment = Some(self.termination.clone()),
+            None => pgn_game.comment = Some(self.termination.clone()),
+        }
+
+        pgn_game
+    }
+}
+
+/// Plays a game from `start_state` until it is over by the rules or adjudicated.
+pub fn play_game(start_state: &State, white_engine: &mut SearchEngine, black_engine: &mut SearchEngine, game_config: &GameConfig) -> GameRecord {
+    let mut state = start_state.clone();
+    let start_fen = state.to_fen();
+
+    let mut pos_list = vec![state.get_zob_key()];
+    let mut mov_list = Vec::new();
+    let mut score_list = Vec::new();
+
+    let (result, termination, adjudicated) = loop {
+        let legal_mov_list = mov_table::gen_legal_mov_list(&mut state);
+
+        if legal_mov_list.is_empty() {
+            if !mov_table::is_in_check(&state, state.player) {
+                break (GameResult::Draw, "Draw by stalemate", false)
+            } else if state.player == def::PLAYER_W {
+                break (GameResult::BlackWin, "Black mates", false)
+            } else {
+                break (GameResult::WhiteWin, "White mates", false)
+            }
+        }
+
+        if state.half_mov_count >= MAX_NON_CAP_HALF_MOV_COUNT {
+            break (GameResult::Draw, "Draw by fifty moves rule", false)
+        }
+
+        if pos_list.iter().filter(|zob_key| **zob_key == state.get_zob_key()).count() >= REP_DRAW_COUNT {
+            break (GameResult::Draw, "Draw by 3-fold repetition", false)
+        }
+
+        if is_insufficient_material(&state) {
+            break (GameResult::Draw, "Draw by insufficient mating material", false)
+        }
+
+        if let Some((result, termination)) = adjudicate(&state, &score_list, game_config) {
+            break (result, termination, true)
+        }
+
+        let search_engine = if state.player == def::PLAYER_W {
+            &mut *white_engine
+        } else {
+            &mut *black_engine
+        };
+
+        let mov = search_to_limit(search_engine, &mut state, game_config.search_limit);
+
+        let score = if state.player == def::PLAYER_W {
+            search_engine.get_last_score()
+        } else {
+            -search_engine.get_last_score()
+        };
+
+        let (from, to, tp, promo) = util::decode_u32_mov(mov);
+        state.do_mov(from, to, tp, promo);
+
+        pos_list.push(state.get_zob_key());
+        mov_list.push(mov);
+        score_list.push(score);
+    };
+
+    GameRecord {
+        start_fen,
+        mov_list,
+        score_list,
+        result,
+        termination: termination.to_owned(),
+        adjudicated,
+    }
+}
+
+pub(crate) fn search_to_limit(search_engine: &mut SearchEngine, state: &mut State, search_limit: SearchLimit) -> u32 {
+    match search_limit {
+        SearchLimit::Nodes(max_nodes) => search_engine.search(state, get_unlimited_time(), MAX_DEPTH, max_nodes),
+        SearchLimit::Depth(max_depth) => search_engine.search(state, get_unlimited_time(), max_depth, u64::MAX),
+        SearchLimit::MoveTime(time_millis) => search_engine.search(state, TimeCapacity { main_time_millis: time_millis }, MAX_DEPTH, u64::MAX),
+    }
+}
+
+fn get_unlimited_time() -> TimeCapacity {
+    TimeCapacity {
+        main_time_millis: u128::MAX,
+    }
+}
+
+fn is_insufficient_material(state: &State) -> bool {
+    let heavy_count = state.wp_count + state.bp_count + state.wr_count + state.br_count + state.wq_count + state.bq_count;
+    let minor_count = state.wn_count + state.bn_count + state.wb_count + state.bb_count;
+
+    heavy_count == 0 && minor_count <= 1
+}
+
+fn adjudicate(state: &State, score_list: &[i32], game_config: &GameConfig) -> Option<(GameResult, &'static str)> {
+    if let Some(resign_adjudication) = game_config.resign_adjudication {
+        let ply_count = resign_adjudication.mov_count * 2;
+
+        if ply_count > 0 && score_list.len() >= ply_count {
+            let recent_score_list = &score_list[score_list.len() - ply_count..];
+
+            if recent_score_list.iter().all(|score| *score >= resign_adjudication.score) {
+                return Some((GameResult::WhiteWin, "White wins by adjudication"))
+            }
+
+            if recent_score_list.iter().all(|score| *score <= -resign_adjudication.score) {
+                return Some((GameResult::BlackWin, "Black wins by adjudication"))
+            }
+        }
+    }
+
+    if let Some(draw_adjudication) = game_config.draw_adjudication {
+        let ply_count = draw_adjudication.mov_count * 2;
+
+        if ply_count > 0 && score_list.len() >= ply_count && state.get_fen_full_mov_count() >= draw_adjudication.min_full_mov_count
+            && score_list[score_list.len() - ply_count..].iter().all(|score| score.abs() <= draw_adjudication.score) {
+            return Some((GameResult::Draw, "Draw by adjudication"))
+        }
+    }
+
+    None
+}
+
+/// Reads the opening positions of an EPD file, one per line.
+/// Only the first four fields are used, the operations are ignored.
+pub fn read_openings(file_path: &str) -> io::Result<Vec<String>> {
+    let epd_str = fs::read_to_string(file_path)?;
+    let mut opening_fen_list = Vec::new();
+
+    for (line_index, line) in epd_str.lines().enumerate() {
+        let field_list = line.split_whitespace().take(4).collect::<Vec<&str>>();
+
+        if field_list.is_empty() || line.trim_start().starts_with('#') {
+            continue
+        }
+
+        let fen = format!("{} 0 1", field_list.join(" "));
+
+        if let Err(error) = State::from_fen(&fen) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("invalid position on line {}: {}", line_index + 1, error)))
+        }
+
+        opening_fen_list.push(fen);
+    }
+
+    Ok(opening_fen_list)
+}
+
+/// Bounds of a sequential probability ratio test on the Elo difference,
+/// with the accepted false positive rate `alpha` and false negative rate `beta`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+impl Sprt {
+    pub fn new(elo0: f64, elo1: f64) -> Self {
+        Sprt {
+            elo0,
+            elo1,
+            alpha: 0.05,
+            beta: 0.05,
+        }
+    }
+
+    /// Returns the log-likelihood ratios below which H0 and above which H1 is accepted.
+    pub fn get_llr_bounds(&self) -> (f64, f64) {
+        ((self.beta / (1. - self.alpha)).ln(), ((1. - self.beta) / self.alpha).ln())
+    }
+}
+
+/// Results of a match from the first engine's point of view.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MatchStats {
+    pub win_count: usize,
+    pub draw_count: usize,
+    pub loss_count: usize,
+}
+
+impl MatchStats {
+    pub fn get_game_count(&self) -> usize {
+        self.win_count + self.draw_count + self.loss_count
+    }
+
+    pub fn get_score(&self) -> f64 {
+        (self.win_count as f64 + self.draw_count as f64 / 2.) / self.get_game_count().max(1) as f64
+    }
+
+    pub fn get_elo(&self) -> f64 {
+        get_elo_from_score(self.get_score())
+    }
+
+    /// Returns the half width of the 95% confidence interval of the Elo difference.
+    pub fn get_elo_error(&self) -> f64 {
+        let score = self.get_score();
+        let score_deviation = (self.get_score_variance() / self.get_game_count().max(1) as f64).sqrt();
+
+        let upper_score = (score + Z_95 * score_deviation).min(1.);
+        let lower_score = (score - Z_95 * score_deviation).max(0.);
+
+        let elo_error = (get_elo_from_score(upper_score) - get_elo_from_score(lower_score)) / 2.;
+
+        // a perfect or zero score leaves the error unbounded
+        if elo_error.is_nan() {
+            f64::INFINITY
+        } else {
+            elo_error
+        }
+    }
+
+    /// Returns the log-likelihood ratio of the SPRT hypotheses,
+    /// using the normal approximation of the game score.
+    pub fn get_llr(&self, sprt: &Sprt) -> f64 {
+        let variance = self.get_score_variance();
+
+        if variance <= 0. {
+            return 0.
+        }
+
+        let score0 = get_score_from_elo(sprt.elo0);
+        let score1 = get_score_from_elo(sprt.elo1);
+
+        self.get_game_count() as f64 * (score1 - score0) * (2. * self.get_score() - score0 - score1) / (2. * variance)
+    }
+
+    fn get_score_variance(&self) -> f64 {
+        let game_count = self.get_game_count();
+
+        if game_count == 0 {
+            return 0.
+        }
+
+        let score = self.get_score();
+
+        (self.win_count as f64 * (1. - score).powi(2) + self.draw_count as f64 * (0.5 - score).powi(2) + self.loss_count as f64 * score.powi(2)) / game_count as f64
+    }
+}
+
+fn get_elo_from_score(score: f64) -> f64 {
+    -400. * (1. / score - 1.).log10()
+}
+
+fn get_score_from_elo(elo: f64) -> f64 {
+    1. / (1. + 10f64.powf(-elo / 400.))
+}
+
+/// A match between two engine configurations. Every opening is played twice
+/// with colors reversed, the first engine playing White in even-numbered games.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MatchConfig {
+    pub engine_config_list: [EngineConfig; 2],
+    pub opening_fen_list: Vec<String>,
+    pub game_count: usize,
+    pub concurrency: usize,
+    pub game_config: GameConfig,
+    pub sprt: Option<Sprt>,
+}
+
+/// Plays the match on `concurrency` threads and returns the final results.
+/// `on_game_finished` is called on the calling thread after every game with the game index, the game and
+/// the results so far. The match stops early once the SPRT accepts either hypothesis; games still in progress
+/// at that point are neither counted nor reported, so the returned results are the ones the SPRT decided on.
+pub fn run_match<F: FnMut(usize, &GameRecord, &MatchStats)>(match_config: &MatchConfig, mut on_game_finished: F) -> MatchStats {
+    let mut match_stats = MatchStats::default();
+
+    let next_game_index = &AtomicUsize::new(0);
+    let stopped = &AtomicBool::new(false);
+    let (sender, receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..match_config.concurrency.max(1) {
+            let sender = sender.clone();
+
+            scope.spawn(move || {
+                let mut engine_list = [match_config.engine_config_list[0].build_engine(), match_config.engine_config_list[1].build_engine()];
+
+                while !stopped.load(Ordering::Relaxed) {
+                    let game_index = next_game_index.fetch_add(1, Ordering::Relaxed);
+
+                    if game_index >= match_config.game_count {
+                        break
+                    }
+
+                    let start_state = match match_config.opening_fen_list.get(game_index / 2 % match_config.opening_fen_list.len().max(1)) {
+                        Some(opening_fen) => State::new(opening_fen),
+                        None => State::new(uci::FEN_START_POS),
+                    };
+
+                    for search_engine in &mut engine_list {
+                        search_engine.reset();
+                    }
+
+                    let [first_engine, second_engine] = &mut engine_list;
+
+                    let game_record = if game_index & 1 == 0 {
+                        play_game(&start_state, first_engine, second_engine, &match_config.game_config)
+                    } else {
+                        play_game(&start_state, second_engine, first_engine, &match_config.game_config)
+                    };
+
+                    if sender.send((game_index, game_record)).is_err() {
+                        break
+                    }
+                }
+            });
+        }
+
+        drop(sender);
+
+        for (game_index, game_record) in receiver {
+            if stopped.load(Ordering::Relaxed) {
+                continue
+            }
+
+            let first_engine_result = match (game_record.result, game_index & 1 == 0) {
+                (GameResult::Draw, _) => GameResult::Draw,
+                (GameResult::WhiteWin, true) | (GameResult::BlackWin, false) => GameResult::WhiteWin,
+                _ => GameResult::BlackWin,
+            };
+
+            match first_engine_result {
+                GameResult::WhiteWin => match_stats.win_count += 1,
+                GameResult::Draw => match_stats.draw_count += 1,
+                GameResult::BlackWin => match_stats.loss_count += 1,
+            }
+
+            on_game_finished(game_index, &game_record, &match_stats);
+
+            if let Some(sprt) = match_config.sprt {
+                let llr = match_stats.get_llr(&sprt);
+                let (lower_bound, upper_bound) = sprt.get_llr_bounds();
+
+                if llr <= lower_bound || llr >= upper_bound {
+                    stopped.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+    });
+
+    match_stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bitmask,
+        zob_keys,
+    };
+
+    #[test]
+    fn test_play_game() {
+        zob_keys::init();
+        bitmask::init();
+
+        let game_config = GameConfig {
+            search_limit: SearchLimit::Depth(3),
+            draw_adjudication: None,
+            resign_adjudication: None,
+        };
+
+        let mut white_engine = EngineConfig::new("white").build_engine();
+        let mut black_engine = EngineConfig::new("black").build_engine();
+
+        let game_record = play_game(&State::new("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1"), &mut white_engine, &mut black_engine, &game_config);
+        assert_eq!(GameResult::WhiteWin, game_record.result);
+        assert_eq!("White mates", game_record.termination);
+        assert_eq!(1, game_record.mov_list.len());
+
+        let pgn_game = game_record.to_pgn_game("white", "black", 1);
+        assert_eq!(Some("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1"), pgn_game.get_tag("FEN"));
+        assert_eq!("Ra8#", pgn_game.mov_list[0].san);
+        assert_eq!(Some("White mates".to_owned()), pgn_game.mov_list[0].comment);
+        assert_eq!("1-0", pgn_game.result);
+
+        let game_record = play_game(&State::new("8/8/8/4k3/8/8/3NK3/8 w - - 0 1"), &mut white_engine, &mut black_engine, &game_config);
+        assert_eq!(GameResult::Draw, game_record.result);
+        assert_eq!("Draw by insufficient mating material", game_record.termination);
+        assert!(game_record.mov_list.is_empty());
+
+        let game_config = GameConfig {
+            search_limit: SearchLimit::Nodes(1000),
+            draw_adjudication: None,
+            resign_adjudication: Some(ResignAdjudication { mov_count: 2, score: 500 }),
+        };
+
+        let game_record = play_game(&State::new("3qk3/8/8/8/8/8/PPPPPPPP/RNBQKBNR w KQ - 0 1"), &mut white_engine, &mut black_engine, &game_config);
+        assert_eq!(GameResult::WhiteWin, game_record.result);
+        assert!(game_record.adjudicated);
+        assert_eq!(4, game_record.score_list.len());
+    }
+
+    #[test]
+    fn test_adjudicate_draw_mov_number() {
+        zob_keys::init();
+        bitmask::init();
+
+        let mut state = State::new("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+
+        for _ in 0..5 {
+            for mov_str in ["g1f3", "g8f6", "f3g1", "f6g8"] {
+                let mov = mov_table::gen_legal_mov_list(&mut state).into_iter().find(|mov| util::format_mov(*mov) == mov_str).unwrap();
+                let (from, to, tp, promo) = util::decode_u32_mov(mov);
+                state.do_mov(from, to, tp, promo);
+            }
+        }
+
+        assert_eq!(11, state.get_fen_full_mov_count());
+
+        let score_list = vec![0; 20];
+        let draw_game_config = |min_full_mov_count| GameConfig {
+            search_limit: SearchLimit::Depth(1),
+            draw_adjudication: Some(DrawAdjudication { min_full_mov_count, mov_count: 2, score: 10 }),
+            resign_adjudication: None,
+        };
+
+        assert!(adjudicate(&state, &score_list, &draw_game_config(12)).is_none());
+        assert!(adjudicate(&state, &score_list, &draw_game_config(20)).is_none());
+        assert_eq!(Some((GameResult::Draw, "Draw by adjudication")), adjudicate(&state, &score_list, &draw_game_config(11)));
+    }
+
+    #[test]
+    fn test_match_stats() {
+        let match_stats = MatchStats {
+            win_count: 60,
+            draw_count: 20,
+            loss_count: 20,
+        };
+
+        assert_eq!(100, match_stats.get_game_count());
+        assert!((match_stats.get_score() - 0.7).abs() < 1e-9);
+        assert!((match_stats.get_elo() - 147.2).abs() < 0.1);
+        assert!((match_stats.get_elo_error() - 66.0).abs() < 0.1);
+
+        let sprt = Sprt::new(0., 5.);
+        let (lower_bound, upper_bound) = sprt.get_llr_bounds();
+        assert!((lower_bound + 2.944).abs() < 0.001);
+        assert!((upper_bound - 2.944).abs() < 0.001);
+        assert!((match_stats.get_llr(&sprt) - 0.884).abs() < 0.001);
+
+        let decided_stats = MatchStats {
+            win_count: 600,
+            draw_count: 200,
+            loss_count: 200,
+        };
+
+        assert!(decided_stats.get_llr(&sprt) > upper_bound);
+
+        let even_stats = MatchStats {
+            win_count: 30,
+            draw_count: 40,
+            loss_count: 30,
+        };
+
+        assert!(even_stats.get_elo().abs() < 1e-9);
+        assert!(even_stats.get_llr(&sprt) < 0.);
+        assert_eq!(0., MatchStats::default().get_llr(&sprt));
+    }
+
+    #[test]
+    fn test_run_match_sprt_stop() {
+        zob_keys::init();
+        bitmask::init();
+
+        // White mates in one, so the engines win and lose alternately
+        let match_config = MatchConfig {
+            engine_config_list: [EngineConfig::new("first"), EngineConfig::new("second")],
+            opening_fen_list: vec!["7k/8/6K1/8/8/8/8/Q7 w - - 0 1".to_owned()],
+            game_count: 200,
+            concurrency: 4,
+            game_config: GameConfig {
+                search_limit: SearchLimit::Depth(3),
+                draw_adjudication: None,
+                resign_adjudication: None,
+            },
+            sprt: Some(Sprt::new(0., 400.)),
+        };
+
+        let sprt = match_config.sprt.unwrap();
+        let (lower_bound, _upper_bound) = sprt.get_llr_bounds();
+
+        let mut reported_stats_list = Vec::new();
+        let match_stats = run_match(&match_config, |_game_index, _game_record, match_stats| reported_stats_list.push(*match_stats));
+
+        assert!(match_stats.get_game_count() < match_config.game_count);
+        assert_eq!(Some(&match_stats), reported_stats_list.last());
+        assert_eq!(match_stats.get_game_count(), reported_stats_list.len());
+
+        assert!(match_stats.get_llr(&sprt) <= lower_bound);
+        assert!(reported_stats_list[..reported_stats_list.len() - 1].iter().all(|stats| stats.get_llr(&sprt) > lower_bound));
+    }
+}
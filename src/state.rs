@@ -6,11 +6,14 @@ use crate::{
     bitboard::BitBoard,
     bitmask,
     def,
+    nnue::{Accumulator, Network},
     util,
     zob_keys,
 };
 
+use std::error::Error;
 use std::fmt;
+use std::sync::Arc;
 
 const FEN_SQRS_INDEX: usize = 0;
 const FEN_PLAYER_INDEX: usize = 1;
@@ -18,37 +21,51 @@ const FEN_CAS_RIGHTS_INDEX: usize = 2;
 const FEN_ENP_SQR_INDEX: usize = 3;
 const FEN_HALF_MOV_INDEX: usize = 4;
 const FEN_FULL_MOV_INDEX: usize = 5;
+const FEN_SEGMENT_COUNT: usize = 6;
+
+const FEN_SEGMENT_NAMES: [&str; FEN_SEGMENT_COUNT] = [
+    "piece placement",
+    "side to move",
+    "castling rights",
+    "en-passant square",
+    "half move clock",
+    "full move number",
+];
 
 const REP_POS_START_INDEX: usize = 4;
 const MAX_NON_CAP_MOV_COUNT: usize = 100;
 
-const K_CAS_SQR_SIZE: usize = 4;
-const Q_CAS_SQR_SIZE: usize = 5;
-
-const WK_BEFORE_CAS_SQRS: [u8; K_CAS_SQR_SIZE] = [def::WK, 0, 0, def::WR];
-const WK_AFTER_CAS_SQRS: [u8; K_CAS_SQR_SIZE] = [0, def::WR, def::WK, 0];
-
-const BK_BEFORE_CAS_SQRS: [u8; K_CAS_SQR_SIZE] = [def::BK, 0, 0, def::BR];
-const BK_AFTER_CAS_SQRS: [u8; K_CAS_SQR_SIZE] = [0, def::BR, def::BK, 0];
-
-const WQ_BEFORE_CAS_SQRS: [u8; Q_CAS_SQR_SIZE] = [def::WR, 0, 0, 0, def::WK];
-const WQ_AFTER_CAS_SQRS: [u8; Q_CAS_SQR_SIZE] = [0, 0, def::WK, def::WR, 0];
-
-const BQ_BEFORE_CAS_SQRS: [u8; Q_CAS_SQR_SIZE] = [def::BR, 0, 0, 0, def::BK];
-const BQ_AFTER_CAS_SQRS: [u8; Q_CAS_SQR_SIZE] = [0, 0, def::BK, def::BR, 0];
-
-const WK_CAS_ALL_MASK: u64 = 0b00000000_00000000_00000000_00000000_00000000_00000000_00000000_11110000;
-const WK_CAS_R_MASK: u64 = 0b00000000_00000000_00000000_00000000_00000000_00000000_00000000_10100000;
-
-const BK_CAS_ALL_MASK: u64 = 0b11110000_00000000_00000000_00000000_00000000_00000000_00000000_00000000;
-const BK_CAS_R_MASK: u64 = 0b10100000_00000000_00000000_00000000_00000000_00000000_00000000_00000000;
+#[derive(Debug, PartialEq)]
+pub enum FenError {
+    MissingField(&'static str),
+    TrailingField(String),
+    InvalidPiecePlacement(String),
+    InvalidKingCount(char, usize),
+    InvalidPlayer(String),
+    InvalidCastlingRights(String),
+    InvalidEnPassantSquare(String),
+    InvalidMoveCount(String),
+}
 
-const WQ_CAS_ALL_MASK: u64 = 0b00000000_00000000_00000000_00000000_00000000_00000000_00000000_00011101;
-const WQ_CAS_R_MASK: u64 = 0b00000000_00000000_00000000_00000000_00000000_00000000_00000000_00001001;
+impl fmt::Display for FenError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FenError::MissingField(field_name) => write!(formatter, "missing {}", field_name),
+            FenError::TrailingField(field_str) => write!(formatter, "unexpected trailing fields {}", field_str),
+            FenError::InvalidPiecePlacement(reason) => write!(formatter, "invalid piece placement, {}", reason),
+            FenError::InvalidKingCount(king_char, king_count) => write!(formatter, "expected exactly one {} but found {}", king_char, king_count),
+            FenError::InvalidPlayer(player_str) => write!(formatter, "invalid side to move {}", player_str),
+            FenError::InvalidCastlingRights(reason) => write!(formatter, "invalid castling rights, {}", reason),
+            FenError::InvalidEnPassantSquare(reason) => write!(formatter, "invalid en-passant square, {}", reason),
+            FenError::InvalidMoveCount(mov_count_str) => write!(formatter, "invalid move counter {}", mov_count_str),
+        }
+    }
+}
 
-const BQ_CAS_ALL_MASK: u64 = 0b00011101_00000000_00000000_00000000_00000000_00000000_00000000_00000000;
-const BQ_CAS_R_MASK: u64 = 0b00001001_00000000_00000000_00000000_00000000_00000000_00000000_00000000;
+impl Error for FenError {}
 
+/// A chess position with the move history needed to undo moves.
+#[derive(Clone)]
 pub struct State {
     pub squares: [u8; def::BOARD_SIZE],
     pub player: u8,
@@ -62,6 +79,8 @@ pub struct State {
     pub bk_index: usize,
 
     pub cas_history: u8,
+    pub cas_rook_squares: [usize; 4],
+    pub chess960: bool,
 
     pub bitboard: BitBoard,
 
@@ -83,20 +102,41 @@ pub struct State {
     pub history_mov_stack: Vec<(u8, usize, usize)>,
     pub half_mov_count_stack: Vec<u16>,
     pub king_index_stack: Vec<(usize, usize)>,
+
+    pub nnue: Option<Accumulator>,
 }
 
 impl State {
+    /// Creates a position from a FEN string, panicking if it is invalid.
+    /// Use `State::from_fen` for untrusted input.
     pub fn new(fen_string: &str) -> Self {
-        let fen_segment_list: Vec<&str> = fen_string.split(" ").collect();
-        let player = get_player_from_fen(fen_segment_list[FEN_PLAYER_INDEX]);
-        let cas_rights = get_cas_rights_from_fen(fen_segment_list[FEN_CAS_RIGHTS_INDEX]);
-        let enp_square = get_enp_sqr_from_fen(fen_segment_list[FEN_ENP_SQR_INDEX]);
-        let half_mov_count = fen_segment_list[FEN_HALF_MOV_INDEX].parse::<u16>().unwrap();
-        let full_mov_count = fen_segment_list[FEN_FULL_MOV_INDEX].parse::<u16>().unwrap();
+        match State::from_fen(fen_string) {
+            Ok(state) => state,
+            Err(error) => panic!("invalid fen {}: {}", fen_string, error),
+        }
+    }
+
+    /// Parses and validates a FEN string.
+    pub fn from_fen(fen_string: &str) -> Result<Self, FenError> {
+        let fen_segment_list: Vec<&str> = fen_string.split_whitespace().collect();
+
+        if fen_segment_list.len() < FEN_SEGMENT_COUNT {
+            return Err(FenError::MissingField(FEN_SEGMENT_NAMES[fen_segment_list.len()]))
+        }
+
+        if fen_segment_list.len() > FEN_SEGMENT_COUNT {
+            return Err(FenError::TrailingField(fen_segment_list[FEN_SEGMENT_COUNT..].join(" ")))
+        }
+
+        let squares = get_squares_from_fen(fen_segment_list[FEN_SQRS_INDEX])?;
+        let player = get_player_from_fen(fen_segment_list[FEN_PLAYER_INDEX])?;
+        let (cas_rights, cas_rook_squares) = get_cas_rights_from_fen(fen_segment_list[FEN_CAS_RIGHTS_INDEX], &squares)?;
+        let enp_square = get_enp_sqr_from_fen(fen_segment_list[FEN_ENP_SQR_INDEX], &squares, player)?;
+        let half_mov_count = get_mov_count_from_fen(fen_segment_list[FEN_HALF_MOV_INDEX])?;
+        let full_mov_count = get_mov_count_from_fen(fen_segment_list[FEN_FULL_MOV_INDEX])?;
 
         let bitmask = bitmask::get_bitmask();
 
-        let mut squares = [0; def::BOARD_SIZE];
         let mut hash_key = 0;
         let mut wk_index = 0;
         let mut bk_index = 0;
@@ -112,91 +152,71 @@ impl State {
         let mut bq_count = 0;
         let mut bitboard = BitBoard::new();
 
-        let rank_string_list: Vec<&str> = fen_segment_list[FEN_SQRS_INDEX].split("/").collect();
-
-        let mut index = 56;
-        for rank_index in 0..def::DIM_SIZE {
-            let rank_string = rank_string_list[rank_index];
+        for (index, &piece) in squares.iter().enumerate() {
+            if piece == 0 {
+                continue
+            }
 
-            for char_code in rank_string.chars() {
-                if char_code.is_numeric() {
-                    index += char_code.to_digit(10).unwrap() as usize;
-                    continue
-                }
+            hash_key ^= zob_keys::get_board_zob_key(index, piece);
 
-                if char_code.is_alphabetic() {
-                    let piece = util::map_piece_char_to_code(char_code);
-                    squares[index] = piece;
-                    hash_key ^= zob_keys::get_board_zob_key(index, piece);
-
-                    match piece {
-                        def::WP => {
-                            bitboard.w_pawn ^= bitmask.index_masks[index];
-                            wp_count += 1;
-                        },
-                        def::BP => {
-                            bitboard.b_pawn ^= bitmask.index_masks[index];
-                            bp_count += 1;
-                        },
-                        def::WN => {
-                            bitboard.w_knight ^= bitmask.index_masks[index];
-                            wn_count += 1;
-                        },
-                        def::BN => {
-                            bitboard.b_knight ^= bitmask.index_masks[index];
-                            bn_count += 1;
-                        },
-                        def::WB => {
-                            bitboard.w_bishop ^= bitmask.index_masks[index];
-                            wb_count += 1;
-                        },
-                        def::BB => {
-                            bitboard.b_bishop ^= bitmask.index_masks[index];
-                            bb_count += 1;
-                        },
-                        def::WR => {
-                            bitboard.w_rook ^= bitmask.index_masks[index];
-                            wr_count += 1;
-                        },
-                        def::BR => {
-                            bitboard.b_rook ^= bitmask.index_masks[index];
-                            br_count += 1;
-                        },
-                        def::WQ => {
-                            bitboard.w_queen ^= bitmask.index_masks[index];
-                            wq_count += 1;
-                        },
-                        def::BQ => {
-                            bitboard.b_queen ^= bitmask.index_masks[index];
-                            bq_count += 1;
-                        },
-                        def::WK => {
-                            wk_index = index;
-                        },
-                        def::BK => {
-                            bk_index = index;
-                        },
-                        _ => ()
-                    }
-
-                    if def::on_same_side(def::PLAYER_W, piece) {
-                        bitboard.w_all ^= bitmask.index_masks[index];
-                    } else {
-                        bitboard.b_all ^= bitmask.index_masks[index];
-                    }
-
-                    index += 1;
-                }
+            match piece {
+                def::WP => {
+                    bitboard.w_pawn ^= bitmask.index_masks[index];
+                    wp_count += 1;
+                },
+                def::BP => {
+                    bitboard.b_pawn ^= bitmask.index_masks[index];
+                    bp_count += 1;
+                },
+                def::WN => {
+                    bitboard.w_knight ^= bitmask.index_masks[index];
+                    wn_count += 1;
+                },
+                def::BN => {
+                    bitboard.b_knight ^= bitmask.index_masks[index];
+                    bn_count += 1;
+                },
+                def::WB => {
+                    bitboard.w_bishop ^= bitmask.index_masks[index];
+                    wb_count += 1;
+                },
+                def::BB => {
+                    bitboard.b_bishop ^= bitmask.index_masks[index];
+                    bb_count += 1;
+                },
+                def::WR => {
+                    bitboard.w_rook ^= bitmask.index_masks[index];
+                    wr_count += 1;
+                },
+                def::BR => {
+                    bitboard.b_rook ^= bitmask.index_masks[index];
+                    br_count += 1;
+                },
+                def::WQ => {
+                    bitboard.w_queen ^= bitmask.index_masks[index];
+                    wq_count += 1;
+                },
+                def::BQ => {
+                    bitboard.b_queen ^= bitmask.index_masks[index];
+                    bq_count += 1;
+                },
+                def::WK => {
+                    wk_index = index;
+                },
+                def::BK => {
+                    bk_index = index;
+                },
+                _ => ()
             }
 
-            if index == def::DIM_SIZE {
-                break
+            if def::on_same_side(def::PLAYER_W, piece) {
+                bitboard.w_all ^= bitmask.index_masks[index];
+            } else {
+                bitboard.b_all ^= bitmask.index_masks[index];
             }
-
-            index -= 16;
         }
 
-        State {
+        Ok(State {
             squares,
             player,
             cas_rights,
@@ -208,6 +228,8 @@ impl State {
             bk_index,
 
             cas_history: 0,
+            cas_rook_squares,
+            chess960: false,
 
             bitboard,
             wp_count,
@@ -229,8 +251,114 @@ impl State {
             half_mov_count_stack: Vec::new(),
             king_index_stack: Vec::new(),
 
+            nnue: None,
+
             full_mov_count,
+        })
+    }
+
+    /// Returns the FEN string of the position.
+    pub fn to_fen(&self) -> String {
+        let fen_sqrs_string = get_fen_sqrs_string(&self.squares);
+
+        let player_string = if self.player == def::PLAYER_W {
+            "w"
+        } else {
+            "b"
+        };
+
+        let mut cas_rights_string = String::new();
+
+        for (cas_index, cas_char) in ['K', 'Q', 'k', 'q'].iter().enumerate() {
+            if self.cas_rights & def::get_cas_right(cas_index) != 0 {
+                cas_rights_string.push(self.get_cas_fen_char(cas_index, *cas_char));
+            }
         }
+
+        if cas_rights_string.is_empty() {
+            cas_rights_string.push('-');
+        }
+
+        let enp_sqr_string = if self.enp_square == 0 {
+            "-".to_owned()
+        } else {
+            util::map_index_to_sqr_notation(self.enp_square)
+        };
+
+        format!("{} {} {} {} {} {}", fen_sqrs_string, player_string, cas_rights_string, enp_sqr_string, self.half_mov_count, self.get_fen_full_mov_count())
+    }
+
+    /// Returns the move number as written in the FEN, which only advances after black moves.
+    pub fn get_fen_full_mov_count(&self) -> u16 {
+        // full_mov_count advances on every ply
+        let ply_count = self.history_mov_stack.len() as u16;
+        let start_on_black = (self.player == def::PLAYER_B) != (ply_count % 2 == 1);
+        self.full_mov_count - ply_count + (ply_count + start_on_black as u16) / 2
+    }
+
+    // castling with an inner rook needs the rook file (Shredder-FEN) to be unambiguous
+    fn get_cas_fen_char(&self, cas_index: usize, cas_char: char) -> char {
+        let rook_index = self.cas_rook_squares[cas_index];
+        let (king_index, rook) = if cas_index < 2 {
+            (self.wk_index, def::WR)
+        } else {
+            (self.bk_index, def::BR)
+        };
+
+        let outer_index_list = if rook_index > king_index {
+            rook_index + 1..rook_index - rook_index % def::DIM_SIZE + def::DIM_SIZE
+        } else {
+            rook_index - rook_index % def::DIM_SIZE..rook_index
+        };
+
+        if outer_index_list.clone().all(|index| self.squares[index] != rook) {
+            return cas_char
+        }
+
+        let file_char = (b'a' + (rook_index % def::DIM_SIZE) as u8) as char;
+
+        if cas_index < 2 {
+            file_char.to_ascii_uppercase()
+        } else {
+            file_char
+        }
+    }
+
+    /// Formats a move in UCI notation; castling is written as king takes rook in chess960.
+    pub fn format_mov(&self, mov: u32) -> String {
+        let (from, to, tp, _promo) = util::decode_u32_mov(mov);
+
+        if self.chess960 && tp == def::MOV_CAS {
+            return format!("{}{}", util::map_index_to_sqr_notation(from), util::map_index_to_sqr_notation(self.cas_rook_squares[def::get_cas_index(to)]))
+        }
+
+        util::format_mov(mov)
+    }
+
+    pub fn format_pv(&self, pv_table: &[u32]) -> String {
+        let mut pv_line = String::new();
+
+        for mov in pv_table.iter().take_while(|mov| **mov != 0) {
+            pv_line.push_str(&self.format_mov(*mov));
+            pv_line.push(' ');
+        }
+
+        pv_line
+    }
+
+    /// Returns the Zobrist key of the position, including the side to move,
+    /// castling rights and en-passant square.
+    #[inline]
+    pub fn get_zob_key(&self) -> u64 {
+        let mut key = self.hash_key
+        ^ zob_keys::get_enp_sqr_zob_key(self.enp_square)
+        ^ zob_keys::get_cas_rights_zob_key(self.cas_rights);
+
+        if self.player == def::PLAYER_B {
+            key ^= zob_keys::get_b_player_zob_key();
+        }
+
+        key
     }
 
     pub fn is_draw(&self, ply: u8) -> bool {
@@ -259,6 +387,11 @@ impl State {
         false
     }
 
+    /// Evaluates with the given network from now on; `None` leaves evaluation to the classical eval.
+    pub fn set_network(&mut self, network: Option<Arc<Network>>) {
+        self.nnue = network.map(|network| Accumulator::new(network, &self.squares));
+    }
+
     pub fn do_null_mov(&mut self) {
         self.player = def::get_opposite_player(self.player);
         self.enp_sqr_stack.push(self.enp_square);
@@ -270,6 +403,7 @@ impl State {
         self.enp_square = self.enp_sqr_stack.pop().unwrap();
     }
 
+    /// Plays a move given in the decoded form returned by `util::decode_u32_mov`.
     pub fn do_mov(&mut self, from: usize, to: usize, mov_type: u8, promo: u8) {
         self.cas_rights_stack.push(self.cas_rights);
         self.enp_sqr_stack.push(self.enp_square);
@@ -280,18 +414,30 @@ impl State {
         self.enp_square = 0;
         self.full_mov_count += 1;
 
+        // only the network needs the board before the move
+        let prev_squares = self.nnue.as_ref().map(|_| self.squares);
+
+        if self.cas_rights != 0 {
+            self.update_cas_rights(from, to);
+        }
+
         match mov_type {
             def::MOV_REG => self.do_reg_mov(from, to),
             def::MOV_PROMO => self.do_promo_mov(from, to, promo),
-            def::MOV_CAS => self.do_cas_mov(to),
+            def::MOV_CAS => self.do_cas_mov(from, to),
             def::MOV_ENP => self.do_enp_mov(from, to),
             def::MOV_CR_ENP => self.do_cr_enp_mov(from, to),
             _ => panic!("invalid mov type {}", mov_type),
         }
 
+        if let (Some(nnue), Some(prev_squares)) = (&mut self.nnue, &prev_squares) {
+            nnue.push_mov(prev_squares, &self.squares);
+        }
+
         self.player = def::get_opposite_player(self.player);
     }
 
+    /// Takes back the last move played with `do_mov`.
     pub fn undo_mov(&mut self, from: usize, to: usize, mov_type: u8) {
         self.full_mov_count -= 1;
         self.cas_rights = self.cas_rights_stack.pop().unwrap();
@@ -307,11 +453,17 @@ impl State {
         match mov_type {
             def::MOV_REG => self.undo_reg_mov(from, to),
             def::MOV_PROMO => self.undo_promo_mov(from, to),
-            def::MOV_CAS => self.undo_cas_mov(to),
+            def::MOV_CAS => self.undo_cas_mov(from, to),
             def::MOV_ENP => self.undo_enp_mov(from, to),
             def::MOV_CR_ENP => self.undo_cr_enp_mov(from, to),
             _ => panic!("invalid mov type {}", mov_type),
         }
+
+        if let Some(nnue) = &mut self.nnue {
+            if !nnue.pop_mov() {
+                nnue.refresh(&self.squares);
+            }
+        }
     }
 
     fn do_reg_mov(&mut self, from: usize, to: usize) {
@@ -341,12 +493,6 @@ impl State {
             },
             def::WR => {
                 self.bitboard.w_rook ^= move_index_mask;
-
-                if from == 0 {
-                    self.cas_rights &= 0b1011;
-                } else if from == 7 {
-                    self.cas_rights &= 0b0111;
-                }
             },
             def::WQ => {
                 self.bitboard.w_queen ^= move_index_mask;
@@ -362,28 +508,14 @@ impl State {
             },
             def::BR => {
                 self.bitboard.b_rook ^= move_index_mask;
-
-                if from == 56 {
-                    self.cas_rights &= 0b1110;
-                } else if from == 63 {
-                    self.cas_rights &= 0b1101;
-                }
             },
             def::BQ => {
                 self.bitboard.b_queen ^= move_index_mask;
             },
             def::WK => {
-                if from == 4 {
-                    self.cas_rights &= 0b0011;
-                }
-
                 self.wk_index = to;
             },
             def::BK => {
-                if from == 60 {
-                    self.cas_rights &= 0b1100;
-                }
-
                 self.bk_index = to;
             },
             _ => (),
@@ -778,84 +910,85 @@ impl State {
         }
     }
 
-    fn do_cas_mov(&mut self, to: usize) {
-        self.half_mov_count = 0;
+    fn update_cas_rights(&mut self, from: usize, to: usize) {
+        match self.squares[from] {
+            def::WK => self.cas_rights &= 0b0011,
+            def::BK => self.cas_rights &= 0b1100,
+            _ => (),
+        }
 
-        if to == def::CAS_SQUARE_WK {
-            self.cas_rights &= 0b0011;
-            self.cas_history |= 0b1100;
-            self.wk_index = to;
+        for (cas_index, rook_index) in self.cas_rook_squares.iter().enumerate() {
+            if from == *rook_index || to == *rook_index {
+                self.cas_rights &= !def::get_cas_right(cas_index);
+            }
+        }
+    }
 
-            self.squares[def::CAS_SQUARE_WK-2..=def::CAS_SQUARE_WK+1].copy_from_slice(&WK_AFTER_CAS_SQRS);
-            self.hash_key ^= zob_keys::get_board_zob_key(def::CAS_SQUARE_WK-2, def::WK)
-                ^ zob_keys::get_board_zob_key(def::CAS_SQUARE_WK, def::WK)
-                ^ zob_keys::get_board_zob_key(def::CAS_SQUARE_WK-1, def::WR)
-                ^ zob_keys::get_board_zob_key(def::CAS_SQUARE_WK+1, def::WR);
-            self.bitboard.w_all ^= WK_CAS_ALL_MASK;
-            self.bitboard.w_rook ^= WK_CAS_R_MASK;
-        } else if to == def::CAS_SQUARE_BK {
-            self.cas_rights &= 0b1100;
-            self.cas_history |= 0b0011;
-            self.bk_index = to;
+    fn do_cas_mov(&mut self, from: usize, to: usize) {
+        self.half_mov_count = 0;
 
-            self.squares[def::CAS_SQUARE_BK-2..=def::CAS_SQUARE_BK+1].copy_from_slice(&BK_AFTER_CAS_SQRS);
-            self.hash_key ^= zob_keys::get_board_zob_key(def::CAS_SQUARE_BK-2, def::BK)
-            ^ zob_keys::get_board_zob_key(def::CAS_SQUARE_BK, def::BK)
-            ^ zob_keys::get_board_zob_key(def::CAS_SQUARE_BK-1, def::BR)
-            ^ zob_keys::get_board_zob_key(def::CAS_SQUARE_BK+1, def::BR);
+        let cas_index = def::get_cas_index(to);
+        let rook_from = self.cas_rook_squares[cas_index];
+        let rook_to = def::CAS_ROOK_TO_SQUARES[cas_index];
 
-            self.bitboard.b_all ^= BK_CAS_ALL_MASK;
-            self.bitboard.b_rook ^= BK_CAS_R_MASK;
-        } else if to == def::CAS_SQUARE_WQ {
+        let (king, rook) = if to == def::CAS_SQUARE_WK || to == def::CAS_SQUARE_WQ {
             self.cas_rights &= 0b0011;
             self.cas_history |= 0b1100;
             self.wk_index = to;
-
-            self.squares[def::CAS_SQUARE_WQ-2..=def::CAS_SQUARE_WQ+2].copy_from_slice(&WQ_AFTER_CAS_SQRS);
-            self.hash_key ^= zob_keys::get_board_zob_key(def::CAS_SQUARE_WQ+2, def::WK)
-                ^ zob_keys::get_board_zob_key(def::CAS_SQUARE_WQ, def::WK)
-                ^ zob_keys::get_board_zob_key(def::CAS_SQUARE_WQ-2, def::WR)
-                ^ zob_keys::get_board_zob_key(def::CAS_SQUARE_WQ+1, def::WR);
-
-            self.bitboard.w_all ^= WQ_CAS_ALL_MASK;
-            self.bitboard.w_rook ^= WQ_CAS_R_MASK;
-        } else if to == def::CAS_SQUARE_BQ {
+            (def::WK, def::WR)
+        } else {
             self.cas_rights &= 0b1100;
             self.cas_history |= 0b0011;
             self.bk_index = to;
+            (def::BK, def::BR)
+        };
+
+        // the king or rook may land on the other's origin in chess960, so clear before placing
+        self.squares[from] = 0;
+        self.squares[rook_from] = 0;
+        self.squares[to] = king;
+        self.squares[rook_to] = rook;
 
-            self.squares[def::CAS_SQUARE_BQ-2..=def::CAS_SQUARE_BQ+2].copy_from_slice(&BQ_AFTER_CAS_SQRS);
-            self.hash_key ^= zob_keys::get_board_zob_key(def::CAS_SQUARE_BQ+2, def::BK)
-            ^ zob_keys::get_board_zob_key(def::CAS_SQUARE_BQ, def::BK)
-            ^ zob_keys::get_board_zob_key(def::CAS_SQUARE_BQ-2, def::BR)
-            ^ zob_keys::get_board_zob_key(def::CAS_SQUARE_BQ+1, def::BR);
+        self.hash_key ^= zob_keys::get_board_zob_key(from, king)
+            ^ zob_keys::get_board_zob_key(to, king)
+            ^ zob_keys::get_board_zob_key(rook_from, rook)
+            ^ zob_keys::get_board_zob_key(rook_to, rook);
 
-            self.bitboard.b_all ^= BQ_CAS_ALL_MASK;
-            self.bitboard.b_rook ^= BQ_CAS_R_MASK;
-        }
+        self.toggle_cas_bitboard(from, to, rook_from, rook_to, king);
     }
 
-    fn undo_cas_mov(&mut self, to: usize) {
-        if to == def::CAS_SQUARE_WK {
-            self.squares[def::CAS_SQUARE_WK-2..=def::CAS_SQUARE_WK+1].copy_from_slice(&WK_BEFORE_CAS_SQRS);
-            self.bitboard.w_all ^= WK_CAS_ALL_MASK;
-            self.bitboard.w_rook ^= WK_CAS_R_MASK;
-            self.cas_history &= 0b0011;
-        } else if to == def::CAS_SQUARE_BK {
-            self.squares[def::CAS_SQUARE_BK-2..=def::CAS_SQUARE_BK+1].copy_from_slice(&BK_BEFORE_CAS_SQRS);
-            self.bitboard.b_all ^= BK_CAS_ALL_MASK;
-            self.bitboard.b_rook ^= BK_CAS_R_MASK;
-            self.cas_history &= 0b1100;
-        } else if to == def::CAS_SQUARE_WQ {
-            self.squares[def::CAS_SQUARE_WQ-2..=def::CAS_SQUARE_WQ+2].copy_from_slice(&WQ_BEFORE_CAS_SQRS);
-            self.bitboard.w_all ^= WQ_CAS_ALL_MASK;
-            self.bitboard.w_rook ^= WQ_CAS_R_MASK;
+    fn undo_cas_mov(&mut self, from: usize, to: usize) {
+        let cas_index = def::get_cas_index(to);
+        let rook_from = self.cas_rook_squares[cas_index];
+        let rook_to = def::CAS_ROOK_TO_SQUARES[cas_index];
+
+        let (king, rook) = if to == def::CAS_SQUARE_WK || to == def::CAS_SQUARE_WQ {
             self.cas_history &= 0b0011;
-        } else if to == def::CAS_SQUARE_BQ {
-            self.squares[def::CAS_SQUARE_BQ-2..=def::CAS_SQUARE_BQ+2].copy_from_slice(&BQ_BEFORE_CAS_SQRS);
-            self.bitboard.b_all ^= BQ_CAS_ALL_MASK;
-            self.bitboard.b_rook ^= BQ_CAS_R_MASK;
+            (def::WK, def::WR)
+        } else {
             self.cas_history &= 0b1100;
+            (def::BK, def::BR)
+        };
+
+        self.squares[to] = 0;
+        self.squares[rook_to] = 0;
+        self.squares[from] = king;
+        self.squares[rook_from] = rook;
+
+        self.toggle_cas_bitboard(from, to, rook_from, rook_to, king);
+    }
+
+    fn toggle_cas_bitboard(&mut self, king_from: usize, king_to: usize, rook_from: usize, rook_to: usize, king: u8) {
+        let bitmask = bitmask::get_bitmask();
+        let king_mask = bitmask.index_masks[king_from] ^ bitmask.index_masks[king_to];
+        let rook_mask = bitmask.index_masks[rook_from] ^ bitmask.index_masks[rook_to];
+
+        if king == def::WK {
+            self.bitboard.w_all ^= king_mask ^ rook_mask;
+            self.bitboard.w_rook ^= rook_mask;
+        } else {
+            self.bitboard.b_all ^= king_mask ^ rook_mask;
+            self.bitboard.b_rook ^= rook_mask;
         }
     }
 
@@ -1022,46 +1155,197 @@ impl fmt::Display for State {
     }
 }
 
-fn get_player_from_fen(fen_player_string: &str) -> u8 {
-    match fen_player_string {
-        "w" => def::PLAYER_W,
-        "b" => def::PLAYER_B,
-        _ => panic!("invalid player {}", fen_player_string),
+/// Returns the piece placement field of a FEN string.
+pub(crate) fn get_fen_sqrs_string(squares: &[u8; def::BOARD_SIZE]) -> String {
+    let mut fen_sqrs_string = String::new();
+
+    for rank in (0..def::DIM_SIZE).rev() {
+        let mut empty_count = 0;
+
+        for file in 0..def::DIM_SIZE {
+            let piece = squares[rank * def::DIM_SIZE + file];
+
+            if piece == 0 {
+                empty_count += 1;
+                continue
+            }
+
+            if empty_count > 0 {
+                fen_sqrs_string.push_str(&empty_count.to_string());
+                empty_count = 0;
+            }
+
+            fen_sqrs_string.push(util::map_piece_code_to_fen_char(piece));
+        }
+
+        if empty_count > 0 {
+            fen_sqrs_string.push_str(&empty_count.to_string());
+        }
+
+        if rank > 0 {
+            fen_sqrs_string.push('/');
+        }
     }
+
+    fen_sqrs_string
 }
 
-fn get_cas_rights_from_fen(fen_cas_rights_player: &str) -> u8 {
-    if fen_cas_rights_player == "-" {
-        return 0
+fn get_squares_from_fen(fen_sqrs_string: &str) -> Result<[u8; def::BOARD_SIZE], FenError> {
+    let rank_count = fen_sqrs_string.split('/').count();
+
+    if rank_count != def::DIM_SIZE {
+        return Err(FenError::InvalidPiecePlacement(format!("expected {} ranks but found {}", def::DIM_SIZE, rank_count)))
     }
 
-    let mut cas_rights = 0;
+    let mut squares = [0; def::BOARD_SIZE];
+    let mut wk_count = 0;
+    let mut bk_count = 0;
+
+    for (rank_index, rank_string) in fen_sqrs_string.split('/').enumerate() {
+        let rank = def::DIM_SIZE - 1 - rank_index;
+        let mut file = 0;
+
+        for char_code in rank_string.chars() {
+            if let Some(empty_count) = char_code.to_digit(10) {
+                if empty_count == 0 || empty_count as usize > def::DIM_SIZE {
+                    return Err(FenError::InvalidPiecePlacement(format!("invalid empty square count {} on rank {}", char_code, rank + 1)))
+                }
+
+                file += empty_count as usize;
+            } else if "KQRBNPkqrbnp".contains(char_code) {
+                if file >= def::DIM_SIZE {
+                    return Err(FenError::InvalidPiecePlacement(format!("rank {} has more than {} squares", rank + 1, def::DIM_SIZE)))
+                }
+
+                let piece = util::map_piece_char_to_code(char_code);
+
+                if def::is_p(piece) && (rank == 0 || rank == def::DIM_SIZE - 1) {
+                    return Err(FenError::InvalidPiecePlacement(format!("pawn on rank {}", rank + 1)))
+                }
+
+                match piece {
+                    def::WK => wk_count += 1,
+                    def::BK => bk_count += 1,
+                    _ => (),
+                }
 
-    if fen_cas_rights_player.contains("K") {
-        cas_rights |= 0b1000;
+                squares[rank * def::DIM_SIZE + file] = piece;
+                file += 1;
+            } else {
+                return Err(FenError::InvalidPiecePlacement(format!("invalid piece {} on rank {}", char_code, rank + 1)))
+            }
+        }
+
+        if file != def::DIM_SIZE {
+            return Err(FenError::InvalidPiecePlacement(format!("rank {} does not have {} squares", rank + 1, def::DIM_SIZE)))
+        }
     }
 
-    if fen_cas_rights_player.contains("Q") {
-        cas_rights |= 0b0100;
+    if wk_count != 1 {
+        return Err(FenError::InvalidKingCount('K', wk_count))
     }
 
-    if fen_cas_rights_player.contains("k") {
-        cas_rights |= 0b0010;
+    if bk_count != 1 {
+        return Err(FenError::InvalidKingCount('k', bk_count))
     }
 
-    if fen_cas_rights_player.contains("q") {
-        cas_rights |= 0b0001;
+    Ok(squares)
+}
+
+fn get_player_from_fen(fen_player_string: &str) -> Result<u8, FenError> {
+    match fen_player_string {
+        "w" => Ok(def::PLAYER_W),
+        "b" => Ok(def::PLAYER_B),
+        _ => Err(FenError::InvalidPlayer(fen_player_string.to_owned())),
+    }
+}
+
+fn get_cas_rights_from_fen(fen_cas_rights_string: &str, squares: &[u8; def::BOARD_SIZE]) -> Result<(u8, [usize; 4]), FenError> {
+    let mut cas_rook_squares = def::DEFAULT_CAS_ROOK_SQUARES;
+
+    if fen_cas_rights_string == "-" {
+        return Ok((0, cas_rook_squares))
+    }
+
+    let mut cas_rights = 0;
+
+    // accepts KQkq (X-FEN, outermost rook) as well as rook files (Shredder-FEN)
+    for cas_char in fen_cas_rights_string.chars() {
+        let (king, rook, rank_start) = if cas_char.is_ascii_uppercase() {
+            (def::WK, def::WR, 0)
+        } else {
+            (def::BK, def::BR, 56)
+        };
+
+        let king_file = match (0..def::DIM_SIZE).find(|file| squares[rank_start + file] == king) {
+            Some(king_file) => king_file,
+            None => return Err(FenError::InvalidCastlingRights(format!("flag {} without king on the back rank", cas_char))),
+        };
+
+        let rook_file = match cas_char.to_ascii_lowercase() {
+            'k' => (king_file + 1..def::DIM_SIZE).rev().find(|file| squares[rank_start + file] == rook),
+            'q' => (0..king_file).find(|file| squares[rank_start + file] == rook),
+            file_char @ 'a'..='h' => Some(file_char as usize - 'a' as usize).filter(|file| squares[rank_start + file] == rook && *file != king_file),
+            _ => return Err(FenError::InvalidCastlingRights(format!("unknown flag {}", cas_char))),
+        };
+
+        let rook_file = match rook_file {
+            Some(rook_file) => rook_file,
+            None => return Err(FenError::InvalidCastlingRights(format!("flag {} without a rook to castle with", cas_char))),
+        };
+
+        let cas_index = match (king == def::WK, rook_file > king_file) {
+            (true, true) => 0,
+            (true, false) => 1,
+            (false, true) => 2,
+            (false, false) => 3,
+        };
+
+        if cas_rights & def::get_cas_right(cas_index) != 0 {
+            return Err(FenError::InvalidCastlingRights(format!("duplicated flag {}", cas_char)))
+        }
+
+        cas_rights |= def::get_cas_right(cas_index);
+        cas_rook_squares[cas_index] = rank_start + rook_file;
     }
 
-    cas_rights
+    Ok((cas_rights, cas_rook_squares))
 }
 
-fn get_enp_sqr_from_fen(fen_enp_sqr_string: &str) -> usize {
+fn get_enp_sqr_from_fen(fen_enp_sqr_string: &str, squares: &[u8; def::BOARD_SIZE], player: u8) -> Result<usize, FenError> {
     if fen_enp_sqr_string == "-" {
-        return 0
+        return Ok(0)
     }
 
-    util::map_sqr_notation_to_index(fen_enp_sqr_string)
+    let sqr_chars: Vec<char> = fen_enp_sqr_string.chars().collect();
+
+    if sqr_chars.len() != 2 || !('a'..='h').contains(&sqr_chars[0]) {
+        return Err(FenError::InvalidEnPassantSquare(format!("{} is not a square", fen_enp_sqr_string)))
+    }
+
+    let (enp_rank, pawn, pawn_offset) = if player == def::PLAYER_W {
+        ('6', def::BP, -8)
+    } else {
+        ('3', def::WP, 8)
+    };
+
+    if sqr_chars[1] != enp_rank {
+        return Err(FenError::InvalidEnPassantSquare(format!("{} is not on rank {}", fen_enp_sqr_string, enp_rank)))
+    }
+
+    let enp_square = util::map_sqr_notation_to_index(fen_enp_sqr_string);
+    let pawn_index = (enp_square as isize + pawn_offset) as usize;
+    let from_index = (enp_square as isize - pawn_offset) as usize;
+
+    if squares[pawn_index] != pawn || squares[enp_square] != 0 || squares[from_index] != 0 {
+        return Err(FenError::InvalidEnPassantSquare(format!("{} does not follow a double pawn push", fen_enp_sqr_string)))
+    }
+
+    Ok(enp_square)
+}
+
+fn get_mov_count_from_fen(fen_mov_count_string: &str) -> Result<u16, FenError> {
+    fen_mov_count_string.parse::<u16>().map_err(|_| FenError::InvalidMoveCount(fen_mov_count_string.to_owned()))
 }
 
 #[cfg(test)]
@@ -1069,6 +1353,7 @@ mod tests {
     use super::*;
     use crate::{
         def,
+        mov_table,
     };
 
     #[test]
@@ -1083,6 +1368,126 @@ mod tests {
         assert_eq!(def::PLAYER_W, state.player);
     }
 
+    #[test]
+    fn test_from_fen() {
+        zob_keys::init();
+        bitmask::init();
+
+        let state = State::from_fen("r1bqkbnr/ppp1p1pp/2n5/3pPp2/3P4/8/PPP2PPP/RNBQKBNR w Kq f6 0 4").unwrap();
+        assert_eq!(0b1001, state.cas_rights);
+        assert_eq!(util::map_sqr_notation_to_index("f6"), state.enp_square);
+        assert_eq!(4, state.full_mov_count);
+        assert_eq!(State::new("r1bqkbnr/ppp1p1pp/2n5/3pPp2/3P4/8/PPP2PPP/RNBQKBNR w Kq f6 0 4").hash_key, state.hash_key);
+
+        assert_eq!(FenError::MissingField("half move clock"), State::from_fen("8/8/8/8/8/8/8/K6k w - -").err().unwrap());
+        assert_eq!(FenError::TrailingField("bm e4".to_owned()), State::from_fen("8/8/8/8/8/8/8/K6k w - - 0 1 bm e4").err().unwrap());
+        assert_eq!(FenError::InvalidPlayer("x".to_owned()), State::from_fen("8/8/8/8/8/8/8/K6k x - - 0 1").err().unwrap());
+        assert_eq!(FenError::InvalidKingCount('k', 0), State::from_fen("8/8/8/8/8/8/8/K7 w - - 0 1").err().unwrap());
+        assert_eq!(FenError::InvalidKingCount('K', 2), State::from_fen("8/8/8/8/8/8/8/KK5k w - - 0 1").err().unwrap());
+        assert_eq!(FenError::InvalidMoveCount("-1".to_owned()), State::from_fen("8/8/8/8/8/8/8/K6k w - - -1 1").err().unwrap());
+        assert_eq!(FenError::InvalidMoveCount("x".to_owned()), State::from_fen("8/8/8/8/8/8/8/K6k w - - 0 x").err().unwrap());
+
+        assert!(matches!(State::from_fen("8/8/8/8/8/8/K6k w - - 0 1"), Err(FenError::InvalidPiecePlacement(_))));
+        assert!(matches!(State::from_fen("8/8/8/8/8/8/8/K5k w - - 0 1"), Err(FenError::InvalidPiecePlacement(_))));
+        assert!(matches!(State::from_fen("8/8/8/8/8/8/8/K7k w - - 0 1"), Err(FenError::InvalidPiecePlacement(_))));
+        assert!(matches!(State::from_fen("8/8/8/8/8/8/8/K5xk w - - 0 1"), Err(FenError::InvalidPiecePlacement(_))));
+        assert!(matches!(State::from_fen("8/8/8/8/8/8/8/KP5k w - - 0 1"), Err(FenError::InvalidPiecePlacement(_))));
+        assert!(matches!(State::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBN1 w KQkq - 0 1"), Err(FenError::InvalidCastlingRights(_))));
+        assert!(matches!(State::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KKkq - 0 1"), Err(FenError::InvalidCastlingRights(_))));
+        assert!(matches!(State::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkx - 0 1"), Err(FenError::InvalidCastlingRights(_))));
+        assert!(matches!(State::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e6 0 1"), Err(FenError::InvalidEnPassantSquare(_))));
+        assert!(matches!(State::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq e3 0 1"), Err(FenError::InvalidEnPassantSquare(_))));
+        assert!(matches!(State::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e9 0 1"), Err(FenError::InvalidEnPassantSquare(_))));
+        assert!(State::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1").is_ok());
+    }
+
+    #[test]
+    fn test_to_fen() {
+        zob_keys::init();
+        bitmask::init();
+
+        let fen_list = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r1bqkbnr/ppp1p1pp/2n5/3pPp2/3P4/8/PPP2PPP/RNBQKBNR w Kq f6 0 4",
+            "r5rk/2p1Nppp/3p3P/pp2p1P1/4P3/2qnPQK1/8/R6R w - - 1 0",
+            "8/8/8/8/8/8/8/K6k b - - 37 102",
+        ];
+
+        for fen in fen_list {
+            assert_eq!(fen, State::new(fen).to_fen());
+        }
+
+        let mut state = State::new("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        state.do_mov(util::map_sqr_notation_to_index("e2"), util::map_sqr_notation_to_index("e4"), def::MOV_CR_ENP, 0);
+        assert_eq!("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1", state.to_fen());
+        assert_eq!(State::new(&state.to_fen()).get_zob_key(), state.get_zob_key());
+
+        state.do_mov(util::map_sqr_notation_to_index("g8"), util::map_sqr_notation_to_index("f6"), def::MOV_REG, 0);
+        assert_eq!("rnbqkb1r/pppppppp/5n2/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 1 2", state.to_fen());
+        assert_eq!(State::new(&state.to_fen()).get_zob_key(), state.get_zob_key());
+    }
+
+    #[test]
+    fn test_chess960_fen() {
+        zob_keys::init();
+        bitmask::init();
+
+        let state = State::from_fen("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9").unwrap();
+        assert_eq!(0b1111, state.cas_rights);
+        assert_eq!([7, 5, 63, 61], state.cas_rook_squares);
+        assert_eq!("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQkq - 2 9", state.to_fen());
+
+        let state = State::from_fen("1r2k1r1/8/8/8/8/8/8/RR2K3 w Bg - 0 1").unwrap();
+        assert_eq!(0b0110, state.cas_rights);
+        assert_eq!(util::map_sqr_notation_to_index("b1"), state.cas_rook_squares[1]);
+        assert_eq!(util::map_sqr_notation_to_index("g8"), state.cas_rook_squares[2]);
+        assert_eq!("1r2k1r1/8/8/8/8/8/8/RR2K3 w Bk - 0 1", state.to_fen());
+
+        assert_eq!(0b0100, State::new("1r2k1r1/8/8/8/8/8/8/RR2K3 w Q - 0 1").cas_rights);
+        assert_eq!(0, State::new("1r2k1r1/8/8/8/8/8/8/RR2K3 w Q - 0 1").cas_rook_squares[1]);
+
+        assert!(matches!(State::from_fen("1r2k1r1/8/8/8/8/8/8/RR2K3 w C - 0 1"), Err(FenError::InvalidCastlingRights(_))));
+        assert!(matches!(State::from_fen("1r2k1r1/8/8/8/8/8/8/RR2K3 w QA - 0 1"), Err(FenError::InvalidCastlingRights(_))));
+        assert!(matches!(State::from_fen("1r2k1r1/8/8/8/8/8/8/RR2K3 w K - 0 1"), Err(FenError::InvalidCastlingRights(_))));
+    }
+
+    #[test]
+    fn test_chess960_cas_mov() {
+        zob_keys::init();
+        bitmask::init();
+
+        let fen = "1r4kr/8/8/8/8/8/8/1R4KR w Qq - 0 1";
+        let mut state = State::new(fen);
+        state.chess960 = true;
+
+        let (g1, c1, b1, d1) = (6, 2, 1, 3);
+        let mov = util::encode_u32_mov(g1, c1, def::MOV_CAS, 0);
+        assert_eq!("g1b1", state.format_mov(mov));
+        assert!(mov_table::gen_legal_mov_list(&mut state).contains(&mov));
+
+        state.do_mov(g1, c1, def::MOV_CAS, 0);
+        assert_eq!(def::WK, state.squares[c1]);
+        assert_eq!(def::WR, state.squares[d1]);
+        assert_eq!(0, state.squares[g1]);
+        assert_eq!(0, state.squares[b1]);
+        assert_eq!(c1, state.wk_index);
+        assert_eq!(0b0001, state.cas_rights);
+        assert_eq!(State::new("1r4kr/8/8/8/8/8/8/2KR3R b q - 0 1").get_zob_key(), state.get_zob_key());
+
+        state.undo_mov(g1, c1, def::MOV_CAS);
+        assert_eq!(fen, state.to_fen());
+        assert_eq!(State::new(fen).get_zob_key(), state.get_zob_key());
+        assert_eq!(State::new(fen).bitboard.w_all, state.bitboard.w_all);
+        assert_eq!(State::new(fen).bitboard.w_rook, state.bitboard.w_rook);
+
+        // king already on its destination, only the rook moves
+        let mut state = State::new("4k3/8/8/8/8/8/8/6KR w K - 0 1");
+        state.do_mov(g1, g1, def::MOV_CAS, 0);
+        assert_eq!("4k3/8/8/8/8/8/8/5RK1 b - - 0 1", state.to_fen());
+        state.undo_mov(g1, g1, def::MOV_CAS);
+        assert_eq!("4k3/8/8/8/8/8/8/6KR w K - 0 1", state.to_fen());
+    }
+
     #[test]
     fn test_do_move_1() {
         zob_keys::init();
@@ -1125,7 +1530,7 @@ mod tests {
         let b_all = state.bitboard.b_all;
 
         state.do_mov(util::map_sqr_notation_to_index("b7"), util::map_sqr_notation_to_index("a8"), def::MOV_PROMO, def::WQ);
-        assert_eq!(0b1111, state.cas_rights);
+        assert_eq!(0b1110, state.cas_rights);
         assert_eq!(0, state.enp_square);
         assert_eq!(def::PLAYER_B, state.player);
         assert_eq!(def::WQ, state.squares[util::map_sqr_notation_to_index("a8")]);
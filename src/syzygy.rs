@@ -0,0 +1,1950 @@
+/*
+ * Copyright (C) 2020-2022 Zixiao Han
+ */
+
+//! Syzygy endgame tablebase probing: WDL tables for the search, DTZ tables for ranking root moves.
+//!
+//! Tables are found by their file names, like `KRvK.rtbw` and `KRvK.rtbz`, in the directories
+//! of the path, and are read into memory the first time they are probed.
+//! Positions with castling rights are never probed.
+
+use crate::{
+    def,
+    mov_table,
+    state::State,
+    util,
+};
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+pub const MAX_PIECE_COUNT: usize = 7;
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xe8, 0x23, 0x5d];
+const DTZ_MAGIC: [u8; 4] = [0xd7, 0x66, 0x0c, 0xa5];
+
+const WDL_EXTENSION: &str = "rtbw";
+const DTZ_EXTENSION: &str = "rtbz";
+
+const FILE_FLAG_SPLIT: u8 = 1;
+const FILE_FLAG_HAS_PAWNS: u8 = 2;
+
+const TABLE_FLAG_STM: u8 = 1;
+const TABLE_FLAG_MAPPED: u8 = 2;
+const TABLE_FLAG_WIN_PLIES: u8 = 4;
+const TABLE_FLAG_LOSS_PLIES: u8 = 8;
+const TABLE_FLAG_WIDE: u8 = 16;
+const TABLE_FLAG_SINGLE_VALUE: u8 = 128;
+
+const PIECE_CHARS: [char; 6] = ['P', 'N', 'B', 'R', 'Q', 'K'];
+const NAME_PIECE_ORDER: [usize; 6] = [5, 4, 3, 2, 1, 0];
+
+const BLACK_PIECE_FLAG: u8 = 8;
+const NO_SYM: usize = 0xfff;
+
+const LEAD_PIECES_SIZE_UNIQUE: u64 = 31332;
+const LEAD_PIECES_SIZE_KINGS: u64 = 462;
+
+/// Win, draw or loss for the side to move. Cursed wins and blessed losses are
+/// decided by the fifty-move rule.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Wdl {
+    Loss,
+    BlessedLoss,
+    Draw,
+    CursedWin,
+    Win,
+}
+
+impl Wdl {
+    fn from_value(value: i32) -> Self {
+        match value {
+            i32::MIN..=-2 => Wdl::Loss,
+            -1 => Wdl::BlessedLoss,
+            0 => Wdl::Draw,
+            1 => Wdl::CursedWin,
+            _ => Wdl::Win,
+        }
+    }
+
+    /// Returns -2 for a loss up to 2 for a win.
+    pub fn get_value(self) -> i32 {
+        self as i32 - 2
+    }
+
+    /// Returns the result for the opponent.
+    pub fn flip(self) -> Self {
+        Wdl::from_value(-self.get_value())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ProbeState {
+    Ok,
+    Fail,
+    ChangeStm,
+    ZeroingBestMov,
+}
+
+struct EncodingTables {
+    map_b1h1h7: [u64; def::BOARD_SIZE],
+    map_a1d1d4: [u64; def::BOARD_SIZE],
+    map_kk: [[u64; def::BOARD_SIZE]; 10],
+    binomial: [[u64; def::BOARD_SIZE]; MAX_PIECE_COUNT],
+    map_pawns: [usize; def::BOARD_SIZE],
+    lead_pawn_idx: [[u64; def::BOARD_SIZE]; 6],
+    lead_pawns_size: [[u64; 4]; 6],
+}
+
+static ENCODING_TABLES: OnceLock<EncodingTables> = OnceLock::new();
+
+fn get_encoding_tables() -> &'static EncodingTables {
+    ENCODING_TABLES.get_or_init(EncodingTables::new)
+}
+
+#[inline]
+fn rank_of(index: usize) -> usize {
+    index >> 3
+}
+
+#[inline]
+fn file_of(index: usize) -> usize {
+    index & 7
+}
+
+// negative below the a1-h8 diagonal, positive above
+#[inline]
+fn off_a1h8(index: usize) -> i32 {
+    rank_of(index) as i32 - file_of(index) as i32
+}
+
+#[inline]
+fn flip_diagonal(index: usize) -> usize {
+    ((index >> 3) | (index << 3)) & 63
+}
+
+impl EncodingTables {
+    fn new() -> Self {
+        let mut map_b1h1h7 = [0; def::BOARD_SIZE];
+        let mut code = 0;
+
+        for (index, b1h1h7_code) in map_b1h1h7.iter_mut().enumerate() {
+            if off_a1h8(index) < 0 {
+                *b1h1h7_code = code;
+                code += 1;
+            }
+        }
+
+        // the a1-d1-d4 triangle, with the squares on the diagonal last
+        let mut map_a1d1d4 = [0; def::BOARD_SIZE];
+        let mut diagonal_index_list = Vec::new();
+        code = 0;
+
+        for (index, a1d1d4_code) in map_a1d1d4.iter_mut().enumerate().take(util::map_sqr_notation_to_index("d4") + 1) {
+            if off_a1h8(index) < 0 && file_of(index) <= 3 {
+                *a1d1d4_code = code;
+                code += 1;
+            } else if off_a1h8(index) == 0 && file_of(index) <= 3 {
+                diagonal_index_list.push(index);
+            }
+        }
+
+        for index in diagonal_index_list {
+            map_a1d1d4[index] = code;
+            code += 1;
+        }
+
+        // the 462 legal placements of two kings with the first one in the a1-d1-d4 triangle
+        let mut map_kk = [[0; def::BOARD_SIZE]; 10];
+        let mut both_on_diagonal_list = Vec::new();
+        let b1_index = util::map_sqr_notation_to_index("b1");
+        code = 0;
+
+        for triangle_code in 0..10 {
+            for (k1_index, k1_code) in map_a1d1d4.iter().enumerate().take(util::map_sqr_notation_to_index("d4") + 1) {
+                if *k1_code != triangle_code || (triangle_code == 0 && k1_index != b1_index) {
+                    continue
+                }
+
+                for (k2_index, kk_code) in map_kk[triangle_code as usize].iter_mut().enumerate() {
+                    if file_of(k1_index).abs_diff(file_of(k2_index)) <= 1 && rank_of(k1_index).abs_diff(rank_of(k2_index)) <= 1 {
+                        continue
+                    }
+
+                    if off_a1h8(k1_index) == 0 && off_a1h8(k2_index) > 0 {
+                        continue
+                    }
+
+                    if off_a1h8(k1_index) == 0 && off_a1h8(k2_index) == 0 {
+                        both_on_diagonal_list.push((triangle_code as usize, k2_index));
+                    } else {
+                        *kk_code = code;
+                        code += 1;
+                    }
+                }
+            }
+        }
+
+        for (triangle_code, k2_index) in both_on_diagonal_list {
+            map_kk[triangle_code][k2_index] = code;
+            code += 1;
+        }
+
+        let mut binomial = [[0; def::BOARD_SIZE]; MAX_PIECE_COUNT];
+        binomial[0][0] = 1;
+
+        for n in 1..def::BOARD_SIZE {
+            for k in 0..MAX_PIECE_COUNT.min(n + 1) {
+                binomial[k][n] = if k > 0 { binomial[k - 1][n - 1] } else { 0 } + if k < n { binomial[k][n - 1] } else { 0 };
+            }
+        }
+
+        // pawns on a2-h7 are mapped to 47..0, the leading pawn is the one with the highest value:
+        // the one nearest to the edge and, on the same file, the one with the lowest rank
+        let mut map_pawns = [0; def::BOARD_SIZE];
+        let mut lead_pawn_idx = [[0; def::BOARD_SIZE]; 6];
+        let mut lead_pawns_size = [[0; 4]; 6];
+        let mut available_sqr_count = 48;
+
+        for lead_pawn_count in 1..=5 {
+            for (file, lead_pawns_file_size) in lead_pawns_size[lead_pawn_count].iter_mut().enumerate() {
+                let mut idx = 0;
+
+                for rank in 1..7 {
+                    let index = rank * def::DIM_SIZE + file;
+
+                    if lead_pawn_count == 1 {
+                        available_sqr_count -= 1;
+                        map_pawns[index] = available_sqr_count;
+                        available_sqr_count -= 1;
+                        map_pawns[index ^ 7] = available_sqr_count;
+                    }
+
+                    lead_pawn_idx[lead_pawn_count][index] = idx;
+                    idx += binomial[lead_pawn_count - 1][map_pawns[index]];
+                }
+
+                *lead_pawns_file_size = idx;
+            }
+        }
+
+        EncodingTables {
+            map_b1h1h7,
+            map_a1d1d4,
+            map_kk,
+            binomial,
+            map_pawns,
+            lead_pawn_idx,
+            lead_pawns_size,
+        }
+    }
+}
+
+#[inline]
+fn read_u8(bytes: &[u8], offset: usize) -> u8 {
+    bytes.get(offset).copied().unwrap_or(0)
+}
+
+#[inline]
+fn read_u16_le(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([read_u8(bytes, offset), read_u8(bytes, offset + 1)])
+}
+
+#[inline]
+fn read_u32_le(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([read_u8(bytes, offset), read_u8(bytes, offset + 1), read_u8(bytes, offset + 2), read_u8(bytes, offset + 3)])
+}
+
+#[inline]
+fn read_u32_be(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([read_u8(bytes, offset), read_u8(bytes, offset + 1), read_u8(bytes, offset + 2), read_u8(bytes, offset + 3)])
+}
+
+#[inline]
+fn read_u64_be(bytes: &[u8], offset: usize) -> u64 {
+    (read_u32_be(bytes, offset) as u64) << 32 | read_u32_be(bytes, offset + 4) as u64
+}
+
+/// The Huffman-coded values of one side and one leading pawn file of a table,
+/// compressed with recursive pairing. Offsets point into the table bytes.
+#[derive(Clone, Default)]
+struct PairsData {
+    flags: u8,
+    pieces: [u8; MAX_PIECE_COUNT],
+    group_idx: [u64; MAX_PIECE_COUNT + 1],
+    group_len: [usize; MAX_PIECE_COUNT + 1],
+    block_size: usize,
+    span: u64,
+    block_count: usize,
+    min_sym_len: usize,
+    lowest_sym_offset: usize,
+    base64: Vec<u64>,
+    sym_len: Vec<u8>,
+    btree_offset: usize,
+    sparse_index_offset: usize,
+    sparse_index_size: usize,
+    block_length_offset: usize,
+    block_length_size: usize,
+    data_offset: usize,
+    map_idx: [usize; 4],
+}
+
+impl PairsData {
+    fn get_table_size(&self) -> u64 {
+        let group_count = self.group_len.iter().position(|group_len| *group_len == 0).unwrap_or(MAX_PIECE_COUNT);
+        self.group_idx[group_count]
+    }
+
+    fn get_btree_syms(&self, bytes: &[u8], sym: usize) -> (usize, usize) {
+        let offset = self.btree_offset + sym * 3;
+        let (b0, b1, b2) = (read_u8(bytes, offset) as usize, read_u8(bytes, offset + 1) as usize, read_u8(bytes, offset + 2) as usize);
+
+        ((b1 & 0xf) << 8 | b0, b2 << 4 | b1 >> 4)
+    }
+
+    fn get_lowest_sym(&self, bytes: &[u8], len: usize) -> u64 {
+        read_u16_le(bytes, self.lowest_sym_offset + len * 2) as u64
+    }
+
+    fn get_block_length(&self, bytes: &[u8], block: usize) -> i64 {
+        read_u16_le(bytes, self.block_length_offset + block * 2) as i64
+    }
+}
+
+/// The material and the encoding of a table, known from its name.
+struct TableEntry {
+    piece_count: usize,
+    has_pawns: bool,
+    has_unique_pieces: bool,
+    pawn_count: [usize; 2],
+    is_symmetric: bool,
+    wdl_path: PathBuf,
+    dtz_path: Option<PathBuf>,
+    wdl_table: OnceLock<Option<Table>>,
+    dtz_table: OnceLock<Option<Table>>,
+}
+
+impl TableEntry {
+    fn new(name: &str, wdl_path: PathBuf, dtz_path: Option<PathBuf>) -> Option<Self> {
+        let (w_name, b_name) = name.split_once('v')?;
+        let mut piece_type_count_list = [[0; 6]; 2];
+
+        for (side, side_name) in [w_name, b_name].iter().enumerate() {
+            if !side_name.starts_with('K') || side_name.matches('K').count() != 1 {
+                return None
+            }
+
+            for piece_char in side_name.chars() {
+                let piece_type_index = PIECE_CHARS.iter().position(|c| *c == piece_char)?;
+                piece_type_count_list[side][piece_type_index] += 1;
+            }
+        }
+
+        let piece_count = w_name.len() + b_name.len();
+
+        if piece_count > MAX_PIECE_COUNT {
+            return None
+        }
+
+        let [w_pawn_count, b_pawn_count] = [piece_type_count_list[0][0], piece_type_count_list[1][0]];
+        let has_unique_pieces = piece_type_count_list.iter().any(|piece_type_count| piece_type_count[..5].contains(&1));
+
+        // the side with fewer pawns leads, when both sides have pawns
+        let is_white_leading = b_pawn_count == 0 || (w_pawn_count > 0 && b_pawn_count >= w_pawn_count);
+        let pawn_count = if is_white_leading {
+            [w_pawn_count, b_pawn_count]
+        } else {
+            [b_pawn_count, w_pawn_count]
+        };
+
+        Some(TableEntry {
+            piece_count,
+            has_pawns: w_pawn_count + b_pawn_count > 0,
+            has_unique_pieces,
+            pawn_count,
+            is_symmetric: w_name == b_name,
+            wdl_path,
+            dtz_path,
+            wdl_table: OnceLock::new(),
+            dtz_table: OnceLock::new(),
+        })
+    }
+
+    fn get_wdl_table(&self) -> Option<&Table> {
+        self.wdl_table.get_or_init(|| fs::read(&self.wdl_path).ok().and_then(|bytes| Table::new(bytes, self, false))).as_ref()
+    }
+
+    fn get_dtz_table(&self) -> Option<&Table> {
+        self.dtz_table.get_or_init(|| self.dtz_path.as_ref().and_then(|dtz_path| fs::read(dtz_path).ok()).and_then(|bytes| Table::new(bytes, self, true))).as_ref()
+    }
+}
+
+struct Table {
+    bytes: Vec<u8>,
+    is_dtz: bool,
+    pairs_table: Vec<Vec<PairsData>>,
+    dtz_map_offset: usize,
+}
+
+impl Table {
+    fn new(bytes: Vec<u8>, entry: &TableEntry, is_dtz: bool) -> Option<Self> {
+        let magic = if is_dtz { DTZ_MAGIC } else { WDL_MAGIC };
+
+        if bytes.get(0..4)? != magic {
+            return None
+        }
+
+        let mut offset = 4;
+        let file_flags = *bytes.get(offset)?;
+        offset += 1;
+
+        if (file_flags & FILE_FLAG_HAS_PAWNS != 0) != entry.has_pawns || (file_flags & FILE_FLAG_SPLIT == 0) != entry.is_symmetric {
+            return None
+        }
+
+        let side_count = if !is_dtz && !entry.is_symmetric { 2 } else { 1 };
+        let file_count = if entry.has_pawns { 4 } else { 1 };
+        let both_have_pawns = entry.has_pawns && entry.pawn_count[1] > 0;
+
+        let mut pairs_table = vec![vec![PairsData::default(); file_count]; side_count];
+
+        for file in 0..file_count {
+            let order_byte = *bytes.get(offset)?;
+            let pawn_order_byte = if both_have_pawns { *bytes.get(offset + 1)? } else { 0xff };
+            let order_list = [[order_byte & 0xf, pawn_order_byte & 0xf], [order_byte >> 4, pawn_order_byte >> 4]];
+            offset += 1 + both_have_pawns as usize;
+
+            for piece_index in 0..entry.piece_count {
+                let piece_byte = *bytes.get(offset)?;
+                offset += 1;
+
+                for (side, side_pairs_list) in pairs_table.iter_mut().enumerate() {
+                    side_pairs_list[file].pieces[piece_index] = if side == 0 { piece_byte & 0xf } else { piece_byte >> 4 };
+                }
+            }
+
+            for (side, side_pairs_list) in pairs_table.iter_mut().enumerate() {
+                set_groups(entry, &mut side_pairs_list[file], order_list[side], file);
+            }
+        }
+
+        offset += offset & 1;
+
+        for file in 0..file_count {
+            for side_pairs_list in pairs_table.iter_mut() {
+                offset = set_sizes(&bytes, offset, &mut side_pairs_list[file])?;
+            }
+        }
+
+        let dtz_map_offset = offset;
+
+        if is_dtz {
+            for pairs in pairs_table[0].iter_mut() {
+                if pairs.flags & TABLE_FLAG_MAPPED == 0 {
+                    continue
+                }
+
+                for map_idx in pairs.map_idx.iter_mut() {
+                    if pairs.flags & TABLE_FLAG_WIDE != 0 {
+                        offset += offset & 1;
+                        *map_idx = (offset - dtz_map_offset) / 2 + 1;
+                        offset += 2 * read_u16_le(&bytes, offset) as usize + 2;
+                    } else {
+                        *map_idx = offset - dtz_map_offset + 1;
+                        offset += read_u8(&bytes, offset) as usize + 1;
+                    }
+                }
+            }
+
+            offset += offset & 1;
+        }
+
+        for file in 0..file_count {
+            for side_pairs_list in pairs_table.iter_mut() {
+                let pairs = &mut side_pairs_list[file];
+                pairs.sparse_index_offset = offset;
+                offset += pairs.sparse_index_size * 6;
+            }
+        }
+
+        for file in 0..file_count {
+            for side_pairs_list in pairs_table.iter_mut() {
+                let pairs = &mut side_pairs_list[file];
+                pairs.block_length_offset = offset;
+                offset += pairs.block_length_size * 2;
+            }
+        }
+
+        for file in 0..file_count {
+            for side_pairs_list in pairs_table.iter_mut() {
+                let pairs = &mut side_pairs_list[file];
+                offset = (offset + 0x3f) & !0x3f;
+                pairs.data_offset = offset;
+                offset += pairs.block_count * pairs.block_size;
+            }
+        }
+
+        if offset > bytes.len() {
+            return None
+        }
+
+        Some(Table {
+            bytes,
+            is_dtz,
+            pairs_table,
+            dtz_map_offset,
+        })
+    }
+
+    fn get_pairs(&self, stm: usize, file: usize) -> &PairsData {
+        &self.pairs_table[stm % self.pairs_table.len()][file]
+    }
+
+    fn decompress_pairs(&self, pairs: &PairsData, idx: u64) -> i32 {
+        if pairs.flags & TABLE_FLAG_SINGLE_VALUE != 0 {
+            return pairs.min_sym_len as i32
+        }
+
+        let bytes = &self.bytes;
+
+        // the sparse index points into the block containing the value k * span + span / 2
+        let sparse_offset = pairs.sparse_index_offset + (idx / pairs.span) as usize * 6;
+        let mut block = read_u32_le(bytes, sparse_offset) as usize;
+        let mut offset = read_u16_le(bytes, sparse_offset + 4) as i64 + (idx % pairs.span) as i64 - (pairs.span / 2) as i64;
+
+        while offset < 0 {
+            if block == 0 {
+                return 0
+            }
+
+            block -= 1;
+            offset += pairs.get_block_length(bytes, block) + 1;
+        }
+
+        while offset > pairs.get_block_length(bytes, block) {
+            offset -= pairs.get_block_length(bytes, block) + 1;
+            block += 1;
+
+            if block >= pairs.block_length_size {
+                return 0
+            }
+        }
+
+        let mut data_offset = pairs.data_offset + block * pairs.block_size;
+        let mut buf64 = read_u64_be(bytes, data_offset);
+        let mut buf64_size = 64;
+        data_offset += 8;
+
+        // canonical Huffman codes: longer codes have lower values
+        let mut sym = loop {
+            let mut len = 0;
+
+            while len + 1 < pairs.base64.len() && buf64 < pairs.base64[len] {
+                len += 1;
+            }
+
+            let sym = ((buf64 - pairs.base64[len]) >> (64 - len - pairs.min_sym_len)) + pairs.get_lowest_sym(bytes, len);
+            let sym = sym as usize;
+
+            if sym >= pairs.sym_len.len() {
+                return 0
+            }
+
+            if offset < pairs.sym_len[sym] as i64 + 1 {
+                break sym
+            }
+
+            offset -= pairs.sym_len[sym] as i64 + 1;
+
+            let bit_len = len + pairs.min_sym_len;
+            buf64 <<= bit_len;
+            buf64_size -= bit_len;
+
+            if buf64_size <= 32 {
+                buf64_size += 32;
+                buf64 |= (read_u32_be(bytes, data_offset) as u64) << (64 - buf64_size);
+                data_offset += 4;
+            }
+        };
+
+        // the symbol expands into adjacent pairs of symbols down to single values
+        while pairs.sym_len[sym] != 0 {
+            let (left_sym, right_sym) = pairs.get_btree_syms(bytes, sym);
+
+            if offset < pairs.sym_len[left_sym] as i64 + 1 {
+                sym = left_sym;
+            } else {
+                offset -= pairs.sym_len[left_sym] as i64 + 1;
+                sym = right_sym;
+            }
+        }
+
+        pairs.get_btree_syms(bytes, sym).0 as i32
+    }
+
+    fn map_dtz_score(&self, tb_file: usize, value: i32, wdl: Wdl) -> i32 {
+        let pairs = self.get_pairs(0, tb_file);
+        let mut value = value;
+
+        if pairs.flags & TABLE_FLAG_MAPPED != 0 {
+            let map_index = match wdl {
+                Wdl::Win | Wdl::Draw => 0,
+                Wdl::Loss => 1,
+                Wdl::CursedWin => 2,
+                Wdl::BlessedLoss => 3,
+            };
+
+            let map_idx = pairs.map_idx[map_index] + value as usize;
+
+            value = if pairs.flags & TABLE_FLAG_WIDE != 0 {
+                read_u16_le(&self.bytes, self.dtz_map_offset + map_idx * 2) as i32
+            } else {
+                read_u8(&self.bytes, self.dtz_map_offset + map_idx) as i32
+            };
+        }
+
+        // the distance is stored in moves unless the table says plies
+        if (wdl == Wdl::Win && pairs.flags & TABLE_FLAG_WIN_PLIES == 0)
+            || (wdl == Wdl::Loss && pairs.flags & TABLE_FLAG_LOSS_PLIES == 0)
+            || wdl == Wdl::CursedWin
+            || wdl == Wdl::BlessedLoss {
+            value *= 2;
+        }
+
+        value + 1
+    }
+}
+
+fn set_groups(entry: &TableEntry, pairs: &mut PairsData, order: [u8; 2], file: usize) {
+    let encoding_tables = get_encoding_tables();
+
+    // the leading group has the pawns of the leading side, three unique pieces or the two kings
+    let mut first_len: i32 = if entry.has_pawns { 0 } else if entry.has_unique_pieces { 3 } else { 2 };
+    let mut group_count = 0;
+    pairs.group_len[0] = 1;
+
+    for piece_index in 1..entry.piece_count {
+        first_len -= 1;
+
+        if first_len > 0 || pairs.pieces[piece_index] == pairs.pieces[piece_index - 1] {
+            pairs.group_len[group_count] += 1;
+        } else {
+            group_count += 1;
+            pairs.group_len[group_count] = 1;
+        }
+    }
+
+    group_count += 1;
+    pairs.group_len[group_count] = 0;
+
+    let both_have_pawns = entry.has_pawns && entry.pawn_count[1] > 0;
+    let mut next_group = if both_have_pawns { 2 } else { 1 };
+    let mut free_sqr_count = def::BOARD_SIZE - pairs.group_len[0] - if both_have_pawns { pairs.group_len[1] } else { 0 };
+    let mut idx = 1u64;
+    let mut k = 0;
+
+    // the groups are encoded in the order of the table, not in the order of the pieces
+    while next_group < group_count || k == order[0] as usize || k == order[1] as usize {
+        if k == order[0] as usize {
+            pairs.group_idx[0] = idx;
+            idx *= if entry.has_pawns {
+                encoding_tables.lead_pawns_size[pairs.group_len[0]][file]
+            } else if entry.has_unique_pieces {
+                LEAD_PIECES_SIZE_UNIQUE
+            } else {
+                LEAD_PIECES_SIZE_KINGS
+            };
+        } else if k == order[1] as usize {
+            pairs.group_idx[1] = idx;
+            idx *= encoding_tables.binomial[pairs.group_len[1]][48 - pairs.group_len[0]];
+        } else {
+            pairs.group_idx[next_group] = idx;
+            idx *= encoding_tables.binomial[pairs.group_len[next_group]][free_sqr_count];
+            free_sqr_count -= pairs.group_len[next_group];
+            next_group += 1;
+        }
+
+        k += 1;
+    }
+
+    pairs.group_idx[group_count] = idx;
+}
+
+fn set_sizes(bytes: &[u8], mut offset: usize, pairs: &mut PairsData) -> Option<usize> {
+    pairs.flags = *bytes.get(offset)?;
+    offset += 1;
+
+    if pairs.flags & TABLE_FLAG_SINGLE_VALUE != 0 {
+        pairs.min_sym_len = *bytes.get(offset)? as usize;
+        return Some(offset + 1)
+    }
+
+    let table_size = pairs.get_table_size();
+
+    pairs.block_size = 1usize.checked_shl(*bytes.get(offset)? as u32)?;
+    pairs.span = 1u64.checked_shl(*bytes.get(offset + 1)? as u32)?;
+    pairs.sparse_index_size = table_size.div_ceil(pairs.span) as usize;
+    let padding = *bytes.get(offset + 2)? as usize;
+    pairs.block_count = read_u32_le(bytes, offset + 3) as usize;
+    pairs.block_length_size = pairs.block_count + padding;
+    let max_sym_len = *bytes.get(offset + 7)? as usize;
+    pairs.min_sym_len = *bytes.get(offset + 8)? as usize;
+    offset += 9;
+
+    if pairs.min_sym_len == 0 || max_sym_len < pairs.min_sym_len || max_sym_len > 32 {
+        return None
+    }
+
+    pairs.lowest_sym_offset = offset;
+
+    let base64_size = max_sym_len - pairs.min_sym_len + 1;
+    pairs.base64 = vec![0; base64_size];
+
+    for len in (0..base64_size - 1).rev() {
+        pairs.base64[len] = (pairs.base64[len + 1].wrapping_add(pairs.get_lowest_sym(bytes, len)).wrapping_sub(pairs.get_lowest_sym(bytes, len + 1))) / 2;
+    }
+
+    for (len, base) in pairs.base64.iter_mut().enumerate() {
+        *base <<= 64 - len - pairs.min_sym_len;
+    }
+
+    offset += base64_size * 2;
+
+    let sym_count = read_u16_le(bytes, offset) as usize;
+    offset += 2;
+    pairs.btree_offset = offset;
+
+    if offset + sym_count * 3 > bytes.len() {
+        return None
+    }
+
+    pairs.sym_len = vec![0; sym_count];
+    let mut visited_list = vec![false; sym_count];
+
+    for sym in 0..sym_count {
+        if !visited_list[sym] {
+            pairs.sym_len[sym] = set_sym_len(bytes, pairs, sym, &mut visited_list)?;
+        }
+    }
+
+    Some(offset + sym_count * 3 + (sym_count & 1))
+}
+
+// every symbol stands for a pair of symbols, down to the symbols of single values
+fn set_sym_len(bytes: &[u8], pairs: &mut PairsData, sym: usize, visited_list: &mut [bool]) -> Option<u8> {
+    visited_list[sym] = true;
+
+    let (left_sym, right_sym) = pairs.get_btree_syms(bytes, sym);
+
+    if right_sym == NO_SYM {
+        return Some(0)
+    }
+
+    if left_sym >= visited_list.len() || right_sym >= visited_list.len() {
+        return None
+    }
+
+    for child_sym in [left_sym, right_sym] {
+        if !visited_list[child_sym] {
+            pairs.sym_len[child_sym] = set_sym_len(bytes, pairs, child_sym, visited_list)?;
+        }
+    }
+
+    Some(pairs.sym_len[left_sym].wrapping_add(pairs.sym_len[right_sym]).wrapping_add(1))
+}
+
+/// Returns the side, the leading pawn file and the index of the position in the table.
+/// `piece_list` holds the pieces in the Syzygy encoding, ordered by square.
+fn encode_position(entry: &TableEntry, pairs_table: &[Vec<PairsData>], piece_list: &[(u8, usize)], player_index: usize, is_black_stronger: bool) -> (usize, usize, u64) {
+    let encoding_tables = get_encoding_tables();
+
+    // tables are stored with White as the stronger side and, when both sides have the same
+    // material, with White to move; other positions are looked up with the colors swapped
+    let is_flipped = (entry.is_symmetric && player_index == 1) || is_black_stronger;
+    let flip_color = if is_flipped { BLACK_PIECE_FLAG } else { 0 };
+    let flip_squares = if is_flipped { 56 } else { 0 };
+    let stm = is_flipped as usize ^ player_index;
+
+    let mut squares = [0; MAX_PIECE_COUNT];
+    let mut pieces = [0; MAX_PIECE_COUNT];
+    let mut size = 0;
+    let mut lead_pawn_count = 0;
+    let mut tb_file = 0;
+    let mut lead_pawn = 0;
+
+    if entry.has_pawns {
+        lead_pawn = pairs_table[0][0].pieces[0] ^ flip_color;
+
+        for &(piece, index) in piece_list.iter().filter(|(piece, _)| *piece == lead_pawn) {
+            squares[size] = index ^ flip_squares;
+            pieces[size] = piece ^ flip_color;
+            size += 1;
+        }
+
+        lead_pawn_count = size;
+
+        let lead_index = (0..lead_pawn_count).max_by_key(|i| encoding_tables.map_pawns[squares[*i]]).unwrap_or(0);
+        squares.swap(0, lead_index);
+
+        tb_file = file_of(squares[0]).min(7 - file_of(squares[0]));
+    }
+
+    for &(piece, index) in piece_list.iter().filter(|(piece, _)| !entry.has_pawns || *piece != lead_pawn) {
+        squares[size] = index ^ flip_squares;
+        pieces[size] = piece ^ flip_color;
+        size += 1;
+    }
+
+    let pairs = &pairs_table[stm % pairs_table.len()][tb_file];
+
+    // order the pieces as in the table
+    for i in lead_pawn_count..size - 1 {
+        if let Some(j) = (i + 1..size).find(|j| pairs.pieces[i] == pieces[*j]) {
+            pieces.swap(i, j);
+            squares.swap(i, j);
+        }
+    }
+
+    // the leading piece goes to the a1-d8 half of the board
+    if file_of(squares[0]) > 3 {
+        for index in squares[..size].iter_mut() {
+            *index ^= 7;
+        }
+    }
+
+    let mut idx;
+
+    if entry.has_pawns {
+        idx = encoding_tables.lead_pawn_idx[lead_pawn_count][squares[0]];
+
+        squares[1..lead_pawn_count].sort_by_key(|index| encoding_tables.map_pawns[*index]);
+
+        for (i, index) in squares.iter().enumerate().take(lead_pawn_count).skip(1) {
+            idx += encoding_tables.binomial[i][encoding_tables.map_pawns[*index]];
+        }
+    } else {
+        // without pawns, the leading piece also goes to the a1-d4 quarter,
+        // and below the a1-h8 diagonal unless it is on it
+        if rank_of(squares[0]) > 3 {
+            for index in squares[..size].iter_mut() {
+                *index ^= 56;
+            }
+        }
+
+        for i in 0..pairs.group_len[0] {
+            if off_a1h8(squares[i]) == 0 {
+                continue
+            }
+
+            if off_a1h8(squares[i]) > 0 {
+                for index in squares[i..size].iter_mut() {
+                    *index = flip_diagonal(*index);
+                }
+            }
+
+            break
+        }
+
+        if entry.has_unique_pieces {
+            let [s0, s1, s2] = [squares[0], squares[1], squares[2]];
+            let adjust1 = (s1 > s0) as u64;
+            let adjust2 = (s2 > s0) as u64 + (s2 > s1) as u64;
+            let [s0, s1, s2] = [s0 as u64, s1 as u64, s2 as u64];
+            let [r0, r1, r2] = [rank_of(squares[0]) as u64, rank_of(squares[1]) as u64, rank_of(squares[2]) as u64];
+
+            idx = if off_a1h8(squares[0]) != 0 {
+                (encoding_tables.map_a1d1d4[s0 as usize] * 63 + (s1 - adjust1)) * 62 + s2 - adjust2
+            } else if off_a1h8(squares[1]) != 0 {
+                (6 * 63 + r0 * 28 + encoding_tables.map_b1h1h7[s1 as usize]) * 62 + s2 - adjust2
+            } else if off_a1h8(squares[2]) != 0 {
+                6 * 63 * 62 + 4 * 28 * 62 + r0 * 7 * 28 + (r1 - adjust1) * 28 + encoding_tables.map_b1h1h7[s2 as usize]
+            } else {
+                6 * 63 * 62 + 4 * 28 * 62 + 4 * 7 * 28 + r0 * 7 * 6 + (r1 - adjust1) * 6 + (r2 - adjust2)
+            };
+        } else {
+            idx = encoding_tables.map_kk[encoding_tables.map_a1d1d4[squares[0]] as usize][squares[1]];
+        }
+    }
+
+    idx *= pairs.group_idx[0];
+
+    // the other groups in ascending order of squares, skipping the squares taken by earlier groups
+    let mut group_start = pairs.group_len[0];
+    let mut has_remaining_pawns = entry.has_pawns && entry.pawn_count[1] > 0;
+    let mut next_group = 1;
+
+    while pairs.group_len[next_group] != 0 {
+        let group_end = group_start + pairs.group_len[next_group];
+        squares[group_start..group_end].sort_unstable();
+
+        let mut group_idx = 0;
+
+        for i in group_start..group_end {
+            let index = squares[i];
+            let adjust = squares[..group_start].iter().filter(|prev_index| index > **prev_index).count();
+            let pawn_adjust = if has_remaining_pawns { 8 } else { 0 };
+
+            group_idx += encoding_tables.binomial[i - group_start + 1][index.wrapping_sub(adjust + pawn_adjust) % def::BOARD_SIZE];
+        }
+
+        has_remaining_pawns = false;
+        idx += group_idx * pairs.group_idx[next_group];
+        group_start = group_end;
+        next_group += 1;
+    }
+
+    (stm, tb_file, idx)
+}
+
+fn get_syzygy_piece(piece: u8) -> u8 {
+    let piece_code = def::get_piece_type_index(piece) as u8 + 1;
+
+    if def::on_same_side(def::PLAYER_B, piece) {
+        piece_code | BLACK_PIECE_FLAG
+    } else {
+        piece_code
+    }
+}
+
+fn get_material_name(state: &State, player: u8) -> String {
+    let mut piece_type_count_list = [0; 6];
+
+    for &piece in state.squares.iter().filter(|piece| **piece != 0 && def::on_same_side(player, **piece)) {
+        piece_type_count_list[def::get_piece_type_index(piece)] += 1;
+    }
+
+    NAME_PIECE_ORDER.iter().flat_map(|piece_type_index| std::iter::repeat_n(PIECE_CHARS[*piece_type_index], piece_type_count_list[*piece_type_index])).collect()
+}
+
+fn get_piece_count(state: &State) -> usize {
+    (state.bitboard.w_all | state.bitboard.b_all).count_ones() as usize
+}
+
+fn is_zeroing_mov(state: &State, from: usize, to: usize, tp: u8) -> bool {
+    is_capture(state, to, tp) || def::is_p(state.squares[from])
+}
+
+fn is_capture(state: &State, to: usize, tp: u8) -> bool {
+    tp == def::MOV_ENP || (tp != def::MOV_CAS && state.squares[to] != 0)
+}
+
+fn is_mated(state: &mut State) -> bool {
+    mov_table::is_in_check(state, state.player) && mov_table::gen_legal_mov_list(state).is_empty()
+}
+
+fn get_dtz_before_zeroing(wdl: Wdl) -> i32 {
+    match wdl {
+        Wdl::Win => 1,
+        Wdl::CursedWin => 101,
+        Wdl::BlessedLoss => -101,
+        Wdl::Loss => -1,
+        Wdl::Draw => 0,
+    }
+}
+
+/// The tables found in the directories of a Syzygy path.
+pub struct Tablebase {
+    entry_map: HashMap<String, TableEntry>,
+    max_piece_count: usize,
+    dtz_table_count: usize,
+}
+
+impl Tablebase {
+    /// Finds the tables in the directories of `path`, separated by `:` (`;` on Windows).
+    pub fn new(path: &str) -> io::Result<Self> {
+        let separator = if cfg!(windows) { ';' } else { ':' };
+        let mut wdl_path_map = HashMap::new();
+        let mut dtz_path_map = HashMap::new();
+
+        for dir in path.split(separator).map(str::trim).filter(|dir| !dir.is_empty()) {
+            for dir_entry in fs::read_dir(dir)? {
+                let file_path = dir_entry?.path();
+
+                let (name, extension) = match (file_path.file_stem().and_then(|stem| stem.to_str()), file_path.extension().and_then(|extension| extension.to_str())) {
+                    (Some(name), Some(extension)) => (name.to_owned(), extension.to_ascii_lowercase()),
+                    _ => continue,
+                };
+
+                match extension.as_str() {
+                    WDL_EXTENSION => wdl_path_map.entry(name).or_insert(file_path),
+                    DTZ_EXTENSION => dtz_path_map.entry(name).or_insert(file_path),
+                    _ => continue,
+                };
+            }
+        }
+
+        let mut entry_map = HashMap::new();
+
+        for (name, wdl_path) in wdl_path_map {
+            let dtz_path = dtz_path_map.remove(&name);
+
+            if let Some(entry) = TableEntry::new(&name, wdl_path, dtz_path) {
+                entry_map.insert(name, entry);
+            }
+        }
+
+        Ok(Tablebase {
+            max_piece_count: entry_map.values().map(|entry| entry.piece_count).max().unwrap_or(0),
+            dtz_table_count: entry_map.values().filter(|entry| entry.dtz_path.is_some()).count(),
+            entry_map,
+        })
+    }
+
+    pub fn get_table_count(&self) -> usize {
+        self.entry_map.len()
+    }
+
+    pub fn get_dtz_table_count(&self) -> usize {
+        self.dtz_table_count
+    }
+
+    /// Returns the most pieces, kings included, of the positions in the tables.
+    pub fn get_max_piece_count(&self) -> usize {
+        self.max_piece_count
+    }
+
+    /// Checks that the position has no castling rights and few enough pieces to be in the tables.
+    pub fn can_probe(&self, state: &State) -> bool {
+        state.cas_rights == 0 && get_piece_count(state) <= self.max_piece_count
+    }
+
+    /// Returns the result of the position ignoring the fifty-move counter, or `None`
+    /// if it cannot be probed.
+    pub fn probe_wdl(&self, state: &mut State) -> Option<Wdl> {
+        if !self.can_probe(state) {
+            return None
+        }
+
+        let mut probe_state = ProbeState::Ok;
+        let wdl = self.search(state, false, &mut probe_state);
+
+        if probe_state == ProbeState::Fail {
+            return None
+        }
+
+        Some(wdl)
+    }
+
+    /// Returns the plies to the next capture or pawn move with the best play, negative when losing,
+    /// 0 for a draw and 101 or more for results decided by the fifty-move rule.
+    pub fn probe_dtz(&self, state: &mut State) -> Option<i32> {
+        if !self.can_probe(state) {
+            return None
+        }
+
+        let mut probe_state = ProbeState::Ok;
+        let dtz = self.probe_dtz_with_state(state, &mut probe_state);
+
+        if probe_state == ProbeState::Fail {
+            return None
+        }
+
+        Some(dtz)
+    }
+
+    /// Ranks the legal moves by the tables: 1000 for wins inside the fifty-move rule, down to
+    /// -1000 for losses. Uses the DTZ tables if available, the WDL tables otherwise.
+    pub fn rank_root_movs(&self, state: &mut State) -> Option<Vec<(u32, i32)>> {
+        if !self.can_probe(state) {
+            return None
+        }
+
+        self.rank_root_movs_by_dtz(state).or_else(|| self.rank_root_movs_by_wdl(state))
+    }
+
+    fn rank_root_movs_by_dtz(&self, state: &mut State) -> Option<Vec<(u32, i32)>> {
+        let half_mov_count = state.half_mov_count as i32;
+        let mut probe_state = ProbeState::Ok;
+        let mut ranked_mov_list = Vec::new();
+
+        for mov in mov_table::gen_legal_mov_list(state) {
+            let (from, to, tp, promo) = util::decode_u32_mov(mov);
+            state.do_mov(from, to, tp, promo);
+
+            let mut dtz = if state.half_mov_count == 0 {
+                get_dtz_before_zeroing(self.search(state, false, &mut probe_state).flip())
+            } else {
+                let dtz = -self.probe_dtz_with_state(state, &mut probe_state);
+                dtz + dtz.signum()
+            };
+
+            if dtz == 2 && is_mated(state) {
+                dtz = 1;
+            }
+
+            state.undo_mov(from, to, tp);
+
+            if probe_state == ProbeState::Fail {
+                return None
+            }
+
+            let rank = if dtz > 0 {
+                if dtz + half_mov_count <= 99 { 1000 } else { 1000 - (dtz + half_mov_count) }
+            } else if dtz < 0 {
+                if -dtz * 2 + half_mov_count < 100 { -1000 } else { -1000 + (-dtz + half_mov_count) }
+            } else {
+                0
+            };
+
+            ranked_mov_list.push((mov, rank));
+        }
+
+        Some(ranked_mov_list)
+    }
+
+    fn rank_root_movs_by_wdl(&self, state: &mut State) -> Option<Vec<(u32, i32)>> {
+        let mut probe_state = ProbeState::Ok;
+        let mut ranked_mov_list = Vec::new();
+
+        for mov in mov_table::gen_legal_mov_list(state) {
+            let (from, to, tp, promo) = util::decode_u32_mov(mov);
+            state.do_mov(from, to, tp, promo);
+            let wdl = self.search(state, false, &mut probe_state).flip();
+            state.undo_mov(from, to, tp);
+
+            if probe_state == ProbeState::Fail {
+                return None
+            }
+
+            let rank = match wdl {
+                Wdl::Loss => -1000,
+                Wdl::BlessedLoss => -899,
+                Wdl::Draw => 0,
+                Wdl::CursedWin => 899,
+                Wdl::Win => 1000,
+            };
+
+            ranked_mov_list.push((mov, rank));
+        }
+
+        Some(ranked_mov_list)
+    }
+
+    // Winning captures are not stored in the tables, and the positions where the side to move
+    // has a drawing capture may be stored as losses, so the captures are searched first.
+    // With `check_zeroing_movs`, pawn moves are searched too, since DTZ tables do not store
+    // positions where a zeroing move is best.
+    fn search(&self, state: &mut State, check_zeroing_movs: bool, probe_state: &mut ProbeState) -> Wdl {
+        let mut best_wdl = Wdl::Loss;
+        let legal_mov_list = mov_table::gen_legal_mov_list(state);
+        let mut searched_mov_count = 0;
+
+        for &mov in &legal_mov_list {
+            let (from, to, tp, promo) = util::decode_u32_mov(mov);
+
+            if !is_capture(state, to, tp) && (!check_zeroing_movs || !def::is_p(state.squares[from])) {
+                continue
+            }
+
+            searched_mov_count += 1;
+
+            state.do_mov(from, to, tp, promo);
+            let wdl = self.search(state, false, probe_state).flip();
+            state.undo_mov(from, to, tp);
+
+            if *probe_state == ProbeState::Fail {
+                return Wdl::Draw
+            }
+
+            if wdl > best_wdl {
+                best_wdl = wdl;
+
+                if wdl == Wdl::Win {
+                    *probe_state = ProbeState::ZeroingBestMov;
+                    return wdl
+                }
+            }
+        }
+
+        // the tables store no positions with en-passant rights, so when all moves have been searched
+        // the table is not probed
+        let all_movs_searched = searched_mov_count > 0 && searched_mov_count == legal_mov_list.len();
+
+        let wdl = if all_movs_searched {
+            best_wdl
+        } else {
+            let wdl = self.probe_wdl_table(state, probe_state);
+
+            if *probe_state == ProbeState::Fail {
+                return Wdl::Draw
+            }
+
+            wdl
+        };
+
+        if best_wdl >= wdl {
+            *probe_state = if best_wdl > Wdl::Draw || all_movs_searched { ProbeState::ZeroingBestMov } else { ProbeState::Ok };
+            return best_wdl
+        }
+
+        *probe_state = ProbeState::Ok;
+
+        wdl
+    }
+
+    fn probe_dtz_with_state(&self, state: &mut State, probe_state: &mut ProbeState) -> i32 {
+        *probe_state = ProbeState::Ok;
+
+        let wdl = self.search(state, true, probe_state);
+
+        if *probe_state == ProbeState::Fail || wdl == Wdl::Draw {
+            return 0
+        }
+
+        if *probe_state == ProbeState::ZeroingBestMov {
+            return get_dtz_before_zeroing(wdl)
+        }
+
+        let dtz = self.probe_dtz_table(state, wdl, probe_state);
+
+        if *probe_state == ProbeState::Fail {
+            return 0
+        }
+
+        if *probe_state != ProbeState::ChangeStm {
+            let cursed_dtz = if wdl == Wdl::CursedWin || wdl == Wdl::BlessedLoss { 100 } else { 0 };
+            return (dtz + cursed_dtz) * wdl.get_value().signum()
+        }
+
+        // the table stores the other side to move, so take the best DTZ after one move
+        let mut min_dtz = 0xffff;
+
+        for mov in mov_table::gen_legal_mov_list(state) {
+            let (from, to, tp, promo) = util::decode_u32_mov(mov);
+            let is_zeroing = is_zeroing_mov(state, from, to, tp);
+
+            state.do_mov(from, to, tp, promo);
+
+            let mut dtz = if is_zeroing {
+                -get_dtz_before_zeroing(self.search(state, false, probe_state))
+            } else {
+                -self.probe_dtz_with_state(state, probe_state)
+            };
+
+            if dtz == 1 && is_mated(state) {
+                min_dtz = 1;
+            }
+
+            if !is_zeroing {
+                dtz += dtz.signum();
+            }
+
+            if dtz < min_dtz && dtz.signum() == wdl.get_value().signum() {
+                min_dtz = dtz;
+            }
+
+            state.undo_mov(from, to, tp);
+
+            if *probe_state == ProbeState::Fail {
+                return 0
+            }
+        }
+
+        if min_dtz == 0xffff { -1 } else { min_dtz }
+    }
+
+    fn probe_wdl_table(&self, state: &State, probe_state: &mut ProbeState) -> Wdl {
+        if get_piece_count(state) == 2 {
+            return Wdl::Draw
+        }
+
+        match self.probe_table(state, false, Wdl::Draw, probe_state) {
+            Some(value) => Wdl::from_value(value - 2),
+            None => Wdl::Draw,
+        }
+    }
+
+    fn probe_dtz_table(&self, state: &State, wdl: Wdl, probe_state: &mut ProbeState) -> i32 {
+        if get_piece_count(state) == 2 {
+            return 0
+        }
+
+        self.probe_table(state, true, wdl, probe_state).unwrap_or(0)
+    }
+
+    fn probe_table(&self, state: &State, is_dtz: bool, wdl: Wdl, probe_state: &mut ProbeState) -> Option<i32> {
+        let w_name = get_material_name(state, def::PLAYER_W);
+        let b_name = get_material_name(state, def::PLAYER_B);
+
+        let lookup_result = match self.entry_map.get(&format!("{}v{}", w_name, b_name)) {
+            Some(entry) => Some((entry, false)),
+            None => self.entry_map.get(&format!("{}v{}", b_name, w_name)).map(|entry| (entry, true)),
+        };
+
+        let table = lookup_result.and_then(|(entry, is_black_stronger)| {
+            let table = if is_dtz { entry.get_dtz_table() } else { entry.get_wdl_table() }?;
+            Some((entry, is_black_stronger, table))
+        });
+
+        let (entry, is_black_stronger, table) = match table {
+            Some(table) => table,
+            None => {
+                *probe_state = ProbeState::Fail;
+                return None
+            },
+        };
+
+        let piece_list: Vec<(u8, usize)> = state.squares.iter().enumerate().filter(|(_, piece)| **piece != 0).map(|(index, piece)| (get_syzygy_piece(*piece), index)).collect();
+        let player_index = if state.player == def::PLAYER_W { 0 } else { 1 };
+
+        let (stm, tb_file, idx) = encode_position(entry, &table.pairs_table, &piece_list, player_index, is_black_stronger);
+
+        // DTZ tables store one side to move, except for symmetric tables without pawns
+        if table.is_dtz && (table.get_pairs(stm, tb_file).flags & TABLE_FLAG_STM) as usize != stm && (!entry.is_symmetric || entry.has_pawns) {
+            *probe_state = ProbeState::ChangeStm;
+            return None
+        }
+
+        let value = table.decompress_pairs(table.get_pairs(stm, tb_file), idx);
+
+        if table.is_dtz {
+            Some(table.map_dtz_score(tb_file, value, wdl))
+        } else {
+            Some(value)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bitmask,
+        zob_keys,
+    };
+
+    use std::collections::HashMap;
+    use std::path::Path;
+
+    const BLOCK_SIZE_LOG2: u8 = 6;
+    const SPAN_LOG2: u8 = 7;
+    const MAX_PAIR_ROUND_COUNT: usize = 6;
+
+    const WP: u8 = 1;
+    const WQ: u8 = 5;
+    const WK: u8 = 6;
+    const BK: u8 = 14;
+
+    // values of the side and leading pawn file of a table, indexed like the probe does
+    type ValueTable = Vec<Vec<Vec<u8>>>;
+
+    fn is_adjacent(index_a: usize, index_b: usize) -> bool {
+        file_of(index_a).abs_diff(file_of(index_b)) <= 1 && rank_of(index_a).abs_diff(rank_of(index_b)) <= 1
+    }
+
+    fn is_queen_attack(q_index: usize, target_index: usize, blocker_index: usize) -> bool {
+        if q_index == target_index {
+            return false
+        }
+
+        let file_step = (file_of(target_index) as i32 - file_of(q_index) as i32).signum();
+        let rank_step = (rank_of(target_index) as i32 - rank_of(q_index) as i32).signum();
+        let file_diff = file_of(target_index).abs_diff(file_of(q_index));
+        let rank_diff = rank_of(target_index).abs_diff(rank_of(q_index));
+
+        if file_diff != 0 && rank_diff != 0 && file_diff != rank_diff {
+            return false
+        }
+
+        let (mut file, mut rank) = (file_of(q_index) as i32 + file_step, rank_of(q_index) as i32 + rank_step);
+
+        while (rank * 8 + file) as usize != target_index {
+            if (rank * 8 + file) as usize == blocker_index {
+                return false
+            }
+
+            file += file_step;
+            rank += rank_step;
+        }
+
+        true
+    }
+
+    fn is_kqk_stalemate(wk_index: usize, wq_index: usize, bk_index: usize) -> bool {
+        if is_queen_attack(wq_index, bk_index, wk_index) {
+            return false
+        }
+
+        (0..def::BOARD_SIZE).filter(|index| is_adjacent(*index, bk_index) && *index != bk_index).all(|index| {
+            // the queen may be taken if the white king does not guard it
+            if index == wq_index {
+                return is_adjacent(index, wk_index)
+            }
+
+            is_adjacent(index, wk_index) || is_queen_attack(wq_index, index, wk_index)
+        })
+    }
+
+    fn build_pairs_table(entry: &TableEntry, piece_list: &[u8], side_count: usize) -> Vec<Vec<PairsData>> {
+        let file_count = if entry.has_pawns { 4 } else { 1 };
+        let mut pairs_table = vec![vec![PairsData::default(); file_count]; side_count];
+
+        for side_pairs_list in pairs_table.iter_mut() {
+            for (file, pairs) in side_pairs_list.iter_mut().enumerate() {
+                pairs.pieces[..piece_list.len()].copy_from_slice(piece_list);
+                set_groups(entry, pairs, [0, 0xf], file);
+            }
+        }
+
+        pairs_table
+    }
+
+    // Fills the values of every placement of the pieces with `get_value(squares, player_index)`,
+    // placements without a value are left as `default_value`.
+    fn gen_value_table<F: Fn(&[usize], usize) -> Option<u8>>(entry: &TableEntry, pairs_table: &[Vec<PairsData>], piece_list: &[u8], default_value: u8, get_value: F) -> ValueTable {
+        let mut value_table: ValueTable = pairs_table.iter().map(|side_pairs_list| side_pairs_list.iter().map(|pairs| vec![default_value; pairs.get_table_size() as usize]).collect()).collect();
+
+        for placement_index in 0..def::BOARD_SIZE.pow(piece_list.len() as u32) {
+            let squares: Vec<usize> = (0..piece_list.len()).map(|i| placement_index / def::BOARD_SIZE.pow(i as u32) % def::BOARD_SIZE).collect();
+
+            if (1..squares.len()).any(|i| squares[..i].contains(&squares[i])) {
+                continue
+            }
+
+            if piece_list.iter().zip(&squares).any(|(piece, index)| *piece & 7 == WP && (rank_of(*index) == 0 || rank_of(*index) == 7)) {
+                continue
+            }
+
+            let mut position_piece_list: Vec<(u8, usize)> = piece_list.iter().copied().zip(squares.iter().copied()).collect();
+            position_piece_list.sort_by_key(|(_, index)| *index);
+
+            for player_index in 0..pairs_table.len().max(2) {
+                let value = match get_value(&squares, player_index) {
+                    Some(value) => value,
+                    None => continue,
+                };
+
+                let (stm, tb_file, idx) = encode_position(entry, pairs_table, &position_piece_list, player_index, false);
+
+                if stm < value_table.len() {
+                    value_table[stm][tb_file][idx as usize] = value;
+                }
+            }
+        }
+
+        value_table
+    }
+
+    // Recursive pairing of the most frequent adjacent symbols, then canonical Huffman codes
+    // with the longer codes numbered first, packed into blocks.
+    struct CompressedPairs {
+        size_bytes: Vec<u8>,
+        sparse_index_bytes: Vec<u8>,
+        block_length_bytes: Vec<u8>,
+        data_bytes: Vec<u8>,
+    }
+
+    fn compress_pairs(value_list: &[u8]) -> CompressedPairs {
+        if value_list.iter().all(|value| *value == value_list[0]) {
+            return CompressedPairs {
+                size_bytes: vec![TABLE_FLAG_SINGLE_VALUE, value_list[0]],
+                sparse_index_bytes: Vec::new(),
+                block_length_bytes: Vec::new(),
+                data_bytes: Vec::new(),
+            }
+        }
+
+        // symbols: (left, right, expanded length), leaves have no right symbol
+        let mut sym_list: Vec<(usize, usize, usize)> = Vec::new();
+        let mut leaf_sym_map = HashMap::new();
+
+        let mut token_list: Vec<usize> = value_list.iter().map(|value| *leaf_sym_map.entry(*value).or_insert_with(|| {
+            sym_list.push((*value as usize, NO_SYM, 1));
+            sym_list.len() - 1
+        })).collect();
+
+        for _ in 0..MAX_PAIR_ROUND_COUNT {
+            let mut pair_count_map: HashMap<(usize, usize), usize> = HashMap::new();
+
+            for pair in token_list.windows(2) {
+                *pair_count_map.entry((pair[0], pair[1])).or_insert(0) += 1;
+            }
+
+            let ((left_sym, right_sym), pair_count) = match pair_count_map.into_iter().filter(|((left_sym, right_sym), _)| sym_list[*left_sym].2 + sym_list[*right_sym].2 <= 256).max_by_key(|(pair, count)| (*count, std::cmp::Reverse(*pair))) {
+                Some(pair_with_count) => pair_with_count,
+                None => break,
+            };
+
+            if pair_count < 4 {
+                break
+            }
+
+            sym_list.push((left_sym, right_sym, sym_list[left_sym].2 + sym_list[right_sym].2));
+            let pair_sym = sym_list.len() - 1;
+
+            let mut paired_token_list = Vec::with_capacity(token_list.len());
+            let mut i = 0;
+
+            while i < token_list.len() {
+                if i + 1 < token_list.len() && token_list[i] == left_sym && token_list[i + 1] == right_sym {
+                    paired_token_list.push(pair_sym);
+                    i += 2;
+                } else {
+                    paired_token_list.push(token_list[i]);
+                    i += 1;
+                }
+            }
+
+            token_list = paired_token_list;
+        }
+
+        // Huffman code lengths
+        let mut freq_list = vec![0usize; sym_list.len()];
+
+        for token in &token_list {
+            freq_list[*token] += 1;
+        }
+
+        let coded_sym_list: Vec<usize> = (0..sym_list.len()).filter(|sym| freq_list[*sym] > 0).collect();
+        let mut code_len_list = vec![0usize; sym_list.len()];
+
+        if coded_sym_list.len() == 1 {
+            code_len_list[coded_sym_list[0]] = 1;
+        } else {
+            let mut node_list: Vec<(usize, Vec<usize>)> = coded_sym_list.iter().map(|sym| (freq_list[*sym], vec![*sym])).collect();
+
+            while node_list.len() > 1 {
+                node_list.sort_by(|node_a, node_b| node_b.0.cmp(&node_a.0));
+                let (freq_a, sym_list_a) = node_list.pop().unwrap();
+                let (freq_b, sym_list_b) = node_list.pop().unwrap();
+
+                for sym in sym_list_a.iter().chain(&sym_list_b) {
+                    code_len_list[*sym] += 1;
+                }
+
+                node_list.push((freq_a + freq_b, [sym_list_a, sym_list_b].concat()));
+            }
+        }
+
+        let min_len = coded_sym_list.iter().map(|sym| code_len_list[*sym]).min().unwrap();
+        let max_len = coded_sym_list.iter().map(|sym| code_len_list[*sym]).max().unwrap();
+
+        // number the coded symbols by descending code length, the others after them
+        let mut sym_order_list = coded_sym_list.clone();
+        sym_order_list.sort_by_key(|sym| std::cmp::Reverse(code_len_list[*sym]));
+        sym_order_list.extend((0..sym_list.len()).filter(|sym| freq_list[*sym] == 0));
+
+        let mut sym_number_list = vec![0; sym_list.len()];
+
+        for (number, sym) in sym_order_list.iter().enumerate() {
+            sym_number_list[*sym] = number;
+        }
+
+        let mut lowest_sym_list = vec![0usize; max_len - min_len + 1];
+        let mut base_list = vec![0u64; max_len - min_len + 1];
+        let count_of_len = |len: usize| coded_sym_list.iter().filter(|sym| code_len_list[**sym] == len).count();
+
+        lowest_sym_list[max_len - min_len] = 0;
+
+        for len in (min_len..max_len).rev() {
+            lowest_sym_list[len - min_len] = lowest_sym_list[len + 1 - min_len] + count_of_len(len + 1);
+            base_list[len - min_len] = (base_list[len + 1 - min_len] + count_of_len(len + 1) as u64) / 2;
+        }
+
+        let get_code = |sym: usize| {
+            let len = code_len_list[sym];
+            (base_list[len - min_len] + (sym_number_list[sym] - lowest_sym_list[len - min_len]) as u64, len)
+        };
+
+        // blocks of whole symbols
+        let block_size = 1 << BLOCK_SIZE_LOG2;
+        let mut block_list: Vec<(Vec<u8>, usize)> = Vec::new();
+        let mut bit_list: Vec<bool> = Vec::new();
+        let mut block_value_count = 0;
+
+        let mut flush_block = |bit_list: &mut Vec<bool>, block_value_count: &mut usize| {
+            let mut block_bytes = vec![0u8; block_size];
+
+            for (bit_index, bit) in bit_list.iter().enumerate() {
+                if *bit {
+                    block_bytes[bit_index / 8] |= 0x80 >> (bit_index % 8);
+                }
+            }
+
+            block_list.push((block_bytes, *block_value_count));
+            bit_list.clear();
+            *block_value_count = 0;
+        };
+
+        for token in &token_list {
+            let (code, len) = get_code(*token);
+
+            if bit_list.len() + len > block_size * 8 {
+                flush_block(&mut bit_list, &mut block_value_count);
+            }
+
+            for bit_index in (0..len).rev() {
+                bit_list.push(code >> bit_index & 1 != 0);
+            }
+
+            block_value_count += sym_list[*token].2;
+        }
+
+        flush_block(&mut bit_list, &mut block_value_count);
+
+        let span = 1usize << SPAN_LOG2;
+        let mut sparse_index_bytes = Vec::new();
+        let block_start_list: Vec<usize> = block_list.iter().scan(0, |start, (_, value_count)| {
+            let block_start = *start;
+            *start += value_count;
+            Some(block_start)
+        }).collect();
+
+        for k in 0..value_list.len().div_ceil(span) {
+            let value_index = k * span + span / 2;
+            let block = block_start_list.iter().rposition(|block_start| *block_start <= value_index).unwrap();
+
+            sparse_index_bytes.extend_from_slice(&(block as u32).to_le_bytes());
+            sparse_index_bytes.extend_from_slice(&((value_index - block_start_list[block]) as u16).to_le_bytes());
+        }
+
+        let mut size_bytes = vec![0, BLOCK_SIZE_LOG2, SPAN_LOG2, 0];
+        size_bytes.extend_from_slice(&(block_list.len() as u32).to_le_bytes());
+        size_bytes.push(max_len as u8);
+        size_bytes.push(min_len as u8);
+
+        for lowest_sym in &lowest_sym_list {
+            size_bytes.extend_from_slice(&(*lowest_sym as u16).to_le_bytes());
+        }
+
+        size_bytes.extend_from_slice(&(sym_list.len() as u16).to_le_bytes());
+
+        for sym in &sym_order_list {
+            let (left, right) = match sym_list[*sym] {
+                (value, NO_SYM, _) => (value, NO_SYM),
+                (left_sym, right_sym, _) => (sym_number_list[left_sym], sym_number_list[right_sym]),
+            };
+
+            size_bytes.extend_from_slice(&[(left & 0xff) as u8, ((left >> 8) | ((right & 0xf) << 4)) as u8, (right >> 4) as u8]);
+        }
+
+        if sym_list.len() & 1 != 0 {
+            size_bytes.push(0);
+        }
+
+        CompressedPairs {
+            size_bytes,
+            sparse_index_bytes,
+            block_length_bytes: block_list.iter().flat_map(|(_, value_count)| ((*value_count - 1) as u16).to_le_bytes()).collect(),
+            data_bytes: block_list.into_iter().flat_map(|(block_bytes, _)| block_bytes).collect(),
+        }
+    }
+
+    fn write_table(file_path: &Path, is_dtz: bool, entry: &TableEntry, piece_list: &[u8], value_table: &ValueTable, flags: u8, dtz_map: &[Vec<u8>]) {
+        let mut bytes = if is_dtz { DTZ_MAGIC.to_vec() } else { WDL_MAGIC.to_vec() };
+        let mut file_flags = 0;
+
+        if !entry.is_symmetric {
+            file_flags |= FILE_FLAG_SPLIT;
+        }
+
+        if entry.has_pawns {
+            file_flags |= FILE_FLAG_HAS_PAWNS;
+        }
+
+        bytes.push(file_flags);
+
+        let file_count = value_table[0].len();
+
+        for _ in 0..file_count {
+            bytes.push(0);
+
+            for piece in piece_list {
+                bytes.push(piece | piece << 4);
+            }
+        }
+
+        if bytes.len() & 1 != 0 {
+            bytes.push(0);
+        }
+
+        let compressed_list: Vec<Vec<CompressedPairs>> = (0..file_count).map(|file| value_table.iter().map(|side_value_list| compress_pairs(&side_value_list[file])).collect()).collect();
+
+        for compressed_pairs in compressed_list.iter().flatten() {
+            let mut size_bytes = compressed_pairs.size_bytes.clone();
+
+            if size_bytes[0] & TABLE_FLAG_SINGLE_VALUE == 0 {
+                size_bytes[0] = flags;
+            }
+
+            bytes.extend_from_slice(&size_bytes);
+        }
+
+        if is_dtz && flags & TABLE_FLAG_MAPPED != 0 {
+            for _ in 0..file_count {
+                for map in dtz_map {
+                    bytes.push(map.len() as u8);
+                    bytes.extend_from_slice(map);
+                }
+            }
+
+            if bytes.len() & 1 != 0 {
+                bytes.push(0);
+            }
+        }
+
+        for compressed_pairs in compressed_list.iter().flatten() {
+            bytes.extend_from_slice(&compressed_pairs.sparse_index_bytes);
+        }
+
+        for compressed_pairs in compressed_list.iter().flatten() {
+            bytes.extend_from_slice(&compressed_pairs.block_length_bytes);
+        }
+
+        for compressed_pairs in compressed_list.iter().flatten() {
+            while bytes.len() & 0x3f != 0 {
+                bytes.push(0);
+            }
+
+            bytes.extend_from_slice(&compressed_pairs.data_bytes);
+        }
+
+        fs::write(file_path, bytes).unwrap();
+    }
+
+    fn get_test_dir(name: &str) -> PathBuf {
+        let test_dir = std::env::temp_dir().join(format!("foxsee_syzygy_{}_{}", name, std::process::id()));
+        fs::create_dir_all(&test_dir).unwrap();
+        test_dir
+    }
+
+    fn chebyshev_distance(index_a: usize, index_b: usize) -> usize {
+        file_of(index_a).abs_diff(file_of(index_b)).max(rank_of(index_a).abs_diff(rank_of(index_b)))
+    }
+
+    // KQvK: White to move always wins, Black to move loses unless stalemated;
+    // the DTZ table stores the distance between the kings for White to move.
+    fn write_kqk_tables(test_dir: &Path, with_dtz: bool) {
+        let piece_list = [WQ, WK, BK];
+        let entry = TableEntry::new("KQvK", PathBuf::new(), None).unwrap();
+
+        let pairs_table = build_pairs_table(&entry, &piece_list, 2);
+        let value_table = gen_value_table(&entry, &pairs_table, &piece_list, 0, |squares, player_index| {
+            let (wq_index, wk_index, bk_index) = (squares[0], squares[1], squares[2]);
+
+            if is_adjacent(wk_index, bk_index) {
+                return None
+            }
+
+            if player_index == 0 {
+                Some(4)
+            } else if is_kqk_stalemate(wk_index, wq_index, bk_index) {
+                Some(2)
+            } else {
+                Some(0)
+            }
+        });
+
+        write_table(&test_dir.join("KQvK.rtbw"), false, &entry, &piece_list, &value_table, 0, &[]);
+
+        if with_dtz {
+            let pairs_table = build_pairs_table(&entry, &piece_list, 1);
+            let value_table = gen_value_table(&entry, &pairs_table, &piece_list, 0, |squares, player_index| {
+                if player_index == 0 && !is_adjacent(squares[1], squares[2]) {
+                    Some(chebyshev_distance(squares[1], squares[2]) as u8)
+                } else {
+                    None
+                }
+            });
+
+            let dtz_map = vec![vec![0, 5, 6, 7, 8, 9, 10, 11], vec![0], vec![0], vec![0]];
+            write_table(&test_dir.join("KQvK.rtbz"), true, &entry, &piece_list, &value_table, TABLE_FLAG_MAPPED, &dtz_map);
+        }
+    }
+
+    fn get_kpk_value(squares: &[usize], player_index: usize) -> Option<u8> {
+        let (wp_index, wk_index, bk_index) = (squares[0], squares[1], squares[2]);
+
+        if is_adjacent(wk_index, bk_index) {
+            return None
+        }
+
+        Some(((rank_of(wp_index) + chebyshev_distance(wk_index, bk_index) + player_index) % 5) as u8)
+    }
+
+    fn probe_wdl_value(tablebase: &Tablebase, fen: &str) -> Option<i32> {
+        tablebase.probe_wdl(&mut State::new(fen)).map(|wdl| wdl.get_value())
+    }
+
+    #[test]
+    fn test_table_entry() {
+        let entry = TableEntry::new("KRPvKR", PathBuf::new(), None).unwrap();
+        assert_eq!(6 - 1, entry.piece_count);
+        assert!(entry.has_pawns);
+        assert!(entry.has_unique_pieces);
+        assert_eq!([1, 0], entry.pawn_count);
+        assert!(!entry.is_symmetric);
+
+        let entry = TableEntry::new("KPvKPP", PathBuf::new(), None).unwrap();
+        assert_eq!([1, 2], entry.pawn_count);
+
+        let entry = TableEntry::new("KNNvKNN", PathBuf::new(), None).unwrap();
+        assert!(!entry.has_unique_pieces);
+        assert!(entry.is_symmetric);
+
+        assert!(TableEntry::new("KQRvR", PathBuf::new(), None).is_none());
+        assert!(TableEntry::new("KQXvK", PathBuf::new(), None).is_none());
+    }
+
+    #[test]
+    fn test_encoding_tables() {
+        let encoding_tables = get_encoding_tables();
+
+        assert_eq!(461, (0..10).flat_map(|triangle_code| encoding_tables.map_kk[triangle_code].iter()).copied().max().unwrap());
+        assert_eq!(47, encoding_tables.map_pawns[util::map_sqr_notation_to_index("a2")]);
+        assert_eq!(46, encoding_tables.map_pawns[util::map_sqr_notation_to_index("h2")]);
+        assert_eq!(0, encoding_tables.map_pawns[util::map_sqr_notation_to_index("e7")]);
+        assert_eq!(1176, encoding_tables.binomial[2][49]);
+    }
+
+    #[test]
+    fn test_probe_kqk() {
+        zob_keys::init();
+        bitmask::init();
+
+        let test_dir = get_test_dir("kqk");
+        write_kqk_tables(&test_dir, false);
+
+        let tablebase = Tablebase::new(test_dir.to_str().unwrap()).unwrap();
+        assert_eq!(1, tablebase.get_table_count());
+        assert_eq!(3, tablebase.get_max_piece_count());
+
+        assert_eq!(Some(2), probe_wdl_value(&tablebase, "8/8/8/3k4/8/8/8/K2Q4 w - - 0 1"));
+        assert_eq!(Some(-2), probe_wdl_value(&tablebase, "8/8/8/3k4/8/8/8/K2Q4 b - - 0 1"));
+
+        // the colors are swapped for Black as the stronger side
+        assert_eq!(Some(-2), probe_wdl_value(&tablebase, "k2q4/8/8/8/3K4/8/8/8 w - - 0 1"));
+        assert_eq!(Some(2), probe_wdl_value(&tablebase, "k2q4/8/8/8/3K4/8/8/8 b - - 0 1"));
+
+        // stalemate, and an unprotected queen next to the king
+        assert_eq!(Some(0), probe_wdl_value(&tablebase, "k7/2Q5/1K6/8/8/8/8/8 b - - 0 1"));
+        assert_eq!(Some(0), probe_wdl_value(&tablebase, "8/8/8/3k4/3Q4/8/8/K7 b - - 0 1"));
+
+        assert_eq!(None, probe_wdl_value(&tablebase, "8/8/8/3k4/8/8/8/K2R4 w - - 0 1"));
+        assert_eq!(None, probe_wdl_value(&tablebase, "8/8/8/3k4/8/8/8/KQ1Q4 w - - 0 1"));
+
+        // every placement agrees with the mirrored and flipped ones
+        for wq_index in [0, 9, 27, 36, 45, 7, 56, 63, 12, 51] {
+            for wk_index in [2, 21, 42, 60] {
+                for bk_index in [5, 31, 47, 58] {
+                    if wq_index == wk_index || wq_index == bk_index || is_adjacent(wk_index, bk_index) {
+                        continue
+                    }
+
+                    let mut squares = [0; def::BOARD_SIZE];
+                    squares[wq_index] = def::WQ;
+                    squares[wk_index] = def::WK;
+                    squares[bk_index] = def::BK;
+
+                    let fen = format!("{} b - - 0 1", crate::state::get_fen_sqrs_string(&squares));
+                    let mut state = State::new(&fen);
+
+                    if mov_table::is_in_check(&state, def::PLAYER_W) {
+                        continue
+                    }
+
+                    let expected_value = if is_kqk_stalemate(wk_index, wq_index, bk_index) || (is_adjacent(wq_index, bk_index) && !is_adjacent(wq_index, wk_index)) {
+                        0
+                    } else {
+                        -2
+                    };
+
+                    assert_eq!(Some(expected_value), tablebase.probe_wdl(&mut state).map(|wdl| wdl.get_value()), "{}", fen);
+                }
+            }
+        }
+
+        fs::remove_dir_all(test_dir).ok();
+    }
+
+    #[test]
+    fn test_probe_kpk() {
+        zob_keys::init();
+        bitmask::init();
+
+        let test_dir = get_test_dir("kpk");
+        let piece_list = [WP, WK, BK];
+        let entry = TableEntry::new("KPvK", PathBuf::new(), None).unwrap();
+        let pairs_table = build_pairs_table(&entry, &piece_list, 2);
+        let value_table = gen_value_table(&entry, &pairs_table, &piece_list, 0, get_kpk_value);
+        write_table(&test_dir.join("KPvK.rtbw"), false, &entry, &piece_list, &value_table, 0, &[]);
+
+        let tablebase = Tablebase::new(test_dir.to_str().unwrap()).unwrap();
+
+        for (fen, squares, player_index) in [
+            ("8/8/8/8/3k4/8/1P6/K7 w - - 0 1", ["b2", "a1", "d4"], 0),
+            ("8/8/8/8/3k4/8/1P6/K7 b - - 0 1", ["b2", "a1", "d4"], 1),
+            ("8/6P1/8/8/8/1k6/8/5K2 w - - 0 1", ["g7", "f1", "b3"], 0),
+            ("8/8/4k3/8/8/4P3/8/4K3 b - - 0 1", ["e3", "e1", "e6"], 1),
+            ("7K/8/8/8/8/8/P7/5k2 w - - 0 1", ["a2", "h8", "f1"], 0),
+        ] {
+            let squares: Vec<usize> = squares.iter().map(|sqr| util::map_sqr_notation_to_index(sqr)).collect();
+            let expected_value = get_kpk_value(&squares, player_index).unwrap() as i32 - 2;
+
+            assert_eq!(Some(expected_value), probe_wdl_value(&tablebase, fen), "{}", fen);
+
+            // the same position with the colors swapped
+            let flipped_fen = State::new(fen).to_fen().split(' ').next().unwrap().split('/').rev().collect::<Vec<&str>>().join("/").chars().map(|c| if c.is_ascii_uppercase() { c.to_ascii_lowercase() } else { c.to_ascii_uppercase() }).collect::<String>();
+            let flipped_fen = format!("{} {} - - 0 1", flipped_fen, if player_index == 0 { "b" } else { "w" });
+
+            assert_eq!(Some(expected_value), probe_wdl_value(&tablebase, &flipped_fen), "{}", flipped_fen);
+        }
+
+        fs::remove_dir_all(test_dir).ok();
+    }
+
+    #[test]
+    fn test_probe_dtz_and_rank_root_movs() {
+        zob_keys::init();
+        bitmask::init();
+
+        let test_dir = get_test_dir("kqk_dtz");
+        write_kqk_tables(&test_dir, true);
+
+        let tablebase = Tablebase::new(test_dir.to_str().unwrap()).unwrap();
+        assert_eq!(1, tablebase.get_dtz_table_count());
+
+        // the kings are 4 squares apart: the map gives 8 moves
+        assert_eq!(Some(17), tablebase.probe_dtz(&mut State::new("8/8/8/3k4/8/8/8/K2Q4 w - - 0 1")));
+
+        let mut state = State::new("8/8/8/8/8/2k5/8/K2Q4 w - - 0 1");
+
+        for ranked_mov_list in [tablebase.rank_root_movs(&mut state).unwrap(), tablebase.rank_root_movs_by_wdl(&mut state).unwrap()] {
+            for (mov, rank) in ranked_mov_list {
+                let mov_str = util::format_mov(mov);
+                let hangs_queen = ["d1c2", "d1d2", "d1d3", "d1d4", "d1b3", "d1c1"].contains(&mov_str.as_str());
+
+                assert_eq!(if hangs_queen && mov_str != "d1c1" { 0 } else { 1000 }, rank, "{}", mov_str);
+            }
+        }
+
+        fs::remove_dir_all(test_dir).ok();
+    }
+
+    #[test]
+    fn test_search_with_tablebase() {
+        zob_keys::init();
+        bitmask::init();
+
+        let test_dir = get_test_dir("kqk_search");
+        write_kqk_tables(&test_dir, false);
+
+        let mut search_engine = crate::SearchEngine::new(def::MIN_HASH_SIZE_UNIT);
+        search_engine.set_silent(true);
+        search_engine.set_tablebase(Some(std::sync::Arc::new(Tablebase::new(test_dir.to_str().unwrap()).unwrap())));
+
+        let mut state = State::new("8/8/8/8/8/2k5/8/K2Q4 w - - 0 1");
+
+        // the search moves still restrict the winning moves
+        search_engine.set_search_movs(vec![util::encode_u32_mov(3, 11, def::MOV_REG, 0), util::encode_u32_mov(3, 4, def::MOV_REG, 0)]);
+        assert_eq!("d1e1", util::format_mov(search_engine.search(&mut state, crate::TimeCapacity { main_time_millis: u128::MAX }, 1, u64::MAX)));
+
+        let best_mov = util::format_mov(search_engine.search(&mut state, crate::TimeCapacity { main_time_millis: u128::MAX }, 3, u64::MAX));
+        assert!(!["d1c2", "d1d2", "d1d3", "d1d4", "d1b3"].contains(&best_mov.as_str()), "{}", best_mov);
+
+        fs::remove_dir_all(test_dir).ok();
+    }
+}
@@ -0,0 +1,329 @@
+/*
+ * Copyright (C) 2020-2022 Zixiao Han
+ */
+
+//! Texel tuning of the evaluation weights on quiet positions labelled with game results.
+
+use crate::{
+    def,
+    eval::{self, EvalParams},
+    state::State,
+};
+
+use std::fs;
+use std::io;
+use std::thread;
+
+const MAX_SCALING_FACTOR: f64 = 4.;
+const SCALING_FACTOR_SEARCH_COUNT: usize = 40;
+
+/// A position with the result of its game from White's point of view: 1, 0.5 or 0.
+pub struct TuningPosition {
+    pub state: State,
+    pub result: f64,
+}
+
+/// Parses a line of an EPD or CSV file holding a position and its game result, such as
+/// `<fen> c9 "1-0";`, `<fen> [0.5]`, `<fen>,0` or `<fen> | <score> | 1.0` as written by datagen.
+/// The move counters of the FEN are optional.
+pub fn parse_position(line: &str) -> Option<(String, f64)> {
+    let (position_str, result_str) = match line.rsplit_once('|') {
+        Some((position_str, result_str)) => (position_str.split('|').next().unwrap_or_default(), result_str),
+        None => line.split_once(',').unwrap_or((line, line)),
+    };
+    let field_list = position_str.split_whitespace().collect::<Vec<&str>>();
+
+    if field_list.len() < 4 {
+        return None
+    }
+
+    let fen = match field_list.get(4..6) {
+        Some([half_mov_str, full_mov_str]) if half_mov_str.parse::<u16>().is_ok() && full_mov_str.parse::<u16>().is_ok() => field_list[..6].join(" "),
+        _ => format!("{} 0 1", field_list[..4].join(" ")),
+    };
+
+    Some((fen, parse_result(result_str)?))
+}
+
+fn parse_result(result_str: &str) -> Option<f64> {
+    if result_str.contains("1/2-1/2") {
+        return Some(0.5)
+    }
+
+    if result_str.contains("1-0") {
+        return Some(1.)
+    }
+
+    if result_str.contains("0-1") {
+        return Some(0.)
+    }
+
+    let value_str = match (result_str.find('['), result_str.find(']')) {
+        (Some(start), Some(end)) if start < end => &result_str[start + 1..end],
+        _ => result_str,
+    };
+
+    value_str.trim().parse::<f64>().ok().filter(|result| (0. ..=1.).contains(result))
+}
+
+/// Reads up to `max_count` positions, skipping the lines without a valid position and result.
+/// Returns the positions with the number of skipped lines.
+pub fn read_positions(file_path: &str, max_count: usize) -> io::Result<(Vec<TuningPosition>, usize)> {
+    let file_str = fs::read_to_string(file_path)?;
+
+    let mut position_list = Vec::new();
+    let mut skipped_count = 0;
+
+    for line in file_str.lines().filter(|line| !line.trim().is_empty()) {
+        if position_list.len() >= max_count {
+            break
+        }
+
+        match parse_position(line).and_then(|(fen, result)| State::from_fen(&fen).ok().map(|state| TuningPosition { state, result })) {
+            Some(position) => position_list.push(position),
+            None => skipped_count += 1,
+        }
+    }
+
+    Ok((position_list, skipped_count))
+}
+
+/// Minimises the mean squared error between the game results and the
+/// evaluation mapped to an expected score by `1 / (1 + 10^(-k * eval / 400))`.
+pub struct Tuner {
+    position_list: Vec<TuningPosition>,
+    thread_count: usize,
+    scaling_factor: f64,
+}
+
+impl Tuner {
+    pub fn new(position_list: Vec<TuningPosition>, thread_count: usize) -> Self {
+        Tuner {
+            position_list,
+            thread_count: thread_count.max(1),
+            scaling_factor: 1.,
+        }
+    }
+
+    pub fn get_scaling_factor(&self) -> f64 {
+        self.scaling_factor
+    }
+
+    pub fn get_error(&mut self, eval_params: &EvalParams) -> f64 {
+        if self.position_list.is_empty() {
+            return 0.
+        }
+
+        let scaling_factor = self.scaling_factor;
+        let chunk_size = self.position_list.len().div_ceil(self.thread_count);
+
+        let total_error = thread::scope(|scope| {
+            let handle_list = self.position_list.chunks_mut(chunk_size).map(|position_chunk| {
+                scope.spawn(move || {
+                    position_chunk.iter_mut().map(|position| {
+                        let expected_result = 1. / (1. + 10f64.powf(-scaling_factor * get_w_eval(&mut position.state, eval_params) as f64 / 400.));
+                        (position.result - expected_result).powi(2)
+                    }).sum::<f64>()
+                })
+            }).collect::<Vec<_>>();
+
+            handle_list.into_iter().map(|handle| handle.join().unwrap()).sum::<f64>()
+        });
+
+        total_error / self.position_list.len() as f64
+    }
+
+    /// Finds the scaling factor that fits the current weights best
+    /// and keeps it for the tuning; returns the factor and its error.
+    pub fn fit_scaling_factor(&mut self, eval_params: &EvalParams) -> (f64, f64) {
+        let mut lower_factor = 0.;
+        let mut upper_factor = MAX_SCALING_FACTOR;
+
+        for _ in 0..SCALING_FACTOR_SEARCH_COUNT {
+            let left_factor = lower_factor + (upper_factor - lower_factor) / 3.;
+            let right_factor = upper_factor - (upper_factor - lower_factor) / 3.;
+
+            self.scaling_factor = left_factor;
+            let left_error = self.get_error(eval_params);
+
+            self.scaling_factor = right_factor;
+            let right_error = self.get_error(eval_params);
+
+            if left_error < right_error {
+                upper_factor = right_factor;
+            } else {
+                lower_factor = left_factor;
+            }
+        }
+
+        self.scaling_factor = (lower_factor + upper_factor) / 2.;
+
+        (self.scaling_factor, self.get_error(eval_params))
+    }
+
+    /// Tunes the weights named in `param_name_list`, or all weights if it is empty, by local search:
+    /// every weight is moved by the step in both directions and kept where the error drops.
+    /// The step is halved whenever a whole pass brings no improvement. `on_iteration` gets the
+    /// iteration, the error and the step after each pass. Returns the final error.
+    pub fn tune<F: FnMut(usize, f64, i32)>(&mut self, eval_params: &mut EvalParams, param_name_list: &[String], initial_step: i32, max_iteration_count: usize, mut on_iteration: F) -> f64 {
+        let param_index_list = eval_params.get_param_list().into_iter().enumerate()
+            .filter(|(_table_index, (name, _param_list))| param_name_list.is_empty() || param_name_list.iter().any(|param_name| param_name == name))
+            .flat_map(|(table_index, (_name, param_list))| (0..param_list.len()).map(move |value_index| (table_index, value_index)))
+            .collect::<Vec<(usize, usize)>>();
+
+        let mut best_error = self.get_error(eval_params);
+        let mut step = initial_step.max(1);
+
+        for iteration in 1..=max_iteration_count {
+            let mut improved = false;
+
+            for &(table_index, value_index) in &param_index_list {
+                for delta in [step, -step] {
+                    eval_params.get_param_list_mut()[table_index].1[value_index] += delta;
+
+                    let error = self.get_error(eval_params);
+
+                    if error < best_error {
+                        best_error = error;
+                        improved = true;
+                        break
+                    }
+
+                    eval_params.get_param_list_mut()[table_index].1[value_index] -= delta;
+                }
+            }
+
+            on_iteration(iteration, best_error, step);
+
+            if !improved {
+                if step == 1 {
+                    break
+                }
+
+                step /= 2;
+            }
+        }
+
+        best_error
+    }
+}
+
+// quiet positions are scored by the static evaluation, from White's point of view
+fn get_w_eval(state: &mut State, eval_params: &EvalParams) -> i32 {
+    let (material_score, is_draw) = eval::eval_materials(state, eval_params);
+
+    if is_draw {
+        return 0
+    }
+
+    let score = eval::eval_state(state, material_score, eval_params);
+
+    if state.player == def::PLAYER_W {
+        score
+    } else {
+        -score
+    }
+}
+
+/// Formats the weights as the constant declarations of `eval.rs`.
+pub fn format_eval_params(eval_params: &EvalParams) -> String {
+    let mut param_str = String::new();
+
+    for (name, param_list) in eval_params.get_param_list() {
+        let value_str_list = param_list.iter().map(|value| format!("{:>3}", value)).collect::<Vec<String>>();
+
+        match param_list.len() {
+            1 => param_str.push_str(&format!("const {}: i32 = {};\n", name, param_list[0])),
+            def::BOARD_SIZE => {
+                param_str.push_str(&format!("const {}: [i32; def::BOARD_SIZE] = [\n", name));
+
+                for row in value_str_list.chunks(def::DIM_SIZE) {
+                    param_str.push_str(&format!("    {},\n", row.join(",")));
+                }
+
+                param_str.push_str("];\n");
+            },
+            table_size if table_size > def::BOARD_SIZE => {
+                param_str.push_str(&format!("const {}: [i32; {}] = [\n", name, table_size));
+
+                for row in value_str_list.chunks(10) {
+                    param_str.push_str(&format!("    {},\n", row.join(", ")));
+                }
+
+                param_str.push_str("];\n");
+            },
+            table_size => {
+                let size_str = if table_size == def::DIM_SIZE {
+                    "def::DIM_SIZE".to_owned()
+                } else {
+                    table_size.to_string()
+                };
+
+                param_str.push_str(&format!("const {}: [i32; {}] = [{}];\n", name, size_str, param_list.iter().map(|value| value.to_string()).collect::<Vec<String>>().join(", ")));
+            },
+        }
+    }
+
+    param_str
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bitmask,
+        zob_keys,
+    };
+
+    #[test]
+    fn test_parse_position() {
+        assert_eq!(Some(("8/8/8/8/8/8/8/K1k5 w - - 0 1".to_owned(), 1.)), parse_position("8/8/8/8/8/8/8/K1k5 w - - c9 \"1-0\";"));
+        assert_eq!(Some(("8/8/8/8/8/8/8/K1k5 b - - 3 40".to_owned(), 0.5)), parse_position("8/8/8/8/8/8/8/K1k5 b - - 3 40 [0.5]"));
+        assert_eq!(Some(("8/8/8/8/8/8/8/K1k5 w - - 0 1".to_owned(), 0.)), parse_position("8/8/8/8/8/8/8/K1k5 w - -,0"));
+        assert_eq!(Some(("8/8/8/8/8/8/8/K1k5 w - - 0 1".to_owned(), 0.5)), parse_position("8/8/8/8/8/8/8/K1k5 w - - c9 \"1/2-1/2\";"));
+        assert_eq!(None, parse_position("8/8/8/8/8/8/8/K1k5 w - -"));
+        assert_eq!(None, parse_position("8/8/8/8/8/8/8/K1k5 w - -,2"));
+        assert_eq!(None, parse_position("1-0"));
+    }
+
+    #[test]
+    fn test_tune() {
+        zob_keys::init();
+        bitmask::init();
+
+        let position_list = [
+            ("4k3/pppp4/8/8/8/8/PPPPP3/4K3 w - - 0 1", 1.),
+            ("4k3/pppp4/8/8/8/8/PPPPP3/4K3 b - - 0 1", 1.),
+            ("4k3/ppppp3/8/8/8/8/PPPP4/4K3 w - - 0 1", 0.),
+            ("4k3/pppp4/8/8/8/8/PPPP4/4K3 w - - 0 1", 0.5),
+            ("4k3/pppp4/8/8/8/8/PPPP4/3NK3 w - - 0 1", 1.),
+        ].iter().map(|(fen, result)| TuningPosition { state: State::new(fen), result: *result }).collect::<Vec<TuningPosition>>();
+
+        let mut tuner = Tuner::new(position_list, 2);
+        let mut eval_params = EvalParams::default();
+
+        let (scaling_factor, initial_error) = tuner.fit_scaling_factor(&eval_params);
+        assert!(scaling_factor > 0. && scaling_factor < MAX_SCALING_FACTOR);
+
+        let mut iteration_count = 0;
+        let error = tuner.tune(&mut eval_params, &["P_VAL".to_owned()], 8, 5, |_iteration, _error, _step| iteration_count += 1);
+
+        assert!(error <= initial_error);
+        assert!(iteration_count > 0 && iteration_count <= 5);
+
+        let mut expected_eval_params = EvalParams::default();
+        expected_eval_params.p_val = eval_params.p_val;
+        assert_eq!(expected_eval_params, eval_params);
+        assert_eq!(error, tuner.get_error(&eval_params));
+    }
+
+    #[test]
+    fn test_format_eval_params() {
+        let param_str = format_eval_params(&EvalParams::default());
+
+        assert!(param_str.starts_with("const Q_VAL: i32 = 1400;\n"));
+        assert!(param_str.contains("const PASS_PAWN_VAL: [i32; def::DIM_SIZE] = [0, 50, 50, 80, 100, 150, 190, 0];\n"));
+        assert!(param_str.contains("const SQR_TABLE_WN: [i32; def::BOARD_SIZE] = [\n    -60,-30,-20,-20,-20,-20,-30,-60,\n    -30,-30,  0,  0,  0,  0,-30,-30,\n"));
+        assert!(param_str.contains("const K_ATTACK_SCORE: [i32; 200] = [\n      0,   0,   5,  10,  20,  30,  40,  50,  60,  70,\n"));
+    }
+}
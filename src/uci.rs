@@ -3,15 +3,20 @@
  */
 
 use crate::{
+    bench,
     def,
+    eval::EvalType,
     util,
 };
 
 use std::io::{self, prelude::*};
+use std::thread;
 
 const DEFAULT_MOVS_TO_GO: u128 = 50;
 const DEFAULT_MOVS_TO_GO_NO_INCREMENT: u128 = 60;
 
+const GO_SUB_CMDS: [&str; 12] = ["searchmoves", "ponder", "wtime", "btime", "winc", "binc", "movestogo", "depth", "nodes", "mate", "movetime", "infinite"];
+
 pub const FEN_START_POS: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
 
 #[derive(Debug)]
@@ -32,16 +37,33 @@ pub struct TimeInfo {
 #[derive(Debug)]
 pub enum UciCommand {
     Noop,
+    IsReady,
     Reset,
     IgnoredOption,
-    Perft(u8),
+    Perft(u8, bool, bool, usize),
+    PerftSuite(String, u8),
+    Bench(u8, usize),
     SetHashSize(usize),
+    SetMultiPV(usize),
+    SetThreads(usize),
+    SetChess960(bool),
+    SetOwnBook(bool),
+    SetBookFile(String),
+    SetEvalType(EvalType),
+    SetEvalFile(String),
+    SetNetworkFile(String),
+    SetSyzygyPath(String),
+    SetBitbaseFile(String),
     Position(String, Vec<Rawmov>),
     PrintDebugInfo,
     StartSearchWithTime(u128),
     StartSearchWithComplextTimeControl((TimeInfo, TimeInfo)),
     StartSearchToDepth(u8),
+    StartSearchWithLimits(u64, Option<u8>, Option<u128>, Option<(TimeInfo, TimeInfo)>),
+    StartSearchMate(u8),
     StartSearchInfinite,
+    RestrictSearchMovs(Vec<Rawmov>, Box<UciCommand>),
+    Ponder(Box<UciCommand>),
 }
 
 pub fn process_uci_cmd(uci_cmd: &str) -> UciCommand {
@@ -51,28 +73,83 @@ pub fn process_uci_cmd(uci_cmd: &str) -> UciCommand {
             println!("id name {} {}", def::ENGINE_NAME, def::VERSION);
             println!("id author {}", def::AUTHOR);
             println!("option name Hash type spin default {} min {} max {}", def::DEFAULT_HASH_SIZE_MB, def::MIN_HASH_SIZE_MB, def::MAX_HASH_SIZE_MB);
+            println!("option name Ponder type check default false");
+            println!("option name MultiPV type spin default {} min 1 max {}", def::DEFAULT_MULTI_PV, def::MAX_MULTI_PV);
+            println!("option name Threads type spin default {} min 1 max {}", def::DEFAULT_THREADS, def::MAX_THREADS);
+            println!("option name UCI_Chess960 type check default false");
+            println!("option name OwnBook type check default false");
+            println!("option name BookFile type string default <empty>");
+            println!("option name EvalType type combo default NNUE var NNUE var Classical");
+            println!("option name EvalFile type string default <empty>");
+            println!("option name NetworkFile type string default <empty>");
+            println!("option name SyzygyPath type string default <empty>");
+            println!("option name BitbaseFile type string default <empty>");
             println!("uciok");
             io::stdout().flush().ok();
             UciCommand::Noop
         },
-        "printdebug" => UciCommand::PrintDebugInfo,
-        "isready" => {
-            println!("readyok");
-            io::stdout().flush().ok();
-            UciCommand::Noop
-        },
+        "printdebug" | "d" => UciCommand::PrintDebugInfo,
+        "isready" => UciCommand::IsReady,
         "setoption" => {
             match cmd_seq[2] {
                 "Hash" => {
                     let hash_size_mb = cmd_seq[4].parse::<usize>().unwrap();
-                    let hash_ratio = hash_size_mb / def::MIN_HASH_SIZE_MB;
 
-                    if hash_ratio == 0 || hash_ratio & (hash_ratio - 1) != 0 {
-                        println!("hash size {} is not supported", hash_size_mb);
-                        return UciCommand::IgnoredOption
+                    match get_hash_size(hash_size_mb) {
+                        Some(hash_size) => UciCommand::SetHashSize(hash_size),
+                        None => {
+                            println!("hash size {} is not supported", hash_size_mb);
+                            UciCommand::IgnoredOption
+                        },
+                    }
+                },
+                "MultiPV" => {
+                    match cmd_seq.get(4).and_then(|multi_pv| multi_pv.parse::<usize>().ok()) {
+                        Some(multi_pv) => UciCommand::SetMultiPV(multi_pv.clamp(1, def::MAX_MULTI_PV)),
+                        None => {
+                            println!("info string invalid MultiPV value {}", cmd_seq.get(4).unwrap_or(&""));
+                            io::stdout().flush().ok();
+                            UciCommand::IgnoredOption
+                        },
                     }
-
-                    UciCommand::SetHashSize(hash_ratio * def::MIN_HASH_SIZE_UNIT)
+                },
+                "Threads" => {
+                    match cmd_seq.get(4).and_then(|thread_count| thread_count.parse::<usize>().ok()) {
+                        Some(thread_count) => UciCommand::SetThreads(thread_count.clamp(1, def::MAX_THREADS)),
+                        None => {
+                            println!("info string invalid Threads value {}", cmd_seq.get(4).unwrap_or(&""));
+                            io::stdout().flush().ok();
+                            UciCommand::IgnoredOption
+                        },
+                    }
+                },
+                "UCI_Chess960" => {
+                    UciCommand::SetChess960(cmd_seq[4] == "true")
+                },
+                "OwnBook" => {
+                    UciCommand::SetOwnBook(cmd_seq[4] == "true")
+                },
+                "BookFile" => {
+                    UciCommand::SetBookFile(cmd_seq.get(4..).unwrap_or_default().join(" "))
+                },
+                "EvalType" => {
+                    if cmd_seq[4].eq_ignore_ascii_case("classical") {
+                        UciCommand::SetEvalType(EvalType::Classical)
+                    } else {
+                        UciCommand::SetEvalType(EvalType::Nnue)
+                    }
+                },
+                "EvalFile" => {
+                    UciCommand::SetEvalFile(cmd_seq.get(4..).unwrap_or_default().join(" "))
+                },
+                "NetworkFile" => {
+                    UciCommand::SetNetworkFile(cmd_seq.get(4..).unwrap_or_default().join(" "))
+                },
+                "SyzygyPath" => {
+                    UciCommand::SetSyzygyPath(cmd_seq.get(4..).unwrap_or_default().join(" "))
+                },
+                "BitbaseFile" => {
+                    UciCommand::SetBitbaseFile(cmd_seq.get(4..).unwrap_or_default().join(" "))
                 },
                 _ => UciCommand::IgnoredOption,
             }
@@ -114,8 +191,9 @@ pub fn process_uci_cmd(uci_cmd: &str) -> UciCommand {
             _ => UciCommand::Noop
         },
         "go" => process_go_cmd(&mut cmd_seq.split_off(0)),
-        "perft" => UciCommand::Perft(cmd_seq[1].parse::<u8>().unwrap()),
-        "ponderhit" => UciCommand::Noop,
+        "perft" => process_perft_cmd(&cmd_seq[1..]),
+        "perftsuite" => process_perft_suite_cmd(&cmd_seq[1..]),
+        "bench" => process_bench_cmd(&cmd_seq[1..]),
         _ => {
             eprintln!("unknown uci command {}", cmd_seq[0]);
             UciCommand::Noop
@@ -123,66 +201,174 @@ pub fn process_uci_cmd(uci_cmd: &str) -> UciCommand {
     }
 }
 
-fn process_go_cmd(go_cmd_seq: &mut Vec<&str>) -> UciCommand {
-    match go_cmd_seq[1] {
-        "movestogo" => process_time_control(go_cmd_seq.split_off(1)),
-        "wtime" => process_time_control(go_cmd_seq.split_off(1)),
-        "btime" => process_time_control(go_cmd_seq.split_off(1)),
-        "movetime" => UciCommand::StartSearchWithTime(go_cmd_seq[2].parse::<u128>().unwrap()),
-        "depth" => UciCommand::StartSearchToDepth(go_cmd_seq[2].parse::<u8>().unwrap()),
-        "infinite" => UciCommand::StartSearchInfinite,
-        "ponder" => UciCommand::Noop,
-        sub_cmd => panic!("unsupported sub command {}", sub_cmd),
+/// Converts a hash size in megabytes to the number of hash table entries,
+/// or `None` if it is not a power of two.
+pub fn get_hash_size(hash_size_mb: usize) -> Option<usize> {
+    let hash_ratio = hash_size_mb / def::MIN_HASH_SIZE_MB;
+
+    if hash_ratio == 0 || hash_ratio & (hash_ratio - 1) != 0 {
+        return None
     }
+
+    Some(hash_ratio * def::MIN_HASH_SIZE_UNIT)
 }
 
-fn process_time_control(go_cmd_seq: Vec<&str>) -> UciCommand {
-    let mut wtime = 0;
-    let mut btime = 0;
-    let mut winc = 0;
-    let mut binc = 0;
-    let mut movestogo = 0;
+// perft <depth> [divide] [hash] [threads <count>], using all cores by default
+fn process_perft_cmd(perft_cmd_seq: &[&str]) -> UciCommand {
+    let thread_count = perft_cmd_seq.iter().position(|token| *token == "threads")
+        .and_then(|threads_index| perft_cmd_seq.get(threads_index + 1)?.parse::<usize>().ok())
+        .unwrap_or_else(|| thread::available_parallelism().map(|thread_count| thread_count.get()).unwrap_or(1))
+        .max(1);
+
+    match perft_cmd_seq.first().and_then(|depth| depth.parse::<u8>().ok()) {
+        Some(depth) => UciCommand::Perft(depth, perft_cmd_seq.contains(&"divide"), perft_cmd_seq.contains(&"hash"), thread_count),
+        None => {
+            println!("info string missing perft depth");
+            io::stdout().flush().ok();
+            UciCommand::Noop
+        },
+    }
+}
 
-    let mut tc_cmd_seq = go_cmd_seq.into_iter();
+// perftsuite <file.epd> [max depth]
+fn process_perft_suite_cmd(perft_suite_cmd_seq: &[&str]) -> UciCommand {
+    let (file_path_seq, max_depth) = match perft_suite_cmd_seq.split_last().map(|(last_token, file_path_seq)| (file_path_seq, last_token.parse::<u8>())) {
+        Some((file_path_seq, Ok(max_depth))) if !file_path_seq.is_empty() => (file_path_seq, max_depth),
+        _ => (perft_suite_cmd_seq, u8::MAX),
+    };
 
-    loop {
-        let cmd_key = tc_cmd_seq.next();
+    if file_path_seq.is_empty() {
+        println!("info string missing perft suite file");
+        io::stdout().flush().ok();
+        return UciCommand::Noop
+    }
 
-        if cmd_key.is_none() {
-            break;
+    UciCommand::PerftSuite(file_path_seq.join(" "), max_depth)
+}
+
+fn process_bench_cmd(bench_cmd_seq: &[&str]) -> UciCommand {
+    let depth = bench_cmd_seq.first().and_then(|depth| depth.parse::<u8>().ok()).unwrap_or(bench::DEFAULT_BENCH_DEPTH);
+    let hash_size_mb = bench_cmd_seq.get(1).and_then(|hash_size_mb| hash_size_mb.parse::<usize>().ok()).unwrap_or(bench::DEFAULT_BENCH_HASH_SIZE_MB);
+
+    match get_hash_size(hash_size_mb) {
+        Some(hash_size) => UciCommand::Bench(depth.max(1), hash_size),
+        None => {
+            println!("hash size {} is not supported", hash_size_mb);
+            UciCommand::Noop
+        },
+    }
+}
+
+fn process_go_cmd(go_cmd_seq: &mut Vec<&str>) -> UciCommand {
+    if let Some(search_movs_index) = go_cmd_seq.iter().position(|token| *token == "searchmoves") {
+        let mut mov_str_list = go_cmd_seq.split_off(search_movs_index + 1);
+        let remaining_index = mov_str_list.iter().position(|token| GO_SUB_CMDS.contains(token)).unwrap_or(mov_str_list.len());
+
+        let mut remaining_cmd_seq = mov_str_list.split_off(remaining_index);
+        go_cmd_seq.pop();
+        go_cmd_seq.append(&mut remaining_cmd_seq);
+
+        let search_mov_list = mov_str_list.into_iter().filter_map(parse_mov_str).collect();
+
+        return match process_go_cmd(go_cmd_seq) {
+            UciCommand::Noop => UciCommand::Noop,
+            search_cmd => UciCommand::RestrictSearchMovs(search_mov_list, Box::new(search_cmd)),
         }
+    }
 
-        let cmd_key = cmd_key.unwrap();
+    if let Some(ponder_index) = go_cmd_seq.iter().position(|token| *token == "ponder") {
+        go_cmd_seq.remove(ponder_index);
+        return match process_go_cmd(go_cmd_seq) {
+            UciCommand::Noop => UciCommand::Noop,
+            search_cmd => UciCommand::Ponder(Box::new(search_cmd)),
+        }
+    }
 
-        match cmd_key {
-            "wtime" => {
-                wtime = tc_cmd_seq.next().unwrap().parse::<u128>().unwrap();
-            },
-            "btime" => {
-                btime = tc_cmd_seq.next().unwrap().parse::<u128>().unwrap();
-            },
-            "winc" => {
-                winc = tc_cmd_seq.next().unwrap().parse::<u128>().unwrap();
-            },
-            "binc" => {
-                binc = tc_cmd_seq.next().unwrap().parse::<u128>().unwrap();
-            },
-            "movestogo" => {
-                movestogo = tc_cmd_seq.next().unwrap().parse::<u128>().unwrap();
+    if go_cmd_seq.get(1) == Some(&"perft") {
+        let mut perft_cmd_seq = go_cmd_seq.split_off(2);
+        perft_cmd_seq.push("divide");
+        return process_perft_cmd(&perft_cmd_seq)
+    }
+
+    let mut wtime = None;
+    let mut btime = None;
+    let mut winc = 0;
+    let mut binc = 0;
+    let mut movestogo = 0;
+    let mut has_time_control = false;
+    let mut nodes = None;
+    let mut depth = None;
+    let mut movetime = None;
+    let mut mate = None;
+
+    let mut limit_cmd_seq = go_cmd_seq.split_off(1).into_iter();
+
+    while let Some(cmd_key) = limit_cmd_seq.next() {
+        let is_valid = match cmd_key {
+            "wtime" => parse_go_value(cmd_key, limit_cmd_seq.next()).map(|value| wtime = Some(value)).is_some(),
+            "btime" => parse_go_value(cmd_key, limit_cmd_seq.next()).map(|value| btime = Some(value)).is_some(),
+            "winc" => parse_go_value(cmd_key, limit_cmd_seq.next()).map(|value| winc = value).is_some(),
+            "binc" => parse_go_value(cmd_key, limit_cmd_seq.next()).map(|value| binc = value).is_some(),
+            "movestogo" => parse_go_value(cmd_key, limit_cmd_seq.next()).map(|value| movestogo = value).is_some(),
+            "nodes" => parse_go_value(cmd_key, limit_cmd_seq.next()).map(|value| nodes = Some(value)).is_some(),
+            "depth" => parse_go_value(cmd_key, limit_cmd_seq.next()).map(|value| depth = Some(value)).is_some(),
+            "movetime" => parse_go_value(cmd_key, limit_cmd_seq.next()).map(|value| movetime = Some(value)).is_some(),
+            "mate" => parse_go_value(cmd_key, limit_cmd_seq.next()).map(|value| mate = Some(value)).is_some(),
+            "infinite" => true,
+            sub_cmd => {
+                println!("info string ignored unsupported go sub command {}", sub_cmd);
+                io::stdout().flush().ok();
+                true
             },
-            _ => {}
+        };
+
+        if !is_valid {
+            return UciCommand::Noop
         }
+
+        has_time_control |= ["wtime", "btime", "winc", "binc", "movestogo"].contains(&cmd_key);
     }
 
-    if movestogo == 0 {
-        if winc == 0 && binc == 0 {
-            movestogo = DEFAULT_MOVS_TO_GO_NO_INCREMENT;
-        } else {
-            movestogo = DEFAULT_MOVS_TO_GO;
-        }
+    if let Some(mate_mov_count) = mate {
+        return UciCommand::StartSearchMate(mate_mov_count)
+    }
+
+    let time_control = if has_time_control {
+        Some(get_time_info(wtime.unwrap_or(0), btime.unwrap_or(0), winc, binc, movestogo))
+    } else {
+        None
+    };
+
+    match (nodes, depth, movetime, time_control) {
+        (None, None, None, None) => UciCommand::StartSearchInfinite,
+        (None, None, Some(movetime), None) => UciCommand::StartSearchWithTime(movetime),
+        (None, Some(depth), None, None) => UciCommand::StartSearchToDepth(depth),
+        (None, None, None, Some(time_control)) => UciCommand::StartSearchWithComplextTimeControl(time_control),
+        (nodes, depth, movetime, time_control) => UciCommand::StartSearchWithLimits(nodes.unwrap_or(u64::MAX), depth, movetime, time_control),
     }
+}
 
-    UciCommand::StartSearchWithComplextTimeControl((
+fn parse_go_value<T: std::str::FromStr>(cmd_key: &str, value_str: Option<&str>) -> Option<T> {
+    match value_str.and_then(|value_str| value_str.parse::<T>().ok()) {
+        Some(value) => Some(value),
+        None => {
+            println!("info string invalid value {} for go sub command {}", value_str.unwrap_or(""), cmd_key);
+            io::stdout().flush().ok();
+            None
+        },
+    }
+}
+
+fn get_time_info(wtime: u128, btime: u128, winc: u128, binc: u128, movestogo: u128) -> (TimeInfo, TimeInfo) {
+    let movestogo = if movestogo != 0 {
+        movestogo
+    } else if winc == 0 && binc == 0 {
+        DEFAULT_MOVS_TO_GO_NO_INCREMENT
+    } else {
+        DEFAULT_MOVS_TO_GO
+    };
+
+    (
         TimeInfo{
             all_time_millis: wtime,
             moves_to_go: movestogo,
@@ -193,7 +379,7 @@ fn process_time_control(go_cmd_seq: Vec<&str>) -> UciCommand {
             moves_to_go: movestogo,
             increment_millis: binc,
         }
-    ))
+    )
 }
 
 fn process_position(fen_str: &str) -> UciCommand {
@@ -203,27 +389,46 @@ fn process_position(fen_str: &str) -> UciCommand {
 fn process_position_with_mov_list(fen_str: &str, mov_str_list: Vec<&str>) -> UciCommand {
     let mut mov_list = Vec::new();
     for mov_str in mov_str_list {
-        mov_list.push(parse_mov_str(mov_str));
+        match parse_mov_str(mov_str) {
+            Some(raw_mov) => mov_list.push(raw_mov),
+            None => {
+                println!("info string invalid move {}", mov_str);
+                io::stdout().flush().ok();
+                return UciCommand::Noop
+            },
+        }
     }
 
     UciCommand::Position(fen_str.to_owned(), mov_list)
 }
 
-fn parse_mov_str(mov_str: &str) -> Rawmov {
+fn parse_mov_str(mov_str: &str) -> Option<Rawmov> {
+    if !mov_str.is_ascii() || (mov_str.len() != 4 && mov_str.len() != 5) {
+        return None
+    }
+
     let from_str = &mov_str[0..2];
     let to_str = &mov_str[2..4];
 
+    if !util::is_sqr_str(from_str) || !util::is_sqr_str(to_str) {
+        return None
+    }
+
     let mut promotion_piece = String::new();
     if mov_str.len() == 5 {
         promotion_piece.push_str(&mov_str[4..]);
+
+        if !["q", "r", "b", "n"].contains(&promotion_piece.as_str()) {
+            return None
+        }
     }
 
-    Rawmov {
+    Some(Rawmov {
         from: util::map_sqr_notation_to_index(from_str),
         to: util::map_sqr_notation_to_index(to_str),
         promo: promotion_piece,
         origin_mov_str: mov_str.to_owned(),
-    }
+    })
 }
 
 #[cfg(test)]
@@ -235,14 +440,314 @@ mod tests {
 
     #[test]
     fn test_parse_mov_str() {
-        let raw_mov = parse_mov_str("e1g1");
+        let raw_mov = parse_mov_str("e1g1").unwrap();
         assert_eq!(util::map_sqr_notation_to_index("e1"), raw_mov.from);
         assert_eq!(util::map_sqr_notation_to_index("g1"), raw_mov.to);
         assert_eq!("".to_owned(), raw_mov.promo);
 
-        let raw_mov = parse_mov_str("a7b8q");
+        let raw_mov = parse_mov_str("a7b8q").unwrap();
         assert_eq!(util::map_sqr_notation_to_index("a7"), raw_mov.from);
         assert_eq!(util::map_sqr_notation_to_index("b8"), raw_mov.to);
         assert_eq!("q".to_owned(), raw_mov.promo);
+
+        assert!(parse_mov_str("").is_none());
+        assert!(parse_mov_str("e2").is_none());
+        assert!(parse_mov_str("e2e").is_none());
+        assert!(parse_mov_str("e2e9").is_none());
+        assert!(parse_mov_str("i2e4").is_none());
+        assert!(parse_mov_str("a7a8k").is_none());
+        assert!(parse_mov_str("a7a8qq").is_none());
+        assert!(parse_mov_str("é2e4").is_none());
+    }
+
+    #[test]
+    fn test_process_position_with_invalid_mov() {
+        match process_uci_cmd("position startpos moves e2e4 e7") {
+            UciCommand::Noop => {},
+            _ => panic!("expected invalid position to be ignored"),
+        }
+
+        match process_uci_cmd("position startpos moves e2e4 e7e5") {
+            UciCommand::Position(_, mov_list) => assert_eq!(2, mov_list.len()),
+            _ => panic!("expected position"),
+        }
+    }
+
+    #[test]
+    fn test_process_chess960_option() {
+        match process_uci_cmd("setoption name UCI_Chess960 value true") {
+            UciCommand::SetChess960(chess960) => assert!(chess960),
+            _ => panic!("expected chess960 option"),
+        }
+
+        match process_uci_cmd("setoption name UCI_Chess960 value false") {
+            UciCommand::SetChess960(chess960) => assert!(!chess960),
+            _ => panic!("expected chess960 option"),
+        }
+    }
+
+    #[test]
+    fn test_process_multi_pv_option() {
+        match process_uci_cmd("setoption name MultiPV value 3") {
+            UciCommand::SetMultiPV(multi_pv) => assert_eq!(3, multi_pv),
+            _ => panic!("expected multi pv option"),
+        }
+
+        for setoption_cmd in ["setoption name MultiPV value abc", "setoption name MultiPV value", "setoption name MultiPV"] {
+            match process_uci_cmd(setoption_cmd) {
+                UciCommand::IgnoredOption => {},
+                _ => panic!("expected {} to be ignored", setoption_cmd),
+            }
+        }
+    }
+
+    #[test]
+    fn test_process_threads_option() {
+        match process_uci_cmd("setoption name Threads value 4") {
+            UciCommand::SetThreads(thread_count) => assert_eq!(4, thread_count),
+            _ => panic!("expected threads option"),
+        }
+
+        for setoption_cmd in ["setoption name Threads value four", "setoption name Threads value -1", "setoption name Threads"] {
+            match process_uci_cmd(setoption_cmd) {
+                UciCommand::IgnoredOption => {},
+                _ => panic!("expected {} to be ignored", setoption_cmd),
+            }
+        }
+    }
+
+    #[test]
+    fn test_process_book_options() {
+        match process_uci_cmd("setoption name OwnBook value true") {
+            UciCommand::SetOwnBook(own_book) => assert!(own_book),
+            _ => panic!("expected own book option"),
+        }
+
+        match process_uci_cmd("setoption name BookFile value /books/my book.bin") {
+            UciCommand::SetBookFile(book_file) => assert_eq!("/books/my book.bin", book_file),
+            _ => panic!("expected book file option"),
+        }
+
+        match process_uci_cmd("setoption name BookFile value") {
+            UciCommand::SetBookFile(book_file) => assert!(book_file.is_empty()),
+            _ => panic!("expected book file option"),
+        }
+    }
+
+    #[test]
+    fn test_process_eval_options() {
+        match process_uci_cmd("setoption name EvalFile value /params/tuned eval.toml") {
+            UciCommand::SetEvalFile(eval_file) => assert_eq!("/params/tuned eval.toml", eval_file),
+            _ => panic!("expected eval file option"),
+        }
+
+        match process_uci_cmd("setoption name NetworkFile value /nets/foxsee 256.nnue") {
+            UciCommand::SetNetworkFile(network_file) => assert_eq!("/nets/foxsee 256.nnue", network_file),
+            _ => panic!("expected network file option"),
+        }
+
+        match process_uci_cmd("setoption name EvalType value Classical") {
+            UciCommand::SetEvalType(eval_type) => assert_eq!(EvalType::Classical, eval_type),
+            _ => panic!("expected eval type option"),
+        }
+
+        match process_uci_cmd("setoption name EvalType value NNUE") {
+            UciCommand::SetEvalType(eval_type) => assert_eq!(EvalType::Nnue, eval_type),
+            _ => panic!("expected eval type option"),
+        }
+    }
+
+    #[test]
+    fn test_process_syzygy_path() {
+        match process_uci_cmd("setoption name SyzygyPath value /tb/3-4-5:/tb/6 men") {
+            UciCommand::SetSyzygyPath(syzygy_path) => assert_eq!("/tb/3-4-5:/tb/6 men", syzygy_path),
+            _ => panic!("expected syzygy path option"),
+        }
+    }
+
+    #[test]
+    fn test_process_bitbase_file() {
+        match process_uci_cmd("setoption name BitbaseFile value /engines/foxsee bitbases.bin") {
+            UciCommand::SetBitbaseFile(bitbase_file) => assert_eq!("/engines/foxsee bitbases.bin", bitbase_file),
+            _ => panic!("expected bitbase file option"),
+        }
+
+        match process_uci_cmd("isready") {
+            UciCommand::IsReady => {},
+            _ => panic!("expected isready"),
+        }
+    }
+
+    #[test]
+    fn test_process_perft() {
+        match process_uci_cmd("perft 5") {
+            UciCommand::Perft(depth, divide, hash, thread_count) => {
+                assert_eq!((5, false, false), (depth, divide, hash));
+                assert!(thread_count >= 1);
+            },
+            _ => panic!("expected perft"),
+        }
+
+        match process_uci_cmd("perft 6 divide hash threads 3") {
+            UciCommand::Perft(depth, divide, hash, thread_count) => assert_eq!((6, true, true, 3), (depth, divide, hash, thread_count)),
+            _ => panic!("expected perft"),
+        }
+
+        match process_uci_cmd("go perft 4") {
+            UciCommand::Perft(depth, divide, hash, _thread_count) => assert_eq!((4, true, false), (depth, divide, hash)),
+            _ => panic!("expected perft"),
+        }
+
+        match process_uci_cmd("perftsuite /suites/perft suite.epd 5") {
+            UciCommand::PerftSuite(file_path, max_depth) => assert_eq!(("/suites/perft suite.epd", 5), (file_path.as_str(), max_depth)),
+            _ => panic!("expected perft suite"),
+        }
+
+        match process_uci_cmd("perftsuite perftsuite.epd") {
+            UciCommand::PerftSuite(file_path, max_depth) => assert_eq!(("perftsuite.epd", u8::MAX), (file_path.as_str(), max_depth)),
+            _ => panic!("expected perft suite"),
+        }
+    }
+
+    #[test]
+    fn test_process_bench() {
+        match process_uci_cmd("bench") {
+            UciCommand::Bench(depth, hash_size) => {
+                assert_eq!(bench::DEFAULT_BENCH_DEPTH, depth);
+                assert_eq!(get_hash_size(bench::DEFAULT_BENCH_HASH_SIZE_MB), Some(hash_size));
+            },
+            _ => panic!("expected bench"),
+        }
+
+        match process_uci_cmd("bench 8 64") {
+            UciCommand::Bench(depth, hash_size) => {
+                assert_eq!(8, depth);
+                assert_eq!(64 * def::MIN_HASH_SIZE_UNIT, hash_size);
+            },
+            _ => panic!("expected bench"),
+        }
+
+        match process_uci_cmd("bench 8 24") {
+            UciCommand::Noop => {},
+            _ => panic!("expected unsupported hash size to be ignored"),
+        }
+    }
+
+    #[test]
+    fn test_process_ponder() {
+        match process_uci_cmd("go ponder wtime 60000 btime 55000 winc 1000 binc 1000") {
+            UciCommand::Ponder(search_cmd) => {
+                match *search_cmd {
+                    UciCommand::StartSearchWithComplextTimeControl((w_time_info, b_time_info)) => {
+                        assert_eq!(60000, w_time_info.all_time_millis);
+                        assert_eq!(55000, b_time_info.all_time_millis);
+                    },
+                    _ => panic!("expected time controlled search"),
+                }
+            },
+            _ => panic!("expected ponder search"),
+        }
+    }
+
+    #[test]
+    fn test_process_search_movs() {
+        match process_uci_cmd("go searchmoves e2e4 d2d4 depth 8") {
+            UciCommand::RestrictSearchMovs(mov_list, search_cmd) => {
+                assert_eq!(2, mov_list.len());
+                assert_eq!("e2e4", mov_list[0].origin_mov_str);
+                assert_eq!("d2d4", mov_list[1].origin_mov_str);
+
+                match *search_cmd {
+                    UciCommand::StartSearchToDepth(depth) => assert_eq!(8, depth),
+                    _ => panic!("expected depth limited search"),
+                }
+            },
+            _ => panic!("expected restricted search"),
+        }
+
+        match process_uci_cmd("go wtime 1000 btime 1000 searchmoves a7a8q") {
+            UciCommand::RestrictSearchMovs(mov_list, search_cmd) => {
+                assert_eq!(1, mov_list.len());
+                assert_eq!("q", mov_list[0].promo);
+
+                match *search_cmd {
+                    UciCommand::StartSearchWithComplextTimeControl(_) => {},
+                    _ => panic!("expected time controlled search"),
+                }
+            },
+            _ => panic!("expected restricted search"),
+        }
+    }
+
+    #[test]
+    fn test_process_node_limit() {
+        match process_uci_cmd("go nodes 50000") {
+            UciCommand::StartSearchWithLimits(nodes, depth, movetime, time_control) => {
+                assert_eq!(50000, nodes);
+                assert_eq!(None, depth);
+                assert_eq!(None, movetime);
+                assert!(time_control.is_none());
+            },
+            _ => panic!("expected node limited search"),
+        }
+
+        match process_uci_cmd("go depth 12 nodes 1000000 movetime 3000") {
+            UciCommand::StartSearchWithLimits(nodes, depth, movetime, time_control) => {
+                assert_eq!(1000000, nodes);
+                assert_eq!(Some(12), depth);
+                assert_eq!(Some(3000), movetime);
+                assert!(time_control.is_none());
+            },
+            _ => panic!("expected node limited search"),
+        }
+
+        match process_uci_cmd("go wtime 60000 btime 55000 winc 1000 binc 500 movestogo 20 nodes 4000") {
+            UciCommand::StartSearchWithLimits(nodes, depth, movetime, time_control) => {
+                assert_eq!(4000, nodes);
+                assert_eq!(None, depth);
+                assert_eq!(None, movetime);
+
+                let (w_time_info, b_time_info) = time_control.expect("expected time control");
+                assert_eq!(60000, w_time_info.all_time_millis);
+                assert_eq!(1000, w_time_info.increment_millis);
+                assert_eq!(20, w_time_info.moves_to_go);
+                assert_eq!(55000, b_time_info.all_time_millis);
+                assert_eq!(500, b_time_info.increment_millis);
+            },
+            _ => panic!("expected node limited search"),
+        }
+
+        match process_uci_cmd("go wtime 60000 btime 55000 depth 6") {
+            UciCommand::StartSearchWithLimits(nodes, depth, _, time_control) => {
+                assert_eq!(u64::MAX, nodes);
+                assert_eq!(Some(6), depth);
+                assert!(time_control.is_some());
+            },
+            _ => panic!("expected depth and time limited search"),
+        }
+    }
+
+    #[test]
+    fn test_process_malformed_go() {
+        for go_cmd in ["go nodes abc", "go nodes", "go depth -1", "go movetime 1.5", "go wtime 1000 btime x", "go ponder nodes abc", "go searchmoves e2e4 depth x", "go perft"] {
+            match process_uci_cmd(go_cmd) {
+                UciCommand::Noop => {},
+                _ => panic!("expected {} to be ignored", go_cmd),
+            }
+        }
+
+        match process_uci_cmd("go unknown depth 5") {
+            UciCommand::StartSearchToDepth(depth) => assert_eq!(5, depth),
+            _ => panic!("expected unsupported sub command to be ignored"),
+        }
+
+        match process_uci_cmd("go perft 4 hash") {
+            UciCommand::Perft(depth, divide, hash, _) => {
+                assert_eq!(4, depth);
+                assert!(divide);
+                assert!(hash);
+            },
+            _ => panic!("expected perft"),
+        }
     }
 }
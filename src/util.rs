@@ -47,6 +47,26 @@ pub fn map_piece_code_to_char(piece_code: u8) -> char {
     }
 }
 
+#[inline]
+pub fn map_piece_code_to_fen_char(piece_code: u8) -> char {
+    match piece_code {
+        def::WK => 'K',
+        def::WQ => 'Q',
+        def::WR => 'R',
+        def::WB => 'B',
+        def::WN => 'N',
+        def::WP => 'P',
+
+        def::BK => 'k',
+        def::BQ => 'q',
+        def::BR => 'r',
+        def::BB => 'b',
+        def::BN => 'n',
+        def::BP => 'p',
+        _ => panic!("invalid piece code {}", piece_code),
+    }
+}
+
 #[inline]
 pub fn map_promo_piece_to_char(piece: u8) -> char {
     match piece {
@@ -84,6 +104,12 @@ pub fn map_index_to_sqr_notation(index: usize) -> String {
     format!("{}{}", file_str, rank)
 }
 
+#[inline]
+pub fn is_sqr_str(sqr_str: &str) -> bool {
+    let sqr_bytes = sqr_str.as_bytes();
+    sqr_bytes.len() == 2 && (b'a'..=b'h').contains(&sqr_bytes[0]) && (b'1'..=b'8').contains(&sqr_bytes[1])
+}
+
 #[inline]
 pub fn map_sqr_notation_to_index(sqr_notation: &str) -> usize {
     let sqr_notation_chars: Vec<char> = sqr_notation.chars().collect();
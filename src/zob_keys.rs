@@ -1,50 +1,67 @@
 use crate::{
     def,
-    XorshiftPrng
+    prng::XorshiftPrng,
 };
 
+use std::sync::OnceLock;
+
 const CASTLING_RIGHTS_COMB_SIZE: usize = 16;
 
-static mut BOARD_ZOB_KEYS: Vec<Vec<u64>> = Vec::new();
-static mut B_PLAYER_ZOB_KEY: u64 = 0;
-static mut ENP_SQR_ZOB_KEYS: Vec<u64> = Vec::new();
-static mut CASTLING_RIGHTS_ZOB_KEYS: Vec<u64> = Vec::new();
+struct ZobKeys {
+    board_zob_keys: Vec<Vec<u64>>,
+    b_player_zob_key: u64,
+    enp_sqr_zob_keys: Vec<u64>,
+    castling_rights_zob_keys: Vec<u64>,
+}
+
+impl ZobKeys {
+    fn new() -> Self {
+        let mut prng = XorshiftPrng::new();
+
+        ZobKeys {
+            board_zob_keys: prng.create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE),
+            b_player_zob_key: prng.gen_rand(),
+            enp_sqr_zob_keys: prng.create_prn_list(def::BOARD_SIZE),
+            castling_rights_zob_keys: prng.create_prn_list(CASTLING_RIGHTS_COMB_SIZE),
+        }
+    }
+}
+
+static ZOB_KEYS: OnceLock<ZobKeys> = OnceLock::new();
 
 pub fn init() {
-    let mut prng = XorshiftPrng::new();
+    ZOB_KEYS.get_or_init(ZobKeys::new);
+}
 
-    unsafe {
-        BOARD_ZOB_KEYS = prng.create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
-        B_PLAYER_ZOB_KEY = prng.gen_rand();
-        ENP_SQR_ZOB_KEYS = prng.create_prn_list(def::BOARD_SIZE);
-        CASTLING_RIGHTS_ZOB_KEYS = prng.create_prn_list(CASTLING_RIGHTS_COMB_SIZE);
+#[inline]
+fn get_zob_keys() -> &'static ZobKeys {
+    match ZOB_KEYS.get() {
+        Some(zob_keys) => zob_keys,
+        None => init_zob_keys(),
     }
 }
 
+#[cold]
+fn init_zob_keys() -> &'static ZobKeys {
+    ZOB_KEYS.get_or_init(ZobKeys::new)
+}
+
 #[inline]
 pub fn get_board_zob_key(index: usize, moving_piece: u8) -> u64 {
-    unsafe {
-        BOARD_ZOB_KEYS[index][moving_piece as usize]
-    }
+    get_zob_keys().board_zob_keys[index][moving_piece as usize]
 }
 
 #[inline]
 pub fn get_b_player_zob_key() -> u64 {
-    unsafe {
-        B_PLAYER_ZOB_KEY
-    }
+    get_zob_keys().b_player_zob_key
 }
 
 #[inline]
 pub fn get_enp_sqr_zob_key(index: usize) -> u64 {
-    unsafe {
-        ENP_SQR_ZOB_KEYS[index]
-    }
+    get_zob_keys().enp_sqr_zob_keys[index]
 }
 
 #[inline]
 pub fn get_cas_rights_zob_key(cas_rights: u8) -> u64 {
-    unsafe {
-        CASTLING_RIGHTS_ZOB_KEYS[cas_rights as usize]
-    }
+    get_zob_keys().castling_rights_zob_keys[cas_rights as usize]
 }